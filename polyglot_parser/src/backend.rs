@@ -0,0 +1,192 @@
+//! Code generation for every target language (each "flavor" of Polyglot).
+//!
+//! Adding a language only requires implementing [`Backend`] and registering it in [`BACKENDS`].
+use crate::diagnostic::{Diagnostic, ErrorCode};
+use crate::source::Span;
+use crate::tree::{Main, NativeSection, Type};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+mod c;
+mod check;
+mod js;
+mod python;
+mod rust;
+mod types;
+mod yaml;
+
+pub use c::C;
+pub use js::JavaScript;
+pub use python::Python;
+pub use rust::Rust;
+pub use types::Types;
+pub use yaml::Yaml;
+
+/// Every available backend, the first one is the default.
+static BACKENDS: &[&dyn Backend] = &[
+    &Yaml,
+    &Python,
+    &C,
+    &Rust,
+    &JavaScript { typescript: false },
+    &JavaScript { typescript: true },
+];
+
+/// Constructs a target language can express, [`Backend::check`] reports programs using the rest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Capabilities {
+    /// Variables can hold the result of a function without a return type
+    pub void_variables: bool,
+    /// `print`, `println` and `dbg` can be used as values
+    pub builtin_values: bool,
+    /// Lists can be compared with `<`, `>`, `<=` and `>=`
+    pub ordered_lists: bool,
+    pub ordered_dicts: bool,
+    pub dict_keys: DictKeys,
+    pub redeclarations: Redeclarations,
+    pub int_width: IntWidth,
+    /// Signed overflow can be wrapped through unsigned ints, most targets leave it undefined otherwise
+    pub unsigned_ints: bool,
+    /// Nested calls the target allows before failing, `None` if only the size of the stack limits them
+    pub recursion_depth: Option<u32>,
+    /// Runtime failures are exceptions, so exceeding `recursion_depth` is an error instead of a crash
+    pub exceptions: bool,
+    /// Sections of native code the backend splices, the first one it finds is used.
+    ///
+    /// Empty if native code is kept as it is.
+    pub native_code: &'static [&'static str],
+}
+
+/// Variables a target can declare again in the same block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Redeclarations {
+    #[default]
+    Never,
+    /// Only with the type of the previous declaration
+    SameType,
+    Any,
+}
+
+/// Ints of a target, the interpreter's are 64 bits wide and wrap on overflow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IntWidth {
+    #[default]
+    Bits64,
+    /// Ints grow as needed, so they never wrap
+    Unbounded,
+    /// Ints are doubles, exact only up to 2^53
+    Double,
+}
+
+/// Types a target can use as the keys of a dictionary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DictKeys {
+    Any,
+    /// Types without lists or dictionaries, which can't be hashed
+    Immutable,
+    /// Types without nums, ranges, structs or dictionaries
+    Hashable,
+    /// Only primitives and strs
+    #[default]
+    Primitives,
+}
+
+/// Files generated by a [`Backend`].
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Output {
+    /// Translated program, written next to the source file with the backend extension
+    pub source: String,
+    /// Support files the program needs (e.g. a runtime), relative to the directory of `source`
+    pub files: Vec<(PathBuf, String)>,
+}
+
+pub trait Backend: Sync {
+    /// Name used to select the backend with `--target`.
+    fn name(&self) -> &'static str;
+
+    /// Extension of the generated source file, without the dot.
+    fn extension(&self) -> &'static str;
+
+    fn capabilities(&self) -> Capabilities;
+
+    /// Every construct of an already checked program the target can't express, reported before translating it.
+    fn check(&self, main: &Main) -> Vec<Diagnostic> {
+        check::unsupported(main, self.name(), self.capabilities())
+    }
+
+    /// Translates an already checked program, failing if it uses something the target can't express.
+    ///
+    /// Backends walk the checked tree, not the [`ir`](crate::ir): lowering them to it is a separate change that
+    /// hasn't been made, so each one still desugars `elif` chains, compound assignments and `--=` on its own.
+    fn emit(&self, main: &Main) -> Result<Output, Diagnostic>;
+
+    /// Commands that build and execute the emitted `source`, run in order from its directory.
+    ///
+    /// The last one runs the program, and there are none if the output can't be executed (e.g. YAML).
+    fn commands(&self, _source: &Path) -> Vec<Command> {
+        Vec::new()
+    }
+}
+
+pub fn backends() -> &'static [&'static dyn Backend] {
+    BACKENDS
+}
+
+pub fn names() -> impl Iterator<Item = &'static str> {
+    BACKENDS.iter().map(|b| b.name())
+}
+
+pub fn find(name: &str) -> Option<&'static dyn Backend> {
+    BACKENDS.iter().copied().find(|b| b.name() == name)
+}
+
+impl DictKeys {
+    pub fn allow(self, key: &Type) -> bool {
+        match self {
+            DictKeys::Any => true,
+            DictKeys::Immutable => !contains(key, &|t| matches!(t, Type::List(_) | Type::Dict(_))),
+            DictKeys::Hashable => {
+                !contains(key, &|t| matches!(t, Type::Num | Type::Range | Type::Struct(_) | Type::Dict(_)))
+            }
+            DictKeys::Primitives => matches!(key, Type::Int | Type::Num | Type::Bool | Type::Char | Type::Str),
+        }
+    }
+}
+
+/// Whether `r#type` or any of the types it's made of matches `found`.
+fn contains(r#type: &Type, found: &dyn Fn(&Type) -> bool) -> bool {
+    found(r#type)
+        || match r#type {
+            Type::Tuple(types) => types.iter().any(|t| contains(t, found)),
+            Type::Struct(members) => members.iter().any(|(_, t)| contains(t, found)),
+            Type::List(element) => contains(element, found),
+            Type::Dict(d) => contains(&d.0, found) || contains(&d.1, found),
+            _ => false,
+        }
+}
+
+/// Code of the first of `targets` a native block or function has a section for.
+///
+/// Fails if there's none, the program can't be translated without it.
+fn native_code<'a>(sections: &'a [NativeSection], targets: &[&str], span: Span) -> Result<&'a str, Diagnostic> {
+    let found = targets.iter().find_map(|t| sections.iter().find(|s| s.target == *t));
+    found.map(|s| s.code.as_str()).ok_or_else(|| {
+        let target = targets[0];
+        Diagnostic::error(
+            ErrorCode::UnsupportedByTarget,
+            format!("native code without an implementation for the `{target}` target"),
+        )
+        .with_primary(span, format!("has no `native {target}` section"))
+    })
+}
+
+/// Appends the lines of `code` to `out`, indented `indent` levels, blank lines are left empty.
+fn splice(out: &mut String, code: &str, indent: usize) {
+    for line in code.lines() {
+        if !line.trim().is_empty() {
+            out.push_str(&"    ".repeat(indent));
+            out.push_str(line);
+        }
+        out.push('\n');
+    }
+}
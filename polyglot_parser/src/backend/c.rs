@@ -0,0 +1,1016 @@
+use super::{native_code, splice, Backend, Capabilities, DictKeys, Output, Types};
+use crate::diagnostic::{Diagnostic, ErrorCode};
+use crate::source::Span;
+use crate::tree::*;
+use rustc_hash::FxHashSet;
+use std::path::Path;
+use std::process::Command;
+
+/// Portable C99, containers are monomorphized for every element type and allocated in an arena that lives as long
+/// as the program.
+pub struct C;
+
+/// Support code shared by every program, written next to the generated source.
+const RUNTIME: &str = include_str!("c/polyglot.h");
+
+impl Backend for C {
+    fn name(&self) -> &'static str {
+        "c"
+    }
+
+    fn extension(&self) -> &'static str {
+        "c"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            dict_keys: DictKeys::Any,
+            unsigned_ints: true,
+            native_code: &["c"],
+            ..Default::default()
+        }
+    }
+
+    fn emit(&self, main: &Main) -> Result<Output, Diagnostic> {
+        let mut emitter = Emitter::new(main);
+        emitter.indent = 1;
+        emitter.exprs(&main.exprs);
+        if let Some(error) = emitter.error.take() {
+            return Err(error);
+        }
+        Ok(Output {
+            source: emitter.finish(),
+            files: vec![("polyglot.h".into(), RUNTIME.into())],
+        })
+    }
+
+    fn commands(&self, source: &Path) -> Vec<Command> {
+        let program = source.with_extension(std::env::consts::EXE_EXTENSION);
+        let mut cc = Command::new("cc");
+        cc.args(["-std=c99", "-o"]).arg(&program).arg(source).arg("-lm");
+        vec![cc, Command::new(program)]
+    }
+}
+
+/// Names that would clash with C keywords, the standard library or the runtime.
+#[rustfmt::skip]
+const RESERVED: &[&str] = &[
+    "auto", "break", "case", "char", "const", "continue", "default", "do", "double", "else", "enum", "extern",
+    "float", "for", "goto", "if", "inline", "int", "long", "register", "restrict", "return", "short", "signed",
+    "sizeof", "static", "struct", "switch", "typedef", "union", "unsigned", "void", "volatile", "while", "_Bool",
+    "_Complex", "_Imaginary", "bool", "true", "false", "main", "NULL", "abs", "atoi", "calloc", "errno", "exit",
+    "fflush", "fputc", "fputs", "free", "malloc", "memcpy", "memmove", "printf", "puts", "realloc", "snprintf",
+    "stderr", "stdin", "stdout", "strcmp", "strlen", "strtod", "strtoll", "vfprintf",
+];
+
+struct Emitter<'a> {
+    main: &'a Main,
+    types: Types,
+    out: String,
+    indent: usize,
+    /// Type definitions and container instances, each one after the types it depends on
+    definitions: Vec<String>,
+    defined: FxHashSet<String>,
+    /// C struct generated for each struct type, typedefs keep their name
+    structs: Vec<(String, Vec<(String, Type)>)>,
+    /// `#include` lines of native code, which must be at the top of the file
+    includes: Vec<String>,
+    prototypes: Vec<String>,
+    functions: Vec<String>,
+    /// Used to give unique names to the temporaries of `for` loops
+    temporaries: usize,
+    /// First construct that can't be translated
+    error: Option<Diagnostic>,
+}
+
+impl<'a> Emitter<'a> {
+    fn new(main: &'a Main) -> Emitter<'a> {
+        Emitter {
+            main,
+            types: Types::new(),
+            out: String::new(),
+            indent: 0,
+            definitions: Vec::new(),
+            defined: FxHashSet::default(),
+            structs: Vec::new(),
+            includes: Vec::new(),
+            prototypes: Vec::new(),
+            functions: Vec::new(),
+            temporaries: 0,
+            error: None,
+        }
+    }
+
+    /// Joins the type definitions and functions needed by the program with its body, which becomes `main`.
+    fn finish(mut self) -> String {
+        let body = std::mem::take(&mut self.out);
+        let mut header = String::new();
+        if let Some(file) = self.main.source_map.files().first() {
+            let name = file.path.file_name().unwrap_or_default().to_string_lossy();
+            header.push_str(&format!("/* Generated by polyglot from {name} */\n"));
+        }
+        header.push_str("#include \"polyglot.h\"\n");
+        for include in &self.includes {
+            header.push_str(&format!("{include}\n"));
+        }
+
+        let mut sections = vec![header];
+        sections.extend(self.definitions);
+        if !self.prototypes.is_empty() {
+            sections.push(self.prototypes.join(""));
+        }
+        sections.extend(self.functions);
+        sections.push(format!("int main(void) {{\n{body}    pg_arena_free();\n    return 0;\n}}\n"));
+        sections.join("\n")
+    }
+
+    fn unsupported(&mut self, span: Span, message: impl Into<String>) -> String {
+        if self.error.is_none() {
+            let message = message.into();
+            self.error = Some(
+                Diagnostic::error(
+                    ErrorCode::UnsupportedByTarget,
+                    format!("{message} are not supported by the C flavor"),
+                )
+                .with_primary(span, message),
+            );
+        }
+        "0".into()
+    }
+
+    fn line(&mut self, text: impl AsRef<str>) {
+        self.out.push_str(&"    ".repeat(self.indent));
+        self.out.push_str(text.as_ref());
+        self.out.push('\n');
+    }
+
+    /// Emits `exprs`, silencing the warnings of variables that are never read.
+    fn exprs(&mut self, exprs: &[Expr]) {
+        for (i, expr) in exprs.iter().enumerate() {
+            self.expr(expr);
+            if let ExprKind::Init { name, r#type, .. } | ExprKind::Decl { name, r#type } = &expr.kind {
+                self.mark_used(name, r#type, &exprs[i + 1..]);
+            }
+        }
+    }
+
+    fn mark_used(&mut self, name: &str, r#type: &Type, rest: &[Expr]) {
+        let numeric = matches!(r#type, Type::Int | Type::Num);
+        if !rest.iter().any(|e| expr_reads(e, name, numeric)) {
+            self.line(format!("(void){};", ident(name)));
+        }
+    }
+
+    fn block(&mut self, exprs: &[Expr], vars: Vec<(String, Type)>) {
+        self.indent += 1;
+        self.types.push();
+        for (name, r#type) in vars {
+            self.types.insert(name, r#type);
+        }
+        self.exprs(exprs);
+        self.types.pop();
+        self.indent -= 1;
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Init { name, r#type, value } => {
+                if matches!(r#type, Type::Void) {
+                    self.unsupported(expr.span, "void variables");
+                    return;
+                }
+                let c_type = self.ctype(r#type);
+                let value = self.owned(value, r#type);
+                self.line(format!("{c_type} {} = {value};", ident(name)));
+                self.types.insert(name, r#type.clone());
+            }
+            ExprKind::Decl { name, r#type } => {
+                let line = format!("{} {} = {};", self.ctype(r#type), ident(name), self.default(r#type));
+                self.line(line);
+                self.types.insert(name, r#type.clone());
+            }
+            ExprKind::Assig { name, value } => {
+                let line = self.assig(name, value);
+                self.line(line);
+            }
+            ExprKind::Typedef { name, r#type } => {
+                if let Type::Struct(members) = r#type {
+                    let name = ident(name);
+                    if !self.structs.iter().any(|(n, _)| *n == name) {
+                        self.define_struct(name, members);
+                    }
+                }
+            }
+            ExprKind::If { cmp, exprs, elif } => {
+                let cmp = self.value(cmp);
+                self.line(format!("if ({cmp}) {{"));
+                self.block(exprs, Vec::new());
+                let mut next = elif.as_deref();
+                while let Some(expr) = next {
+                    match &expr.kind {
+                        ExprKind::Elif { cmp, exprs, elif } => {
+                            let cmp = self.value(cmp);
+                            self.line(format!("}} else if ({cmp}) {{"));
+                            self.block(exprs, Vec::new());
+                            next = elif.as_deref();
+                        }
+                        ExprKind::Else { exprs } => {
+                            self.line("} else {");
+                            self.block(exprs, Vec::new());
+                            next = None;
+                        }
+                        _ => unreachable!("if chains only contain elif and else"),
+                    }
+                }
+                self.line("}");
+            }
+            ExprKind::Elif { .. } | ExprKind::Else { .. } => unreachable!("emitted with their if"),
+            ExprKind::For { var, range, exprs } => self.for_loop(var, range, exprs),
+            ExprKind::While { cmp, exprs } => {
+                let cmp = self.value(cmp);
+                self.line(format!("while ({cmp}) {{"));
+                self.block(exprs, Vec::new());
+                self.line("}");
+            }
+            ExprKind::Fn {
+                name,
+                r#type,
+                args,
+                exprs,
+            } => self.function(name, r#type, args, exprs),
+            ExprKind::Call { name, args } => {
+                let call = self.call(name, args);
+                self.line(format!("{call};"));
+            }
+            ExprKind::Native { sections } => self.native(sections, expr.span),
+            ExprKind::NativeFn {
+                name,
+                r#type,
+                args,
+                sections,
+            } => {
+                let signature = self.signature(name, r#type, args);
+                let out = std::mem::replace(&mut self.out, format!("{signature} {{\n"));
+                let indent = std::mem::replace(&mut self.indent, 1);
+                self.native(sections, expr.span);
+                self.indent = indent;
+                let mut function = std::mem::replace(&mut self.out, out);
+                function.push_str("}\n");
+                self.functions.push(function);
+            }
+            ExprKind::Err => unreachable!("backends only receive valid programs"),
+        }
+    }
+
+    /// Iterates over a copy of lists, so the body can modify the original.
+    fn for_loop(&mut self, var: &str, range: &Value, exprs: &[Expr]) {
+        let element = self.types.element_of(range);
+        let index = format!("pg_i{}", self.temporaries);
+        let items = format!("pg_items{}", self.temporaries);
+        self.temporaries += 1;
+
+        let bind = match (range.r#type.clone(), &range.kind) {
+            (Type::Range, ValueKind::Range((start, end))) => {
+                self.line(format!("for (pg_int {index} = {start}; {index} < {end}; {index}++) {{"));
+                index
+            }
+            (Type::Range, _) => {
+                let range = self.value(range);
+                self.line(format!("pg_range {items} = {range};"));
+                self.line(format!("for (pg_int {index} = {items}.start; {index} < {items}.end; {index}++) {{"));
+                index
+            }
+            (t @ Type::List(_), _) => {
+                let list = self.ctype(&t);
+                let range = self.value(range);
+                self.line(format!("{list} {items} = {list}_copy({range});"));
+                self.line(format!("for (size_t {index} = 0; {index} < {items}.len; {index}++) {{"));
+                format!("{items}.items[{index}]")
+            }
+            _ => {
+                self.unsupported(range.span, "loops over values other than ranges and lists");
+                return;
+            }
+        };
+
+        self.indent += 1;
+        let c_type = self.ctype(&element);
+        self.line(format!("{c_type} {} = {bind};", ident(var)));
+        self.mark_used(var, &element, exprs);
+        self.indent -= 1;
+        self.block(exprs, vec![(var.to_owned(), element)]);
+        self.line("}");
+    }
+
+    /// Functions are hoisted to the top level, C doesn't allow nesting them.
+    fn function(&mut self, name: &str, r#type: &Type, args: &[(String, Type)], exprs: &[Expr]) {
+        let signature = self.signature(name, r#type, args);
+        let out = std::mem::replace(&mut self.out, format!("{signature} {{\n"));
+        let indent = std::mem::replace(&mut self.indent, 0);
+        self.block(exprs, args.to_vec());
+        // Polyglot functions can't return a value yet, so the result is always the default one
+        if !matches!(r#type, Type::Void) {
+            let default = self.default(r#type);
+            self.line(format!("    return {default};"));
+        }
+        self.line("}");
+        self.indent = indent;
+        let function = std::mem::replace(&mut self.out, out);
+        self.functions.push(function);
+    }
+
+    /// Declares a function, returning the signature its definition starts with.
+    fn signature(&mut self, name: &str, r#type: &Type, args: &[(String, Type)]) -> String {
+        self.types.insert_fn(name, r#type.clone(), args);
+        let params = if args.is_empty() {
+            "void".to_owned()
+        } else {
+            args.iter()
+                .map(|(name, t)| format!("{} {}", self.ctype(t), ident(name)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let signature = format!("{} {}({params})", self.ctype(r#type), ident(name));
+        self.prototypes.push(format!("{signature};\n"));
+        signature
+    }
+
+    /// Splices the C section of native code, moving its `#include` lines to the top of the file.
+    fn native(&mut self, sections: &[NativeSection], span: Span) {
+        match native_code(sections, &["c"], span) {
+            Ok(code) => {
+                let (includes, code): (Vec<_>, Vec<_>) =
+                    code.lines().partition(|line| line.trim_start().starts_with("#include"));
+                for include in includes {
+                    if !self.includes.iter().any(|i| i == include.trim()) {
+                        self.includes.push(include.trim().to_owned());
+                    }
+                }
+                splice(&mut self.out, &code.join("\n"), self.indent);
+            }
+            Err(error) => {
+                self.error.get_or_insert(error);
+            }
+        }
+    }
+
+    /// Lists are modified in place when assigning an operation on themselves.
+    fn assig(&mut self, name: &str, value: &Value) -> String {
+        let var_t = self.types.get(name).cloned().unwrap_or(Type::Err);
+        let var = ident(name);
+        let is_self = |v: &Value| matches!(&v.kind, ValueKind::Var(v) if v == name);
+        match (&var_t, &value.kind) {
+            (Type::List(element), ValueKind::Op(op @ (Op::Add(v) | Op::Sub(v)))) if is_self(&v.0) => {
+                let list = self.ctype(&var_t);
+                let (whole, single) = if matches!(op, Op::Add(_)) {
+                    ("extend", "push")
+                } else {
+                    ("remove_each", "remove")
+                };
+                let (operation, operand) = self.list_operand(&var_t, element, &v.1, whole, single);
+                format!("{list}_{operation}(&{var}, {operand});")
+            }
+            (Type::List(element), ValueKind::Op(Op::ListRemoveAll(v))) if v.0 == name => {
+                let list = self.ctype(&var_t);
+                let (operation, operand) = self.list_operand(&var_t, element, &v.1, "remove_all_each", "remove_all");
+                format!("{list}_{operation}(&{var}, {operand});")
+            }
+            // Int operations go through the runtime, so only nums can use augmented assignment
+            (Type::Num, ValueKind::Op(op)) => {
+                let augmented = match op {
+                    Op::Add(v) => Some(("+", v)),
+                    Op::Sub(v) => Some(("-", v)),
+                    Op::Mul(v) => Some(("*", v)),
+                    Op::Div(v) => Some(("/", v)),
+                    _ => None,
+                };
+                match augmented {
+                    Some((symbol, v)) if is_self(&v.0) => format!("{var} {symbol}= {};", self.value(&v.1)),
+                    _ => format!("{var} = {};", self.value(value)),
+                }
+            }
+            _ => format!("{var} = {};", self.owned(value, &var_t)),
+        }
+    }
+
+    /// Operation and operand to apply `value` to a list, either as a whole list or as a single element.
+    fn list_operand(
+        &mut self,
+        list: &Type,
+        element: &Type,
+        value: &Value,
+        whole: &'static str,
+        single: &'static str,
+    ) -> (&'static str, String) {
+        if value.r#type == *list {
+            (whole, self.owned(value, list))
+        } else {
+            (single, self.owned(value, element))
+        }
+    }
+
+    fn call(&mut self, name: &str, args: &[Value]) -> String {
+        if !self.types.is_builtin(name) {
+            let arg_types = self.types.get_fn(name).map(|f| f.args.clone()).unwrap_or_default();
+            let args = args
+                .iter()
+                .zip(arg_types.iter())
+                .map(|(a, t)| self.owned(a, t))
+                .collect::<Vec<_>>()
+                .join(", ");
+            return format!("{}({args})", ident(name));
+        }
+
+        let arg = &args[0];
+        match name {
+            "dbg" => {
+                let text = self.main.source_map.snippet(arg.span).unwrap_or_default();
+                let shown = self.show(arg, true);
+                format!("pg_println(pg_cat(2, {}, {shown}))", c_str(&format!("{text} = ")))
+            }
+            _ => {
+                let shown = match &arg.kind {
+                    ValueKind::Str(s) => self.interpolated(s),
+                    _ => self.show(arg, false),
+                };
+                format!("pg_{name}({shown})")
+            }
+        }
+    }
+
+    /// String literal with its `{var}` replaced, concatenated at runtime if needed.
+    fn interpolated(&mut self, s: &str) -> String {
+        let segments = interpolation(s);
+        if !segments.iter().any(|(_, seg)| matches!(seg, Segment::Var(_))) {
+            return c_str(s);
+        }
+
+        let mut parts = Vec::new();
+        for (_, segment) in segments {
+            match segment {
+                Segment::Text(text) => parts.push(c_str(text)),
+                Segment::Var(var) => {
+                    let var = self.types.var(var, Default::default());
+                    parts.push(self.show(&var, false));
+                }
+            }
+        }
+        format!("pg_cat({}, {})", parts.len(), parts.join(", "))
+    }
+
+    /// String with `value` printed like every other flavor does.
+    fn show(&mut self, value: &Value, nested: bool) -> String {
+        let r#type = value.r#type.clone();
+        if matches!(r#type, Type::Str) && !nested {
+            return self.value(value);
+        }
+        let prefix = self.prefix(&r#type);
+        format!("{prefix}_str({}, {nested})", self.value(value))
+    }
+
+    fn value(&mut self, value: &Value) -> String {
+        match &value.kind {
+            ValueKind::Range((start, end)) => format!("(pg_range){{{start}, {end}}}"),
+            ValueKind::Int(i) => i.to_string(),
+            ValueKind::Num(n) => format!("{n:?}"),
+            ValueKind::Bool(b) => b.to_string(),
+            ValueKind::Char(c) => c_char(*c),
+            ValueKind::Str(s) => c_str(s),
+            ValueKind::Tuple(_) | ValueKind::Struct(_) | ValueKind::List(_) | ValueKind::Dict(_) => {
+                let r#type = value.r#type.clone();
+                self.coerced(value, &r#type)
+            }
+            ValueKind::TupleAccess { name, access_mode, .. } => match access_mode {
+                TupleAccessMode::Member(member) => format!("{}.{}", ident(name), ident(member)),
+                TupleAccessMode::Index(index) => format!("{}.f{index}", ident(name)),
+            },
+            ValueKind::ListAccess { name, access_mode, .. } => {
+                let container = self.types.get(name).cloned().unwrap_or(Type::Err);
+                let prefix = self.ctype(&container);
+                let key = match access_mode {
+                    ListAccessMode::List(index) => self.value(index),
+                    ListAccessMode::Dict(key) => match &container {
+                        Type::Dict(d) => self.owned(key, &d.0),
+                        _ => self.value(key),
+                    },
+                };
+                let line = self.main.source_map.line_col(value.span).0.line;
+                let text = self.main.source_map.snippet(value.span).unwrap_or(name);
+                format!("{prefix}_get({}, {key}, {line}, {})", ident(name), c_str(text))
+            }
+            ValueKind::Var(name) => ident(name),
+            ValueKind::Op(op) => self.op(op, value.span),
+            ValueKind::Cmp(cmp) => self.cmp(cmp, value.span),
+            ValueKind::Parenthesis(p) => format!("({})", self.value(p)),
+            ValueKind::Conversion { r#type, value } => self.conversion(r#type, value),
+            ValueKind::Call { name, args } => {
+                if self.types.is_builtin(name) {
+                    return self.unsupported(value.span, "builtin calls used as values");
+                }
+                self.call(name, args)
+            }
+            ValueKind::RetExpr(r) => match *r {},
+            ValueKind::Err => unreachable!("backends only receive valid programs"),
+        }
+    }
+
+    /// Value used as an operand, wrapped in parenthesis so Polyglot's evaluation order is kept.
+    fn operand(&mut self, value: &Value) -> String {
+        let translated = self.value(value);
+        match &value.kind {
+            ValueKind::Op(_) | ValueKind::Cmp(_) => format!("({translated})"),
+            ValueKind::Int(i) if *i < 0 => format!("({translated})"),
+            ValueKind::Num(n) if *n < 0.0 => format!("({translated})"),
+            _ => translated,
+        }
+    }
+
+    fn op(&mut self, op: &Op, span: Span) -> String {
+        let binary = |e: &mut Self, v: &(Value, Value), symbol: &str| {
+            format!("{} {symbol} {}", e.operand(&v.0), e.operand(&v.1))
+        };
+        let call = |e: &mut Self, v: &(Value, Value), function: &str| {
+            format!("{function}({}, {})", e.value(&v.0), e.value(&v.1))
+        };
+        match op {
+            Op::Add(v) | Op::Sub(v) => match v.0.r#type.clone() {
+                list @ Type::List(_) => {
+                    let Type::List(element) = &list else { unreachable!() };
+                    let (whole, single) = if matches!(op, Op::Add(_)) {
+                        ("concat", "pushed")
+                    } else {
+                        ("removed_each", "removed")
+                    };
+                    let (operation, operand) = self.list_operand(&list, element, &v.1, whole, single);
+                    format!("{}_{operation}({}, {operand})", self.ctype(&list), self.value(&v.0))
+                }
+                Type::Str => call(self, v, "pg_str_concat"),
+                Type::Char => {
+                    let (a, b) = (self.value(&v.0), self.value(&v.1));
+                    format!("pg_str_concat(pg_c_str({a}, false), pg_c_str({b}, false))")
+                }
+                Type::Int if matches!(op, Op::Add(_)) => call(self, v, "pg_i_add"),
+                Type::Int => call(self, v, "pg_i_sub"),
+                _ if matches!(op, Op::Add(_)) => binary(self, v, "+"),
+                _ => binary(self, v, "-"),
+            },
+            Op::ListRemoveAll(v) => {
+                let list = self.types.get(&v.0).cloned().unwrap_or(Type::Err);
+                let Type::List(element) = &list else {
+                    return self.unsupported(span, "removals from values other than lists");
+                };
+                let (operation, operand) = self.list_operand(&list, element, &v.1, "removed_all_each", "removed_all");
+                format!("{}_{operation}({}, {operand})", self.ctype(&list), ident(&v.0))
+            }
+            Op::Mul(v) if matches!(v.0.r#type, Type::Int) => call(self, v, "pg_i_mul"),
+            Op::Mul(v) => binary(self, v, "*"),
+            // C already truncates integer divisions towards zero, and the remainder takes the sign of the dividend
+            Op::Div(v) | Op::Mod(v) if matches!((&v.0.r#type, &v.1.r#type), (Type::Int, Type::Int)) => {
+                let function = if matches!(op, Op::Div(_)) { "pg_i_div" } else { "pg_i_mod" };
+                let line = self.main.source_map.line_col(span).0.line;
+                let text = self.main.source_map.snippet(span).unwrap_or_default();
+                format!("{function}({}, {}, {line}, {})", self.value(&v.0), self.value(&v.1), c_str(text))
+            }
+            Op::Div(v) => binary(self, v, "/"),
+            Op::Mod(v) => call(self, v, "pg_n_mod"),
+            Op::Pow(v) => match (v.0.r#type.clone(), v.1.r#type.clone()) {
+                (Type::Int, Type::Int) => call(self, v, "pg_i_pow"),
+                _ => call(self, v, "pg_n_pow"),
+            },
+        }
+    }
+
+    fn cmp(&mut self, cmp: &Cmp, span: Span) -> String {
+        let (symbol, v) = match cmp {
+            Cmp::Less(v) => ("<", v),
+            Cmp::Greater(v) => (">", v),
+            Cmp::LessEq(v) => ("<=", v),
+            Cmp::GreatEq(v) => (">=", v),
+            Cmp::Equal(v) => ("==", v),
+            Cmp::NotEq(v) => ("!=", v),
+            Cmp::Or(v) => ("||", v),
+            Cmp::And(v) => ("&&", v),
+            Cmp::Not(v) => return format!("!{}", self.operand(v)),
+            Cmp::Err => unreachable!("backends only receive valid programs"),
+        };
+        let r#type = v.0.r#type.clone();
+        match (symbol, &r#type) {
+            ("||" | "&&", _) | (_, Type::Int | Type::Num | Type::Bool | Type::Char) => {
+                format!("{} {symbol} {}", self.operand(&v.0), self.operand(&v.1))
+            }
+            (_, Type::Str) => {
+                format!("strcmp({}, {}) {symbol} 0", self.value(&v.0), self.value(&v.1))
+            }
+            ("==" | "!=", _) => {
+                let prefix = self.prefix(&r#type);
+                let negation = if symbol == "!=" { "!" } else { "" };
+                format!("{negation}{prefix}_eq({}, {})", self.value(&v.0), self.value(&v.1))
+            }
+            _ => self.unsupported(span, "ordered comparisons of compound values"),
+        }
+    }
+
+    fn conversion(&mut self, into: &Type, value: &Value) -> String {
+        let from = value.r#type.clone();
+        let converted = self.value(value);
+        match &from {
+            Type::List(element) => {
+                let name = self.converter(element, into);
+                let (from_list, into_list) = (self.ctype(&from), self.ctype(&Type::List(Box::new(into.clone()))));
+                let map = format!("{from_list}_to_{}", self.code(into));
+                self.define(&map, || format!("PG_LIST_MAP({from_list}, {into_list}, {map}, {name})\n"));
+                format!("{map}({converted})")
+            }
+            Type::Dict(d) => {
+                let (key, value) = (self.converter(&d.0, into), self.converter(&d.1, into));
+                let into_dict = Type::Dict(Box::new((into.clone(), into.clone())));
+                let (from_dict, into_dict) = (self.ctype(&from), self.ctype(&into_dict));
+                let map = format!("{from_dict}_to_{}", self.code(into));
+                self.define(&map, || format!("PG_DICT_MAP({from_dict}, {into_dict}, {map}, {key}, {value})\n"));
+                format!("{map}({converted})")
+            }
+            _ => self.convert(&from, into, &converted),
+        }
+    }
+
+    /// Function converting a single primitive, used to convert the elements of containers.
+    fn converter(&mut self, from: &Type, into: &Type) -> String {
+        let name = format!("pg_{}_to_{}", self.code(from), self.code(into));
+        let (from_type, into_type) = (self.ctype(from), self.ctype(into));
+        let body = self.convert(from, into, "v");
+        self.define(&name, || format!("static inline {into_type} {name}({from_type} v) {{\n    return {body};\n}}\n"));
+        name
+    }
+
+    /// Conversion of the primitive expression `value`, aborting like every other flavor when it fails.
+    fn convert(&mut self, from: &Type, into: &Type, value: &str) -> String {
+        let shown = |e: &mut Self| format!("{}_str({value}, false)", e.prefix(from));
+        match (into, from) {
+            (into, from) if std::mem::discriminant(into) == std::mem::discriminant(from) => value.into(),
+            (Type::Int, Type::Num | Type::Bool) => format!("(pg_int)({value})"),
+            (Type::Int, Type::Str) => format!("pg_int_parse({value})"),
+            (Type::Int, _) => format!("pg_int_parse({})", shown(self)),
+            (Type::Bool, Type::Int | Type::Num) => format!("({value}) != 0"),
+            (Type::Bool, Type::Str) => format!("pg_bool_parse({value})"),
+            (Type::Bool, _) => format!("pg_bool_parse({})", shown(self)),
+            (Type::Char, Type::Str) => format!("pg_char_parse({value})"),
+            (Type::Char, _) => format!("pg_char_parse({})", shown(self)),
+            _ => shown(self),
+        }
+    }
+
+    /// Translates `value`, building compound literals with the type `expected` requires.
+    fn coerced(&mut self, value: &Value, expected: &Type) -> String {
+        let found = value.r#type.clone();
+        match (expected, &found, &value.kind) {
+            (Type::Struct(members), _, ValueKind::Tuple(items)) => {
+                let name = self.ctype(expected);
+                let items = items
+                    .iter()
+                    .zip(members)
+                    .map(|(v, (_, t))| self.owned(v, t))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("({name}){{{items}}}")
+            }
+            (Type::Struct(members), _, ValueKind::Struct(items)) => {
+                let name = self.ctype(expected);
+                let items = items
+                    .iter()
+                    .map(|(member, v)| {
+                        let t = members
+                            .iter()
+                            .find(|(m, _)| m == member)
+                            .map_or(Type::Err, |(_, t)| t.clone());
+                        format!(".{} = {}", ident(member), self.owned(v, &t))
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("({name}){{{items}}}")
+            }
+            (Type::Struct(members), Type::Tuple(_), _) => {
+                let name = self.ctype(expected);
+                let tuple = self.ctype(&found);
+                let from = format!("{name}_from_{tuple}");
+                self.define(&from, || {
+                    let fields = (0..members.len())
+                        .map(|i| format!("t.f{i}"))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    format!("static inline {name} {from}({tuple} t) {{\n    return ({name}){{{fields}}};\n}}\n")
+                });
+                format!("{from}({})", self.value(value))
+            }
+            (Type::Tuple(types), _, ValueKind::Tuple(items)) => {
+                let name = self.ctype(expected);
+                let items = items
+                    .iter()
+                    .zip(types)
+                    .map(|(v, t)| self.owned(v, t))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("({name}){{{items}}}")
+            }
+            (Type::List(t), _, ValueKind::List(items)) => {
+                let (name, element) = (self.ctype(expected), self.ctype(t));
+                let items = items.iter().map(|v| self.owned(v, t)).collect::<Vec<_>>();
+                format!("{name}_of({}, ({element}[]){{{}}})", items.len(), items.join(", "))
+            }
+            (Type::Dict(d), _, ValueKind::Dict(pairs)) => {
+                let (name, key, value) = (self.ctype(expected), self.ctype(&d.0), self.ctype(&d.1));
+                let keys = pairs.iter().map(|(k, _)| self.owned(k, &d.0)).collect::<Vec<_>>();
+                let values = pairs.iter().map(|(_, v)| self.owned(v, &d.1)).collect::<Vec<_>>();
+                format!(
+                    "{name}_of({}, ({key}[]){{{}}}, ({value}[]){{{}}})",
+                    pairs.len(),
+                    keys.join(", "),
+                    values.join(", ")
+                )
+            }
+            _ => self.value(value),
+        }
+    }
+
+    /// Like [`Emitter::coerced`], but copying lists and dictionaries stored somewhere else, as they have value
+    /// semantics in Polyglot.
+    fn owned(&mut self, value: &Value, expected: &Type) -> String {
+        let coerced = self.coerced(value, expected);
+        let place =
+            matches!(value.kind, ValueKind::Var(_) | ValueKind::TupleAccess { .. } | ValueKind::ListAccess { .. });
+        match expected {
+            Type::List(_) | Type::Dict(_) if place => format!("{}_copy({coerced})", self.ctype(expected)),
+            _ => coerced,
+        }
+    }
+
+    /// Adds a definition named `name` if it doesn't exist yet.
+    fn define(&mut self, name: &str, definition: impl FnOnce() -> String) {
+        if self.defined.insert(name.to_owned()) {
+            let definition = definition();
+            self.definitions.push(definition);
+        }
+    }
+
+    /// Name of the C type of `type`, defining it and the types it contains if needed.
+    fn ctype(&mut self, r#type: &Type) -> String {
+        match r#type {
+            Type::Range => "pg_range".into(),
+            Type::Int => "pg_int".into(),
+            Type::Num => "pg_num".into(),
+            Type::Bool => "pg_bool".into(),
+            Type::Char => "pg_char".into(),
+            Type::Str => "pg_str".into(),
+            Type::List(element) => {
+                let (c_type, prefix) = (self.ctype(element), self.prefix(element));
+                let name = format!("list_{}", self.code(element));
+                self.define(&name, || format!("PG_LIST({c_type}, {name}, {prefix})\n"));
+                name
+            }
+            Type::Dict(d) => {
+                let (key, value) = (self.ctype(&d.0), self.ctype(&d.1));
+                let (key_prefix, value_prefix) = (self.prefix(&d.0), self.prefix(&d.1));
+                let name = format!("dict_{}{}", self.code(&d.0), self.code(&d.1));
+                self.define(&name, || format!("PG_DICT({key}, {value}, {name}, {key_prefix}, {value_prefix})\n"));
+                name
+            }
+            Type::Tuple(types) => {
+                let name = format!("tuple_{}", types.iter().map(|t| self.code(t)).collect::<String>());
+                if !self.defined.contains(&name) {
+                    let fields = types
+                        .iter()
+                        .enumerate()
+                        .map(|(i, t)| (format!("f{i}"), t.clone()))
+                        .collect();
+                    self.define_record(&name, fields, false);
+                }
+                name
+            }
+            Type::Struct(members) => {
+                if let Some((name, _)) = self.structs.iter().find(|(_, m)| m == members) {
+                    return name.clone();
+                }
+                let name = format!("Struct{}", self.structs.len());
+                self.define_struct(name.clone(), members);
+                name
+            }
+            Type::Void => "void".into(),
+            Type::Custom(_) | Type::Err => unreachable!("backends only receive valid programs"),
+        }
+    }
+
+    /// Short name of `type`, used to name the monomorphized containers.
+    ///
+    /// No code is a prefix of another one, so a concatenation of codes can't be ambiguous.
+    fn code(&mut self, r#type: &Type) -> String {
+        match r#type {
+            Type::Range => "r".into(),
+            Type::Int => "i".into(),
+            Type::Num => "n".into(),
+            Type::Bool => "b".into(),
+            Type::Char => "c".into(),
+            Type::Str => "s".into(),
+            Type::Tuple(types) => {
+                let codes = types.iter().map(|t| self.code(t)).collect::<String>();
+                format!("t{}{codes}", types.len())
+            }
+            Type::Struct(_) => {
+                let name = self.ctype(r#type);
+                format!("s{}{name}", name.len())
+            }
+            Type::List(element) => format!("l{}", self.code(element)),
+            Type::Dict(d) => format!("d{}{}", self.code(&d.0), self.code(&d.1)),
+            Type::Void | Type::Custom(_) | Type::Err => unreachable!("containers always hold value types"),
+        }
+    }
+
+    /// Prefix of the runtime functions of `type`.
+    fn prefix(&mut self, r#type: &Type) -> String {
+        match r#type {
+            Type::Range | Type::Int | Type::Num | Type::Bool | Type::Char | Type::Str => {
+                format!("pg_{}", self.code(r#type))
+            }
+            t => self.ctype(t),
+        }
+    }
+
+    fn define_struct(&mut self, name: String, members: &[(String, Type)]) {
+        self.structs.push((name.clone(), members.to_vec()));
+        let fields = members.iter().map(|(m, t)| (ident(m), t.clone())).collect();
+        self.define_record(&name, fields, true);
+    }
+
+    /// Defines a C struct for a tuple or struct, with the functions every type has.
+    fn define_record(&mut self, name: &str, fields: Vec<(String, Type)>, named: bool) {
+        let fields = fields
+            .into_iter()
+            .map(|(f, t)| (f, self.ctype(&t), self.prefix(&t)))
+            .collect::<Vec<_>>();
+        self.define(name, || {
+            let mut definition = String::from("typedef struct {\n");
+            for (field, c_type, _) in &fields {
+                definition.push_str(&format!("    {c_type} {field};\n"));
+            }
+            definition.push_str(&format!("}} {name};\n\n"));
+
+            let equal = fields
+                .iter()
+                .map(|(field, _, prefix)| format!("{prefix}_eq(a.{field}, b.{field})"))
+                .collect::<Vec<_>>();
+            let equal = if equal.is_empty() {
+                "true".to_owned()
+            } else {
+                equal.join(" && ")
+            };
+            definition
+                .push_str(&format!("static inline bool {name}_eq({name} a, {name} b) {{\n    return {equal};\n}}\n\n"));
+
+            definition.push_str(&format!("static inline void {name}_show(pg_buf *b, {name} v, bool nested) {{\n"));
+            definition.push_str("    (void)nested;\n");
+            for (i, (field, _, prefix)) in fields.iter().enumerate() {
+                let separator = if i == 0 { "(" } else { ", " };
+                let label = if named { format!("{field}: ") } else { String::new() };
+                definition.push_str(&format!("    pg_buf_puts(b, \"{separator}{label}\");\n"));
+                definition.push_str(&format!("    {prefix}_show(b, v.{field}, true);\n"));
+            }
+            if fields.is_empty() {
+                definition.push_str("    pg_buf_puts(b, \"(\");\n");
+            }
+            definition.push_str("    pg_buf_puts(b, \")\");\n}\n\n");
+            definition.push_str(&format!("PG_STR({name}, {name})\n"));
+            definition
+        });
+    }
+
+    /// Value of a variable declared without initialization.
+    fn default(&mut self, r#type: &Type) -> String {
+        match r#type {
+            Type::Range => "(pg_range){0, 0}".into(),
+            Type::Int | Type::Char => "0".into(),
+            Type::Num => "0.0".into(),
+            Type::Bool => "false".into(),
+            Type::Str => "\"\"".into(),
+            Type::Tuple(types) => {
+                let name = self.ctype(r#type);
+                let items = types.iter().map(|t| self.default(t)).collect::<Vec<_>>();
+                format!("({name}){{{}}}", items.join(", "))
+            }
+            Type::Struct(members) => {
+                let name = self.ctype(r#type);
+                let items = members.iter().map(|(_, t)| self.default(t)).collect::<Vec<_>>();
+                format!("({name}){{{}}}", items.join(", "))
+            }
+            Type::List(_) | Type::Dict(_) => format!("{}_new()", self.ctype(r#type)),
+            Type::Void | Type::Custom(_) | Type::Err => unreachable!("variables always have a value type"),
+        }
+    }
+}
+
+/// Whether `name` is read by `expr`, assigning it doesn't count.
+///
+/// `numeric` variables become augmented assignments when operating on themselves, which doesn't count either.
+fn expr_reads(expr: &Expr, name: &str, numeric: bool) -> bool {
+    let reads = |value: &Value| value_reads(value, name);
+    let block = |exprs: &[Expr]| exprs.iter().any(|e| expr_reads(e, name, numeric));
+    match &expr.kind {
+        ExprKind::Init { value, .. } => reads(value),
+        ExprKind::Assig { name: target, value } if numeric && target == name => match &value.kind {
+            ValueKind::Op(Op::Add(v) | Op::Sub(v) | Op::Mul(v) | Op::Div(v)) if matches!(value.r#type, Type::Num) => {
+                (!matches!(&v.0.kind, ValueKind::Var(v) if v == name) && reads(&v.0)) || reads(&v.1)
+            }
+            _ => reads(value),
+        },
+        ExprKind::Assig { value, .. } => reads(value),
+        ExprKind::If { cmp, exprs, elif } | ExprKind::Elif { cmp, exprs, elif } => {
+            reads(cmp) || block(exprs) || elif.as_ref().is_some_and(|e| expr_reads(e, name, numeric))
+        }
+        ExprKind::Else { exprs } => block(exprs),
+        ExprKind::For { range, exprs, .. } => reads(range) || block(exprs),
+        ExprKind::While { cmp, exprs } => reads(cmp) || block(exprs),
+        ExprKind::Call { args, .. } => args.iter().any(reads),
+        // Native code can't be inspected, at worst the variable gets a redundant `(void)`
+        ExprKind::Decl { .. }
+        | ExprKind::Typedef { .. }
+        | ExprKind::Fn { .. }
+        | ExprKind::Native { .. }
+        | ExprKind::NativeFn { .. }
+        | ExprKind::Err => false,
+    }
+}
+
+fn value_reads(value: &Value, name: &str) -> bool {
+    let reads = |value: &Value| value_reads(value, name);
+    let pair = |v: &(Value, Value)| reads(&v.0) || reads(&v.1);
+    match &value.kind {
+        ValueKind::Var(var) => var == name,
+        ValueKind::TupleAccess { name: var, .. } => var == name,
+        ValueKind::ListAccess {
+            name: var, access_mode, ..
+        } => {
+            let (ListAccessMode::List(key) | ListAccessMode::Dict(key)) = access_mode;
+            var == name || reads(key)
+        }
+        ValueKind::Tuple(items) | ValueKind::List(items) => items.iter().any(reads),
+        ValueKind::Struct(members) => members.iter().any(|(_, v)| reads(v)),
+        ValueKind::Dict(pairs) => pairs.iter().any(pair),
+        ValueKind::Op(op) => match op {
+            Op::ListRemoveAll(v) => v.0 == name || reads(&v.1),
+            Op::Add(v) | Op::Sub(v) | Op::Mul(v) | Op::Div(v) | Op::Mod(v) | Op::Pow(v) => pair(v),
+        },
+        ValueKind::Cmp(cmp) => match cmp {
+            Cmp::Not(v) => reads(v),
+            Cmp::Less(v)
+            | Cmp::Greater(v)
+            | Cmp::LessEq(v)
+            | Cmp::GreatEq(v)
+            | Cmp::Equal(v)
+            | Cmp::NotEq(v)
+            | Cmp::Or(v)
+            | Cmp::And(v) => pair(v),
+            Cmp::Err => false,
+        },
+        ValueKind::Parenthesis(v) | ValueKind::Conversion { value: v, .. } => reads(v),
+        ValueKind::Call { args, .. } => args.iter().any(reads),
+        ValueKind::Str(s) => interpolation(s).iter().any(|(_, seg)| *seg == Segment::Var(name)),
+        ValueKind::Range(_) | ValueKind::Int(_) | ValueKind::Num(_) | ValueKind::Bool(_) | ValueKind::Char(_) => false,
+        ValueKind::RetExpr(r) => match *r {},
+        ValueKind::Err => false,
+    }
+}
+
+fn ident(name: &str) -> String {
+    let generated = ["pg_", "PG_", "list_", "dict_", "tuple_", "Struct"];
+    if RESERVED.contains(&name) || generated.iter().any(|p| name.starts_with(p)) {
+        format!("{name}_")
+    } else {
+        name.into()
+    }
+}
+
+fn c_char(c: char) -> String {
+    if c.is_ascii_graphic() && c != '\'' && c != '\\' || c == ' ' {
+        format!("'{c}'")
+    } else {
+        format!("0x{:X}", c as u32)
+    }
+}
+
+/// Polyglot strings have no escape sequences, so every special character is escaped.
+fn c_str(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            // Avoids trigraphs
+            '?' => escaped.push_str("\\?"),
+            c if c.is_ascii_control() => escaped.push_str(&format!("\\{:03o}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
@@ -0,0 +1,666 @@
+/* Runtime of the C flavor of Polyglot.
+ *
+ * Every allocation is served from a single arena that lives as long as the program, so values can be
+ * copied around freely without tracking ownership. Container types are instantiated per element type
+ * by the generated program with the PG_LIST and PG_DICT macros. */
+#ifndef POLYGLOT_H
+#define POLYGLOT_H
+
+#include <float.h>
+#include <inttypes.h>
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef int64_t pg_int;
+typedef double pg_num;
+typedef bool pg_bool;
+typedef uint32_t pg_char;
+typedef const char *pg_str;
+typedef struct {
+    pg_int start, end;
+} pg_range;
+
+/* ARENA */
+typedef struct pg_block {
+    struct pg_block *next;
+    size_t used, size;
+} pg_block;
+
+#define PG_ALIGN 16
+#define PG_BLOCK_SIZE ((size_t)1 << 16)
+#define PG_HEADER ((sizeof(pg_block) + PG_ALIGN - 1) / PG_ALIGN * PG_ALIGN)
+
+static pg_block *pg_arena = NULL;
+
+static inline void pg_arena_free(void) {
+    while (pg_arena != NULL) {
+        pg_block *next = pg_arena->next;
+        free(pg_arena);
+        pg_arena = next;
+    }
+}
+
+static inline void *pg_alloc(size_t size) {
+    size = (size + PG_ALIGN - 1) / PG_ALIGN * PG_ALIGN;
+    if (pg_arena == NULL || pg_arena->size - pg_arena->used < size) {
+        size_t block_size = size > PG_BLOCK_SIZE ? size : PG_BLOCK_SIZE;
+        pg_block *block = malloc(PG_HEADER + block_size);
+        if (block == NULL) {
+            fputs("ERROR: Out of memory.\n", stderr);
+            pg_arena_free();
+            exit(1);
+        }
+        block->next = pg_arena;
+        block->used = 0;
+        block->size = block_size;
+        pg_arena = block;
+    }
+    void *ptr = (unsigned char *)pg_arena + PG_HEADER + pg_arena->used;
+    pg_arena->used += size;
+    return ptr;
+}
+
+/* Copy of `size` bytes in a new allocation of `capacity` bytes. */
+static inline void *pg_realloc(const void *ptr, size_t size, size_t capacity) {
+    void *copy = pg_alloc(capacity);
+    if (size > 0) {
+        memcpy(copy, ptr, size);
+    }
+    return copy;
+}
+
+/* Prints a runtime error and exits, like every other flavor does. */
+static inline void pg_fail(const char *format, ...) {
+    va_list args;
+    fflush(stdout);
+    va_start(args, format);
+    vfprintf(stderr, format, args);
+    va_end(args);
+    fputc('\n', stderr);
+    pg_arena_free();
+    exit(1);
+}
+
+/* STRINGS */
+typedef struct {
+    char *data;
+    size_t len, cap;
+} pg_buf;
+
+static inline void pg_buf_push(pg_buf *b, const char *s, size_t n) {
+    if (b->len + n + 1 > b->cap) {
+        size_t cap = b->cap ? b->cap * 2 : 32;
+        while (cap < b->len + n + 1) {
+            cap *= 2;
+        }
+        b->data = pg_realloc(b->data, b->len, cap);
+        b->cap = cap;
+    }
+    memcpy(b->data + b->len, s, n);
+    b->len += n;
+    b->data[b->len] = '\0';
+}
+
+static inline void pg_buf_puts(pg_buf *b, const char *s) {
+    pg_buf_push(b, s, strlen(s));
+}
+
+static inline pg_str pg_buf_str(pg_buf *b) {
+    return b->data != NULL ? b->data : "";
+}
+
+/* Concatenation of `n` strings. */
+static inline pg_str pg_cat(int n, ...) {
+    pg_buf b = {NULL, 0, 0};
+    va_list args;
+    va_start(args, n);
+    for (int i = 0; i < n; i++) {
+        pg_buf_puts(&b, va_arg(args, pg_str));
+    }
+    va_end(args);
+    return pg_buf_str(&b);
+}
+
+static inline pg_str pg_str_concat(pg_str a, pg_str b) {
+    return pg_cat(2, a, b);
+}
+
+static inline void pg_print(pg_str s) {
+    fputs(s, stdout);
+}
+
+static inline void pg_println(pg_str s) {
+    puts(s);
+}
+
+/* UTF-8 encoding of `c`, returns the number of bytes written. */
+static inline size_t pg_utf8_encode(pg_char c, char out[4]) {
+    if (c < 0x80) {
+        out[0] = (char)c;
+        return 1;
+    }
+    if (c < 0x800) {
+        out[0] = (char)(0xC0 | (c >> 6));
+        out[1] = (char)(0x80 | (c & 0x3F));
+        return 2;
+    }
+    if (c < 0x10000) {
+        out[0] = (char)(0xE0 | (c >> 12));
+        out[1] = (char)(0x80 | ((c >> 6) & 0x3F));
+        out[2] = (char)(0x80 | (c & 0x3F));
+        return 3;
+    }
+    out[0] = (char)(0xF0 | (c >> 18));
+    out[1] = (char)(0x80 | ((c >> 12) & 0x3F));
+    out[2] = (char)(0x80 | ((c >> 6) & 0x3F));
+    out[3] = (char)(0x80 | (c & 0x3F));
+    return 4;
+}
+
+/* Code point starting at `s`, stores its length in bytes in `len`. */
+static inline pg_char pg_utf8_decode(pg_str s, size_t *len) {
+    const unsigned char *u = (const unsigned char *)s;
+    if (u[0] < 0x80) {
+        *len = 1;
+        return u[0];
+    }
+    if ((u[0] & 0xE0) == 0xC0 && u[1] != 0) {
+        *len = 2;
+        return ((pg_char)(u[0] & 0x1F) << 6) | (u[1] & 0x3F);
+    }
+    if ((u[0] & 0xF0) == 0xE0 && u[1] != 0 && u[2] != 0) {
+        *len = 3;
+        return ((pg_char)(u[0] & 0x0F) << 12) | ((pg_char)(u[1] & 0x3F) << 6) | (u[2] & 0x3F);
+    }
+    if (u[1] != 0 && u[2] != 0 && u[3] != 0) {
+        *len = 4;
+        return ((pg_char)(u[0] & 0x07) << 18) | ((pg_char)(u[1] & 0x3F) << 12) | ((pg_char)(u[2] & 0x3F) << 6) |
+               (u[3] & 0x3F);
+    }
+    *len = 1;
+    return u[0];
+}
+
+/* PRIMITIVES
+ * Every type has `_eq`, `_show` (appends the value to a buffer, quoting strings inside containers)
+ * and `_str` functions, the container instances follow the same naming. */
+#define PG_STR(P, T)                                                                                               \
+    static inline pg_str P##_str(T v, bool nested) {                                                               \
+        pg_buf b = {NULL, 0, 0};                                                                                   \
+        P##_show(&b, v, nested);                                                                                   \
+        return pg_buf_str(&b);                                                                                     \
+    }
+
+static inline bool pg_i_eq(pg_int a, pg_int b) {
+    return a == b;
+}
+
+static inline bool pg_n_eq(pg_num a, pg_num b) {
+    return a == b;
+}
+
+static inline bool pg_b_eq(pg_bool a, pg_bool b) {
+    return a == b;
+}
+
+static inline bool pg_c_eq(pg_char a, pg_char b) {
+    return a == b;
+}
+
+static inline bool pg_s_eq(pg_str a, pg_str b) {
+    return strcmp(a, b) == 0;
+}
+
+static inline bool pg_r_eq(pg_range a, pg_range b) {
+    return a.start == b.start && a.end == b.end;
+}
+
+static inline void pg_i_show(pg_buf *b, pg_int v, bool nested) {
+    char tmp[32];
+    (void)nested;
+    snprintf(tmp, sizeof tmp, "%" PRId64, v);
+    pg_buf_puts(b, tmp);
+}
+
+/* Shortest representation that reads back to the same number, always with a decimal point. */
+static inline void pg_n_show(pg_buf *b, pg_num v, bool nested) {
+    char tmp[64];
+    int precision;
+    (void)nested;
+    if (v != v) {
+        pg_buf_puts(b, "nan");
+        return;
+    }
+    if (v > DBL_MAX || v < -DBL_MAX) {
+        pg_buf_puts(b, v > 0 ? "inf" : "-inf");
+        return;
+    }
+    for (precision = 1; precision < 17; precision++) {
+        snprintf(tmp, sizeof tmp, "%.*e", precision - 1, v);
+        if (strtod(tmp, NULL) == v) {
+            break;
+        }
+    }
+    snprintf(tmp, sizeof tmp, "%.*e", precision - 1, v);
+    int exponent = atoi(strchr(tmp, 'e') + 1);
+    if (exponent < -4 || exponent >= 16) {
+        pg_buf_puts(b, tmp);
+        return;
+    }
+    int decimals = precision - 1 - exponent;
+    snprintf(tmp, sizeof tmp, "%.*f", decimals > 0 ? decimals : 0, v);
+    pg_buf_puts(b, tmp);
+    if (decimals <= 0) {
+        pg_buf_puts(b, ".0");
+    }
+}
+
+static inline void pg_b_show(pg_buf *b, pg_bool v, bool nested) {
+    (void)nested;
+    pg_buf_puts(b, v ? "true" : "false");
+}
+
+static inline void pg_c_show(pg_buf *b, pg_char v, bool nested) {
+    char tmp[4];
+    if (nested) {
+        pg_buf_puts(b, "\"");
+    }
+    pg_buf_push(b, tmp, pg_utf8_encode(v, tmp));
+    if (nested) {
+        pg_buf_puts(b, "\"");
+    }
+}
+
+static inline void pg_s_show(pg_buf *b, pg_str v, bool nested) {
+    if (nested) {
+        pg_buf_puts(b, "\"");
+    }
+    pg_buf_puts(b, v);
+    if (nested) {
+        pg_buf_puts(b, "\"");
+    }
+}
+
+static inline void pg_r_show(pg_buf *b, pg_range v, bool nested) {
+    pg_i_show(b, v.start, nested);
+    pg_buf_puts(b, "..");
+    pg_i_show(b, v.end, nested);
+}
+
+PG_STR(pg_i, pg_int)
+PG_STR(pg_n, pg_num)
+PG_STR(pg_b, pg_bool)
+PG_STR(pg_c, pg_char)
+PG_STR(pg_s, pg_str)
+PG_STR(pg_r, pg_range)
+
+/* ARITHMETIC
+ * Implemented here so programs don't need to be linked with the math library.
+ * Ints wrap on overflow like in the interpreter, which signed ints only do through unsigned ones in C. */
+static inline pg_int pg_i_add(pg_int a, pg_int b) {
+    return (pg_int)((uint64_t)a + (uint64_t)b);
+}
+
+static inline pg_int pg_i_sub(pg_int a, pg_int b) {
+    return (pg_int)((uint64_t)a - (uint64_t)b);
+}
+
+static inline pg_int pg_i_mul(pg_int a, pg_int b) {
+    return (pg_int)((uint64_t)a * (uint64_t)b);
+}
+
+static inline pg_int pg_i_pow(pg_int base, pg_int exponent) {
+    uint64_t result = 1;
+    uint64_t power = (uint64_t)base;
+    if (exponent < 0) {
+        return base == 1 ? 1 : base == -1 ? (exponent % 2 == 0 ? 1 : -1) : 0;
+    }
+    while (exponent > 0) {
+        if (exponent & 1) {
+            result *= power;
+        }
+        power *= power;
+        exponent >>= 1;
+    }
+    return (pg_int)result;
+}
+
+/* Integer division and remainder, C leaves division by zero and `INT64_MIN / -1` undefined. */
+static inline pg_int pg_i_div(pg_int a, pg_int b, int line, const char *text) {
+    if (b == 0) {
+        pg_fail("ERROR: Division by zero in line %d, \"%s\".", line, text);
+    }
+    return b == -1 ? (pg_int)(0 - (uint64_t)a) : a / b;
+}
+
+static inline pg_int pg_i_mod(pg_int a, pg_int b, int line, const char *text) {
+    if (b == 0) {
+        pg_fail("ERROR: Division by zero in line %d, \"%s\".", line, text);
+    }
+    return b == -1 ? 0 : a % b;
+}
+
+static inline pg_num pg_n_pow(pg_num base, pg_num exponent) {
+    pg_num result = 1.0;
+    if (exponent != (pg_num)(int64_t)exponent) {
+        pg_fail("ERROR: Fractional exponents are not supported, \"%s\".", pg_n_str(exponent, false));
+    }
+    int64_t e = (int64_t)exponent;
+    bool negative = e < 0;
+    if (negative) {
+        e = -e;
+    }
+    while (e > 0) {
+        if (e & 1) {
+            result *= base;
+        }
+        base *= base;
+        e >>= 1;
+    }
+    return negative ? 1.0 / result : result;
+}
+
+/* Remainder with the sign of the dividend, like `fmod`. */
+static inline pg_num pg_n_mod(pg_num a, pg_num b) {
+    pg_num quotient = a / b;
+    if (quotient > -9.2e18 && quotient < 9.2e18) {
+        quotient = (pg_num)(int64_t)quotient;
+    }
+    return a - b * quotient;
+}
+
+/* CONVERSIONS */
+static inline pg_int pg_int_parse(pg_str s) {
+    const char *p = s;
+    char *end;
+    while (*p == ' ' || *p == '\t' || *p == '\n' || *p == '\r') {
+        p++;
+    }
+    if (*p == '\0') {
+        pg_fail("ERROR: \"%s\" can not be parsed into an int", s);
+    }
+    pg_int value = strtoll(p, &end, 10);
+    while (*end == ' ' || *end == '\t' || *end == '\n' || *end == '\r') {
+        end++;
+    }
+    if (*end != '\0') {
+        pg_fail("ERROR: \"%s\" can not be parsed into an int", s);
+    }
+    return value;
+}
+
+static inline pg_bool pg_bool_parse(pg_str s) {
+    if (strcmp(s, "true") == 0 || strcmp(s, "false") == 0) {
+        return s[0] == 't';
+    }
+    pg_fail("ERROR: \"%s\" can not be parsed into a bool", s);
+    return false;
+}
+
+static inline pg_char pg_char_parse(pg_str s) {
+    size_t len;
+    pg_char c = pg_utf8_decode(s, &len);
+    if (s[0] == '\0' || s[len] != '\0') {
+        pg_fail("ERROR: \"%s\" can not be parsed into a char", s);
+    }
+    return c;
+}
+
+/* LISTS
+ * `L` is a growable array of `T`, P is the prefix of the `_eq` and `_show` functions of `T`.
+ * Operations ending in `ed` or `concat` return a new list, the rest modify the list in place. */
+#define PG_LIST(T, L, P)                                                                                           \
+    typedef struct {                                                                                               \
+        T *items;                                                                                                  \
+        size_t len, cap;                                                                                           \
+    } L;                                                                                                           \
+                                                                                                                   \
+    static inline L L##_new(void) {                                                                                \
+        L l;                                                                                                       \
+        l.items = NULL;                                                                                            \
+        l.len = l.cap = 0;                                                                                         \
+        return l;                                                                                                  \
+    }                                                                                                              \
+                                                                                                                   \
+    static inline void L##_push(L *l, T v) {                                                                       \
+        if (l->len == l->cap) {                                                                                    \
+            l->cap = l->cap ? l->cap * 2 : 4;                                                                      \
+            l->items = pg_realloc(l->items, l->len * sizeof(T), l->cap * sizeof(T));                               \
+        }                                                                                                          \
+        l->items[l->len++] = v;                                                                                    \
+    }                                                                                                              \
+                                                                                                                   \
+    static inline L L##_of(size_t n, const T *items) {                                                             \
+        L l = L##_new();                                                                                           \
+        for (size_t i = 0; i < n; i++) {                                                                           \
+            L##_push(&l, items[i]);                                                                                \
+        }                                                                                                          \
+        return l;                                                                                                  \
+    }                                                                                                              \
+                                                                                                                   \
+    static inline L L##_copy(L l) {                                                                                \
+        return L##_of(l.len, l.items);                                                                             \
+    }                                                                                                              \
+                                                                                                                   \
+    static inline void L##_extend(L *l, L other) {                                                                 \
+        for (size_t i = 0; i < other.len; i++) {                                                                   \
+            L##_push(l, other.items[i]);                                                                           \
+        }                                                                                                          \
+    }                                                                                                              \
+                                                                                                                   \
+    static inline T L##_get(L l, pg_int i, int line, const char *text) {                                           \
+        if (i < 0 || (size_t)i >= l.len) {                                                                         \
+            pg_fail("ERROR: Out-of-bounds in line %d, \"%s\".", line, text);                                      \
+        }                                                                                                          \
+        return l.items[i];                                                                                         \
+    }                                                                                                              \
+                                                                                                                   \
+    static inline void L##_remove(L *l, T v) {                                                                     \
+        for (size_t i = 0; i < l->len; i++) {                                                                      \
+            if (P##_eq(l->items[i], v)) {                                                                          \
+                memmove(l->items + i, l->items + i + 1, (l->len - i - 1) * sizeof(T));                             \
+                l->len--;                                                                                          \
+                return;                                                                                            \
+            }                                                                                                      \
+        }                                                                                                          \
+    }                                                                                                              \
+                                                                                                                   \
+    static inline void L##_remove_all(L *l, T v) {                                                                 \
+        size_t kept = 0;                                                                                           \
+        for (size_t i = 0; i < l->len; i++) {                                                                      \
+            if (!P##_eq(l->items[i], v)) {                                                                         \
+                l->items[kept++] = l->items[i];                                                                    \
+            }                                                                                                      \
+        }                                                                                                          \
+        l->len = kept;                                                                                             \
+    }                                                                                                              \
+                                                                                                                   \
+    static inline void L##_remove_each(L *l, L removed) {                                                          \
+        for (size_t i = 0; i < removed.len; i++) {                                                                 \
+            L##_remove(l, removed.items[i]);                                                                       \
+        }                                                                                                          \
+    }                                                                                                              \
+                                                                                                                   \
+    static inline void L##_remove_all_each(L *l, L removed) {                                                      \
+        for (size_t i = 0; i < removed.len; i++) {                                                                 \
+            L##_remove_all(l, removed.items[i]);                                                                   \
+        }                                                                                                          \
+    }                                                                                                              \
+                                                                                                                   \
+    static inline L L##_pushed(L l, T v) {                                                                         \
+        l = L##_copy(l);                                                                                           \
+        L##_push(&l, v);                                                                                           \
+        return l;                                                                                                  \
+    }                                                                                                              \
+                                                                                                                   \
+    static inline L L##_concat(L a, L b) {                                                                         \
+        a = L##_copy(a);                                                                                           \
+        L##_extend(&a, b);                                                                                         \
+        return a;                                                                                                  \
+    }                                                                                                              \
+                                                                                                                   \
+    static inline L L##_removed(L l, T v) {                                                                        \
+        l = L##_copy(l);                                                                                           \
+        L##_remove(&l, v);                                                                                         \
+        return l;                                                                                                  \
+    }                                                                                                              \
+                                                                                                                   \
+    static inline L L##_removed_each(L l, L removed) {                                                             \
+        l = L##_copy(l);                                                                                           \
+        L##_remove_each(&l, removed);                                                                              \
+        return l;                                                                                                  \
+    }                                                                                                              \
+                                                                                                                   \
+    static inline L L##_removed_all(L l, T v) {                                                                    \
+        l = L##_copy(l);                                                                                           \
+        L##_remove_all(&l, v);                                                                                     \
+        return l;                                                                                                  \
+    }                                                                                                              \
+                                                                                                                   \
+    static inline L L##_removed_all_each(L l, L removed) {                                                         \
+        l = L##_copy(l);                                                                                           \
+        L##_remove_all_each(&l, removed);                                                                          \
+        return l;                                                                                                  \
+    }                                                                                                              \
+                                                                                                                   \
+    static inline bool L##_eq(L a, L b) {                                                                          \
+        if (a.len != b.len) {                                                                                      \
+            return false;                                                                                          \
+        }                                                                                                          \
+        for (size_t i = 0; i < a.len; i++) {                                                                       \
+            if (!P##_eq(a.items[i], b.items[i])) {                                                                 \
+                return false;                                                                                      \
+            }                                                                                                      \
+        }                                                                                                          \
+        return true;                                                                                               \
+    }                                                                                                              \
+                                                                                                                   \
+    static inline void L##_show(pg_buf *b, L l, bool nested) {                                                     \
+        (void)nested;                                                                                              \
+        pg_buf_puts(b, "[");                                                                                       \
+        for (size_t i = 0; i < l.len; i++) {                                                                       \
+            if (i > 0) {                                                                                           \
+                pg_buf_puts(b, ", ");                                                                              \
+            }                                                                                                      \
+            P##_show(b, l.items[i], true);                                                                         \
+        }                                                                                                          \
+        pg_buf_puts(b, "]");                                                                                       \
+    }                                                                                                              \
+                                                                                                                   \
+    PG_STR(L, L)
+
+/* DICTIONARIES
+ * `D` maps `K` to `V` keeping the insertion order, keys are compared with `PK##_eq`. */
+#define PG_DICT(K, V, D, PK, PV)                                                                                   \
+    typedef struct {                                                                                               \
+        K *keys;                                                                                                   \
+        V *values;                                                                                                 \
+        size_t len, cap;                                                                                           \
+    } D;                                                                                                           \
+                                                                                                                   \
+    static inline D D##_new(void) {                                                                                \
+        D d;                                                                                                       \
+        d.keys = NULL;                                                                                             \
+        d.values = NULL;                                                                                           \
+        d.len = d.cap = 0;                                                                                         \
+        return d;                                                                                                  \
+    }                                                                                                              \
+                                                                                                                   \
+    static inline V *D##_find(D d, K k) {                                                                          \
+        for (size_t i = 0; i < d.len; i++) {                                                                       \
+            if (PK##_eq(d.keys[i], k)) {                                                                           \
+                return &d.values[i];                                                                               \
+            }                                                                                                      \
+        }                                                                                                          \
+        return NULL;                                                                                               \
+    }                                                                                                              \
+                                                                                                                   \
+    static inline void D##_set(D *d, K k, V v) {                                                                   \
+        V *found = D##_find(*d, k);                                                                                \
+        if (found != NULL) {                                                                                       \
+            *found = v;                                                                                            \
+            return;                                                                                                \
+        }                                                                                                          \
+        if (d->len == d->cap) {                                                                                    \
+            d->cap = d->cap ? d->cap * 2 : 4;                                                                      \
+            d->keys = pg_realloc(d->keys, d->len * sizeof(K), d->cap * sizeof(K));                                 \
+            d->values = pg_realloc(d->values, d->len * sizeof(V), d->cap * sizeof(V));                             \
+        }                                                                                                          \
+        d->keys[d->len] = k;                                                                                       \
+        d->values[d->len++] = v;                                                                                   \
+    }                                                                                                              \
+                                                                                                                   \
+    static inline D D##_of(size_t n, const K *keys, const V *values) {                                             \
+        D d = D##_new();                                                                                           \
+        for (size_t i = 0; i < n; i++) {                                                                           \
+            D##_set(&d, keys[i], values[i]);                                                                       \
+        }                                                                                                          \
+        return d;                                                                                                  \
+    }                                                                                                              \
+                                                                                                                   \
+    static inline D D##_copy(D d) {                                                                                \
+        return D##_of(d.len, d.keys, d.values);                                                                    \
+    }                                                                                                              \
+                                                                                                                   \
+    static inline V D##_get(D d, K k, int line, const char *text) {                                                \
+        V *found = D##_find(d, k);                                                                                 \
+        if (found == NULL) {                                                                                       \
+            pg_fail("ERROR: Key not found in line %d, \"%s\".", line, text);                                      \
+        }                                                                                                          \
+        return *found;                                                                                             \
+    }                                                                                                              \
+                                                                                                                   \
+    static inline bool D##_eq(D a, D b) {                                                                          \
+        if (a.len != b.len) {                                                                                      \
+            return false;                                                                                          \
+        }                                                                                                          \
+        for (size_t i = 0; i < a.len; i++) {                                                                       \
+            V *found = D##_find(b, a.keys[i]);                                                                     \
+            if (found == NULL || !PV##_eq(a.values[i], *found)) {                                                  \
+                return false;                                                                                      \
+            }                                                                                                      \
+        }                                                                                                          \
+        return true;                                                                                               \
+    }                                                                                                              \
+                                                                                                                   \
+    static inline void D##_show(pg_buf *b, D d, bool nested) {                                                     \
+        (void)nested;                                                                                              \
+        pg_buf_puts(b, "[");                                                                                       \
+        for (size_t i = 0; i < d.len; i++) {                                                                       \
+            if (i > 0) {                                                                                           \
+                pg_buf_puts(b, ", ");                                                                              \
+            }                                                                                                      \
+            PK##_show(b, d.keys[i], true);                                                                         \
+            pg_buf_puts(b, " -> ");                                                                                \
+            PV##_show(b, d.values[i], true);                                                                       \
+        }                                                                                                          \
+        pg_buf_puts(b, "]");                                                                                       \
+    }                                                                                                              \
+                                                                                                                   \
+    PG_STR(D, D)
+
+/* Conversion of every element of a list or dictionary with `F`. */
+#define PG_LIST_MAP(FROM, TO, NAME, F)                                                                             \
+    static inline TO NAME(FROM l) {                                                                                \
+        TO out = TO##_new();                                                                                       \
+        for (size_t i = 0; i < l.len; i++) {                                                                       \
+            TO##_push(&out, F(l.items[i]));                                                                        \
+        }                                                                                                          \
+        return out;                                                                                                \
+    }
+
+#define PG_DICT_MAP(FROM, TO, NAME, FK, FV)                                                                        \
+    static inline TO NAME(FROM d) {                                                                                \
+        TO out = TO##_new();                                                                                       \
+        for (size_t i = 0; i < d.len; i++) {                                                                       \
+            TO##_set(&out, FK(d.keys[i]), FV(d.values[i]));                                                        \
+        }                                                                                                          \
+        return out;                                                                                                \
+    }
+
+#endif
@@ -0,0 +1,270 @@
+//! Pass reporting every construct a target can't express, before anything is translated.
+use super::{native_code, Capabilities, IntWidth, Redeclarations, Types};
+use crate::diagnostic::{Diagnostic, ErrorCode};
+use crate::source::Span;
+use crate::tree::*;
+use crate::visit::{self, Visitor};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+/// Diagnostics for every construct of `main` that `target` can't translate with its `capabilities`.
+pub fn unsupported(main: &Main, target: &str, capabilities: Capabilities) -> Vec<Diagnostic> {
+    let mut checker = Checker {
+        target,
+        capabilities,
+        types: Types::new(),
+        declared: vec![FxHashMap::default()],
+        diagnostics: Vec::new(),
+        overflow: false,
+        functions: Vec::new(),
+        open: Vec::new(),
+        calls: FxHashMap::default(),
+    };
+    visit::walk_block(&mut checker, &main.exprs);
+    checker.recursion();
+    checker.diagnostics
+}
+
+struct Checker<'a> {
+    target: &'a str,
+    capabilities: Capabilities,
+    types: Types,
+    /// Variables declared in each block, with the type of their last declaration
+    declared: Vec<FxHashMap<String, Type>>,
+    diagnostics: Vec<Diagnostic>,
+    /// Whether the ints of the program were already warned about, once is enough
+    overflow: bool,
+    /// Functions with a body, in the order they're declared
+    functions: Vec<(String, Span)>,
+    /// Functions whose body is being checked, innermost last
+    open: Vec<String>,
+    /// Functions called by each function
+    calls: FxHashMap<String, FxHashSet<String>>,
+}
+
+impl Visitor<'_> for Checker<'_> {
+    fn visit_block(&mut self, exprs: &[Expr]) {
+        self.block(exprs, &[]);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        let span = expr.span;
+        match &expr.kind {
+            ExprKind::Init { name, r#type, value } => {
+                self.visit_value(value);
+                if matches!(r#type, Type::Void) && !self.capabilities.void_variables {
+                    self.report(span, "void variables");
+                }
+                // Its type comes from the value, which was already checked
+                self.declare(name, r#type, span);
+            }
+            ExprKind::Decl { name, r#type } => {
+                self.r#type(r#type, span);
+                self.declare(name, r#type, span);
+            }
+            ExprKind::Typedef { r#type, .. } => self.r#type(r#type, span),
+            ExprKind::For { var, range, exprs } => {
+                self.visit_value(range);
+                let element = self.types.element_of(range);
+                self.block(exprs, &[(var.clone(), element)]);
+            }
+            ExprKind::Fn {
+                name,
+                r#type,
+                args,
+                exprs,
+            } => {
+                self.signature(name, r#type, args, span);
+                self.functions.push((name.clone(), span));
+                self.open.push(name.clone());
+                self.block(exprs, args);
+                self.open.pop();
+            }
+            ExprKind::Native { sections } => self.native(sections, span),
+            ExprKind::NativeFn {
+                name,
+                r#type,
+                args,
+                sections,
+            } => {
+                self.signature(name, r#type, args, span);
+                self.native(sections, span);
+            }
+            ExprKind::Call { name, .. } => {
+                self.call(name);
+                visit::walk_expr(self, expr);
+            }
+            _ => visit::walk_expr(self, expr),
+        }
+    }
+
+    fn visit_value(&mut self, value: &Value) {
+        let span = value.span;
+        match &value.kind {
+            ValueKind::Dict(_) => {
+                visit::walk_value(self, value);
+                self.r#type(&value.r#type, span);
+                return;
+            }
+            ValueKind::Cmp(Cmp::Less(v) | Cmp::Greater(v) | Cmp::LessEq(v) | Cmp::GreatEq(v)) => {
+                match &v.0.r#type {
+                    Type::List(_) if !self.capabilities.ordered_lists => {
+                        self.report(span, "ordered comparisons of lists")
+                    }
+                    Type::Dict(_) if !self.capabilities.ordered_dicts => {
+                        self.report(span, "ordered comparisons of dictionaries")
+                    }
+                    _ => {}
+                }
+            }
+            ValueKind::Call { name, .. } => {
+                if self.types.is_builtin(name) && !self.capabilities.builtin_values {
+                    self.report(span, "builtin calls used as values")
+                }
+                self.call(name);
+            }
+            ValueKind::Int(i) if self.capabilities.int_width == IntWidth::Double && i.unsigned_abs() > 1 << 53 => {
+                self.warn(span, "ints beyond 2^53", "is rounded to the nearest double")
+            }
+            // Multiplications and powers are what realistically grows an int out of its range
+            ValueKind::Op(Op::Mul(_) | Op::Pow(_)) if matches!(value.r#type, Type::Int) => self.overflow(span),
+            _ => {}
+        }
+        visit::walk_value(self, value);
+    }
+}
+
+impl Checker<'_> {
+    fn report(&mut self, span: Span, construct: impl std::fmt::Display) {
+        let target = self.target;
+        self.diagnostics.push(
+            Diagnostic::error(
+                ErrorCode::UnsupportedByTarget,
+                format!("{construct} are not supported by the `{target}` target"),
+            )
+            .with_primary(span, format!("can't be translated into `{target}`")),
+        );
+    }
+
+    fn warn(&mut self, span: Span, construct: impl std::fmt::Display, label: impl Into<String>) {
+        let target = self.target;
+        self.diagnostics.push(
+            Diagnostic::warning(
+                ErrorCode::DivergesInTarget,
+                format!("{construct} behave differently in the `{target}` target"),
+            )
+            .with_primary(span, label),
+        );
+    }
+
+    /// Warns about the first int operation that may overflow if the target doesn't wrap ints like the interpreter.
+    fn overflow(&mut self, span: Span) {
+        let (construct, label) = match (self.capabilities.int_width, self.capabilities.unsigned_ints) {
+            _ if self.overflow => return,
+            (IntWidth::Bits64, true) => return,
+            (IntWidth::Bits64, false) => ("ints that overflow", "may overflow, which is undefined"),
+            (IntWidth::Unbounded, _) => ("ints that overflow", "keeps growing instead of wrapping on overflow"),
+            (IntWidth::Double, _) => ("ints beyond 2^53", "loses precision beyond 2^53"),
+        };
+        self.overflow = true;
+        self.warn(span, construct, label);
+    }
+
+    fn call(&mut self, name: &str) {
+        if let Some(caller) = self.open.last() {
+            self.calls.entry(caller.clone()).or_default().insert(name.to_owned());
+        }
+    }
+
+    /// Warns about the functions that call themselves, directly or not, if the target limits how deep they can go.
+    fn recursion(&mut self) {
+        let Some(depth) = self.capabilities.recursion_depth else {
+            return;
+        };
+        let fails = if self.capabilities.exceptions { "fails" } else { "crashes" };
+        for (name, span) in std::mem::take(&mut self.functions) {
+            let mut pending = vec![name.as_str()];
+            let mut seen = FxHashSet::default();
+            let mut recursive = false;
+            while let Some(caller) = pending.pop() {
+                for callee in self.calls.get(caller).into_iter().flatten() {
+                    recursive |= *callee == name;
+                    if seen.insert(callee.as_str()) {
+                        pending.push(callee);
+                    }
+                }
+            }
+            if recursive {
+                let label = format!("{fails} after {depth} nested calls");
+                self.warn(span, "recursive functions", label);
+            }
+        }
+    }
+
+    fn block(&mut self, exprs: &[Expr], vars: &[(String, Type)]) {
+        self.types.push();
+        self.declared.push(FxHashMap::default());
+        for (name, r#type) in vars {
+            self.declared.last_mut().unwrap().insert(name.clone(), r#type.clone());
+            self.types.insert(name, r#type.clone());
+        }
+        visit::walk_block(self, exprs);
+        self.declared.pop();
+        self.types.pop();
+    }
+
+    fn declare(&mut self, name: &str, r#type: &Type, span: Span) {
+        let declared = self.declared.last_mut().unwrap();
+        let previous = declared.insert(name.to_owned(), r#type.clone());
+        match (previous, self.capabilities.redeclarations) {
+            (Some(_), Redeclarations::Never) => self.report(span, "redeclarations"),
+            (Some(p), Redeclarations::SameType) if p != *r#type => {
+                self.report(span, "redeclarations with a different type")
+            }
+            _ => {}
+        }
+        self.types.insert(name, r#type.clone());
+    }
+
+    fn signature(&mut self, name: &str, r#type: &Type, args: &[(String, Type)], span: Span) {
+        self.types.insert_fn(name, r#type.clone(), args);
+        self.r#type(r#type, span);
+        for (_, arg) in args {
+            self.r#type(arg, span);
+        }
+    }
+
+    fn native(&mut self, sections: &[NativeSection], span: Span) {
+        // Targets without native sections keep native code as it is
+        let targets = self.capabilities.native_code;
+        if targets.is_empty() {
+            return;
+        }
+        if let Err(diagnostic) = native_code(sections, targets, span) {
+            self.diagnostics.push(diagnostic);
+        }
+    }
+
+    fn r#type(&mut self, r#type: &Type, span: Span) {
+        match r#type {
+            Type::Tuple(types) => {
+                for t in types {
+                    self.r#type(t, span);
+                }
+            }
+            Type::Struct(members) => {
+                for (_, t) in members {
+                    self.r#type(t, span);
+                }
+            }
+            Type::List(element) => self.r#type(element, span),
+            Type::Dict(d) => {
+                if !self.capabilities.dict_keys.allow(&d.0) {
+                    self.report(span, format!("dictionaries with `{}` keys", d.0));
+                }
+                self.r#type(&d.0, span);
+                self.r#type(&d.1, span);
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,844 @@
+use super::{native_code, splice, Backend, Capabilities, DictKeys, IntWidth, Output, Redeclarations, Types};
+use crate::diagnostic::{Diagnostic, ErrorCode};
+use crate::source::Span;
+use crate::tree::*;
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::process::Command;
+
+/// ES2022 modules for Node, optionally with TypeScript annotations.
+///
+/// Values are never modified in place (`l += 1` builds a new array), so Polyglot's value semantics hold without
+/// copying. Ints are JavaScript numbers, so they are only exact up to 2^53.
+pub struct JavaScript {
+    pub typescript: bool,
+}
+
+impl Backend for JavaScript {
+    fn name(&self) -> &'static str {
+        if self.typescript {
+            "ts"
+        } else {
+            "js"
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        // `.mjs` makes Node load the program as a module without a `package.json`
+        if self.typescript {
+            "ts"
+        } else {
+            "mjs"
+        }
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            void_variables: true,
+            builtin_values: true,
+            ordered_lists: true,
+            ordered_dicts: false,
+            dict_keys: DictKeys::Primitives,
+            redeclarations: if self.typescript {
+                Redeclarations::SameType
+            } else {
+                Redeclarations::Any
+            },
+            int_width: IntWidth::Double,
+            unsigned_ints: false,
+            recursion_depth: None,
+            exceptions: true,
+            native_code: if self.typescript { &["ts", "js"] } else { &["js"] },
+        }
+    }
+
+    fn emit(&self, main: &Main) -> Result<Output, Diagnostic> {
+        let mut emitter = Emitter::new(main, self.typescript);
+        for expr in &main.exprs {
+            emitter.expr(expr);
+        }
+        if let Some(error) = emitter.error.take() {
+            return Err(error);
+        }
+        let mut files = vec![("runtime.js".into(), RUNTIME.to_owned())];
+        if self.typescript {
+            files.push(("runtime.d.ts".into(), RUNTIME_TYPES.to_owned()));
+        }
+        Ok(Output {
+            source: emitter.finish(),
+            files,
+        })
+    }
+
+    fn commands(&self, source: &Path) -> Vec<Command> {
+        // Node can't run TypeScript without compiling it, but Deno can
+        let mut runtime = Command::new(if self.typescript { "deno" } else { "node" });
+        if self.typescript {
+            runtime.args(["run", "--quiet"]);
+        }
+        runtime.arg(source);
+        vec![runtime]
+    }
+}
+
+/// Support functions imported by the generated modules.
+const RUNTIME: &str = include_str!("js/runtime.js");
+/// Declarations of [`RUNTIME`], so TypeScript can check the programs that use it.
+const RUNTIME_TYPES: &str = include_str!("js/runtime.d.ts");
+
+/// Names that would clash with JavaScript keywords, globals or the functions imported from the runtime.
+#[rustfmt::skip]
+const RESERVED: &[&str] = &[
+    "arguments", "await", "break", "case", "catch", "class", "const", "continue", "debugger", "default", "delete",
+    "do", "else", "enum", "eval", "export", "extends", "false", "finally", "for", "function", "if", "implements",
+    "import", "in", "instanceof", "interface", "let", "new", "null", "package", "private", "protected", "public",
+    "return", "static", "super", "switch", "this", "throw", "true", "try", "typeof", "undefined", "var", "void",
+    "while", "with", "yield", "Array", "Infinity", "Map", "Math", "NaN", "Number", "Object", "String", "Symbol",
+    "console", "globalThis", "process", "compare", "convert", "eq", "fail", "idiv", "imod", "index", "ipow", "key", "print",
+    "println", "range", "record", "remove", "removeAll", "show", "tuple",
+];
+
+struct Emitter<'a> {
+    main: &'a Main,
+    types: Types,
+    out: String,
+    indent: usize,
+    typescript: bool,
+    /// Runtime functions used by the program
+    imports: BTreeSet<&'static str>,
+    /// Type aliases of the typedefs, only used by TypeScript
+    aliases: Vec<(String, Vec<(String, Type)>)>,
+    /// Variables already declared with `let` in each scope, redeclarations become assignments
+    declared: Vec<BTreeSet<String>>,
+    /// First construct that can't be translated
+    error: Option<Diagnostic>,
+}
+
+impl<'a> Emitter<'a> {
+    fn new(main: &'a Main, typescript: bool) -> Emitter<'a> {
+        Emitter {
+            main,
+            types: Types::new(),
+            out: String::new(),
+            indent: 0,
+            typescript,
+            imports: BTreeSet::new(),
+            aliases: Vec::new(),
+            declared: vec![BTreeSet::new()],
+            error: None,
+        }
+    }
+
+    /// Joins the imports needed by the program with its body.
+    fn finish(self) -> String {
+        let mut header = String::new();
+        if let Some(file) = self.main.source_map.files().first() {
+            let name = file.path.file_name().unwrap_or_default().to_string_lossy();
+            header.push_str(&format!("// Generated by polyglot from {name}\n"));
+        }
+        // `Range` is only a type, imported on its own so the import disappears when TypeScript is compiled
+        let values = self
+            .imports
+            .iter()
+            .copied()
+            .filter(|name| *name != "Range")
+            .collect::<Vec<_>>();
+        if !values.is_empty() {
+            header.push_str(&format!("import {{ {} }} from \"./runtime.js\";\n", values.join(", ")));
+        }
+        if self.imports.contains("Range") {
+            header.push_str("import type { Range } from \"./runtime.js\";\n");
+        }
+        format!("{header}\n{}", self.out)
+    }
+
+    fn unsupported(&mut self, span: Span, message: impl Into<String>) -> String {
+        if self.error.is_none() {
+            let message = message.into();
+            let flavor = if self.typescript { "TypeScript" } else { "JavaScript" };
+            self.error = Some(
+                Diagnostic::error(
+                    ErrorCode::UnsupportedByTarget,
+                    format!("{message} are not supported by the {flavor} flavor"),
+                )
+                .with_primary(span, message),
+            );
+        }
+        "undefined".into()
+    }
+
+    fn line(&mut self, text: impl AsRef<str>) {
+        self.out.push_str(&"    ".repeat(self.indent));
+        self.out.push_str(text.as_ref());
+        self.out.push('\n');
+    }
+
+    fn import(&mut self, name: &'static str) -> &'static str {
+        self.imports.insert(name);
+        name
+    }
+
+    fn block(&mut self, exprs: &[Expr], vars: Vec<(String, Type)>) {
+        self.indent += 1;
+        self.types.push();
+        self.declared.push(BTreeSet::new());
+        for (name, r#type) in vars {
+            self.declared.last_mut().unwrap().insert(name.clone());
+            self.types.insert(name, r#type);
+        }
+        for expr in exprs {
+            self.expr(expr);
+        }
+        self.declared.pop();
+        self.types.pop();
+        self.indent -= 1;
+    }
+
+    /// `let` declaration of `name`, or an assignment if the scope already declared it.
+    fn declare(&mut self, name: &str, r#type: &Type, value: String, span: Span) {
+        let previous = self.types.get(name).cloned();
+        let declared = self.declared.last_mut().unwrap();
+        if declared.insert(name.to_owned()) {
+            let annotation = self.annotation(r#type, span);
+            self.line(format!("let {}{annotation} = {value};", ident(name)));
+        } else {
+            if self.typescript && previous.as_ref() != Some(r#type) {
+                self.unsupported(span, "redeclarations with a different type");
+            }
+            self.line(format!("{} = {value};", ident(name)));
+        }
+        self.types.insert(name, r#type.clone());
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Init { name, r#type, value } => {
+                self.check(r#type, expr.span);
+                let value = self.coerced(value, r#type);
+                self.declare(name, r#type, value, expr.span);
+            }
+            ExprKind::Decl { name, r#type } => {
+                self.check(r#type, expr.span);
+                let value = self.default(r#type);
+                self.declare(name, r#type, value, expr.span);
+            }
+            ExprKind::Assig { name, value } => {
+                let line = self.assig(name, value);
+                self.line(line);
+            }
+            ExprKind::Typedef { name, r#type } => {
+                if let Type::Struct(members) = r#type {
+                    self.check(r#type, expr.span);
+                    if self.typescript {
+                        let fields = self.fields(members);
+                        self.line(format!("type {} = Readonly<{{ {fields} }}>;", ident(name)));
+                    }
+                    self.aliases.push((ident(name), members.clone()));
+                }
+            }
+            ExprKind::If { cmp, exprs, elif } => {
+                let cmp = self.value(cmp);
+                self.line(format!("if ({cmp}) {{"));
+                self.block(exprs, Vec::new());
+                let mut next = elif.as_deref();
+                while let Some(expr) = next {
+                    match &expr.kind {
+                        ExprKind::Elif { cmp, exprs, elif } => {
+                            let cmp = self.value(cmp);
+                            self.line(format!("}} else if ({cmp}) {{"));
+                            self.block(exprs, Vec::new());
+                            next = elif.as_deref();
+                        }
+                        ExprKind::Else { exprs } => {
+                            self.line("} else {");
+                            self.block(exprs, Vec::new());
+                            next = None;
+                        }
+                        _ => unreachable!("if chains only contain elif and else"),
+                    }
+                }
+                self.line("}");
+            }
+            ExprKind::Elif { .. } | ExprKind::Else { .. } => unreachable!("emitted with their if"),
+            ExprKind::For { var, range, exprs } => {
+                let element = self.types.element_of(range);
+                // Ranges and lists are iterable, and lists are never modified in place, so there's nothing to copy
+                let range = match range.r#type.clone() {
+                    Type::Range | Type::List(_) => self.value(range),
+                    _ => self.unsupported(range.span, "loops over values other than ranges and lists"),
+                };
+                self.line(format!("for (let {} of {range}) {{", ident(var)));
+                self.block(exprs, vec![(var.clone(), element)]);
+                self.line("}");
+            }
+            ExprKind::While { cmp, exprs } => {
+                let cmp = self.value(cmp);
+                self.line(format!("while ({cmp}) {{"));
+                self.block(exprs, Vec::new());
+                self.line("}");
+            }
+            ExprKind::Fn {
+                name,
+                r#type,
+                args,
+                exprs,
+            } => {
+                self.signature(name, r#type, args, expr.span);
+                self.block(exprs, args.clone());
+                // Polyglot functions can't return a value yet, so the result is always the default one
+                if !matches!(r#type, Type::Void) {
+                    let default = self.default(r#type);
+                    self.line(format!("    return {default};"));
+                }
+                self.line("}");
+            }
+            ExprKind::Call { name, args } => {
+                let call = self.call(name, args);
+                self.line(format!("{call};"));
+            }
+            ExprKind::Native { sections } => self.native(sections, expr.span),
+            ExprKind::NativeFn {
+                name,
+                r#type,
+                args,
+                sections,
+            } => {
+                self.signature(name, r#type, args, expr.span);
+                self.indent += 1;
+                self.native(sections, expr.span);
+                self.indent -= 1;
+                self.line("}");
+            }
+            ExprKind::Err => unreachable!("backends only receive valid programs"),
+        }
+    }
+
+    /// Emits the first line of a function definition.
+    fn signature(&mut self, name: &str, r#type: &Type, args: &[(String, Type)], span: Span) {
+        self.types.insert_fn(name, r#type.clone(), args);
+        let params = args
+            .iter()
+            .map(|(name, t)| {
+                self.check(t, span);
+                format!("{}{}", ident(name), self.annotation(t, span))
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let ret = self.annotation(r#type, span);
+        self.line(format!("function {}({params}){ret} {{", ident(name)));
+    }
+
+    /// TypeScript falls back to the JavaScript section, which is valid TypeScript too.
+    fn native(&mut self, sections: &[NativeSection], span: Span) {
+        let targets: &[&str] = if self.typescript { &["ts", "js"] } else { &["js"] };
+        match native_code(sections, targets, span) {
+            Ok(code) => splice(&mut self.out, code, self.indent),
+            Err(error) => {
+                self.error.get_or_insert(error);
+            }
+        }
+    }
+
+    /// Assignments of the form `a = a op b` use augmented assignment when it keeps the same semantics.
+    fn assig(&mut self, name: &str, value: &Value) -> String {
+        let var_t = self.types.get(name).cloned().unwrap_or(Type::Err);
+        let augmented = match &value.kind {
+            ValueKind::Op(Op::Add(v)) => Some(("+", v)),
+            ValueKind::Op(Op::Sub(v)) => Some(("-", v)),
+            ValueKind::Op(Op::Mul(v)) => Some(("*", v)),
+            ValueKind::Op(Op::Div(v)) if matches!(var_t, Type::Num) => Some(("/", v)),
+            ValueKind::Op(Op::Mod(v)) if matches!(var_t, Type::Num) => Some(("%", v)),
+            ValueKind::Op(Op::Pow(v)) if matches!(var_t, Type::Num) => Some(("**", v)),
+            _ => None,
+        };
+        if let Some((symbol, operands)) = augmented {
+            let is_self = matches!(&operands.0.kind, ValueKind::Var(v) if v == name);
+            let numeric = matches!(var_t, Type::Int | Type::Num) && value.r#type == var_t;
+            if is_self && (numeric || (symbol == "+" && matches!(var_t, Type::Str))) {
+                return format!("{} {symbol}= {};", ident(name), self.value(&operands.1));
+            }
+        }
+        format!("{} = {};", ident(name), self.coerced(value, &var_t))
+    }
+
+    fn call(&mut self, name: &str, args: &[Value]) -> String {
+        if !self.types.is_builtin(name) {
+            let arg_types = self.types.get_fn(name).map(|f| f.args.clone()).unwrap_or_default();
+            let args = args
+                .iter()
+                .zip(arg_types.iter())
+                .map(|(a, t)| self.coerced(a, t))
+                .collect::<Vec<_>>()
+                .join(", ");
+            return format!("{}({args})", ident(name));
+        }
+
+        let arg = &args[0];
+        match name {
+            "dbg" => {
+                let text = self.main.source_map.snippet(arg.span).unwrap_or_default();
+                let shown = self.show(arg, true);
+                format!("{}(`{} = ${{{shown}}}`)", self.import("println"), template_escape(text))
+            }
+            _ => {
+                let shown = match &arg.kind {
+                    ValueKind::Str(s) => self.interpolated(s),
+                    _ => self.show(arg, false),
+                };
+                let print = if name == "print" {
+                    self.import("print")
+                } else {
+                    self.import("println")
+                };
+                format!("{print}({shown})")
+            }
+        }
+    }
+
+    /// String literal with its `{var}` replaced, as a template literal if needed.
+    fn interpolated(&mut self, s: &str) -> String {
+        let segments = interpolation(s);
+        if !segments.iter().any(|(_, seg)| matches!(seg, Segment::Var(_))) {
+            return js_str(s);
+        }
+
+        let mut out = String::from("`");
+        for (_, segment) in segments {
+            match segment {
+                Segment::Text(text) => out.push_str(&template_escape(text)),
+                Segment::Var(var) => {
+                    let var = self.types.var(var, Default::default());
+                    out.push_str(&format!("${{{}}}", self.show(&var, false)));
+                }
+            }
+        }
+        out.push('`');
+        out
+    }
+
+    /// Expression printing `value` like every other flavor does.
+    fn show(&mut self, value: &Value, nested: bool) -> String {
+        match value.r#type.clone() {
+            Type::Int | Type::Char | Type::Str if !nested => self.value(value),
+            r#type => {
+                let show = self.import("show");
+                let nested = if nested { ", true" } else { "" };
+                format!("{show}({}, {}{nested})", self.value(value), js_str(&code(&r#type)))
+            }
+        }
+    }
+
+    fn value(&mut self, value: &Value) -> String {
+        match &value.kind {
+            ValueKind::Range((start, end)) => format!("{}({start}, {end})", self.import("range")),
+            ValueKind::Int(i) => i.to_string(),
+            ValueKind::Num(n) => format!("{n:?}"),
+            ValueKind::Bool(b) => b.to_string(),
+            ValueKind::Char(c) => js_str(&c.to_string()),
+            ValueKind::Str(s) => js_str(s),
+            ValueKind::Tuple(_) | ValueKind::Struct(_) | ValueKind::List(_) | ValueKind::Dict(_) => {
+                let r#type = value.r#type.clone();
+                self.coerced(value, &r#type)
+            }
+            ValueKind::TupleAccess { name, access_mode, .. } => match access_mode {
+                TupleAccessMode::Member(member) => format!("{}.{member}", ident(name)),
+                TupleAccessMode::Index(index) => format!("{}[{index}]", ident(name)),
+            },
+            ValueKind::ListAccess { name, access_mode, .. } => {
+                let (helper, key) = match access_mode {
+                    ListAccessMode::List(index) => ("index", self.value(index)),
+                    ListAccessMode::Dict(key) => ("key", self.value(key)),
+                };
+                let helper = self.import(helper);
+                let line = self.main.source_map.line_col(value.span).0.line;
+                let text = self.main.source_map.snippet(value.span).unwrap_or(name);
+                format!("{helper}({}, {key}, {line}, {})", ident(name), js_str(text))
+            }
+            ValueKind::Var(name) => ident(name),
+            ValueKind::Op(op) => self.op(op, &value.r#type, value.span),
+            ValueKind::Cmp(cmp) => self.cmp(cmp, value.span),
+            ValueKind::Parenthesis(p) => format!("({})", self.value(p)),
+            ValueKind::Conversion { r#type, value } => self.conversion(r#type, value),
+            ValueKind::Call { name, args } => self.call(name, args),
+            ValueKind::RetExpr(r) => match *r {},
+            ValueKind::Err => unreachable!("backends only receive valid programs"),
+        }
+    }
+
+    /// Value used as an operand, wrapped in parenthesis so Polyglot's evaluation order is kept.
+    fn operand(&mut self, value: &Value) -> String {
+        let translated = self.value(value);
+        match &value.kind {
+            ValueKind::Op(_) | ValueKind::Cmp(_) => format!("({translated})"),
+            ValueKind::Int(i) if *i < 0 => format!("({translated})"),
+            ValueKind::Num(n) if *n < 0.0 => format!("({translated})"),
+            _ => translated,
+        }
+    }
+
+    fn op(&mut self, op: &Op, r#type: &Type, span: Span) -> String {
+        let binary = |e: &mut Self, v: &(Value, Value), symbol: &str| {
+            format!("{} {symbol} {}", e.operand(&v.0), e.operand(&v.1))
+        };
+        match op {
+            Op::Add(v) | Op::Sub(v) if matches!(r#type, Type::List(_)) => {
+                let list = self.value(&v.0);
+                let (items, whole) = self.list_operand(r#type, &v.1);
+                match (op, whole) {
+                    (Op::Add(_), true) => format!("[...{list}, ...{items}]"),
+                    (Op::Add(_), false) => format!("[...{list}, {items}]"),
+                    (_, true) => format!("{}({list}, {items})", self.import("remove")),
+                    (_, false) => format!("{}({list}, [{items}])", self.import("remove")),
+                }
+            }
+            Op::ListRemoveAll(v) => {
+                let list = self.types.get(&v.0).cloned().unwrap_or(Type::Err);
+                if !matches!(list, Type::List(_)) {
+                    return self.unsupported(span, "removals from values other than lists");
+                }
+                let (items, whole) = self.list_operand(&list, &v.1);
+                let items = if whole { items } else { format!("[{items}]") };
+                format!("{}({}, {items})", self.import("removeAll"), ident(&v.0))
+            }
+            Op::Add(v) => binary(self, v, "+"),
+            Op::Sub(v) => binary(self, v, "-"),
+            Op::Mul(v) => binary(self, v, "*"),
+            Op::Div(v) | Op::Mod(v) if matches!(r#type, Type::Int) => {
+                let helper = self.import(if matches!(op, Op::Div(_)) { "idiv" } else { "imod" });
+                let line = self.main.source_map.line_col(span).0.line;
+                let text = self.main.source_map.snippet(span).unwrap_or_default();
+                format!("{helper}({}, {}, {line}, {})", self.value(&v.0), self.value(&v.1), js_str(text))
+            }
+            Op::Div(v) => binary(self, v, "/"),
+            // The remainder of `%` already takes the sign of the dividend, like in Polyglot
+            Op::Mod(v) => binary(self, v, "%"),
+            Op::Pow(v) if matches!(r#type, Type::Int) => {
+                format!("{}({}, {})", self.import("ipow"), self.value(&v.0), self.value(&v.1))
+            }
+            Op::Pow(v) => binary(self, v, "**"),
+        }
+    }
+
+    /// `value` as an operand of a list operation, and whether it's a whole list or a single element.
+    fn list_operand(&mut self, list: &Type, value: &Value) -> (String, bool) {
+        let whole = value.r#type == *list;
+        let expected = match list {
+            Type::List(element) if !whole => element.as_ref().clone(),
+            _ => list.clone(),
+        };
+        (self.coerced(value, &expected), whole)
+    }
+
+    fn cmp(&mut self, cmp: &Cmp, span: Span) -> String {
+        let (symbol, v) = match cmp {
+            Cmp::Less(v) => ("<", v),
+            Cmp::Greater(v) => (">", v),
+            Cmp::LessEq(v) => ("<=", v),
+            Cmp::GreatEq(v) => (">=", v),
+            Cmp::Equal(v) => ("===", v),
+            Cmp::NotEq(v) => ("!==", v),
+            Cmp::Or(v) => ("||", v),
+            Cmp::And(v) => ("&&", v),
+            Cmp::Not(v) => return format!("!{}", self.operand(v)),
+            Cmp::Err => unreachable!("backends only receive valid programs"),
+        };
+        let r#type = v.0.r#type.clone();
+        if primitive(&r#type) || matches!(symbol, "||" | "&&") {
+            return format!("{} {symbol} {}", self.operand(&v.0), self.operand(&v.1));
+        }
+        let (lhs, rhs) = (self.value(&v.0), self.value(&v.1));
+        match (symbol, &r#type) {
+            ("===", _) => format!("{}({lhs}, {rhs})", self.import("eq")),
+            ("!==", _) => format!("!{}({lhs}, {rhs})", self.import("eq")),
+            (_, Type::List(_) | Type::Tuple(_)) => format!("{}({lhs}, {rhs}) {symbol} 0", self.import("compare")),
+            _ => self.unsupported(span, "ordered comparisons of values other than primitives, lists and tuples"),
+        }
+    }
+
+    fn conversion(&mut self, into: &Type, value: &Value) -> String {
+        let from = value.r#type.clone();
+        let converted = self.value(value);
+        let target = match into {
+            Type::Int => "i",
+            Type::Bool => "b",
+            Type::Char => "c",
+            _ => "s",
+        };
+        // `Type` considers ints and nums equal, but the conversion truncates
+        if std::mem::discriminant(&from) == std::mem::discriminant(into) && primitive(&from) {
+            return converted;
+        }
+        let convert = self.import("convert");
+        format!("{convert}({converted}, {}, \"{target}\")", js_str(&code(&from)))
+    }
+
+    /// Translates `value`, building the literals with the type `expected` requires.
+    fn coerced(&mut self, value: &Value, expected: &Type) -> String {
+        let found = value.r#type.clone();
+        match (expected, &found, &value.kind) {
+            (Type::Struct(members), _, ValueKind::Tuple(items)) => {
+                let fields = items
+                    .iter()
+                    .zip(members)
+                    .map(|(v, (m, t))| format!("{m}: {}", self.coerced(v, t)))
+                    .collect::<Vec<_>>();
+                self.record(fields)
+            }
+            (Type::Struct(members), _, ValueKind::Struct(items)) => {
+                let fields = items
+                    .iter()
+                    .map(|(member, v)| {
+                        let t = members
+                            .iter()
+                            .find(|(m, _)| m == member)
+                            .map_or(Type::Err, |(_, t)| t.clone());
+                        format!("{member}: {}", self.coerced(v, &t))
+                    })
+                    .collect::<Vec<_>>();
+                self.record(fields)
+            }
+            (Type::Struct(members), Type::Tuple(_), _) => {
+                // Variables are read directly, anything else is evaluated once by an arrow function
+                let tuple = match &value.kind {
+                    ValueKind::Var(name) => ident(name),
+                    _ => "t".into(),
+                };
+                let fields = members
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (m, _))| format!("{m}: {tuple}[{i}]"))
+                    .collect::<Vec<_>>();
+                let record = self.record(fields);
+                if matches!(value.kind, ValueKind::Var(_)) {
+                    return record;
+                }
+                let param = format!("t{}", self.annotation(&found, value.span));
+                format!("(({param}) => {record})({})", self.value(value))
+            }
+            (Type::Tuple(types), _, ValueKind::Tuple(items)) => {
+                let items = items
+                    .iter()
+                    .zip(types)
+                    .map(|(v, t)| self.coerced(v, t))
+                    .collect::<Vec<_>>();
+                format!("{}({})", self.import("tuple"), items.join(", "))
+            }
+            (Type::List(t), _, ValueKind::List(items)) => {
+                let items = items.iter().map(|v| self.coerced(v, t)).collect::<Vec<_>>();
+                format!("[{}]", items.join(", "))
+            }
+            (Type::Dict(d), _, ValueKind::Dict(pairs)) => {
+                let object = self.dict_kind(&d.0, value.span);
+                let pairs = pairs
+                    .iter()
+                    .map(|(k, v)| (self.coerced(k, &d.0), self.coerced(v, &d.1), k))
+                    .collect::<Vec<_>>();
+                if object {
+                    let pairs = pairs
+                        .into_iter()
+                        .map(|(k, v, key)| match &key.kind {
+                            // A literal `__proto__` key would set the prototype instead of adding an entry
+                            ValueKind::Str(_) | ValueKind::Char(_) if k != "\"__proto__\"" => {
+                                format!("{k}: {v}")
+                            }
+                            _ => format!("[{k}]: {v}"),
+                        })
+                        .collect::<Vec<_>>();
+                    format!("{{ {} }}", pairs.join(", "))
+                } else {
+                    let pairs = pairs
+                        .into_iter()
+                        .map(|(k, v, _)| format!("[{k}, {v}]"))
+                        .collect::<Vec<_>>();
+                    format!("new Map([{}])", pairs.join(", "))
+                }
+            }
+            _ => self.value(value),
+        }
+    }
+
+    /// Frozen object with `fields`.
+    fn record(&mut self, fields: Vec<String>) -> String {
+        format!("{}({{ {} }})", self.import("record"), fields.join(", "))
+    }
+
+    /// Whether dicts with keys of `type` are objects, or `Map`s otherwise.
+    fn dict_kind(&mut self, key: &Type, span: Span) -> bool {
+        match key {
+            Type::Char | Type::Str => true,
+            Type::Int | Type::Num | Type::Bool => false,
+            // `Map` compares objects by identity, so compound keys would never be found
+            _ => {
+                self.unsupported(span, "dictionaries with compound keys");
+                false
+            }
+        }
+    }
+
+    /// Reports the parts of `type` that can't be translated.
+    fn check(&mut self, r#type: &Type, span: Span) {
+        match r#type {
+            Type::Tuple(t) => t.iter().for_each(|t| self.check(t, span)),
+            Type::Struct(members) => members.iter().for_each(|(_, t)| self.check(t, span)),
+            Type::List(l) => self.check(l, span),
+            Type::Dict(d) => {
+                self.dict_kind(&d.0, span);
+                self.check(&d.0, span);
+                self.check(&d.1, span);
+            }
+            _ => {}
+        }
+    }
+
+    /// `: type` in TypeScript, nothing in JavaScript.
+    fn annotation(&mut self, r#type: &Type, span: Span) -> String {
+        if self.typescript {
+            format!(": {}", self.ts_type(r#type, span))
+        } else {
+            String::new()
+        }
+    }
+
+    fn ts_type(&mut self, r#type: &Type, span: Span) -> String {
+        match r#type {
+            Type::Range => {
+                self.import("Range");
+                "Range".into()
+            }
+            Type::Int | Type::Num => "number".into(),
+            Type::Bool => "boolean".into(),
+            Type::Char | Type::Str => "string".into(),
+            Type::Tuple(t) => {
+                let items = t.iter().map(|t| self.ts_type(t, span)).collect::<Vec<_>>();
+                format!("readonly [{}]", items.join(", "))
+            }
+            Type::Struct(members) => {
+                if let Some((name, _)) = self.aliases.iter().find(|(_, m)| m == members) {
+                    return name.clone();
+                }
+                format!("Readonly<{{ {} }}>", self.fields(members))
+            }
+            Type::List(l) => {
+                let element = self.ts_type(l, span);
+                if element.contains(' ') {
+                    format!("({element})[]")
+                } else {
+                    format!("{element}[]")
+                }
+            }
+            Type::Dict(d) => {
+                let (key, value) = (self.ts_type(&d.0, span), self.ts_type(&d.1, span));
+                if self.dict_kind(&d.0, span) {
+                    format!("Record<string, {value}>")
+                } else {
+                    format!("Map<{key}, {value}>")
+                }
+            }
+            Type::Void => "void".into(),
+            Type::Custom(_) | Type::Err => "unknown".into(),
+        }
+    }
+
+    /// Members of an object type.
+    fn fields(&mut self, members: &[(String, Type)]) -> String {
+        members
+            .iter()
+            .map(|(m, t)| format!("{m}: {}", self.ts_type(t, Span::default())))
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
+    /// Value of a variable declared without initialization.
+    fn default(&mut self, r#type: &Type) -> String {
+        match r#type {
+            Type::Range => format!("{}(0, 0)", self.import("range")),
+            Type::Int => "0".into(),
+            Type::Num => "0.0".into(),
+            Type::Bool => "false".into(),
+            Type::Char => js_str("\0"),
+            Type::Str => js_str(""),
+            Type::Tuple(t) => {
+                let items = t.iter().map(|t| self.default(t)).collect::<Vec<_>>();
+                format!("{}({})", self.import("tuple"), items.join(", "))
+            }
+            Type::Struct(members) => {
+                let fields = members
+                    .iter()
+                    .map(|(m, t)| format!("{m}: {}", self.default(t)))
+                    .collect::<Vec<_>>();
+                self.record(fields)
+            }
+            Type::List(_) => "[]".into(),
+            Type::Dict(d) => {
+                if self.dict_kind(&d.0, Span::default()) {
+                    "{}".into()
+                } else {
+                    "new Map()".into()
+                }
+            }
+            Type::Void | Type::Custom(_) | Type::Err => "undefined".into(),
+        }
+    }
+}
+
+/// Types whose values JavaScript compares by value.
+fn primitive(r#type: &Type) -> bool {
+    matches!(r#type, Type::Int | Type::Num | Type::Bool | Type::Char | Type::Str)
+}
+
+/// Type code the runtime uses to know how to show or convert a value, described in `runtime.js`.
+fn code(r#type: &Type) -> String {
+    match r#type {
+        Type::Int => "i".into(),
+        Type::Num => "n".into(),
+        Type::Bool => "b".into(),
+        Type::Char => "c".into(),
+        Type::Str => "s".into(),
+        Type::Range => "r".into(),
+        Type::List(l) => format!("l{}", code(l)),
+        Type::Dict(d) => format!("d{}{}", code(&d.0), code(&d.1)),
+        Type::Tuple(t) => format!("t{}{}", t.len(), t.iter().map(code).collect::<String>()),
+        Type::Struct(members) => {
+            let codes = members
+                .iter()
+                .map(|(m, t)| format!("{}:{m}{}", m.encode_utf16().count(), code(t)))
+                .collect::<String>();
+            format!("S{}:{codes}", members.len())
+        }
+        Type::Void | Type::Custom(_) | Type::Err => "x".into(),
+    }
+}
+
+fn ident(name: &str) -> String {
+    if RESERVED.contains(&name) {
+        format!("{name}_")
+    } else {
+        name.into()
+    }
+}
+
+fn js_str(s: &str) -> String {
+    format!("\"{}\"", escape(s).replace('"', "\\\""))
+}
+
+/// Text of a template literal, where `${` would start an interpolation.
+fn template_escape(s: &str) -> String {
+    escape(s).replace('`', "\\`").replace('$', "\\$")
+}
+
+/// Polyglot strings have no escape sequences, so every special character is escaped.
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            // `\0` followed by a digit would be an octal escape, which modules don't allow
+            '\0' => escaped.push_str("\\x00"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,26 @@
+// Declarations of the runtime of the JavaScript flavor of Polyglot, see `runtime.js`.
+
+export interface Range extends Iterable<number> {
+    readonly start: number;
+    readonly end: number;
+}
+
+export function show(value: unknown, code: string, nested?: boolean): string;
+export function print(text: unknown): void;
+export function println(text: unknown): void;
+export function fail(message: string): never;
+export function range(start: number, end: number): Range;
+export function tuple<T extends unknown[]>(...items: T): Readonly<T>;
+export function record<T extends object>(fields: T): Readonly<T>;
+export function index<T>(items: readonly T[], i: number, line: number, text: string): T;
+export function key<K, V>(dict: Map<K, V>, k: K, line: number, text: string): V;
+export function key<V>(dict: Record<string, V>, k: string, line: number, text: string): V;
+export function eq(a: unknown, b: unknown): boolean;
+export function compare(a: unknown, b: unknown): number;
+export function remove<T>(items: readonly T[], removed: readonly T[]): T[];
+export function removeAll<T>(items: readonly T[], removed: readonly T[]): T[];
+export function idiv(a: number, b: number, line: number, text: string): number;
+export function imod(a: number, b: number, line: number, text: string): number;
+export function ipow(base: number, exponent: number): number;
+// The result has the type the program expects, which the code alone doesn't express
+export function convert(value: unknown, code: string, target: "i" | "b" | "c" | "s"): any;
@@ -0,0 +1,240 @@
+// Runtime of the JavaScript flavor of Polyglot.
+//
+// JavaScript values don't know their Polyglot type (`5` may be an int or a num), so functions that depend on it
+// receive a type code: `i` int, `n` num, `b` bool, `c` char, `s` str, `r` range, `l<T>` list, `d<K><V>` dict,
+// `t<count><T>...` tuple and `S<count>:(<length>:<name><T>)...` struct.
+// Chars are strings with a single code point, dicts with str or char keys are objects and the rest are `Map`s.
+
+const parsed = new Map();
+
+function parse(code) {
+    let i = 0;
+    const number = () => {
+        const start = i;
+        while (code[i] >= "0" && code[i] <= "9") i++;
+        const value = Number(code.slice(start, i));
+        // Struct counts and member lengths are followed by `:`, as a name may start with a digit
+        if (code[i] === ":") i++;
+        return value;
+    };
+    const next = () => {
+        const kind = code[i++];
+        switch (kind) {
+            case "l":
+                return { kind, element: next() };
+            case "d":
+                return { kind, key: next(), value: next() };
+            case "t": {
+                const count = number();
+                return { kind, items: Array.from({ length: count }, next) };
+            }
+            case "S": {
+                const count = number();
+                const members = [];
+                for (let m = 0; m < count; m++) {
+                    const length = number();
+                    const name = code.slice(i, i + length);
+                    i += length;
+                    members.push([name, next()]);
+                }
+                return { kind, members };
+            }
+            default:
+                return { kind };
+        }
+    };
+    return next();
+}
+
+function typeOf(code) {
+    if (!parsed.has(code)) parsed.set(code, parse(code));
+    return parsed.get(code);
+}
+
+function entries(dict) {
+    return dict instanceof Map ? [...dict.entries()] : Object.entries(dict);
+}
+
+function showNum(value) {
+    if (Number.isNaN(value)) return "nan";
+    if (!Number.isFinite(value)) return value > 0 ? "inf" : "-inf";
+    const [mantissa, exponent] = value.toExponential().split("e");
+    const exp = Number(exponent);
+    if (exp < -4 || exp >= 16) {
+        return `${mantissa}e${exp < 0 ? "-" : "+"}${String(Math.abs(exp)).padStart(2, "0")}`;
+    }
+    const digits = mantissa.replace("-", "").replace(".", "").length;
+    const fixed = value.toFixed(Math.max(digits - 1 - exp, 1));
+    return Object.is(value, -0) ? "-0.0" : fixed;
+}
+
+function display(value, type, nested) {
+    switch (type.kind) {
+        case "i":
+            return String(value);
+        case "n":
+            return showNum(value);
+        case "b":
+            return value ? "true" : "false";
+        case "c":
+        case "s":
+            return nested ? `"${value}"` : value;
+        case "r":
+            return `${value.start}..${value.end}`;
+        case "l":
+            return `[${value.map((v) => display(v, type.element, true)).join(", ")}]`;
+        case "d": {
+            const items = entries(value).map(
+                ([k, v]) => `${display(k, type.key, true)} -> ${display(v, type.value, true)}`,
+            );
+            return `[${items.join(", ")}]`;
+        }
+        case "t":
+            return `(${value.map((v, i) => display(v, type.items[i], true)).join(", ")})`;
+        case "S":
+            return `(${type.members.map(([name, t]) => `${name}: ${display(value[name], t, true)}`).join(", ")})`;
+        default:
+            return String(value);
+    }
+}
+
+/** Text of `value`, printed like every other flavor does. */
+export function show(value, code, nested = false) {
+    return display(value, typeOf(code), nested);
+}
+
+export function print(text) {
+    process.stdout.write(String(text));
+}
+
+export function println(text) {
+    process.stdout.write(`${text}\n`);
+}
+
+export function fail(message) {
+    process.stderr.write(message + "\n");
+    process.exit(1);
+}
+
+/** `start..end`, which can be iterated with `for...of`. */
+export function range(start, end) {
+    return Object.freeze({
+        start,
+        end,
+        *[Symbol.iterator]() {
+            for (let i = start; i < end; i++) yield i;
+        },
+    });
+}
+
+export function tuple(...items) {
+    return Object.freeze(items);
+}
+
+/** Struct with `fields`. */
+export function record(fields) {
+    return Object.freeze(fields);
+}
+
+export function index(items, i, line, text) {
+    if (i >= 0 && i < items.length) return items[i];
+    return fail(`ERROR: Out-of-bounds in line ${line}, "${text}".`);
+}
+
+export function key(dict, k, line, text) {
+    if (dict instanceof Map ? dict.has(k) : Object.hasOwn(dict, k)) {
+        return dict instanceof Map ? dict.get(k) : dict[k];
+    }
+    return fail(`ERROR: Key not found in line ${line}, "${text}".`);
+}
+
+/** Structural equality, used for everything but primitives. */
+export function eq(a, b) {
+    if (a === b) return true;
+    if (Array.isArray(a) && Array.isArray(b)) {
+        return a.length === b.length && a.every((v, i) => eq(v, b[i]));
+    }
+    if (a instanceof Map && b instanceof Map) {
+        return a.size === b.size && [...a].every(([k, v]) => b.has(k) && eq(v, b.get(k)));
+    }
+    if (typeof a === "object" && typeof b === "object" && a !== null && b !== null) {
+        const keys = Object.keys(a);
+        return keys.length === Object.keys(b).length && keys.every((k) => Object.hasOwn(b, k) && eq(a[k], b[k]));
+    }
+    return false;
+}
+
+/** Lexicographic comparison of lists and tuples, negative if `a` goes first. */
+export function compare(a, b) {
+    if (!Array.isArray(a)) return a < b ? -1 : a > b ? 1 : 0;
+    for (let i = 0; i < Math.min(a.length, b.length); i++) {
+        const order = compare(a[i], b[i]);
+        if (order !== 0) return order;
+    }
+    return a.length - b.length;
+}
+
+/** `items - removed`, removing the first occurrence of every element. */
+export function remove(items, removed) {
+    const result = [...items];
+    for (const item of removed) {
+        const i = result.findIndex((v) => eq(v, item));
+        if (i !== -1) result.splice(i, 1);
+    }
+    return result;
+}
+
+/** `items -- removed`, removing every occurrence of every element. */
+export function removeAll(items, removed) {
+    return items.filter((v) => !removed.some((item) => eq(v, item)));
+}
+
+/** Integer division truncating towards zero. */
+export function idiv(a, b, line, text) {
+    if (b === 0) return fail(`ERROR: Division by zero in line ${line}, "${text}".`);
+    return Math.trunc(a / b);
+}
+
+/** Integer remainder, which already takes the sign of the dividend like in Polyglot. */
+export function imod(a, b, line, text) {
+    if (b === 0) return fail(`ERROR: Division by zero in line ${line}, "${text}".`);
+    return a % b;
+}
+
+export function ipow(base, exponent) {
+    if (exponent < 0) return base === 1 ? 1 : base === -1 ? (exponent % 2 === 0 ? 1 : -1) : 0;
+    return base ** exponent;
+}
+
+function into(value, type, target) {
+    const text = type.kind === "c" || type.kind === "s" ? value : display(value, type, false);
+    switch (target) {
+        case "i":
+            if (type.kind === "n") return Math.trunc(value);
+            if (type.kind === "b") return value ? 1 : 0;
+            if (type.kind === "i") return value;
+            if (/^\s*[+-]?\d+\s*$/.test(text)) return Number(text.trim());
+            return fail(`ERROR: "${text}" can not be parsed into an int`);
+        case "b":
+            if (type.kind === "i" || type.kind === "n") return value !== 0;
+            if (type.kind === "b") return value;
+            if (text === "true" || text === "false") return text === "true";
+            return fail(`ERROR: "${text}" can not be parsed into a bool`);
+        case "c":
+            if ([...text].length === 1) return text;
+            return fail(`ERROR: "${text}" can not be parsed into a char`);
+        default:
+            return text;
+    }
+}
+
+/** Converts `value` of type `code` into an int, bool, char or str, element by element for lists and dicts. */
+export function convert(value, code, target) {
+    const type = typeOf(code);
+    if (type.kind === "l") return value.map((v) => into(v, type.element, target));
+    if (type.kind === "d") {
+        const converted = entries(value).map(([k, v]) => [into(k, type.key, target), into(v, type.value, target)]);
+        return target === "s" || target === "c" ? Object.fromEntries(converted) : new Map(converted);
+    }
+    return into(value, type, target);
+}
@@ -0,0 +1,934 @@
+use super::{native_code, splice, Backend, Capabilities, DictKeys, IntWidth, Output, Redeclarations, Types};
+use crate::diagnostic::Diagnostic;
+use crate::source::Span;
+use crate::tree::*;
+use crate::visit::{self, Visitor};
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::process::Command;
+
+/// Python 3.9+, everything is translated to builtin types except structs, which become dataclasses.
+pub struct Python;
+
+impl Backend for Python {
+    fn name(&self) -> &'static str {
+        "python"
+    }
+
+    fn extension(&self) -> &'static str {
+        "py"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            void_variables: true,
+            builtin_values: true,
+            ordered_lists: true,
+            ordered_dicts: false,
+            dict_keys: DictKeys::Immutable,
+            redeclarations: Redeclarations::Any,
+            int_width: IntWidth::Unbounded,
+            unsigned_ints: false,
+            // The default of `sys.getrecursionlimit()`
+            recursion_depth: Some(1000),
+            exceptions: true,
+            native_code: &["python"],
+        }
+    }
+
+    fn emit(&self, main: &Main) -> Result<Output, Diagnostic> {
+        let mut emitter = Emitter::new(main);
+        for expr in &main.exprs {
+            emitter.expr(expr);
+        }
+        if let Some(error) = emitter.error.take() {
+            return Err(error);
+        }
+        Ok(Output {
+            source: emitter.finish(),
+            files: Vec::new(),
+        })
+    }
+
+    fn commands(&self, source: &Path) -> Vec<Command> {
+        let mut python = Command::new("python3");
+        python.arg(source);
+        vec![python]
+    }
+}
+
+/// Names that would shadow Python keywords or the builtins used by the generated code.
+#[rustfmt::skip]
+const RESERVED: &[&str] = &[
+    "False", "None", "True", "and", "as", "assert", "async", "await", "break", "case", "class", "continue", "def",
+    "del", "elif", "else", "except", "finally", "for", "from", "global", "if", "import", "in", "is", "lambda",
+    "match", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while", "with", "yield", "abs", "bool",
+    "dataclass", "dict", "fields", "float", "getattr", "int", "is_dataclass", "isinstance", "len", "list", "math",
+    "print", "range", "repr", "str", "sys", "tuple",
+];
+
+/// Runtime support, only the helpers used by the program are emitted.
+const HELPERS: &[(&str, &str)] = &[
+    (
+        "_show",
+        r#"def _show(value, nested=False):
+    if isinstance(value, bool):
+        return "true" if value else "false"
+    if isinstance(value, str):
+        return f'"{value}"' if nested else value
+    if isinstance(value, range):
+        return f"{value.start}..{value.stop}"
+    if isinstance(value, list):
+        return "[" + ", ".join(_show(v, True) for v in value) + "]"
+    if isinstance(value, tuple):
+        return "(" + ", ".join(_show(v, True) for v in value) + ")"
+    if isinstance(value, dict):
+        return "[" + ", ".join(f"{_show(k, True)} -> {_show(v, True)}" for k, v in value.items()) + "]"
+    if is_dataclass(value):
+        return "(" + ", ".join(f"{f.name}: {_show(getattr(value, f.name), True)}" for f in fields(value)) + ")"
+    return repr(value)"#,
+    ),
+    (
+        "_index",
+        r#"def _index(items, index, line, text):
+    if 0 <= index < len(items):
+        return items[index]
+    sys.exit(f'ERROR: Out-of-bounds in line {line}, "{text}".')"#,
+    ),
+    (
+        "_key",
+        r#"def _key(items, key, line, text):
+    if key in items:
+        return items[key]
+    sys.exit(f'ERROR: Key not found in line {line}, "{text}".')"#,
+    ),
+    (
+        "_remove",
+        r#"def _remove(items, removed):
+    items = items.copy()
+    for item in removed:
+        if item in items:
+            items.remove(item)
+    return items"#,
+    ),
+    (
+        "_remove_all",
+        r#"def _remove_all(items, removed):
+    return [item for item in items if item not in removed]"#,
+    ),
+    (
+        "_div",
+        r#"def _div(a, b, line, text):
+    if b == 0:
+        sys.exit(f'ERROR: Division by zero in line {line}, "{text}".')
+    quotient = abs(a) // abs(b)
+    return quotient if (a < 0) == (b < 0) else -quotient"#,
+    ),
+    (
+        "_mod",
+        r#"def _mod(a, b, line, text):
+    return a - b * _div(a, b, line, text)"#,
+    ),
+    (
+        "_fdiv",
+        r#"def _fdiv(a, b):
+    if b != 0:
+        return a / b
+    if a == 0 or a != a:
+        return math.nan
+    return math.copysign(math.inf, a) * math.copysign(1, b)"#,
+    ),
+    (
+        "_fmod",
+        r#"def _fmod(a, b):
+    if b == 0 or math.isinf(a) or a != a or b != b:
+        return math.nan
+    return math.fmod(a, b)"#,
+    ),
+    (
+        "_ipow",
+        r#"def _ipow(a, b):
+    if b >= 0:
+        return a ** b
+    if a in (1, -1):
+        return 1 if a == 1 or b % 2 == 0 else -1
+    return 0"#,
+    ),
+    (
+        "_pow",
+        r#"def _pow(a, b):
+    try:
+        return a ** b
+    except OverflowError:
+        return -math.inf if a < 0 and b % 2 == 1 else math.inf"#,
+    ),
+    (
+        "_convert",
+        r#"def _convert(value, into):
+    if isinstance(value, list):
+        return [into(v) for v in value]
+    if isinstance(value, dict):
+        return {into(k): into(v) for k, v in value.items()}
+    return into(value)"#,
+    ),
+    (
+        "_int",
+        r#"def _int(value):
+    try:
+        return int(value)
+    except ValueError:
+        sys.exit(f'ERROR: "{value}" can not be parsed into an int')"#,
+    ),
+    (
+        "_bool",
+        r#"def _bool(value):
+    if not isinstance(value, str):
+        return value != 0
+    if value in ("true", "false"):
+        return value == "true"
+    sys.exit(f'ERROR: "{value}" can not be parsed into a bool')"#,
+    ),
+    (
+        "_char",
+        r#"def _char(value):
+    text = _show(value)
+    if len(text) == 1:
+        return text
+    sys.exit(f'ERROR: "{text}" can not be parsed into a char')"#,
+    ),
+];
+
+struct Emitter<'a> {
+    main: &'a Main,
+    types: Types,
+    names: Names,
+    out: String,
+    indent: usize,
+    /// Dataclass generated for each struct type, typedefs keep their name
+    classes: Vec<(String, Vec<(String, Type)>)>,
+    helpers: BTreeSet<&'static str>,
+    imports: BTreeSet<&'static str>,
+    dataclasses: BTreeSet<&'static str>,
+    /// First native block without a Python section
+    error: Option<Diagnostic>,
+}
+
+impl<'a> Emitter<'a> {
+    fn new(main: &'a Main) -> Emitter<'a> {
+        Emitter {
+            main,
+            types: Types::new(),
+            names: Names::new(main),
+            out: String::new(),
+            indent: 0,
+            classes: Vec::new(),
+            helpers: BTreeSet::new(),
+            imports: BTreeSet::new(),
+            dataclasses: BTreeSet::new(),
+            error: None,
+        }
+    }
+
+    /// Joins the imports, helpers and classes needed by the program with its body.
+    fn finish(mut self) -> String {
+        let body = std::mem::take(&mut self.out);
+        let mut sections = Vec::new();
+        if !self.classes.is_empty() {
+            self.dataclasses.insert("dataclass");
+        }
+
+        let mut header = String::new();
+        if let Some(file) = self.main.source_map.files().first() {
+            let name = file.path.file_name().unwrap_or_default().to_string_lossy();
+            header.push_str(&format!("# Generated by polyglot from {name}\n"));
+        }
+        for import in &self.imports {
+            header.push_str(&format!("import {import}\n"));
+        }
+        if !self.dataclasses.is_empty() {
+            let names = self.dataclasses.iter().copied().collect::<Vec<_>>().join(", ");
+            header.push_str(&format!("from dataclasses import {names}\n"));
+        }
+        sections.push(header);
+        for (name, code) in HELPERS.iter().filter(|(name, _)| self.helpers.contains(name)) {
+            debug_assert!(name.starts_with('_'));
+            sections.push(format!("{code}\n"));
+        }
+        for (name, members) in &self.classes {
+            let mut class = format!("@dataclass(frozen=True)\nclass {name}:\n");
+            for (member, r#type) in members {
+                class.push_str(&format!("    {}: {}\n", ident(member), self.hint_of(r#type)));
+            }
+            sections.push(class);
+        }
+        sections.push(body.trim_end().to_owned());
+        sections.join("\n\n") + "\n"
+    }
+
+    fn line(&mut self, text: impl AsRef<str>) {
+        self.out.push_str(&"    ".repeat(self.indent));
+        self.out.push_str(text.as_ref());
+        self.out.push('\n');
+    }
+
+    fn helper(&mut self, name: &'static str) -> &'static str {
+        self.helpers.insert(name);
+        match name {
+            "_show" => {
+                self.dataclasses.extend(["fields", "is_dataclass"]);
+            }
+            "_mod" => {
+                self.helper("_div");
+            }
+            "_char" => {
+                self.helper("_show");
+                self.imports.insert("sys");
+            }
+            "_pow" | "_fdiv" | "_fmod" => {
+                self.imports.insert("math");
+            }
+            "_index" | "_key" | "_int" | "_bool" | "_div" => {
+                self.imports.insert("sys");
+            }
+            _ => {}
+        }
+        name
+    }
+
+    fn block(&mut self, exprs: &[Expr], vars: Vec<(String, Type)>) {
+        self.indent += 1;
+        self.types.push();
+        for (name, r#type) in vars {
+            self.types.insert(name, r#type);
+        }
+
+        let len = self.out.len();
+        for expr in exprs {
+            self.expr(expr);
+        }
+        if self.out.len() == len {
+            self.line("pass");
+        }
+
+        self.types.pop();
+        self.indent -= 1;
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Init { name, r#type, value } => {
+                let value = self.coerced(value, r#type);
+                let declared = self.names.declare(name);
+                self.line(format!("{declared} = {value}"));
+                self.types.insert(name, r#type.clone());
+            }
+            ExprKind::Decl { name, r#type } => {
+                let line = format!("{}: {} = {}", self.names.declare(name), self.hint(r#type), self.default(r#type));
+                self.line(line);
+                self.types.insert(name, r#type.clone());
+            }
+            ExprKind::Assig { name, value } => {
+                let line = self.assig(name, value);
+                self.line(line);
+            }
+            ExprKind::Typedef { name, r#type } => {
+                if let Type::Struct(members) = r#type {
+                    for (_, t) in members {
+                        self.hint(t);
+                    }
+                    self.classes.push((name.clone(), members.clone()));
+                }
+            }
+            ExprKind::If { cmp, exprs, elif } | ExprKind::Elif { cmp, exprs, elif } => {
+                let keyword = if matches!(expr.kind, ExprKind::If { .. }) {
+                    "if"
+                } else {
+                    "elif"
+                };
+                let cmp = self.value(cmp);
+                self.line(format!("{keyword} {cmp}:"));
+                self.names.push();
+                self.block(exprs, Vec::new());
+                self.names.pop();
+                if let Some(elif) = elif {
+                    self.expr(elif);
+                }
+            }
+            ExprKind::Else { exprs } => {
+                self.line("else:");
+                self.names.push();
+                self.block(exprs, Vec::new());
+                self.names.pop();
+            }
+            ExprKind::For { var, range, exprs } => {
+                let element = self.types.element_of(range);
+                let range = self.value(range);
+                self.names.push();
+                let declared = self.names.declare(var);
+                self.line(format!("for {declared} in {range}:"));
+                self.block(exprs, vec![(var.clone(), element)]);
+                self.names.pop();
+            }
+            ExprKind::While { cmp, exprs } => {
+                let cmp = self.value(cmp);
+                self.line(format!("while {cmp}:"));
+                self.names.push();
+                self.block(exprs, Vec::new());
+                self.names.pop();
+            }
+            ExprKind::Fn {
+                name,
+                r#type,
+                args,
+                exprs,
+            } => {
+                let top_level = self.def(name, r#type, args);
+                self.block(exprs, args.clone());
+                self.names.leave_fn();
+                // Polyglot functions can't return a value yet, so the result is always the default one
+                if !matches!(r#type, Type::Void) {
+                    let default = self.default(r#type);
+                    self.indent += 1;
+                    self.line(format!("return {default}"));
+                    self.indent -= 1;
+                }
+                if top_level {
+                    self.out.push_str("\n\n");
+                }
+            }
+            ExprKind::Native { sections } => self.native(sections, expr.span),
+            ExprKind::NativeFn {
+                name,
+                r#type,
+                args,
+                sections,
+            } => {
+                let top_level = self.def(name, r#type, args);
+                self.indent += 1;
+                self.native(sections, expr.span);
+                self.indent -= 1;
+                self.names.leave_fn();
+                if top_level {
+                    self.out.push_str("\n\n");
+                }
+            }
+            ExprKind::Call { name, args } => {
+                let call = self.call(name, args);
+                self.line(call);
+            }
+            ExprKind::Err => unreachable!("backends only receive valid programs"),
+        }
+    }
+
+    /// Emits the `def` line of a function and starts its scope, returning whether it's at the top level.
+    fn def(&mut self, name: &str, r#type: &Type, args: &[(String, Type)]) -> bool {
+        self.types.insert_fn(name, r#type.clone(), args);
+        self.names.enter_fn();
+        let params = args
+            .iter()
+            .map(|(name, t)| format!("{}: {}", self.names.declare(name), self.hint(t)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let ret = self.hint(r#type);
+        // Top level functions are surrounded by two blank lines, as PEP 8 recommends
+        let top_level = self.indent == 0;
+        if top_level && !self.out.is_empty() {
+            self.out.push_str("\n\n");
+        }
+        self.line(format!("def {}({params}) -> {ret}:", ident(name)));
+        top_level
+    }
+
+    fn native(&mut self, sections: &[NativeSection], span: Span) {
+        match native_code(sections, &["python"], span) {
+            Ok(code) if code.trim().is_empty() => self.line("pass"),
+            Ok(code) => splice(&mut self.out, code, self.indent),
+            Err(error) => {
+                self.error.get_or_insert(error);
+            }
+        }
+    }
+
+    /// Assignments of the form `a = a op b` use augmented assignment when it keeps the same semantics.
+    fn assig(&mut self, name: &str, value: &Value) -> String {
+        let var_t = self.types.get(name).cloned().unwrap_or(Type::Err);
+        let augmented = match &value.kind {
+            ValueKind::Op(Op::Add(v)) => Some(("+", v)),
+            ValueKind::Op(Op::Sub(v)) => Some(("-", v)),
+            ValueKind::Op(Op::Mul(v)) => Some(("*", v)),
+            ValueKind::Op(Op::Pow(v)) if matches!(var_t, Type::Int) && non_negative(&v.1) => Some(("**", v)),
+            _ => None,
+        };
+        if let Some((symbol, operands)) = augmented {
+            let is_self = matches!(&operands.0.kind, ValueKind::Var(v) if v == name);
+            if is_self && matches!(var_t, Type::Int | Type::Num | Type::Str) {
+                return format!("{} {symbol}= {}", self.names.get(name), self.value(&operands.1));
+            }
+        }
+        format!("{} = {}", self.names.get(name), self.coerced(value, &var_t))
+    }
+
+    fn call(&mut self, name: &str, args: &[Value]) -> String {
+        if !self.types.is_builtin(name) {
+            let arg_types = self.types.get_fn(name).map(|f| f.args.clone()).unwrap_or_default();
+            let args = args
+                .iter()
+                .zip(arg_types.iter())
+                .map(|(a, t)| self.coerced(a, t))
+                .collect::<Vec<_>>()
+                .join(", ");
+            return format!("{}({args})", ident(name));
+        }
+
+        let arg = &args[0];
+        match name {
+            "dbg" => {
+                let text = self.main.source_map.snippet(arg.span).unwrap_or_default();
+                let show = self.helper("_show");
+                format!("print({} + {show}({}, True))", py_str(&format!("{text} = ")), self.value(arg))
+            }
+            _ => {
+                let shown = match &arg.kind {
+                    ValueKind::Str(s) => self.interpolated(s),
+                    _ => self.display(arg),
+                };
+                if name == "print" {
+                    format!("print({shown}, end=\"\")")
+                } else {
+                    format!("print({shown})")
+                }
+            }
+        }
+    }
+
+    /// String literal with its `{var}` replaced, as an f-string if needed.
+    fn interpolated(&mut self, s: &str) -> String {
+        let segments = interpolation(s);
+        if !segments.iter().any(|(_, seg)| matches!(seg, Segment::Var(_))) {
+            return py_str(s);
+        }
+
+        let mut out = String::from("f\"");
+        for (_, segment) in segments {
+            match segment {
+                Segment::Text(text) => out.push_str(&escape(text).replace('{', "{{").replace('}', "}}")),
+                Segment::Var(var) => {
+                    let var = self.types.var(var, Default::default());
+                    out.push_str(&format!("{{{}}}", self.display(&var)));
+                }
+            }
+        }
+        out.push('"');
+        out
+    }
+
+    /// Expression printing `value` like every other flavor does.
+    fn display(&mut self, value: &Value) -> String {
+        match value.r#type.clone() {
+            Type::Int | Type::Char | Type::Str => self.value(value),
+            _ => format!("{}({})", self.helper("_show"), self.value(value)),
+        }
+    }
+
+    fn value(&mut self, value: &Value) -> String {
+        match &value.kind {
+            ValueKind::Range((start, end)) => format!("range({start}, {end})"),
+            ValueKind::Int(i) => i.to_string(),
+            ValueKind::Num(n) => format!("{n:?}"),
+            ValueKind::Bool(b) => if *b { "True" } else { "False" }.into(),
+            ValueKind::Char(c) => py_str(&c.to_string()),
+            ValueKind::Str(s) => py_str(s),
+            ValueKind::Tuple(t) => {
+                let items = t.iter().map(|v| self.value(v)).collect::<Vec<_>>();
+                tuple(items)
+            }
+            ValueKind::Struct(members) => {
+                let Type::Struct(types) = value.r#type.clone() else {
+                    unreachable!()
+                };
+                let class = self.class_of(&types);
+                let members = members
+                    .iter()
+                    .map(|(name, v)| format!("{}={}", ident(name), self.value(v)))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("{class}({members})")
+            }
+            ValueKind::TupleAccess { name, access_mode, .. } => match access_mode {
+                TupleAccessMode::Member(member) => format!("{}.{}", self.names.get(name), ident(member)),
+                TupleAccessMode::Index(index) => format!("{}[{index}]", self.names.get(name)),
+            },
+            ValueKind::List(l) => {
+                let items = l.iter().map(|v| self.value(v)).collect::<Vec<_>>();
+                format!("[{}]", items.join(", "))
+            }
+            ValueKind::ListAccess { name, access_mode, .. } => {
+                let (helper, key) = match access_mode {
+                    ListAccessMode::List(index) => ("_index", self.value(index)),
+                    ListAccessMode::Dict(key) => ("_key", self.value(key)),
+                };
+                let helper = self.helper(helper);
+                let line = self.main.source_map.line_col(value.span).0.line;
+                let text = self.main.source_map.snippet(value.span).unwrap_or(name);
+                format!("{helper}({}, {key}, {line}, {})", self.names.get(name), py_str(text))
+            }
+            ValueKind::Dict(d) => {
+                let pairs = d
+                    .iter()
+                    .map(|(k, v)| format!("{}: {}", self.value(k), self.value(v)))
+                    .collect::<Vec<_>>();
+                format!("{{{}}}", pairs.join(", "))
+            }
+            ValueKind::Var(name) => self.names.get(name),
+            ValueKind::Op(op) => self.op(op, value.span),
+            ValueKind::Cmp(cmp) => self.cmp(cmp),
+            ValueKind::Parenthesis(p) => format!("({})", self.value(p)),
+            ValueKind::Conversion { r#type, value } => {
+                let into = match r#type {
+                    Type::Int => self.helper("_int"),
+                    Type::Bool => self.helper("_bool"),
+                    Type::Char => self.helper("_char"),
+                    _ => self.helper("_show"),
+                };
+                let converted = self.value(value);
+                if matches!(value.r#type.clone(), Type::List(_) | Type::Dict(_)) {
+                    format!("{}({converted}, {into})", self.helper("_convert"))
+                } else {
+                    format!("{into}({converted})")
+                }
+            }
+            ValueKind::Call { name, args } => self.call(name, args),
+            ValueKind::RetExpr(r) => match *r {},
+            ValueKind::Err => unreachable!("backends only receive valid programs"),
+        }
+    }
+
+    /// Value used as an operand, wrapped in parenthesis so Polyglot's evaluation order is kept.
+    fn operand(&mut self, value: &Value) -> String {
+        let translated = self.value(value);
+        match &value.kind {
+            ValueKind::Op(_) | ValueKind::Cmp(_) => format!("({translated})"),
+            ValueKind::Int(i) if *i < 0 => format!("({translated})"),
+            ValueKind::Num(n) if *n < 0.0 => format!("({translated})"),
+            _ => translated,
+        }
+    }
+
+    fn op(&mut self, op: &Op, span: Span) -> String {
+        let binary = |e: &mut Self, v: &(Value, Value), symbol: &str| {
+            format!("{} {symbol} {}", e.operand(&v.0), e.operand(&v.1))
+        };
+        match op {
+            Op::Add(v) | Op::Sub(v) if matches!(v.0.r#type.clone(), Type::List(_)) => {
+                let removed = self.as_list(&v.0, &v.1);
+                let lhs = self.operand(&v.0);
+                if matches!(op, Op::Add(_)) {
+                    format!("{lhs} + {removed}")
+                } else {
+                    format!("{}({lhs}, {removed})", self.helper("_remove"))
+                }
+            }
+            Op::ListRemoveAll(v) => {
+                let list = self.types.var(&v.0, v.1.span);
+                let removed = self.as_list(&list, &v.1);
+                format!("{}({}, {removed})", self.helper("_remove_all"), self.names.get(&v.0))
+            }
+            Op::Add(v) => binary(self, v, "+"),
+            Op::Sub(v) => binary(self, v, "-"),
+            Op::Mul(v) => binary(self, v, "*"),
+            // Floats raise when they overflow instead of becoming infinite
+            Op::Pow(v) if matches!(v.0.r#type, Type::Num) || matches!(v.1.r#type, Type::Num) => {
+                format!("{}({}, {})", self.helper("_pow"), self.value(&v.0), self.value(&v.1))
+            }
+            Op::Pow(v) if non_negative(&v.1) => binary(self, v, "**"),
+            // A negative exponent truncates the result to an int
+            Op::Pow(v) => format!("{}({}, {})", self.helper("_ipow"), self.value(&v.0), self.value(&v.1)),
+            Op::Div(v) | Op::Mod(v) => {
+                let ints = matches!((v.0.r#type.clone(), v.1.r#type.clone()), (Type::Int, Type::Int));
+                let (a, b) = (self.value(&v.0), self.value(&v.1));
+                // Python raises when dividing by zero, ints fail like every flavor and nums give infinity or NaN
+                match (op, ints) {
+                    (_, true) => {
+                        let helper = self.helper(if matches!(op, Op::Div(_)) { "_div" } else { "_mod" });
+                        let line = self.main.source_map.line_col(span).0.line;
+                        let text = self.main.source_map.snippet(span).unwrap_or_default();
+                        format!("{helper}({a}, {b}, {line}, {})", py_str(text))
+                    }
+                    (Op::Div(_), false) => format!("{}({a}, {b})", self.helper("_fdiv")),
+                    (_, false) => format!("{}({a}, {b})", self.helper("_fmod")),
+                }
+            }
+        }
+    }
+
+    /// `value` as a list that can be concatenated to or removed from `list`.
+    fn as_list(&mut self, list: &Value, value: &Value) -> String {
+        if list.r#type == value.r#type {
+            self.value(value)
+        } else {
+            format!("[{}]", self.value(value))
+        }
+    }
+
+    fn cmp(&mut self, cmp: &Cmp) -> String {
+        let (symbol, v) = match cmp {
+            Cmp::Less(v) => ("<", v),
+            Cmp::Greater(v) => (">", v),
+            Cmp::LessEq(v) => ("<=", v),
+            Cmp::GreatEq(v) => (">=", v),
+            Cmp::Equal(v) => ("==", v),
+            Cmp::NotEq(v) => ("!=", v),
+            Cmp::Or(v) => ("or", v),
+            Cmp::And(v) => ("and", v),
+            Cmp::Not(v) => return format!("not {}", self.operand(v)),
+            Cmp::Err => unreachable!("backends only receive valid programs"),
+        };
+        format!("{} {symbol} {}", self.operand(&v.0), self.operand(&v.1))
+    }
+
+    /// Translates `value`, turning tuples into the dataclass of `expected` when a struct is required.
+    fn coerced(&mut self, value: &Value, expected: &Type) -> String {
+        let found = value.r#type.clone();
+        match (expected, &found, &value.kind) {
+            (Type::Struct(members), Type::Tuple(_), ValueKind::Tuple(items)) => {
+                let class = self.class_of(members);
+                let items = items
+                    .iter()
+                    .zip(members)
+                    .map(|(v, (_, t))| self.coerced(v, t))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("{class}({items})")
+            }
+            (Type::Struct(members), Type::Tuple(_), _) => {
+                let class = self.class_of(members);
+                format!("{class}(*{})", self.value(value))
+            }
+            // Python keeps ints as they are, but a `num` always prints with its decimals
+            (Type::Num, Type::Int, _) => format!("float({})", self.value(value)),
+            (Type::Tuple(types), _, ValueKind::Tuple(items)) => {
+                let items = items.iter().zip(types).map(|(v, t)| self.coerced(v, t)).collect();
+                tuple(items)
+            }
+            (Type::List(t), _, ValueKind::List(items)) => {
+                let items = items.iter().map(|v| self.coerced(v, t)).collect::<Vec<_>>();
+                format!("[{}]", items.join(", "))
+            }
+            (Type::Dict(d), _, ValueKind::Dict(pairs)) => {
+                let pairs = pairs
+                    .iter()
+                    .map(|(k, v)| format!("{}: {}", self.coerced(k, &d.0), self.coerced(v, &d.1)))
+                    .collect::<Vec<_>>();
+                format!("{{{}}}", pairs.join(", "))
+            }
+            _ => self.value(value),
+        }
+    }
+
+    /// Name of the dataclass for a struct with `members`, creating one if it doesn't exist.
+    fn class_of(&mut self, members: &[(String, Type)]) -> String {
+        if let Some((name, _)) = self.classes.iter().find(|(_, m)| m == members) {
+            return name.clone();
+        }
+        for (_, t) in members {
+            self.hint(t);
+        }
+        let name = format!("Struct{}", self.classes.len());
+        self.classes.push((name.clone(), members.to_vec()));
+        name
+    }
+
+    /// Type hint of `type`, registering the dataclasses it needs.
+    fn hint(&mut self, r#type: &Type) -> String {
+        match r#type {
+            Type::Struct(members) => self.class_of(members),
+            Type::Tuple(t) => {
+                let items = t.iter().map(|t| self.hint(t)).collect::<Vec<_>>();
+                format!("tuple[{}]", items.join(", "))
+            }
+            Type::List(l) => format!("list[{}]", self.hint(l)),
+            Type::Dict(d) => format!("dict[{}, {}]", self.hint(&d.0), self.hint(&d.1)),
+            t => self.hint_of(t),
+        }
+    }
+
+    /// Type hint of an already registered type.
+    fn hint_of(&self, r#type: &Type) -> String {
+        match r#type {
+            Type::Range => "range".into(),
+            Type::Int => "int".into(),
+            Type::Num => "float".into(),
+            Type::Bool => "bool".into(),
+            Type::Char | Type::Str => "str".into(),
+            Type::Tuple(t) => format!("tuple[{}]", t.iter().map(|t| self.hint_of(t)).collect::<Vec<_>>().join(", ")),
+            Type::Struct(members) => self
+                .classes
+                .iter()
+                .find(|(_, m)| m == members)
+                .map_or_else(|| "object".into(), |(name, _)| name.clone()),
+            Type::List(l) => format!("list[{}]", self.hint_of(l)),
+            Type::Dict(d) => format!("dict[{}, {}]", self.hint_of(&d.0), self.hint_of(&d.1)),
+            Type::Void => "None".into(),
+            Type::Custom(_) | Type::Err => "object".into(),
+        }
+    }
+
+    /// Value of a variable declared without initialization.
+    fn default(&mut self, r#type: &Type) -> String {
+        match r#type {
+            Type::Range => "range(0)".into(),
+            Type::Int => "0".into(),
+            Type::Num => "0.0".into(),
+            Type::Bool => "False".into(),
+            Type::Char => py_str("\0"),
+            Type::Str => py_str(""),
+            Type::Tuple(t) => {
+                let items = t.iter().map(|t| self.default(t)).collect();
+                tuple(items)
+            }
+            Type::Struct(members) => {
+                let class = self.class_of(members);
+                let items = members.iter().map(|(_, t)| self.default(t)).collect::<Vec<_>>();
+                format!("{class}({})", items.join(", "))
+            }
+            Type::List(_) => "[]".into(),
+            Type::Dict(_) => "{}".into(),
+            Type::Void | Type::Custom(_) | Type::Err => "None".into(),
+        }
+    }
+}
+
+/// Python name of each variable. Python has no block scope, so a declaration that shadows a variable of an enclosing
+/// block is renamed to keep the outer one unchanged.
+struct Names {
+    /// Variables of each block, the ones before `function` belong to the functions around the current one
+    scopes: Vec<FxHashMap<String, String>>,
+    function: usize,
+    /// Start of the scopes of the functions being translated around the current one
+    functions: Vec<usize>,
+    /// Every name declared in the program, which a renamed variable can't take
+    taken: FxHashSet<String>,
+}
+
+impl Names {
+    fn new(main: &Main) -> Names {
+        let mut declared = Declared(FxHashSet::default());
+        declared.visit_block(&main.exprs);
+        Names {
+            scopes: vec![FxHashMap::default()],
+            function: 0,
+            functions: Vec::new(),
+            taken: declared.0,
+        }
+    }
+
+    fn push(&mut self) {
+        self.scopes.push(FxHashMap::default());
+    }
+
+    fn pop(&mut self) {
+        self.scopes.pop();
+    }
+
+    /// Starts the scope of a function, which can't see the variables around it.
+    fn enter_fn(&mut self) {
+        self.functions.push(self.function);
+        self.function = self.scopes.len();
+        self.push();
+    }
+
+    fn leave_fn(&mut self) {
+        self.scopes.truncate(self.function);
+        self.function = self.functions.pop().unwrap_or_default();
+    }
+
+    fn get(&self, name: &str) -> String {
+        self.scopes[self.function..]
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).cloned())
+            .unwrap_or_else(|| ident(name))
+    }
+
+    /// Name of a variable declared in the current block.
+    fn declare(&mut self, name: &str) -> String {
+        let (current, enclosing) = self.scopes[self.function..].split_last().unwrap();
+        let python = match current.get(name) {
+            // Declared again in the same block, which replaces the previous variable
+            Some(python) => python.clone(),
+            None if enclosing.iter().any(|scope| scope.contains_key(name)) => (1..)
+                .map(|i| format!("{}_{i}", ident(name)))
+                .find(|renamed| !self.taken.contains(renamed))
+                .unwrap(),
+            None => ident(name),
+        };
+        self.taken.insert(python.clone());
+        self.scopes.last_mut().unwrap().insert(name.to_owned(), python.clone());
+        python
+    }
+}
+
+/// Collects the Python names of every variable, function and type a program declares.
+struct Declared(FxHashSet<String>);
+
+impl<'ast> Visitor<'ast> for Declared {
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        match &expr.kind {
+            ExprKind::Init { name, .. }
+            | ExprKind::Decl { name, .. }
+            | ExprKind::Typedef { name, .. }
+            | ExprKind::For { var: name, .. } => {
+                self.0.insert(ident(name));
+            }
+            ExprKind::Fn { name, args, .. } | ExprKind::NativeFn { name, args, .. } => {
+                self.0.insert(ident(name));
+                self.0.extend(args.iter().map(|(arg, _)| ident(arg)));
+            }
+            _ => {}
+        }
+        visit::walk_expr(self, expr)
+    }
+}
+
+fn ident(name: &str) -> String {
+    if RESERVED.contains(&name) {
+        format!("{name}_")
+    } else {
+        name.into()
+    }
+}
+
+/// Whether `exponent` is a literal that `**` raises ints to without turning them into floats.
+fn non_negative(exponent: &Value) -> bool {
+    matches!(exponent.kind, ValueKind::Int(i) if i >= 0)
+}
+
+fn tuple(items: Vec<String>) -> String {
+    if items.len() == 1 {
+        format!("({},)", items[0])
+    } else {
+        format!("({})", items.join(", "))
+    }
+}
+
+fn py_str(s: &str) -> String {
+    format!("\"{}\"", escape(s))
+}
+
+/// Polyglot strings have no escape sequences, so every special character is escaped.
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            '\0' => escaped.push_str("\\0"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,1102 @@
+use super::{native_code, splice, Backend, Capabilities, DictKeys, IntWidth, Output, Redeclarations, Types};
+use crate::diagnostic::{Diagnostic, ErrorCode};
+use crate::source::Span;
+use crate::tree::*;
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::process::Command;
+
+/// Rust 2021, a single file crate using `Vec`, derived structs and a `Dict` that keeps the order of its keys.
+pub struct Rust;
+
+impl Backend for Rust {
+    fn name(&self) -> &'static str {
+        "rust"
+    }
+
+    fn extension(&self) -> &'static str {
+        "rs"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            void_variables: false,
+            builtin_values: false,
+            ordered_lists: true,
+            ordered_dicts: true,
+            dict_keys: DictKeys::Any,
+            redeclarations: Redeclarations::Any,
+            int_width: IntWidth::Bits64,
+            unsigned_ints: true,
+            recursion_depth: None,
+            exceptions: false,
+            native_code: &["rust"],
+        }
+    }
+
+    fn emit(&self, main: &Main) -> Result<Output, Diagnostic> {
+        let mut emitter = Emitter::new(main);
+        emitter.indent = 1;
+        for expr in &main.exprs {
+            emitter.expr(expr);
+        }
+        if let Some(error) = emitter.error.take() {
+            return Err(error);
+        }
+        Ok(Output {
+            source: emitter.finish(),
+            files: Vec::new(),
+        })
+    }
+
+    fn commands(&self, source: &Path) -> Vec<Command> {
+        let program = source.with_extension(std::env::consts::EXE_EXTENSION);
+        let mut rustc = Command::new("rustc");
+        rustc.args(["--edition", "2021", "-O", "-o"]).arg(&program).arg(source);
+        vec![rustc, Command::new(program)]
+    }
+}
+
+/// Names that would clash with Rust keywords or the items used by the generated code.
+#[rustfmt::skip]
+const RESERVED: &[&str] = &[
+    "as", "async", "await", "box", "break", "const", "continue", "crate", "do", "dyn", "else", "enum", "extern",
+    "false", "final", "fn", "for", "if", "impl", "in", "let", "loop", "macro", "match", "mod", "move", "mut",
+    "override", "priv", "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "try",
+    "type", "typeof", "union", "unsafe", "unsized", "use", "virtual", "where", "while", "yield", "main", "bool",
+    "char", "f64", "i64", "str", "std", "String", "Vec", "Dict", "Show",
+];
+
+/// Runtime support, only the helpers used by the program are emitted.
+const HELPERS: &[(&str, &str)] = &[
+    (
+        "Show",
+        r#"trait Show {
+    fn show(&self, nested: bool) -> String;
+}
+
+impl Show for i64 {
+    fn show(&self, _: bool) -> String {
+        self.to_string()
+    }
+}
+
+impl Show for f64 {
+    fn show(&self, _: bool) -> String {
+        if self.is_nan() {
+            return "nan".into();
+        }
+        if self.is_infinite() {
+            return if *self > 0.0 { "inf" } else { "-inf" }.into();
+        }
+        let scientific = format!("{self:e}");
+        let (mantissa, exponent) = scientific.split_once('e').unwrap();
+        let exponent: i32 = exponent.parse().unwrap();
+        if !(-4..16).contains(&exponent) {
+            let sign = if exponent < 0 { '-' } else { '+' };
+            return format!("{mantissa}e{sign}{:02}", exponent.abs());
+        }
+        let digits = mantissa.trim_start_matches('-').replace('.', "").len() as i32;
+        format!("{:.*}", (digits - 1 - exponent).max(1) as usize, self)
+    }
+}
+
+impl Show for bool {
+    fn show(&self, _: bool) -> String {
+        self.to_string()
+    }
+}
+
+impl Show for char {
+    fn show(&self, nested: bool) -> String {
+        if nested { format!("\"{self}\"") } else { self.to_string() }
+    }
+}
+
+impl Show for String {
+    fn show(&self, nested: bool) -> String {
+        if nested { format!("\"{self}\"") } else { self.clone() }
+    }
+}
+
+impl Show for std::ops::Range<i64> {
+    fn show(&self, _: bool) -> String {
+        format!("{}..{}", self.start, self.end)
+    }
+}
+
+impl<T: Show> Show for Vec<T> {
+    fn show(&self, _: bool) -> String {
+        let items = self.iter().map(|v| v.show(true)).collect::<Vec<_>>();
+        format!("[{}]", items.join(", "))
+    }
+}
+
+fn _show<T: Show>(value: &T, nested: bool) -> String {
+    value.show(nested)
+}"#,
+    ),
+    (
+        "Dict",
+        r#"/// Dictionary that keeps its keys in the order they were inserted, like every other flavor.
+#[derive(Clone, Debug)]
+struct Dict<K, V>(Vec<(K, V)>);
+
+impl<K, V> Dict<K, V> {
+    fn new() -> Self {
+        Dict(Vec::new())
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.0.iter().map(|(k, v)| (k, v))
+    }
+}
+
+impl<K: PartialEq, V> Dict<K, V> {
+    fn get(&self, key: &K) -> Option<&V> {
+        self.0.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    fn insert(&mut self, key: K, value: V) {
+        match self.0.iter_mut().find(|(k, _)| *k == key) {
+            Some(entry) => entry.1 = value,
+            None => self.0.push((key, value)),
+        }
+    }
+}
+
+impl<K: PartialEq, V> FromIterator<(K, V)> for Dict<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(entries: I) -> Self {
+        let mut dict = Dict::new();
+        for (k, v) in entries {
+            dict.insert(k, v);
+        }
+        dict
+    }
+}
+
+impl<K: PartialEq, V, const N: usize> From<[(K, V); N]> for Dict<K, V> {
+    fn from(entries: [(K, V); N]) -> Self {
+        entries.into_iter().collect()
+    }
+}
+
+/// Dictionaries with the same entries are equal, whatever order they were inserted in.
+impl<K: PartialEq, V: PartialEq> PartialEq for Dict<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len() && self.iter().all(|(k, v)| other.get(k) == Some(v))
+    }
+}"#,
+    ),
+    (
+        "ShowDict",
+        r#"impl<K: Show, V: Show> Show for Dict<K, V> {
+    fn show(&self, _: bool) -> String {
+        let items = self.iter().map(|(k, v)| format!("{} -> {}", k.show(true), v.show(true))).collect::<Vec<_>>();
+        format!("[{}]", items.join(", "))
+    }
+}"#,
+    ),
+    (
+        "_fail",
+        r#"fn _fail(message: String) -> ! {
+    use std::io::Write;
+    let _ = std::io::stdout().flush();
+    eprintln!("{message}");
+    std::process::exit(1)
+}"#,
+    ),
+    (
+        "_index",
+        r#"fn _index<T: Clone>(items: &[T], index: i64, line: u32, text: &str) -> T {
+    match usize::try_from(index).ok().and_then(|i| items.get(i)) {
+        Some(item) => item.clone(),
+        None => _fail(format!("ERROR: Out-of-bounds in line {line}, \"{text}\".")),
+    }
+}"#,
+    ),
+    (
+        "_key",
+        r#"fn _key<K: PartialEq, V: Clone>(items: &Dict<K, V>, key: &K, line: u32, text: &str) -> V {
+    match items.get(key) {
+        Some(item) => item.clone(),
+        None => _fail(format!("ERROR: Key not found in line {line}, \"{text}\".")),
+    }
+}"#,
+    ),
+    (
+        "_remove",
+        r#"fn _remove<T: PartialEq>(items: &mut Vec<T>, removed: &[T]) {
+    for item in removed {
+        if let Some(i) = items.iter().position(|v| v == item) {
+            items.remove(i);
+        }
+    }
+}"#,
+    ),
+    (
+        "_remove_all",
+        r#"fn _remove_all<T: PartialEq>(items: &mut Vec<T>, removed: &[T]) {
+    items.retain(|v| !removed.contains(v));
+}"#,
+    ),
+    (
+        "_div",
+        r#"fn _div(a: i64, b: i64, line: u32, text: &str) -> i64 {
+    match b {
+        0 => _fail(format!("ERROR: Division by zero in line {line}, \"{text}\".")),
+        _ => a.wrapping_div(b),
+    }
+}"#,
+    ),
+    (
+        "_rem",
+        r#"fn _rem(a: i64, b: i64, line: u32, text: &str) -> i64 {
+    match b {
+        0 => _fail(format!("ERROR: Division by zero in line {line}, \"{text}\".")),
+        _ => a.wrapping_rem(b),
+    }
+}"#,
+    ),
+    (
+        "_pow",
+        r#"fn _pow(base: i64, exponent: i64) -> i64 {
+    match u32::try_from(exponent) {
+        Ok(exponent) => base.wrapping_pow(exponent),
+        Err(_) if exponent > 0 => base.wrapping_pow(u32::MAX),
+        Err(_) if base == 1 => 1,
+        Err(_) if base == -1 => if exponent % 2 == 0 { 1 } else { -1 },
+        Err(_) => 0,
+    }
+}"#,
+    ),
+    (
+        "_int",
+        r#"fn _int(value: &str) -> i64 {
+    match value.trim().parse() {
+        Ok(value) => value,
+        Err(_) => _fail(format!("ERROR: \"{value}\" can not be parsed into an int")),
+    }
+}"#,
+    ),
+    (
+        "_bool",
+        r#"fn _bool(value: &str) -> bool {
+    match value {
+        "true" => true,
+        "false" => false,
+        _ => _fail(format!("ERROR: \"{value}\" can not be parsed into a bool")),
+    }
+}"#,
+    ),
+    (
+        "_char",
+        r#"fn _char(value: &str) -> char {
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => c,
+        _ => _fail(format!("ERROR: \"{value}\" can not be parsed into a char")),
+    }
+}"#,
+    ),
+];
+
+struct Emitter<'a> {
+    main: &'a Main,
+    types: Types,
+    out: String,
+    indent: usize,
+    /// Struct generated for each struct type, typedefs keep their name
+    structs: Vec<(String, Vec<(String, Type)>)>,
+    helpers: BTreeSet<&'static str>,
+    /// Arity of the tuples that need to be shown
+    tuples: BTreeSet<usize>,
+    /// First construct that can't be translated
+    error: Option<Diagnostic>,
+}
+
+impl<'a> Emitter<'a> {
+    fn new(main: &'a Main) -> Emitter<'a> {
+        Emitter {
+            main,
+            types: Types::new(),
+            out: String::new(),
+            indent: 0,
+            structs: Vec::new(),
+            helpers: BTreeSet::new(),
+            tuples: BTreeSet::new(),
+            error: None,
+        }
+    }
+
+    /// Joins the imports, helpers and structs needed by the program with its body, which becomes `main`.
+    fn finish(mut self) -> String {
+        let body = std::mem::take(&mut self.out);
+        if self.helpers.contains("Show") {
+            // Every struct implements `Show`, so the implementations for its members are needed too
+            for (_, members) in self.structs.clone() {
+                for (_, t) in &members {
+                    self.show_type(t);
+                }
+            }
+        }
+        let mut header = String::new();
+        if let Some(file) = self.main.source_map.files().first() {
+            let name = file.path.file_name().unwrap_or_default().to_string_lossy();
+            header.push_str(&format!("// Generated by polyglot from {name}\n"));
+        }
+        // Polyglot programs follow their own naming conventions and may leave values unused
+        header.push_str("#![allow(dead_code, unused_variables, unused_mut, unused_assignments, unused_parens)]\n");
+        header.push_str("#![allow(non_snake_case, non_camel_case_types)]\n");
+
+        let mut sections = vec![header];
+        for (name, code) in HELPERS.iter().filter(|(name, _)| self.helpers.contains(name)) {
+            debug_assert!(name.starts_with('_') || name.starts_with("Show") || *name == "Dict");
+            sections.push(format!("{code}\n"));
+        }
+        if self.helpers.contains("Show") {
+            for arity in &self.tuples {
+                sections.push(show_tuple(*arity));
+            }
+        }
+        for (name, members) in &self.structs {
+            let mut definition = format!("#[derive(Clone, Debug, PartialEq)]\nstruct {name} {{\n");
+            for (member, r#type) in members {
+                definition.push_str(&format!("    {}: {},\n", ident(member), self.rust_type_of(r#type)));
+            }
+            definition.push_str("}\n");
+            if self.helpers.contains("Show") {
+                let fields = members
+                    .iter()
+                    .map(|(m, _)| format!("{m}: {{}}"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let values = members
+                    .iter()
+                    .map(|(m, _)| format!(", self.{}.show(true)", ident(m)))
+                    .collect::<String>();
+                definition.push_str(&format!(
+                    "\nimpl Show for {name} {{\n    fn show(&self, _: bool) -> String {{\n        format!(\"({fields})\"{values})\n    }}\n}}\n"
+                ));
+            }
+            sections.push(definition);
+        }
+        sections.push(format!("fn main() {{\n{body}}}\n"));
+        sections.join("\n")
+    }
+
+    fn unsupported(&mut self, span: Span, message: impl Into<String>) -> String {
+        if self.error.is_none() {
+            let message = message.into();
+            self.error = Some(
+                Diagnostic::error(
+                    ErrorCode::UnsupportedByTarget,
+                    format!("{message} are not supported by the Rust flavor"),
+                )
+                .with_primary(span, message),
+            );
+        }
+        "()".into()
+    }
+
+    fn line(&mut self, text: impl AsRef<str>) {
+        self.out.push_str(&"    ".repeat(self.indent));
+        self.out.push_str(text.as_ref());
+        self.out.push('\n');
+    }
+
+    fn helper(&mut self, name: &'static str) -> &'static str {
+        self.helpers.insert(name);
+        match name {
+            "_show" => {
+                self.helpers.insert("Show");
+            }
+            "_index" | "_key" | "_div" | "_rem" | "_int" | "_bool" | "_char" => {
+                self.helpers.insert("_fail");
+            }
+            _ => {}
+        }
+        name
+    }
+
+    fn block(&mut self, exprs: &[Expr], vars: Vec<(String, Type)>) {
+        self.indent += 1;
+        self.types.push();
+        for (name, r#type) in vars {
+            self.types.insert(name, r#type);
+        }
+        for expr in exprs {
+            self.expr(expr);
+        }
+        self.types.pop();
+        self.indent -= 1;
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Init { name, r#type, value } => {
+                if matches!(r#type, Type::Void) {
+                    self.unsupported(expr.span, "void variables");
+                    return;
+                }
+                let rust_type = self.rust_type(r#type);
+                let value = self.owned(value, r#type);
+                self.line(format!("let mut {}: {rust_type} = {value};", ident(name)));
+                self.types.insert(name, r#type.clone());
+            }
+            ExprKind::Decl { name, r#type } => {
+                let rust_type = self.rust_type(r#type);
+                let line = format!("let mut {}: {rust_type} = {};", ident(name), self.default(r#type));
+                self.line(line);
+                self.types.insert(name, r#type.clone());
+            }
+            ExprKind::Assig { name, value } => {
+                let line = self.assig(name, value);
+                self.line(line);
+            }
+            ExprKind::Typedef { name, r#type } => {
+                if let Type::Struct(members) = r#type {
+                    for (_, t) in members {
+                        self.rust_type(t);
+                    }
+                    let name = ident(name);
+                    if !self.structs.iter().any(|(n, _)| *n == name) {
+                        self.structs.push((name, members.clone()));
+                    }
+                }
+            }
+            ExprKind::If { cmp, exprs, elif } => {
+                let cmp = self.value(cmp);
+                self.line(format!("if {cmp} {{"));
+                self.block(exprs, Vec::new());
+                let mut next = elif.as_deref();
+                while let Some(expr) = next {
+                    match &expr.kind {
+                        ExprKind::Elif { cmp, exprs, elif } => {
+                            let cmp = self.value(cmp);
+                            self.line(format!("}} else if {cmp} {{"));
+                            self.block(exprs, Vec::new());
+                            next = elif.as_deref();
+                        }
+                        ExprKind::Else { exprs } => {
+                            self.line("} else {");
+                            self.block(exprs, Vec::new());
+                            next = None;
+                        }
+                        _ => unreachable!("if chains only contain elif and else"),
+                    }
+                }
+                self.line("}");
+            }
+            ExprKind::Elif { .. } | ExprKind::Else { .. } => unreachable!("emitted with their if"),
+            ExprKind::For { var, range, exprs } => {
+                let element = self.types.element_of(range);
+                // Iterates over a copy, so the body can modify the original
+                let range = match (range.r#type.clone(), &range.kind) {
+                    (Type::Range, ValueKind::Range((start, end))) => format!("{start}..{end}"),
+                    (Type::Range | Type::List(_), _) => format!("{}.clone()", self.operand(range)),
+                    _ => self.unsupported(range.span, "loops over values other than ranges and lists"),
+                };
+                self.line(format!("for mut {} in {range} {{", ident(var)));
+                self.block(exprs, vec![(var.clone(), element)]);
+                self.line("}");
+            }
+            ExprKind::While { cmp, exprs } => {
+                let cmp = self.value(cmp);
+                self.line(format!("while {cmp} {{"));
+                self.block(exprs, Vec::new());
+                self.line("}");
+            }
+            ExprKind::Fn {
+                name,
+                r#type,
+                args,
+                exprs,
+            } => {
+                self.signature(name, r#type, args);
+                self.block(exprs, args.clone());
+                // Polyglot functions can't return a value yet, so the result is always the default one
+                if !matches!(r#type, Type::Void) {
+                    let default = self.default(r#type);
+                    self.line(format!("    {default}"));
+                }
+                self.line("}");
+            }
+            ExprKind::Call { name, args } => {
+                let call = self.call(name, args);
+                self.line(format!("{call};"));
+            }
+            ExprKind::Native { sections } => self.native(sections, expr.span),
+            ExprKind::NativeFn {
+                name,
+                r#type,
+                args,
+                sections,
+            } => {
+                self.signature(name, r#type, args);
+                self.indent += 1;
+                self.native(sections, expr.span);
+                self.indent -= 1;
+                self.line("}");
+            }
+            ExprKind::Err => unreachable!("backends only receive valid programs"),
+        }
+    }
+
+    /// Emits the first line of a function definition.
+    fn signature(&mut self, name: &str, r#type: &Type, args: &[(String, Type)]) {
+        self.types.insert_fn(name, r#type.clone(), args);
+        let params = args
+            .iter()
+            .map(|(name, t)| format!("mut {}: {}", ident(name), self.rust_type(t)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let ret = match r#type {
+            Type::Void => String::new(),
+            t => format!(" -> {}", self.rust_type(t)),
+        };
+        self.line(format!("fn {}({params}){ret} {{", ident(name)));
+    }
+
+    fn native(&mut self, sections: &[NativeSection], span: Span) {
+        match native_code(sections, &["rust"], span) {
+            Ok(code) => splice(&mut self.out, code, self.indent),
+            Err(error) => {
+                self.error.get_or_insert(error);
+            }
+        }
+    }
+
+    /// Operations of a variable on itself are done in place when Rust allows it.
+    fn assig(&mut self, name: &str, value: &Value) -> String {
+        let var_t = self.types.get(name).cloned().unwrap_or(Type::Err);
+        let var = ident(name);
+        let is_self = |v: &Value| matches!(&v.kind, ValueKind::Var(v) if v == name);
+        match (&var_t, &value.kind) {
+            (Type::List(element), ValueKind::Op(op @ (Op::Add(v) | Op::Sub(v)))) if is_self(&v.0) => {
+                let (items, whole) = self.list_operand(&var_t, element, &v.1);
+                match (op, whole) {
+                    (Op::Add(_), true) => format!("{var}.extend({items});"),
+                    (Op::Add(_), false) => format!("{var}.push({items});"),
+                    (_, true) => format!("{}(&mut {var}, &{items});", self.helper("_remove")),
+                    (_, false) => format!("{}(&mut {var}, &[{items}]);", self.helper("_remove")),
+                }
+            }
+            (Type::List(element), ValueKind::Op(Op::ListRemoveAll(v))) if v.0 == name => {
+                let (items, whole) = self.list_operand(&var_t, element, &v.1);
+                let items = if whole { items } else { format!("[{items}]") };
+                format!("{}(&mut {var}, &{items});", self.helper("_remove_all"))
+            }
+            (Type::Str, ValueKind::Op(Op::Add(v))) if is_self(&v.0) => {
+                format!("{var}.push_str(&{});", self.operand(&v.1))
+            }
+            // Ints wrap and check their divisions, so only nums can use augmented assignment
+            (Type::Num, ValueKind::Op(op)) => {
+                let augmented = match op {
+                    Op::Add(v) => Some(("+", v)),
+                    Op::Sub(v) => Some(("-", v)),
+                    Op::Mul(v) => Some(("*", v)),
+                    Op::Div(v) => Some(("/", v)),
+                    Op::Mod(v) => Some(("%", v)),
+                    _ => None,
+                };
+                match augmented {
+                    Some((symbol, v)) if is_self(&v.0) => format!("{var} {symbol}= {};", self.numeric(&v.1, &var_t)),
+                    _ => format!("{var} = {};", self.numeric(value, &var_t)),
+                }
+            }
+            _ => format!("{var} = {};", self.owned(value, &var_t)),
+        }
+    }
+
+    /// `value` as an operand of a list operation, and whether it's a whole list or a single element.
+    fn list_operand(&mut self, list: &Type, element: &Type, value: &Value) -> (String, bool) {
+        if value.r#type == *list {
+            (self.owned(value, list), true)
+        } else {
+            (self.owned(value, element), false)
+        }
+    }
+
+    fn call(&mut self, name: &str, args: &[Value]) -> String {
+        if !self.types.is_builtin(name) {
+            let arg_types = self.types.get_fn(name).map(|f| f.args.clone()).unwrap_or_default();
+            let args = args
+                .iter()
+                .zip(arg_types.iter())
+                .map(|(a, t)| self.owned(a, t))
+                .collect::<Vec<_>>()
+                .join(", ");
+            return format!("{}({args})", ident(name));
+        }
+
+        let arg = &args[0];
+        match name {
+            "dbg" => {
+                let text = self.main.source_map.snippet(arg.span).unwrap_or_default();
+                let shown = self.show(arg, true);
+                format!("println!(\"{} = {{}}\", {shown})", format_escape(text))
+            }
+            _ => {
+                let (format, args) = match &arg.kind {
+                    ValueKind::Str(s) => self.interpolated(s),
+                    _ => ("{}".to_owned(), vec![self.show(arg, false)]),
+                };
+                let args = args.iter().map(|a| format!(", {a}")).collect::<String>();
+                format!("{name}!(\"{format}\"{args})")
+            }
+        }
+    }
+
+    /// Format string and arguments of a string literal with its `{var}` replaced.
+    fn interpolated(&mut self, s: &str) -> (String, Vec<String>) {
+        let mut format = String::new();
+        let mut args = Vec::new();
+        for (_, segment) in interpolation(s) {
+            match segment {
+                Segment::Text(text) => format.push_str(&format_escape(text)),
+                Segment::Var(var) => {
+                    let var = self.types.var(var, Default::default());
+                    format.push_str("{}");
+                    args.push(self.show(&var, false));
+                }
+            }
+        }
+        (format, args)
+    }
+
+    /// Expression printing `value` like every other flavor does.
+    fn show(&mut self, value: &Value, nested: bool) -> String {
+        match value.r#type.clone() {
+            Type::Int | Type::Char | Type::Str if !nested => self.value(value),
+            r#type => {
+                self.show_type(&r#type);
+                format!("{}(&{}, {nested})", self.helper("_show"), self.operand(value))
+            }
+        }
+    }
+
+    /// Registers the `Show` implementations needed by `type`.
+    fn show_type(&mut self, r#type: &Type) {
+        self.helpers.insert("Show");
+        match r#type {
+            Type::Tuple(types) => {
+                self.tuples.insert(types.len());
+                for t in types {
+                    self.show_type(t);
+                }
+            }
+            Type::Struct(members) => {
+                for (_, t) in members {
+                    self.show_type(t);
+                }
+            }
+            Type::List(element) => self.show_type(element),
+            Type::Dict(d) => {
+                self.helpers.insert("Dict");
+                self.helpers.insert("ShowDict");
+                self.show_type(&d.0);
+                self.show_type(&d.1);
+            }
+            _ => {}
+        }
+    }
+
+    fn value(&mut self, value: &Value) -> String {
+        match &value.kind {
+            ValueKind::Range((start, end)) => format!("({start}..{end})"),
+            ValueKind::Int(i) => i.to_string(),
+            ValueKind::Num(n) => format!("{n:?}"),
+            ValueKind::Bool(b) => b.to_string(),
+            ValueKind::Char(c) => format!("'{}'", c.escape_default()),
+            ValueKind::Str(s) => format!("String::from({})", rust_str(s)),
+            ValueKind::Tuple(_) | ValueKind::Struct(_) | ValueKind::List(_) | ValueKind::Dict(_) => {
+                let r#type = value.r#type.clone();
+                self.coerced(value, &r#type)
+            }
+            ValueKind::TupleAccess { name, access_mode, .. } => match access_mode {
+                TupleAccessMode::Member(member) => format!("{}.{}", ident(name), ident(member)),
+                TupleAccessMode::Index(index) => format!("{}.{index}", ident(name)),
+            },
+            ValueKind::ListAccess { name, access_mode, .. } => {
+                let container = self.types.get(name).cloned().unwrap_or(Type::Err);
+                let (helper, key) = match (access_mode, &container) {
+                    (ListAccessMode::Dict(key), Type::Dict(d)) => ("_key", format!("&{}", self.owned(key, &d.0))),
+                    (ListAccessMode::List(index) | ListAccessMode::Dict(index), _) => ("_index", self.value(index)),
+                };
+                let helper = self.helper(helper);
+                let line = self.main.source_map.line_col(value.span).0.line;
+                let text = self.main.source_map.snippet(value.span).unwrap_or(name);
+                format!("{helper}(&{}, {key}, {line}, {})", ident(name), rust_str(text))
+            }
+            ValueKind::Var(name) => ident(name),
+            ValueKind::Op(op) => self.op(op, &value.r#type, value.span),
+            ValueKind::Cmp(cmp) => self.cmp(cmp, value.span),
+            ValueKind::Parenthesis(p) => format!("({})", self.value(p)),
+            ValueKind::Conversion { r#type, value } => self.conversion(r#type, value),
+            ValueKind::Call { name, args } => {
+                if self.types.is_builtin(name) {
+                    return self.unsupported(value.span, "builtin calls used as values");
+                }
+                self.call(name, args)
+            }
+            ValueKind::RetExpr(r) => match *r {},
+            ValueKind::Err => unreachable!("backends only receive valid programs"),
+        }
+    }
+
+    /// Value used as an operand, wrapped in parenthesis so Polyglot's evaluation order is kept.
+    fn operand(&mut self, value: &Value) -> String {
+        let translated = self.value(value);
+        match &value.kind {
+            ValueKind::Op(_) | ValueKind::Cmp(_) => format!("({translated})"),
+            ValueKind::Int(i) if *i < 0 => format!("({translated})"),
+            ValueKind::Num(n) if *n < 0.0 => format!("({translated})"),
+            _ => translated,
+        }
+    }
+
+    /// Operand of a numeric operation of type `expected`, converting integers as Rust doesn't do it implicitly.
+    fn numeric(&mut self, value: &Value, expected: &Type) -> String {
+        let operand = self.operand(value);
+        match (expected, value.r#type.clone()) {
+            (Type::Num, Type::Int) => format!("({operand} as f64)"),
+            (Type::Int, Type::Num) => format!("({operand} as i64)"),
+            _ => operand,
+        }
+    }
+
+    fn op(&mut self, op: &Op, r#type: &Type, span: Span) -> String {
+        let binary = |e: &mut Self, v: &(Value, Value), symbol: &str| {
+            format!("{} {symbol} {}", e.numeric(&v.0, r#type), e.numeric(&v.1, r#type))
+        };
+        match op {
+            Op::Add(v) | Op::Sub(v) if matches!(r#type, Type::List(_)) => {
+                let Type::List(element) = &r#type else { unreachable!() };
+                let list = self.owned(&v.0, r#type);
+                let (items, whole) = self.list_operand(r#type, element, &v.1);
+                let operation = match (op, whole) {
+                    (Op::Add(_), true) => format!("l.extend({items})"),
+                    (Op::Add(_), false) => format!("l.push({items})"),
+                    (_, true) => format!("{}(&mut l, &{items})", self.helper("_remove")),
+                    (_, false) => format!("{}(&mut l, &[{items}])", self.helper("_remove")),
+                };
+                format!("{{ let mut l = {list}; {operation}; l }}")
+            }
+            Op::Add(v) if matches!(r#type, Type::Str) => {
+                format!("format!(\"{{}}{{}}\", {}, {})", self.operand(&v.0), self.operand(&v.1))
+            }
+            Op::ListRemoveAll(v) => {
+                let list = self.types.get(&v.0).cloned().unwrap_or(Type::Err);
+                let Type::List(element) = &list else {
+                    return self.unsupported(span, "removals from values other than lists");
+                };
+                let (items, whole) = self.list_operand(&list, element, &v.1);
+                let items = if whole { items } else { format!("[{items}]") };
+                let remove_all = self.helper("_remove_all");
+                format!("{{ let mut l = {}.clone(); {remove_all}(&mut l, &{items}); l }}", ident(&v.0))
+            }
+            // Ints wrap like in the interpreter, Rust's operators would panic or be rejected by `arithmetic_overflow`
+            Op::Add(v) | Op::Sub(v) | Op::Mul(v) if matches!(r#type, Type::Int) => {
+                let method = match op {
+                    Op::Add(_) => "wrapping_add",
+                    Op::Sub(_) => "wrapping_sub",
+                    _ => "wrapping_mul",
+                };
+                format!("i64::{method}({}, {})", self.value(&v.0), self.value(&v.1))
+            }
+            // Rust already truncates integer divisions towards zero, and the remainder takes the sign of the dividend
+            Op::Div(v) | Op::Mod(v) if matches!(r#type, Type::Int) => {
+                let helper = self.helper(if matches!(op, Op::Div(_)) { "_div" } else { "_rem" });
+                let line = self.main.source_map.line_col(span).0.line;
+                let text = self.main.source_map.snippet(span).unwrap_or_default();
+                format!("{helper}({}, {}, {line}, {})", self.value(&v.0), self.value(&v.1), rust_str(text))
+            }
+            Op::Add(v) => binary(self, v, "+"),
+            Op::Sub(v) => binary(self, v, "-"),
+            Op::Mul(v) => binary(self, v, "*"),
+            Op::Div(v) => binary(self, v, "/"),
+            Op::Mod(v) => binary(self, v, "%"),
+            Op::Pow(v) if matches!(r#type, Type::Int) => {
+                format!("{}({}, {})", self.helper("_pow"), self.value(&v.0), self.value(&v.1))
+            }
+            Op::Pow(v) => {
+                let (base, exponent) = (self.numeric(&v.0, &Type::Num), self.numeric(&v.1, &Type::Num));
+                format!("f64::powf({base}, {exponent})")
+            }
+        }
+    }
+
+    fn cmp(&mut self, cmp: &Cmp, span: Span) -> String {
+        let (symbol, v) = match cmp {
+            Cmp::Less(v) => ("<", v),
+            Cmp::Greater(v) => (">", v),
+            Cmp::LessEq(v) => ("<=", v),
+            Cmp::GreatEq(v) => (">=", v),
+            Cmp::Equal(v) => ("==", v),
+            Cmp::NotEq(v) => ("!=", v),
+            Cmp::Or(v) => ("||", v),
+            Cmp::And(v) => ("&&", v),
+            Cmp::Not(v) => return format!("!{}", self.operand(v)),
+            Cmp::Err => unreachable!("backends only receive valid programs"),
+        };
+        let types = (v.0.r#type.clone(), v.1.r#type.clone());
+        match (symbol, &types) {
+            ("<" | ">" | "<=" | ">=", (Type::Dict(_), _)) => {
+                self.unsupported(span, "ordered comparisons of dictionaries")
+            }
+            (_, (Type::Int, Type::Num) | (Type::Num, Type::Int)) => {
+                format!("{} {symbol} {}", self.numeric(&v.0, &Type::Num), self.numeric(&v.1, &Type::Num))
+            }
+            _ => format!("{} {symbol} {}", self.operand(&v.0), self.operand(&v.1)),
+        }
+    }
+
+    fn conversion(&mut self, into: &Type, value: &Value) -> String {
+        let from = value.r#type.clone();
+        let converted = self.operand(value);
+        match &from {
+            Type::List(element) => {
+                let element = self.convert(element, into, "(*v)");
+                format!("{converted}.iter().map(|v| {element}).collect::<Vec<_>>()")
+            }
+            Type::Dict(d) => {
+                let (key, value) = (self.convert(&d.0, into, "(*k)"), self.convert(&d.1, into, "(*v)"));
+                format!("{converted}.iter().map(|(k, v)| ({key}, {value})).collect::<{}<_, _>>()", self.helper("Dict"))
+            }
+            _ => self.convert(&from, into, &converted),
+        }
+    }
+
+    /// Conversion of the primitive expression `value`, exiting like every other flavor when it fails.
+    fn convert(&mut self, from: &Type, into: &Type, value: &str) -> String {
+        let shown = |e: &mut Self| {
+            e.show_type(from);
+            format!("{}(&{value}, false)", e.helper("_show"))
+        };
+        match (into, from) {
+            (Type::Str, Type::Str) => format!("{value}.clone()"),
+            (into, from) if std::mem::discriminant(into) == std::mem::discriminant(from) => value.into(),
+            (Type::Int, Type::Num | Type::Bool) => format!("({value} as i64)"),
+            (Type::Int, Type::Str) => format!("{}(&{value})", self.helper("_int")),
+            (Type::Int, _) => format!("{}(&{value}.to_string())", self.helper("_int")),
+            (Type::Bool, Type::Int) => format!("({value} != 0)"),
+            (Type::Bool, Type::Num) => format!("({value} != 0.0)"),
+            (Type::Bool, Type::Str) => format!("{}(&{value})", self.helper("_bool")),
+            (Type::Bool, _) => format!("{}(&{value}.to_string())", self.helper("_bool")),
+            (Type::Char, Type::Str) => format!("{}(&{value})", self.helper("_char")),
+            (Type::Char, _) => {
+                let shown = shown(self);
+                format!("{}(&{shown})", self.helper("_char"))
+            }
+            _ => shown(self),
+        }
+    }
+
+    /// Translates `value`, building the literals with the type `expected` requires.
+    fn coerced(&mut self, value: &Value, expected: &Type) -> String {
+        let found = value.r#type.clone();
+        match (expected, &found, &value.kind) {
+            (Type::Struct(members), _, ValueKind::Tuple(items)) => {
+                let name = self.struct_of(members);
+                let fields = items
+                    .iter()
+                    .zip(members)
+                    .map(|(v, (m, t))| format!("{}: {}", ident(m), self.owned(v, t)))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("{name} {{ {fields} }}")
+            }
+            (Type::Struct(members), _, ValueKind::Struct(items)) => {
+                let name = self.struct_of(members);
+                let fields = items
+                    .iter()
+                    .map(|(member, v)| {
+                        let t = members
+                            .iter()
+                            .find(|(m, _)| m == member)
+                            .map_or(Type::Err, |(_, t)| t.clone());
+                        format!("{}: {}", ident(member), self.owned(v, &t))
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("{name} {{ {fields} }}")
+            }
+            (Type::Struct(members), Type::Tuple(_), _) => {
+                let name = self.struct_of(members);
+                let fields = members
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (m, _))| format!("{}: t.{i}", ident(m)))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("{{ let t = {}; {name} {{ {fields} }} }}", self.owned(value, &found))
+            }
+            (Type::Tuple(types), _, ValueKind::Tuple(items)) => {
+                let items = items
+                    .iter()
+                    .zip(types)
+                    .map(|(v, t)| self.owned(v, t))
+                    .collect::<Vec<_>>();
+                if items.len() == 1 {
+                    format!("({},)", items[0])
+                } else {
+                    format!("({})", items.join(", "))
+                }
+            }
+            (Type::List(t), _, ValueKind::List(items)) => {
+                let items = items.iter().map(|v| self.owned(v, t)).collect::<Vec<_>>();
+                format!("vec![{}]", items.join(", "))
+            }
+            (Type::Dict(d), _, ValueKind::Dict(pairs)) => {
+                self.rust_type(expected);
+                let pairs = pairs
+                    .iter()
+                    .map(|(k, v)| format!("({}, {})", self.owned(k, &d.0), self.owned(v, &d.1)))
+                    .collect::<Vec<_>>();
+                format!("Dict::from([{}])", pairs.join(", "))
+            }
+            (Type::Num, Type::Int, _) | (Type::Int, Type::Num, _) => self.numeric(value, expected),
+            _ => self.value(value),
+        }
+    }
+
+    /// Like [`Emitter::coerced`], but cloning values stored somewhere else, as everything has value semantics in
+    /// Polyglot.
+    fn owned(&mut self, value: &Value, expected: &Type) -> String {
+        let coerced = self.coerced(value, expected);
+        let place = matches!(value.kind, ValueKind::Var(_) | ValueKind::TupleAccess { .. });
+        match expected {
+            Type::Int | Type::Num | Type::Bool | Type::Char => coerced,
+            _ if place => format!("{coerced}.clone()"),
+            _ => coerced,
+        }
+    }
+
+    /// Name of the struct with `members`, creating one if it doesn't exist.
+    fn struct_of(&mut self, members: &[(String, Type)]) -> String {
+        if let Some((name, _)) = self.structs.iter().find(|(_, m)| m == members) {
+            return name.clone();
+        }
+        let name = format!("Struct{}", self.structs.len());
+        self.structs.push((name.clone(), members.to_vec()));
+        for (_, t) in members {
+            self.rust_type(t);
+        }
+        name
+    }
+
+    /// Rust type of `type`, registering the structs it needs.
+    fn rust_type(&mut self, r#type: &Type) -> String {
+        match r#type {
+            Type::Struct(members) => self.struct_of(members),
+            Type::Tuple(t) => {
+                let items = t.iter().map(|t| self.rust_type(t)).collect::<Vec<_>>();
+                if items.len() == 1 {
+                    format!("({},)", items[0])
+                } else {
+                    format!("({})", items.join(", "))
+                }
+            }
+            Type::List(l) => format!("Vec<{}>", self.rust_type(l)),
+            Type::Dict(d) => {
+                self.helper("Dict");
+                format!("Dict<{}, {}>", self.rust_type(&d.0), self.rust_type(&d.1))
+            }
+            t => self.rust_type_of(t),
+        }
+    }
+
+    /// Rust type of an already registered type.
+    fn rust_type_of(&self, r#type: &Type) -> String {
+        match r#type {
+            Type::Range => "std::ops::Range<i64>".into(),
+            Type::Int => "i64".into(),
+            Type::Num => "f64".into(),
+            Type::Bool => "bool".into(),
+            Type::Char => "char".into(),
+            Type::Str => "String".into(),
+            Type::Tuple(t) => {
+                let items = t.iter().map(|t| self.rust_type_of(t)).collect::<Vec<_>>();
+                if items.len() == 1 {
+                    format!("({},)", items[0])
+                } else {
+                    format!("({})", items.join(", "))
+                }
+            }
+            Type::Struct(members) => self
+                .structs
+                .iter()
+                .find(|(_, m)| m == members)
+                .map_or_else(|| "()".into(), |(name, _)| name.clone()),
+            Type::List(l) => format!("Vec<{}>", self.rust_type_of(l)),
+            Type::Dict(d) => format!("Dict<{}, {}>", self.rust_type_of(&d.0), self.rust_type_of(&d.1)),
+            Type::Void | Type::Custom(_) | Type::Err => "()".into(),
+        }
+    }
+
+    /// Value of a variable declared without initialization.
+    fn default(&mut self, r#type: &Type) -> String {
+        match r#type {
+            Type::Range => "0..0".into(),
+            Type::Int => "0".into(),
+            Type::Num => "0.0".into(),
+            Type::Bool => "false".into(),
+            Type::Char => "'\\0'".into(),
+            Type::Str => "String::new()".into(),
+            Type::Tuple(t) => {
+                let items = t.iter().map(|t| self.default(t)).collect::<Vec<_>>();
+                if items.len() == 1 {
+                    format!("({},)", items[0])
+                } else {
+                    format!("({})", items.join(", "))
+                }
+            }
+            Type::Struct(members) => {
+                let name = self.struct_of(members);
+                let fields = members
+                    .iter()
+                    .map(|(m, t)| format!("{}: {}", ident(m), self.default(t)))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("{name} {{ {fields} }}")
+            }
+            Type::List(_) => "Vec::new()".into(),
+            Type::Dict(_) => "Dict::new()".into(),
+            Type::Void | Type::Custom(_) | Type::Err => "()".into(),
+        }
+    }
+}
+
+/// `Show` for tuples with `arity` elements.
+fn show_tuple(arity: usize) -> String {
+    let generics = (0..arity).map(|i| format!("T{i}: Show")).collect::<Vec<_>>().join(", ");
+    let names = (0..arity).map(|i| format!("T{i}")).collect::<Vec<_>>();
+    let tuple = if arity == 1 {
+        format!("({},)", names[0])
+    } else {
+        format!("({})", names.join(", "))
+    };
+    let placeholders = vec!["{}"; arity].join(", ");
+    let values = (0..arity).map(|i| format!(", self.{i}.show(true)")).collect::<String>();
+    format!(
+        "impl<{generics}> Show for {tuple} {{\n    fn show(&self, _: bool) -> String {{\n        format!(\"({placeholders})\"{values})\n    }}\n}}\n"
+    )
+}
+
+fn ident(name: &str) -> String {
+    if RESERVED.contains(&name) {
+        format!("{name}_")
+    } else {
+        name.into()
+    }
+}
+
+fn rust_str(s: &str) -> String {
+    format!("\"{}\"", s.escape_default())
+}
+
+/// Text of a format string, where braces must be doubled.
+fn format_escape(s: &str) -> String {
+    s.replace('{', "{{").replace('}', "}}").escape_default().to_string()
+}
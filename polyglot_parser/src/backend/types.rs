@@ -0,0 +1,67 @@
+use crate::source::Span;
+use crate::tree::*;
+use rustc_hash::FxHashMap;
+
+/// Types of the variables and functions visible while a backend walks the tree.
+///
+/// The checker already validated the program, so lookups that fail just result in [`Type::Err`].
+#[derive(Debug, Default)]
+pub struct Types {
+    scopes: Vec<FxHashMap<String, Type>>,
+    fns: FxHashMap<String, Fn>,
+}
+
+impl Types {
+    pub fn new() -> Types {
+        Types {
+            scopes: vec![FxHashMap::default()],
+            fns: FxHashMap::default(),
+        }
+    }
+
+    pub fn push(&mut self) {
+        self.scopes.push(FxHashMap::default())
+    }
+
+    pub fn pop(&mut self) {
+        self.scopes.pop();
+    }
+
+    pub fn insert(&mut self, name: impl Into<String>, r#type: Type) {
+        self.scopes.last_mut().unwrap().insert(name.into(), r#type);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Type> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    pub fn insert_fn(&mut self, name: impl Into<String>, r#type: Type, args: &[(String, Type)]) {
+        let args = args.iter().map(|(_, t)| t.clone()).collect();
+        self.fns.insert(name.into(), Fn { r#type, args });
+    }
+
+    /// User defined function, builtins are not included.
+    pub fn get_fn(&self, name: &str) -> Option<&Fn> {
+        self.fns.get(name)
+    }
+
+    /// Whether a call to `name` refers to a builtin and not to a function with the same name.
+    pub fn is_builtin(&self, name: &str) -> bool {
+        BUILTINS.contains(&name) && !self.fns.contains_key(name)
+    }
+
+    /// Type of the element a `for` loop binds when iterating over `range`.
+    pub fn element_of(&self, range: &Value) -> Type {
+        match range.r#type.clone() {
+            Type::Range => Type::Int,
+            Type::List(l) => *l,
+            t => t,
+        }
+    }
+
+    /// Reference to the variable `name`, typed like it was declared.
+    pub fn var(&self, name: &str, span: Span) -> Value {
+        let r#type = self.get(name).cloned().unwrap_or(Type::Err);
+        Value::new(ValueKind::Var(name.into()), span, r#type)
+    }
+}
@@ -0,0 +1,41 @@
+use super::{Backend, Capabilities, DictKeys, IntWidth, Output, Redeclarations};
+use crate::diagnostic::Diagnostic;
+use crate::serialize::{self, Format};
+use crate::tree::Main;
+
+/// Serializes the checked tree, useful for debugging and for external tools.
+pub struct Yaml;
+
+impl Backend for Yaml {
+    fn name(&self) -> &'static str {
+        "yaml"
+    }
+
+    fn extension(&self) -> &'static str {
+        "yml"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            void_variables: true,
+            builtin_values: true,
+            ordered_lists: true,
+            ordered_dicts: true,
+            dict_keys: DictKeys::Any,
+            redeclarations: Redeclarations::Any,
+            // The tree keeps the program as it is, its ints behave like the interpreter's
+            int_width: IntWidth::Bits64,
+            unsigned_ints: true,
+            recursion_depth: None,
+            exceptions: false,
+            native_code: &[],
+        }
+    }
+
+    fn emit(&self, main: &Main) -> Result<Output, Diagnostic> {
+        Ok(Output {
+            source: String::from_utf8(serialize::write(Format::Yaml, main)).unwrap(),
+            files: Vec::new(),
+        })
+    }
+}
@@ -0,0 +1,568 @@
+use crate::source::{LineCol, SourceMap, Span};
+use serde::Serialize;
+use serde_json::json;
+use show_my_errors::{AnnotationList, Stylesheet};
+use std::io::{IsTerminal, Write};
+use std::ops::Range;
+
+/// How diagnostics are rendered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ErrorFormat {
+    /// Colored, rustc-like snippets on stderr
+    Human,
+    /// One JSON object per diagnostic and line on stderr
+    Json,
+    /// A single SARIF 2.1.0 log on stderr
+    Sarif,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Note,
+    Warning,
+    Error,
+}
+
+/// Every diagnostic the checker can produce, each one with a stable code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    SyntaxError,
+    WrongAssignmentType,
+    UndeclaredVariable,
+    NotAList,
+    WrongElementType,
+    DuplicateFunction,
+    UndeclaredFunction,
+    WrongArgumentType,
+    InvalidAccess,
+    NegativeIndex,
+    ListAccessedAsDict,
+    WrongAccessType,
+    MismatchedOperands,
+    ComparingCompound,
+    ComparingVoid,
+    MismatchedComparison,
+    WrongNegationType,
+    UndeclaredType,
+    OperationNotPermitted,
+    TupleAccessedByMember,
+    UndeclaredMember,
+    StructAccessedByIndex,
+    IndexOutOfBounds,
+    MissingTypeAnnotation,
+    InvalidConversion,
+    UnsupportedByTarget,
+    InvalidNativeSection,
+    DivisionByZero,
+    IntegerOverflow,
+    ConstantCondition,
+    UnsupportedModule,
+    InvalidOperandType,
+    DivergesInTarget,
+    WrongConditionType,
+    NotIterable,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    pub primary: bool,
+}
+
+/// How confident a suggestion is, mirrors rustc's applicability levels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Applicability {
+    /// The edits are correct and can be applied by `--fix`
+    MachineApplicable,
+    /// The edits probably fix the error, but may change the meaning of the program
+    MaybeIncorrect,
+    /// The edits contain placeholders the user has to fill in
+    HasPlaceholders,
+    /// The suggestion carries no edits
+    Unspecified,
+}
+
+/// Replaces the text at `span` with `replacement`, an empty span inserts it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextEdit {
+    pub span: Span,
+    pub replacement: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    pub message: String,
+    pub edits: Vec<TextEdit>,
+    pub applicability: Applicability,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: ErrorCode,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub suggestions: Vec<Suggestion>,
+}
+
+// IMPLS
+impl ErrorCode {
+    pub const ALL: [ErrorCode; 35] = [
+        ErrorCode::SyntaxError,
+        ErrorCode::WrongAssignmentType,
+        ErrorCode::UndeclaredVariable,
+        ErrorCode::NotAList,
+        ErrorCode::WrongElementType,
+        ErrorCode::DuplicateFunction,
+        ErrorCode::UndeclaredFunction,
+        ErrorCode::WrongArgumentType,
+        ErrorCode::InvalidAccess,
+        ErrorCode::NegativeIndex,
+        ErrorCode::ListAccessedAsDict,
+        ErrorCode::WrongAccessType,
+        ErrorCode::MismatchedOperands,
+        ErrorCode::ComparingCompound,
+        ErrorCode::ComparingVoid,
+        ErrorCode::MismatchedComparison,
+        ErrorCode::WrongNegationType,
+        ErrorCode::UndeclaredType,
+        ErrorCode::OperationNotPermitted,
+        ErrorCode::TupleAccessedByMember,
+        ErrorCode::UndeclaredMember,
+        ErrorCode::StructAccessedByIndex,
+        ErrorCode::IndexOutOfBounds,
+        ErrorCode::MissingTypeAnnotation,
+        ErrorCode::InvalidConversion,
+        ErrorCode::UnsupportedByTarget,
+        ErrorCode::InvalidNativeSection,
+        ErrorCode::DivisionByZero,
+        ErrorCode::IntegerOverflow,
+        ErrorCode::ConstantCondition,
+        ErrorCode::UnsupportedModule,
+        ErrorCode::InvalidOperandType,
+        ErrorCode::DivergesInTarget,
+        ErrorCode::WrongConditionType,
+        ErrorCode::NotIterable,
+    ];
+
+    /// Stable identifier, e.g. `E0002`.
+    pub fn as_str(&self) -> String {
+        let index = Self::ALL.iter().position(|c| c == self).unwrap();
+        format!("E{:04}", index + 1)
+    }
+
+    /// Short description used by SARIF rules.
+    pub fn description(&self) -> &'static str {
+        match self {
+            ErrorCode::SyntaxError => "the source could not be parsed",
+            ErrorCode::WrongAssignmentType => "value type does not match the variable type",
+            ErrorCode::UndeclaredVariable => "use of a variable that was not declared",
+            ErrorCode::NotAList => "variable is not a list or dictionary",
+            ErrorCode::WrongElementType => "element type does not match the list type",
+            ErrorCode::DuplicateFunction => "function declared more than once",
+            ErrorCode::UndeclaredFunction => "call to a function that was not declared",
+            ErrorCode::WrongArgumentType => "argument type does not match the parameter type",
+            ErrorCode::InvalidAccess => "access to a variable that was not declared",
+            ErrorCode::NegativeIndex => "list accessed with a negative index",
+            ErrorCode::ListAccessedAsDict => "list accessed with a non integer key",
+            ErrorCode::WrongAccessType => "dictionary accessed with a key of the wrong type",
+            ErrorCode::MismatchedOperands => "operation between values of different types",
+            ErrorCode::ComparingCompound => "tuples and structs cannot be compared",
+            ErrorCode::ComparingVoid => "void values cannot be compared",
+            ErrorCode::MismatchedComparison => "comparison between values of different types",
+            ErrorCode::WrongNegationType => "negation of a non boolean value",
+            ErrorCode::UndeclaredType => "use of a type that was not declared",
+            ErrorCode::OperationNotPermitted => "operation only allowed as a statement",
+            ErrorCode::TupleAccessedByMember => "tuple accessed by member name",
+            ErrorCode::UndeclaredMember => "struct member does not exist",
+            ErrorCode::StructAccessedByIndex => "struct accessed by index",
+            ErrorCode::IndexOutOfBounds => "tuple index out of bounds",
+            ErrorCode::MissingTypeAnnotation => "variable declared without type or value",
+            ErrorCode::InvalidConversion => "conversion between incompatible types",
+            ErrorCode::UnsupportedByTarget => "construct the selected target cannot express",
+            ErrorCode::InvalidNativeSection => "native code for an unknown or repeated target",
+            ErrorCode::DivisionByZero => "division of a constant by zero",
+            ErrorCode::IntegerOverflow => "constant operation that overflows an int",
+            ErrorCode::ConstantCondition => "condition that is always true or always false",
+            ErrorCode::UnsupportedModule => "use of a module, which are not implemented yet",
+            ErrorCode::InvalidOperandType => "arithmetic operator applied to values that don't support it",
+            ErrorCode::DivergesInTarget => "construct the selected target runs differently than the interpreter",
+            ErrorCode::WrongConditionType => "condition that is not a boolean value",
+            ErrorCode::NotIterable => "loop over a value that is not a range or list",
+        }
+    }
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: ErrorCode, message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            severity,
+            code,
+            message: message.into(),
+            labels: Vec::new(),
+            suggestions: Vec::new(),
+        }
+    }
+
+    pub fn error(code: ErrorCode, message: impl Into<String>) -> Diagnostic {
+        Diagnostic::new(Severity::Error, code, message)
+    }
+
+    pub fn warning(code: ErrorCode, message: impl Into<String>) -> Diagnostic {
+        Diagnostic::new(Severity::Warning, code, message)
+    }
+
+    /// Adds the label pointing at the cause of the diagnostic.
+    pub fn with_primary(mut self, span: Span, message: impl Into<String>) -> Diagnostic {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            primary: true,
+        });
+        self
+    }
+
+    pub fn with_secondary(mut self, span: Span, message: impl Into<String>) -> Diagnostic {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            primary: false,
+        });
+        self
+    }
+
+    pub fn with_suggestion(mut self, message: impl Into<String>) -> Diagnostic {
+        self.suggestions.push(Suggestion {
+            message: message.into(),
+            edits: Vec::new(),
+            applicability: Applicability::Unspecified,
+        });
+        self
+    }
+
+    /// Adds a suggestion carrying the edits that fix the diagnostic.
+    pub fn with_fix(
+        mut self,
+        message: impl Into<String>,
+        edits: Vec<TextEdit>,
+        applicability: Applicability,
+    ) -> Diagnostic {
+        self.suggestions.push(Suggestion {
+            message: message.into(),
+            edits,
+            applicability,
+        });
+        self
+    }
+
+    pub fn primary_span(&self) -> Span {
+        self.labels
+            .iter()
+            .find(|l| l.primary)
+            .or_else(|| self.labels.first())
+            .map_or_else(Span::default, |l| l.span)
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Note => write!(f, "note"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// Renders `diagnostics` in the requested format, resolving their spans with `sources`.
+///
+/// Every format is written to stderr, stdout is left to what commands print. Errors past `error_limit` are left
+/// out, the human format ends with a summary of everything found.
+pub fn emit(
+    format: ErrorFormat,
+    sources: &SourceMap,
+    diagnostics: &[Diagnostic],
+    error_limit: Option<usize>,
+) -> std::io::Result<()> {
+    let shown = limit_errors(diagnostics, error_limit);
+    match format {
+        ErrorFormat::Human => {
+            let colored = std::io::stderr().is_terminal();
+            let mut out = std::io::stderr();
+            emit_human(sources, &shown, colored, &mut out)?;
+            let hidden = diagnostics.len() - shown.len();
+            if hidden > 0 {
+                writeln!(out, "note: error limit reached, {hidden} more {} not shown", plural(hidden, "error"))?;
+            }
+            if let Some(summary) = summary(diagnostics) {
+                writeln!(out, "{summary}")?;
+            }
+            Ok(())
+        }
+        ErrorFormat::Json => emit_json(sources, &shown, &mut std::io::stderr()),
+        ErrorFormat::Sarif => emit_sarif(sources, &shown, &mut std::io::stderr()),
+    }
+}
+
+/// Keeps every warning and note but only the first `limit` errors.
+pub fn limit_errors(diagnostics: &[Diagnostic], limit: Option<usize>) -> Vec<Diagnostic> {
+    let mut errors = 0;
+    diagnostics
+        .iter()
+        .filter(|d| {
+            errors += d.is_error() as usize;
+            !d.is_error() || limit.is_none_or(|limit| errors <= limit)
+        })
+        .cloned()
+        .collect()
+}
+
+/// Closing line of the human output, e.g. "error: aborting due to 7 errors; 2 warnings emitted".
+pub fn summary(diagnostics: &[Diagnostic]) -> Option<String> {
+    let errors = diagnostics.iter().filter(|d| d.is_error()).count();
+    let warnings = diagnostics.iter().filter(|d| d.severity == Severity::Warning).count();
+    let emitted = format!("{warnings} {} emitted", plural(warnings, "warning"));
+    match (errors, warnings) {
+        (0, 0) => None,
+        (0, _) => Some(format!("warning: {emitted}")),
+        (_, 0) => Some(format!("error: aborting due to {errors} {}", plural(errors, "error"))),
+        _ => Some(format!("error: aborting due to {errors} {}; {emitted}", plural(errors, "error"))),
+    }
+}
+
+fn plural(n: usize, word: &str) -> String {
+    if n == 1 {
+        word.into()
+    } else {
+        format!("{word}s")
+    }
+}
+
+pub fn emit_human(
+    sources: &SourceMap,
+    diagnostics: &[Diagnostic],
+    colored: bool,
+    out: &mut impl Write,
+) -> std::io::Result<()> {
+    for diagnostic in diagnostics {
+        let primary = diagnostic.primary_span();
+        let file = sources.file(primary.file_id);
+        let mut list = AnnotationList::new(file.path.to_string_lossy(), &file.contents);
+        // Trees read back with `--from-ast` don't keep the contents of their files, only where their lines start
+        let loaded = !file.contents.is_empty();
+        let mut shown = loaded;
+        for label in diagnostic.labels.iter().filter(|l| loaded && l.span.file_id == primary.file_id) {
+            let range = single_line(&file.contents, label.span);
+            let header = label.primary.then(|| diagnostic.message.clone());
+            let added = match (label.primary, diagnostic.severity) {
+                (true, Severity::Error) => list.error(range, header, label.message.as_str()),
+                (true, Severity::Warning) => list.warning(range, header, label.message.as_str()),
+                _ => list.info(range, header, label.message.as_str()),
+            };
+            shown = shown && added.is_ok();
+        }
+
+        if shown && !diagnostic.labels.is_empty() {
+            let rendered = if colored {
+                list.to_ansi_string(&Stylesheet::colored())?
+            } else {
+                list.to_string()?
+            };
+            write!(out, "{rendered}")?;
+        } else {
+            let (LineCol { line, column }, _) = sources.line_col(primary);
+            writeln!(out, "{}: {}", diagnostic.severity, diagnostic.message)?;
+            writeln!(out, "  --> {}:{line}:{column}", file.path.display())?;
+            for label in &diagnostic.labels {
+                writeln!(out, "   = {}", label.message)?;
+            }
+        }
+        for suggestion in &diagnostic.suggestions {
+            match preview(&file.contents, &suggestion.edits) {
+                Some(line) => writeln!(out, "   = help: {}: `{line}`", suggestion.message)?,
+                None => writeln!(out, "   = help: {}", suggestion.message)?,
+            }
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+pub fn emit_json(sources: &SourceMap, diagnostics: &[Diagnostic], out: &mut impl Write) -> std::io::Result<()> {
+    for diagnostic in diagnostics {
+        let span = diagnostic.primary_span();
+        let (start, end) = sources.line_col(span);
+        let object = json!({
+            "file": sources.path(span.file_id).to_string_lossy(),
+            "severity": diagnostic.severity,
+            "code": diagnostic.code.as_str(),
+            "message": diagnostic.message,
+            "range": { "start": span.start, "end": span.end },
+            "start": start,
+            "end": end,
+            "labels": diagnostic.labels.iter().map(|label| {
+                let (start, end) = sources.line_col(label.span);
+                json!({
+                    "file": sources.path(label.span.file_id).to_string_lossy(),
+                    "range": { "start": label.span.start, "end": label.span.end },
+                    "start": start,
+                    "end": end,
+                    "message": label.message,
+                    "primary": label.primary,
+                })
+            }).collect::<Vec<_>>(),
+            "suggestions": diagnostic.suggestions.iter().map(|s| json!({
+                "message": s.message,
+                "applicability": s.applicability,
+                "edits": s.edits.iter().map(|edit| {
+                    let (start, end) = sources.line_col(edit.span);
+                    json!({
+                        "range": { "start": edit.span.start, "end": edit.span.end },
+                        "start": start,
+                        "end": end,
+                        "replacement": edit.replacement,
+                    })
+                }).collect::<Vec<_>>(),
+            })).collect::<Vec<_>>(),
+        });
+        writeln!(out, "{object}")?;
+    }
+    Ok(())
+}
+
+pub fn emit_sarif(sources: &SourceMap, diagnostics: &[Diagnostic], out: &mut impl Write) -> std::io::Result<()> {
+    let location = |span: Span, message: Option<&str>| {
+        let (start, end) = sources.line_col(span);
+        let mut location = json!({
+            "physicalLocation": {
+                "artifactLocation": { "uri": sources.path(span.file_id).to_string_lossy() },
+                "region": {
+                    "startLine": start.line,
+                    "startColumn": start.column,
+                    "endLine": end.line,
+                    "endColumn": end.column,
+                    "byteOffset": span.start,
+                    "byteLength": span.end - span.start,
+                },
+            }
+        });
+        if let Some(message) = message {
+            location["message"] = json!({ "text": message });
+        }
+        location
+    };
+
+    let rules = ErrorCode::ALL
+        .iter()
+        .map(|code| {
+            json!({
+                "id": code.as_str(),
+                "name": format!("{code:?}"),
+                "shortDescription": { "text": code.description() },
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let results = diagnostics
+        .iter()
+        .map(|diagnostic| {
+            let level = match diagnostic.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+                Severity::Note => "note",
+            };
+            let mut text = diagnostic.message.clone();
+            if let Some(primary) = diagnostic.labels.iter().find(|l| l.primary) {
+                text = format!("{text}: {}", primary.message);
+            }
+            json!({
+                "ruleId": diagnostic.code.as_str(),
+                "ruleIndex": ErrorCode::ALL.iter().position(|c| *c == diagnostic.code),
+                "level": level,
+                "message": { "text": text },
+                "locations": [location(diagnostic.primary_span(), None)],
+                "relatedLocations": diagnostic
+                    .labels
+                    .iter()
+                    .filter(|l| !l.primary)
+                    .map(|l| location(l.span, Some(&l.message)))
+                    .collect::<Vec<_>>(),
+                "fixes": diagnostic
+                    .suggestions
+                    .iter()
+                    .filter(|s| !s.edits.is_empty())
+                    .map(|s| json!({
+                        "description": { "text": s.message },
+                        "artifactChanges": s.edits.iter().map(|edit| json!({
+                            "artifactLocation": { "uri": sources.path(edit.span.file_id).to_string_lossy() },
+                            "replacements": [{
+                                "deletedRegion": {
+                                    "byteOffset": edit.span.start,
+                                    "byteLength": edit.span.end - edit.span.start,
+                                },
+                                "insertedContent": { "text": edit.replacement },
+                            }],
+                        })).collect::<Vec<_>>(),
+                    }))
+                    .collect::<Vec<_>>(),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let log = json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules,
+                }
+            },
+            "columnKind": "unicodeCodePoints",
+            "results": results,
+        }]
+    });
+    writeln!(out, "{}", serde_json::to_string_pretty(&log)?)
+}
+
+/// The line touched by `edits` once they are applied, if all of them fall on the same line.
+fn preview(source: &str, edits: &[TextEdit]) -> Option<String> {
+    let first = edits.first()?;
+    let line_start = source.get(..first.span.start)?.rfind('\n').map_or(0, |i| i + 1);
+    let line_end = source.get(first.span.end..)?.find('\n').map_or(source.len(), |i| first.span.end + i);
+    if edits.iter().any(|e| e.span.start < line_start || e.span.end > line_end) {
+        return None;
+    }
+
+    let mut edits = edits.iter().collect::<Vec<_>>();
+    edits.sort_by_key(|e| (e.span.start, e.span.end));
+    let mut line = String::new();
+    let mut last = line_start;
+    for edit in edits {
+        line += source.get(last..edit.span.start)?;
+        line += &edit.replacement;
+        last = edit.span.end;
+    }
+    line += &source[last..line_end];
+    Some(line.trim().to_owned())
+}
+
+/// `show_my_errors` can't annotate ranges spanning multiple lines, so they are cut at the first line break.
+fn single_line(source: &str, span: Span) -> Range<usize> {
+    let start = span.start.min(source.len());
+    let end = span.end.clamp(start, source.len());
+    let end = source[start..end].find('\n').map_or(end, |i| start + i);
+    // Empty ranges (e.g. an unexpected token) point at the next character instead
+    match source[start..].chars().next() {
+        Some(c) if start == end && c != '\n' => start..start + c.len_utf8(),
+        _ => start..end,
+    }
+}
@@ -0,0 +1,65 @@
+use crate::diagnostic::{Applicability, Diagnostic, TextEdit};
+use crate::source::{FileId, Span};
+
+/// Result of applying the suggestions of a file.
+#[derive(Debug, PartialEq)]
+pub struct Fixed {
+    pub source: String,
+    /// Number of suggestions applied
+    pub applied: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FixError {
+    /// Two machine applicable edits touch the same text, so none of them is applied
+    Overlap(Span, Span),
+}
+
+impl std::fmt::Display for FixError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FixError::Overlap(a, b) => {
+                write!(f, "suggested edits at {}..{} and {}..{} overlap", a.start, a.end, b.start, b.end)
+            }
+        }
+    }
+}
+
+/// Applies every machine applicable suggestion of `diagnostics` that targets `file_id`.
+///
+/// Returns `Ok(None)` when there is nothing to fix, the file is only rewritten if no edits overlap.
+pub fn apply(source: &str, file_id: FileId, diagnostics: &[Diagnostic]) -> Result<Option<Fixed>, FixError> {
+    let suggestions = diagnostics
+        .iter()
+        .flat_map(|d| &d.suggestions)
+        .filter(|s| s.applicability == Applicability::MachineApplicable)
+        .filter(|s| !s.edits.is_empty() && s.edits.iter().all(|e| e.span.file_id == file_id))
+        .collect::<Vec<_>>();
+    if suggestions.is_empty() {
+        return Ok(None);
+    }
+
+    let mut edits = suggestions.iter().flat_map(|s| &s.edits).collect::<Vec<&TextEdit>>();
+    edits.sort_by_key(|e| (e.span.start, e.span.end));
+    for pair in edits.windows(2) {
+        let (a, b) = (pair[0].span, pair[1].span);
+        // Two insertions at the same point are ambiguous too
+        if b.start < a.end || (a == b && a.is_empty()) {
+            return Err(FixError::Overlap(a, b));
+        }
+    }
+
+    let mut fixed = String::with_capacity(source.len());
+    let mut last = 0;
+    for edit in edits {
+        fixed += &source[last..edit.span.start];
+        fixed += &edit.replacement;
+        last = edit.span.end;
+    }
+    fixed += &source[last..];
+
+    Ok(Some(Fixed {
+        source: fixed,
+        applied: suggestions.len(),
+    }))
+}
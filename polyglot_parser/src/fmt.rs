@@ -0,0 +1,369 @@
+//! Canonical layout of Polyglot source, printed from the syntax tree so programs that don't check can be formatted
+//! too.
+//!
+//! Blocks are indented four spaces, operators are surrounded by spaces and the `=>` of consecutive one line arms of an
+//! `if` are aligned. Comments aren't part of the tree: each one is printed on the line it was written on if it follows
+//! code, or on its own line before the next statement otherwise. At most one blank line is kept between statements.
+use crate::diagnostic::Diagnostic;
+use crate::parser::{dedent, syntax_error, Parser, Rule};
+use pest::iterators::Pair;
+use pest::Parser as _;
+use std::ops::Range;
+
+const INDENT: &str = "    ";
+
+/// Formats a whole program, syntax errors are reported in file `0`.
+pub fn format(source: &str) -> Result<String, Diagnostic> {
+    let main = Parser::parse(Rule::Main, source)
+        .map_err(|e| syntax_error(e, 0))?
+        .next()
+        .unwrap();
+    // Matches any text, so it can't fail once the program parsed
+    let comments = Parser::parse(Rule::Comments, source).unwrap().next().unwrap();
+    let mut formatter = Formatter {
+        source,
+        comments: comments
+            .into_inner()
+            .filter(|pair| pair.as_rule() == Rule::CommentText)
+            .map(|pair| pair.as_span().start()..pair.as_span().end())
+            .collect(),
+        printed: 0,
+        out: String::new(),
+        depth: 0,
+        last: None,
+    };
+    for pair in main.into_inner().filter(|pair| pair.as_rule() == Rule::Expr) {
+        formatter.statement(pair);
+    }
+    formatter.comments_before(source.len());
+    Ok(formatter.out)
+}
+
+struct Formatter<'a> {
+    source: &'a str,
+    comments: Vec<Range<usize>>,
+    /// Number of comments already printed
+    printed: usize,
+    out: String,
+    depth: usize,
+    /// End of the last statement or comment of the current block, none before its first one
+    last: Option<usize>,
+}
+
+/// One `if`, `elif` or `else` of a chain.
+struct Arm<'i> {
+    header: String,
+    start: usize,
+    body: Body<'i>,
+}
+
+enum Body<'i> {
+    /// Statement after `=>`
+    Line(Pair<'i, Rule>),
+    /// Statements of the arm and where its block ends
+    Block(Vec<Pair<'i, Rule>>, usize),
+}
+
+impl Formatter<'_> {
+    fn statement(&mut self, expr: Pair<Rule>) {
+        let span = expr.as_span();
+        self.comments_before(span.start());
+        self.separate(span.start());
+        self.indent();
+        self.expr(expr.into_inner().next().unwrap());
+        self.last = Some(span.end());
+    }
+
+    /// Prints a statement from the current position, ending its last line.
+    fn expr(&mut self, pair: Pair<Rule>) {
+        let end = pair.as_span().end() - "end".len();
+        match pair.as_rule() {
+            Rule::If => return self.r#if(pair),
+            Rule::Native => {
+                let sections = pair.into_inner().collect();
+                return self.sections(sections, true);
+            }
+            Rule::Fn | Rule::NativeFn | Rule::For | Rule::While => {}
+            _ => {
+                let line = self.line(pair);
+                self.out.push_str(&line);
+                self.out.push('\n');
+                return;
+            }
+        }
+
+        let rule = pair.as_rule();
+        let (body, signature): (Vec<_>, Vec<_>) = pair
+            .into_inner()
+            .partition(|p| matches!(p.as_rule(), Rule::Expr | Rule::NativeSection));
+        let mut header = match rule {
+            Rule::For => "for",
+            Rule::While => "while",
+            Rule::NativeFn => "native fn",
+            _ => "fn",
+        }
+        .to_owned();
+        for (i, part) in signature.into_iter().enumerate() {
+            let separator = match part.as_rule() {
+                Rule::ArgsDef => "",
+                Rule::Type => ": ",
+                _ if rule == Rule::For && i == 1 => " in ",
+                _ => " ",
+            };
+            header.push_str(separator);
+            header.push_str(&self.value(part));
+        }
+        self.out.push_str(&header);
+        self.out.push('\n');
+        if rule == Rule::NativeFn {
+            return self.sections(body, false);
+        }
+        self.block(body, end);
+        self.indent();
+        self.out.push_str("end\n");
+    }
+
+    fn r#if(&mut self, pair: Pair<Rule>) {
+        let arms = self.arms(pair);
+        // Consecutive one line arms align their `=>`
+        let mut widths = vec![0; arms.len()];
+        let mut i = 0;
+        while i < arms.len() {
+            let run = arms[i..]
+                .iter()
+                .take_while(|arm| matches!(arm.body, Body::Line(_)))
+                .count();
+            let width = arms[i..i + run]
+                .iter()
+                .map(|arm| arm.header.chars().count())
+                .max()
+                .unwrap_or(0);
+            widths[i..i + run].fill(width);
+            i += run.max(1);
+        }
+
+        let ends_with_block = matches!(arms.last().map(|arm| &arm.body), Some(Body::Block(..)));
+        for (i, (arm, width)) in arms.into_iter().zip(widths).enumerate() {
+            if i > 0 {
+                self.comments_before(arm.start);
+                self.indent();
+            }
+            match arm.body {
+                Body::Line(expr) => {
+                    self.out.push_str(&format!("{:width$} => ", arm.header));
+                    self.expr(expr.into_inner().next().unwrap());
+                }
+                Body::Block(exprs, end) => {
+                    self.out.push_str(&arm.header);
+                    self.out.push('\n');
+                    self.block(exprs, end);
+                }
+            }
+        }
+        if ends_with_block {
+            self.indent();
+            self.out.push_str("end\n");
+        }
+    }
+
+    /// Flattens an `if` with its `elif` and `else` arms.
+    fn arms<'i>(&self, pair: Pair<'i, Rule>) -> Vec<Arm<'i>> {
+        let mut arms = Vec::new();
+        let mut next = Some(pair);
+        while let Some(pair) = next.take() {
+            let span = pair.as_span();
+            let keyword = match pair.as_rule() {
+                Rule::If => "if",
+                Rule::Elif => "elif",
+                _ => "else",
+            };
+            let mut header = keyword.to_owned();
+            let mut after = span.start() + keyword.len();
+            let mut exprs = Vec::new();
+            for child in pair.into_inner() {
+                match child.as_rule() {
+                    Rule::Value => {
+                        after = child.as_span().end();
+                        header = format!("{keyword} {}", self.value(child));
+                    }
+                    Rule::Expr => exprs.push(child),
+                    _ => next = Some(child),
+                }
+            }
+            let body = if self.after(after).starts_with("=>") {
+                Body::Line(exprs.pop().unwrap())
+            } else {
+                let end = next
+                    .as_ref()
+                    .map_or(span.end() - "end".len(), |next| next.as_span().start());
+                Body::Block(exprs, end)
+            };
+            arms.push(Arm {
+                header,
+                start: span.start(),
+                body,
+            });
+        }
+        arms
+    }
+
+    /// Prints the statements of a block one level deeper, along with the comments before `end`.
+    fn block(&mut self, exprs: Vec<Pair<Rule>>, end: usize) {
+        self.depth += 1;
+        self.last = None;
+        for expr in exprs {
+            self.statement(expr);
+        }
+        self.comments_before(end);
+        self.depth -= 1;
+    }
+
+    /// Prints native sections and the `end` that closes them, the code keeps its indentation relative to its first
+    /// line.
+    fn sections(&mut self, sections: Vec<Pair<Rule>>, first_indented: bool) {
+        for (i, section) in sections.into_iter().enumerate() {
+            self.comments_before(section.as_span().start());
+            if i > 0 || !first_indented {
+                self.indent();
+            }
+            let mut inner = section.into_inner();
+            let target = inner.next().unwrap().as_str();
+            let code = dedent(inner.next().unwrap().as_str());
+            self.out.push_str(&format!("native {target}\n"));
+            for line in code.trim_end().lines() {
+                if !line.trim().is_empty() {
+                    self.depth += 1;
+                    self.indent();
+                    self.depth -= 1;
+                    self.out.push_str(line);
+                }
+                self.out.push('\n');
+            }
+        }
+        self.indent();
+        self.out.push_str("end\n");
+    }
+
+    /// A statement that fits in one line.
+    fn line(&self, pair: Pair<Rule>) -> String {
+        let rule = pair.as_rule();
+        let parts = pair.into_inner().map(|p| self.value(p)).collect::<Vec<_>>();
+        let assign = |symbol| format!("{} {symbol} {}", parts[0], parts[1]);
+        match rule {
+            Rule::Use => format!("use {}", parts[0]),
+            Rule::Init if parts.len() == 3 => format!("var {}: {} = {}", parts[0], parts[1], parts[2]),
+            Rule::Init => format!("var {} = {}", parts[0], parts[1]),
+            Rule::Decl => format!("var {}: {}", parts[0], parts[1]),
+            Rule::UntypedDecl => format!("var {}", parts[0]),
+            Rule::Typedef => format!("type {} = {}", parts[0], parts[1]),
+            Rule::Assig => assign("="),
+            Rule::AddAssig => assign("+="),
+            Rule::SubAssig => assign("-="),
+            Rule::ListRemAssig => assign("--="),
+            Rule::MulAssig => assign("*="),
+            Rule::DivAssig => assign("/="),
+            Rule::PowAssig => assign("^="),
+            Rule::ModAssig => assign("%="),
+            // Calls and module accesses are values too
+            _ => self.rebuild(rule, parts),
+        }
+    }
+
+    /// A value or type with normalized spacing.
+    fn value(&self, pair: Pair<Rule>) -> String {
+        let rule = pair.as_rule();
+        let text = pair.as_str();
+        let parts = pair.into_inner().map(|p| self.value(p)).collect::<Vec<_>>();
+        match rule {
+            Rule::ArgsDef if parts.is_empty() => "()".to_owned(),
+            _ if parts.is_empty() => text.to_owned(),
+            _ => self.rebuild(rule, parts),
+        }
+    }
+
+    fn rebuild(&self, rule: Rule, parts: Vec<String>) -> String {
+        let binary = |symbol| format!("{} {symbol} {}", parts[0], parts[1]);
+        match rule {
+            Rule::Add => binary("+"),
+            Rule::Sub => binary("-"),
+            Rule::Mul => binary("*"),
+            Rule::Div => binary("/"),
+            Rule::Mod => binary("%"),
+            Rule::Pow => binary("^"),
+            Rule::Less => binary("<"),
+            Rule::Great => binary(">"),
+            Rule::LessEq => binary("<="),
+            Rule::GreatEq => binary(">="),
+            Rule::Equal => binary("=="),
+            Rule::NotEq => binary("!="),
+            Rule::Or => binary("||"),
+            Rule::And => binary("&&"),
+            Rule::DictPair => binary("->"),
+            Rule::StructVal | Rule::StructMem => format!("{}: {}", parts[0], parts[1]),
+            Rule::Not => format!("!{}", parts[0]),
+            Rule::Range => format!("{}..{}", parts[0], parts[1]),
+            Rule::Parenthesis => format!("({})", parts[0]),
+            Rule::Tuple | Rule::Struct | Rule::TTuple | Rule::TStruct => format!("({})", parts.join(", ")),
+            Rule::List | Rule::Dict | Rule::TList => format!("[{}]", parts.join(", ")),
+            Rule::TDict => format!("[{} -> {}]", parts[0], parts[1]),
+            Rule::TupleAccess => format!("{}.{}", parts[0], parts[1]),
+            Rule::ListAccess => format!("{}[{}]", parts[0], parts[1]),
+            Rule::ModuleAccess => format!("{}::{}", parts[0], parts[1]),
+            Rule::Call | Rule::TypeConversion => format!("{}({})", parts[0], parts[1..].join(", ")),
+            Rule::ArgsDef => {
+                let args = parts
+                    .chunks(2)
+                    .map(|arg| format!("{}: {}", arg[0], arg[1]))
+                    .collect::<Vec<_>>();
+                format!("({})", args.join(", "))
+            }
+            // Value, Lhs, Op, Cmp, Type and the other rules that only wrap another one
+            _ => parts.into_iter().next().unwrap(),
+        }
+    }
+
+    /// Prints the comments that start before `pos`.
+    fn comments_before(&mut self, pos: usize) {
+        while let Some(comment) = self.comments.get(self.printed).filter(|c| c.start < pos).cloned() {
+            self.printed += 1;
+            let text = self.source[comment.clone()].trim_end();
+            let line = self.source[..comment.start].rsplit('\n').next().unwrap_or_default();
+            if !line.trim().is_empty() && self.out.ends_with('\n') {
+                // Follows code, so it stays at the end of its line
+                self.out.pop();
+                self.out.push(' ');
+            } else {
+                self.separate(comment.start);
+                self.indent();
+            }
+            self.out.push_str(text);
+            self.out.push('\n');
+            self.last = Some(comment.end);
+        }
+    }
+
+    /// Keeps one blank line between the last statement or comment and the one at `start`, if there was any.
+    fn separate(&mut self, start: usize) {
+        if let Some(last) = self.last.filter(|&last| last < start) {
+            if self.source[last..start].matches('\n').count() > 1 {
+                self.out.push('\n');
+            }
+        }
+    }
+
+    fn indent(&mut self) {
+        self.out.push_str(&INDENT.repeat(self.depth));
+    }
+
+    /// Source after `pos`, skipping whitespace and comments.
+    fn after(&self, mut pos: usize) -> &str {
+        loop {
+            let rest = self.source[pos..].trim_start();
+            pos = self.source.len() - rest.len();
+            match self.comments.iter().find(|c| c.start == pos) {
+                Some(comment) => pos = comment.end,
+                None => return rest,
+            }
+        }
+    }
+}
@@ -0,0 +1,318 @@
+//! Compile-time evaluation of the operations and comparisons whose operands are all literals.
+//!
+//! Folded values follow the semantics of the [interpreter](crate::interpreter) exactly, operations that would
+//! fail or give a different result in some flavor, like dividing by zero or overflowing an int, are reported
+//! instead. Branches of `if` chains with a constant condition are removed, and a warning points at the condition.
+use crate::diagnostic::{Diagnostic, ErrorCode};
+use crate::interpreter::pow;
+use crate::source::Span;
+use crate::tree::*;
+use crate::visit::{self, Fold};
+use std::cmp::Ordering;
+
+/// Folds every constant subtree of a checked program, returning the problems found while evaluating them.
+pub fn fold(main: &mut Main) -> Vec<Diagnostic> {
+    let mut folder = Folder {
+        diagnostics: Vec::new(),
+    };
+    main.exprs = folder.fold_block(std::mem::take(&mut main.exprs));
+    folder.diagnostics
+}
+
+struct Folder {
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// Arm of an `if` chain, the `else` has no condition.
+struct Arm {
+    cmp: Option<Value>,
+    exprs: Vec<Expr>,
+    span: Span,
+}
+
+impl Fold for Folder {
+    fn fold_block(&mut self, exprs: Vec<Expr>) -> Vec<Expr> {
+        let mut folded = Vec::with_capacity(exprs.len());
+        for expr in exprs {
+            self.expr(expr, &mut folded);
+        }
+        folded
+    }
+
+    fn fold_value(&mut self, value: Value) -> Value {
+        let mut value = visit::walk_fold_value(self, value);
+        let constant = match &value.kind {
+            ValueKind::Op(op) => self.op(op, value.span),
+            ValueKind::Cmp(cmp) => cmp_constant(cmp),
+            ValueKind::Parenthesis(inner) if is_literal(&inner.kind) => Some(inner.kind.clone()),
+            _ => None,
+        };
+        if let Some(kind) = constant {
+            value.kind = kind;
+        }
+        value
+    }
+}
+
+impl Folder {
+    /// Folds `expr` into `out`, where it may be replaced by any number of expressions.
+    fn expr(&mut self, expr: Expr, out: &mut Vec<Expr>) {
+        match expr.kind {
+            ExprKind::If { .. } => self.branches(expr, out),
+            ExprKind::While { cmp, exprs } => {
+                let cmp = self.fold_value(cmp);
+                if let ValueKind::Bool(false) = cmp.kind {
+                    self.constant(cmp.span, false);
+                    return;
+                }
+                let kind = ExprKind::While {
+                    cmp,
+                    exprs: self.fold_block(exprs),
+                };
+                out.push(Expr::new(kind, expr.span));
+            }
+            _ => out.push(self.fold_expr(expr)),
+        }
+    }
+
+    /// Removes the arms of an `if` chain that never run, along with every arm after one that always does.
+    fn branches(&mut self, expr: Expr, out: &mut Vec<Expr>) {
+        let mut arms = Vec::new();
+        let mut next = Some(expr);
+        while let Some(expr) = next.take() {
+            let span = expr.span;
+            match expr.kind {
+                ExprKind::If { cmp, exprs, elif } | ExprKind::Elif { cmp, exprs, elif } => {
+                    arms.push(Arm {
+                        cmp: Some(cmp),
+                        exprs,
+                        span,
+                    });
+                    next = elif.map(|elif| *elif);
+                }
+                ExprKind::Else { exprs } => arms.push(Arm { cmp: None, exprs, span }),
+                _ => unreachable!("if chains only contain elifs and elses"),
+            }
+        }
+
+        let mut kept = Vec::new();
+        for mut arm in arms {
+            if let Some(cmp) = arm.cmp.take() {
+                let cmp = arm.cmp.insert(self.fold_value(cmp));
+                match cmp.kind {
+                    ValueKind::Bool(always) => {
+                        self.constant(cmp.span, always);
+                        if !always {
+                            continue;
+                        }
+                    }
+                    _ => {
+                        arm.exprs = self.fold_block(arm.exprs);
+                        kept.push(arm);
+                        continue;
+                    }
+                }
+            }
+            arm.exprs = self.fold_block(arm.exprs);
+            kept.push(arm);
+            break;
+        }
+
+        let always = |arm: &Arm| !matches!(&arm.cmp, Some(cmp) if !matches!(cmp.kind, ValueKind::Bool(true)));
+        match kept.len() {
+            0 => {}
+            // Only a branch that always runs is left, its body is kept in a block if it declares anything
+            1 if always(&kept[0]) => {
+                let arm = kept.pop().unwrap();
+                if !arm.exprs.iter().any(declares) {
+                    out.extend(arm.exprs);
+                    return;
+                }
+                let cmp = arm
+                    .cmp
+                    .unwrap_or_else(|| Value::new(ValueKind::Bool(true), arm.span, Type::Bool));
+                let kind = ExprKind::If {
+                    cmp,
+                    exprs: arm.exprs,
+                    elif: None,
+                };
+                out.push(Expr::new(kind, arm.span));
+            }
+            _ => {
+                let mut chain: Option<Box<Expr>> = None;
+                for (i, arm) in kept.into_iter().enumerate().rev() {
+                    let elif = chain.take();
+                    let kind = match arm.cmp {
+                        // An arm that always runs can only be the last one
+                        _ if elif.is_none() && always(&arm) => ExprKind::Else { exprs: arm.exprs },
+                        Some(cmp) if i == 0 => ExprKind::If {
+                            cmp,
+                            exprs: arm.exprs,
+                            elif,
+                        },
+                        Some(cmp) => ExprKind::Elif {
+                            cmp,
+                            exprs: arm.exprs,
+                            elif,
+                        },
+                        None => unreachable!("elses are always the last arm"),
+                    };
+                    chain = Some(Box::new(Expr::new(kind, arm.span)));
+                }
+                out.push(*chain.unwrap());
+            }
+        }
+    }
+
+    fn constant(&mut self, span: Span, always: bool) {
+        let label = if always {
+            "this branch always runs"
+        } else {
+            "this branch never runs"
+        };
+        self.diagnostics.push(
+            Diagnostic::warning(ErrorCode::ConstantCondition, format!("condition is always {always}"))
+                .with_primary(span, label),
+        );
+    }
+
+    /// Result of an operation on literals, errors are reported and leave the operation as it is.
+    fn op(&mut self, op: &Op, span: Span) -> Option<ValueKind> {
+        let (v, symbol) = match op {
+            Op::Add(v) => (v, '+'),
+            Op::Sub(v) => (v, '-'),
+            Op::Mul(v) => (v, '*'),
+            Op::Div(v) => (v, '/'),
+            Op::Mod(v) => (v, '%'),
+            Op::Pow(v) => (v, '^'),
+            Op::ListRemoveAll(_) => return None,
+        };
+        let zero = match &v.1.kind {
+            ValueKind::Int(i) => *i == 0,
+            ValueKind::Num(n) => *n == 0.0,
+            _ => false,
+        };
+        if zero && matches!(symbol, '/' | '%') && matches!(v.0.kind, ValueKind::Int(_) | ValueKind::Num(_)) {
+            self.diagnostics.push(
+                Diagnostic::error(ErrorCode::DivisionByZero, "division by zero")
+                    .with_primary(span, "this operation always fails")
+                    .with_secondary(v.1.span, "the divisor is zero"),
+            );
+            return None;
+        }
+
+        match (&v.0.kind, &v.1.kind) {
+            (ValueKind::Int(a), ValueKind::Int(b)) => {
+                let (a, b) = (*a, *b);
+                let result = match symbol {
+                    '+' => a.checked_add(b),
+                    '-' => a.checked_sub(b),
+                    '*' => a.checked_mul(b),
+                    '/' => a.checked_div(b),
+                    '%' => a.checked_rem(b),
+                    _ => checked_pow(a, b),
+                };
+                if result.is_none() {
+                    self.diagnostics.push(
+                        Diagnostic::error(ErrorCode::IntegerOverflow, "integer overflow")
+                            .with_primary(span, format!("`{a} {symbol} {b}` doesn't fit in an int")),
+                    );
+                }
+                result.map(ValueKind::Int)
+            }
+            (ValueKind::Int(_) | ValueKind::Num(_), ValueKind::Int(_) | ValueKind::Num(_)) => {
+                let (a, b) = (number(&v.0.kind), number(&v.1.kind));
+                let result = match symbol {
+                    '+' => a + b,
+                    '-' => a - b,
+                    '*' => a * b,
+                    '/' => a / b,
+                    '%' => a % b,
+                    _ => a.powf(b),
+                };
+                // Infinities and NaN have no literal
+                result.is_finite().then_some(ValueKind::Num(result))
+            }
+            (ValueKind::Str(a), ValueKind::Str(b)) if symbol == '+' => Some(ValueKind::Str(format!("{a}{b}"))),
+            (ValueKind::Char(a), ValueKind::Char(b)) if symbol == '+' => Some(ValueKind::Str(format!("{a}{b}"))),
+            _ => None,
+        }
+    }
+}
+
+/// Result of a comparison whose result is known, `&&` and `||` drop operands that can't change it.
+fn cmp_constant(cmp: &Cmp) -> Option<ValueKind> {
+    let v = match cmp {
+        Cmp::Not(v) => {
+            return match v.kind {
+                ValueKind::Bool(b) => Some(ValueKind::Bool(!b)),
+                _ => None,
+            }
+        }
+        Cmp::And(v) | Cmp::Or(v) => {
+            let and = matches!(cmp, Cmp::And(_));
+            return match (&v.0.kind, &v.1.kind) {
+                (ValueKind::Bool(b), _) if *b != and => Some(ValueKind::Bool(*b)),
+                (ValueKind::Bool(_), rhs) => Some(rhs.clone()),
+                // The left operand is still evaluated, only once
+                (lhs, ValueKind::Bool(b)) if *b == and => Some(lhs.clone()),
+                _ => None,
+            };
+        }
+        Cmp::Less(v) | Cmp::Greater(v) | Cmp::LessEq(v) | Cmp::GreatEq(v) | Cmp::Equal(v) | Cmp::NotEq(v) => v,
+        Cmp::Err => return None,
+    };
+    let order = match (&v.0.kind, &v.1.kind) {
+        (ValueKind::Int(a), ValueKind::Int(b)) => a.cmp(b),
+        (ValueKind::Int(_) | ValueKind::Num(_), ValueKind::Int(_) | ValueKind::Num(_)) => {
+            number(&v.0.kind).partial_cmp(&number(&v.1.kind))?
+        }
+        (ValueKind::Bool(a), ValueKind::Bool(b)) => a.cmp(b),
+        (ValueKind::Char(a), ValueKind::Char(b)) => a.cmp(b),
+        (ValueKind::Str(a), ValueKind::Str(b)) => a.cmp(b),
+        _ => return None,
+    };
+    let result = match cmp {
+        Cmp::Less(_) => order == Ordering::Less,
+        Cmp::Greater(_) => order == Ordering::Greater,
+        Cmp::LessEq(_) => order != Ordering::Greater,
+        Cmp::GreatEq(_) => order != Ordering::Less,
+        Cmp::Equal(_) => order == Ordering::Equal,
+        _ => order != Ordering::Equal,
+    };
+    Some(ValueKind::Bool(result))
+}
+
+/// `base ^ exponent`, or nothing if it overflows.
+fn checked_pow(base: i64, exponent: i64) -> Option<i64> {
+    match u32::try_from(exponent) {
+        Ok(exponent) => base.checked_pow(exponent),
+        // Negative exponents round towards zero
+        Err(_) if exponent < 0 || matches!(base, -1..=1) => Some(pow(base, exponent)),
+        Err(_) => None,
+    }
+}
+
+fn number(kind: &ValueKind) -> f64 {
+    match kind {
+        ValueKind::Int(i) => *i as f64,
+        ValueKind::Num(n) => *n,
+        _ => unreachable!("only ints and nums are numbers"),
+    }
+}
+
+fn is_literal(kind: &ValueKind) -> bool {
+    matches!(kind, ValueKind::Int(_) | ValueKind::Num(_) | ValueKind::Bool(_) | ValueKind::Char(_) | ValueKind::Str(_))
+}
+
+/// Whether `expr` declares something visible in the rest of its block.
+fn declares(expr: &Expr) -> bool {
+    matches!(
+        expr.kind,
+        ExprKind::Init { .. }
+            | ExprKind::Decl { .. }
+            | ExprKind::Typedef { .. }
+            | ExprKind::Fn { .. }
+            | ExprKind::NativeFn { .. }
+    )
+}
@@ -0,0 +1,443 @@
+//! Differential testing, checking every flavor of a program behaves like the [`interpreter`].
+//!
+//! [`compare`] runs each program of a [`corpus`] with the interpreter, the [`vm`] and every backend whose toolchain
+//! is installed, and reports the targets whose stdout or exit code differ. Every run has a time limit, so a program
+//! that never ends in some target doesn't stop the rest.
+use crate::backend::{Backend, Output};
+use crate::diagnostic::Diagnostic;
+use crate::interpreter::{self, RuntimeError};
+use crate::parser::check;
+use crate::prune::prune;
+use crate::tree::{Expr, ExprKind};
+use crate::vm;
+use std::io::{Read, Write};
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+/// Program of a corpus.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    /// File it comes from, followed by the line of the code block in Markdown files (`README.md:12`)
+    pub name: String,
+    pub source: String,
+}
+
+/// Programs in the file at `path`, every code block of a Markdown file is a different program.
+pub fn corpus(path: &Path) -> std::io::Result<Vec<Program>> {
+    let source = std::fs::read_to_string(path)?;
+    let name = path.display().to_string();
+    if path.extension().is_some_and(|e| e == "md") {
+        let blocks = code_blocks(&source).into_iter();
+        return Ok(blocks
+            .map(|(line, source)| Program {
+                name: format!("{name}:{line}"),
+                source,
+            })
+            .collect());
+    }
+    Ok(vec![Program { name, source }])
+}
+
+/// Indented and fenced code blocks of a Markdown document, with the line where each one starts.
+fn code_blocks(markdown: &str) -> Vec<(usize, String)> {
+    let mut blocks = Vec::new();
+    let mut block: Option<(usize, String)> = None;
+    let mut fenced = false;
+    // Indented blocks can't interrupt a paragraph
+    let mut can_start = true;
+    for (i, line) in markdown.lines().enumerate() {
+        let blank = line.trim().is_empty();
+        if line.trim_start().starts_with("```") {
+            if fenced {
+                blocks.extend(block.take());
+            } else {
+                block = Some((i + 2, String::new()));
+            }
+            fenced = !fenced;
+            continue;
+        }
+        let code = if fenced {
+            Some(line)
+        } else {
+            line.strip_prefix("    ").or_else(|| line.strip_prefix('\t'))
+        };
+        match (code, &mut block) {
+            (Some(code), Some((_, text))) => {
+                text.push_str(code);
+                text.push('\n');
+            }
+            (Some(code), None) if can_start && !blank => block = Some((i + 1, format!("{code}\n"))),
+            (_, Some((_, text))) if blank => text.push('\n'),
+            _ => blocks.extend(block.take()),
+        }
+        can_start = blank || line.starts_with('#');
+    }
+    blocks.extend(block);
+    blocks
+}
+
+/// What a program printed to stdout and how it exited.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Execution {
+    pub stdout: String,
+    /// `None` if it was killed by a signal, or panicked when running in this process
+    pub code: Option<i32>,
+}
+
+/// How a target ran a program, compared to the interpreter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Same,
+    /// Printed something else or exited with another code
+    Diverged(Execution),
+    /// The backend can't translate the program
+    Unsupported(String),
+    /// The translated program didn't build, or its toolchain couldn't be started
+    BuildFailed(String),
+    /// The program was stopped after running for longer than the timeout
+    TimedOut,
+}
+
+/// Results of one program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Run {
+    pub program: String,
+    /// What the interpreter did
+    pub expected: Execution,
+    /// Outcome of every target, in the same order as [`Report::targets`]
+    pub outcomes: Vec<Outcome>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Report {
+    /// Every target compared with the interpreter
+    pub targets: Vec<&'static str>,
+    /// Backends that couldn't be tested, with the reason
+    pub skipped: Vec<(&'static str, String)>,
+    /// Programs that don't pass the checker, so they weren't run
+    pub rejected: Vec<String>,
+    /// Programs that made the interpreter panic, so there was nothing to compare with
+    pub crashed: Vec<String>,
+    /// Programs the interpreter didn't finish in time
+    pub timed_out: Vec<String>,
+    /// Programs with native code, which the interpreter can't run
+    pub native: Vec<String>,
+    pub runs: Vec<Run>,
+}
+
+impl Report {
+    /// Whether every target behaved like the interpreter, programs a backend can't translate are fine.
+    ///
+    /// A corpus where no program could be run proves nothing, so it's a failure too.
+    pub fn success(&self) -> bool {
+        !self.runs.is_empty()
+            && self.crashed.is_empty()
+            && self.timed_out.is_empty()
+            && self
+                .runs
+                .iter()
+                .flat_map(|run| &run.outcomes)
+                .all(|outcome| matches!(outcome, Outcome::Same | Outcome::Unsupported(_)))
+    }
+}
+
+/// Runs `programs` with the interpreter, the VM and every one of `backends` that can be executed, stopping each run
+/// after `timeout`.
+pub fn compare(programs: &[Program], backends: &[&'static dyn Backend], timeout: Duration) -> Report {
+    let mut report = Report {
+        targets: vec!["vm"],
+        ..Report::default()
+    };
+    let mut runnable = Vec::new();
+    for backend in backends {
+        match missing_toolchain(*backend) {
+            Some(reason) => report.skipped.push((backend.name(), reason)),
+            None => {
+                report.targets.push(backend.name());
+                runnable.push(*backend);
+            }
+        }
+    }
+
+    static SCRATCH: AtomicUsize = AtomicUsize::new(0);
+    let scratch = std::env::temp_dir().join(format!(
+        "polyglot-harness-{}-{}",
+        std::process::id(),
+        SCRATCH.fetch_add(1, Ordering::Relaxed)
+    ));
+    for (i, program) in programs.iter().enumerate() {
+        let (mut main, diagnostics) = check(Path::new(&program.name), &program.source);
+        if diagnostics.iter().any(Diagnostic::is_error) {
+            report.rejected.push(program.name.clone());
+            continue;
+        }
+        if uses_native(&main.exprs) {
+            report.native.push(program.name.clone());
+            continue;
+        }
+
+        let interpreted = main.clone();
+        let Some(expected) = in_process(timeout, move |out| interpreter::run(&interpreted, out)) else {
+            report.timed_out.push(program.name.clone());
+            continue;
+        };
+        if expected.code.is_none() {
+            report.crashed.push(program.name.clone());
+            continue;
+        }
+        let compared = |found: Option<Execution>| match found {
+            Some(found) if found == expected => Outcome::Same,
+            Some(found) => Outcome::Diverged(found),
+            None => Outcome::TimedOut,
+        };
+        let compiled = main.clone();
+        let found = in_process(timeout, move |out| vm::run(&vm::compile(&compiled), &compiled.source_map, out));
+        let mut outcomes = vec![compared(found)];
+        // Backends get the program pruned like a release build, so removing something still used shows up as a difference
+        prune(&mut main);
+        for backend in &runnable {
+            let dir = scratch.join(format!("{i}-{}", backend.name()));
+            if let Some(diagnostic) = backend.check(&main).into_iter().find(Diagnostic::is_error) {
+                outcomes.push(Outcome::Unsupported(diagnostic.message));
+                continue;
+            }
+            outcomes.push(match backend.emit(&main) {
+                Err(diagnostic) => Outcome::Unsupported(diagnostic.message),
+                Ok(output) => match execute(*backend, output, &dir, timeout) {
+                    Ok(found) => compared(found),
+                    Err(error) => Outcome::BuildFailed(error),
+                },
+            });
+        }
+        report.runs.push(Run {
+            program: program.name.clone(),
+            expected,
+            outcomes,
+        });
+    }
+    // Leftovers in the temporary directory are harmless
+    let _ = std::fs::remove_dir_all(scratch);
+    report
+}
+
+fn uses_native(exprs: &[Expr]) -> bool {
+    exprs.iter().any(|expr| match &expr.kind {
+        ExprKind::Native { .. } | ExprKind::NativeFn { .. } => true,
+        ExprKind::If { exprs, elif, .. } | ExprKind::Elif { exprs, elif, .. } => {
+            uses_native(exprs) || elif.as_deref().is_some_and(|e| uses_native(std::slice::from_ref(e)))
+        }
+        ExprKind::Else { exprs }
+        | ExprKind::For { exprs, .. }
+        | ExprKind::While { exprs, .. }
+        | ExprKind::Fn { exprs, .. } => uses_native(exprs),
+        _ => false,
+    })
+}
+
+/// Why the programs of `backend` can't be executed, if they can't.
+pub(crate) fn missing_toolchain(backend: &dyn Backend) -> Option<String> {
+    let commands = backend.commands(Path::new("./main"));
+    if commands.is_empty() {
+        return Some("its output can't be executed".into());
+    }
+    // Commands with a path run files built by the previous ones
+    let tools = commands
+        .iter()
+        .map(Command::get_program)
+        .filter(|p| Path::new(p).components().count() == 1);
+    for tool in tools {
+        let found = std::env::var_os("PATH").is_some_and(|paths| {
+            std::env::split_paths(&paths).any(|dir| {
+                let path = dir.join(tool);
+                path.is_file() || path.with_extension(std::env::consts::EXE_EXTENSION).is_file()
+            })
+        });
+        if !found {
+            return Some(format!("`{}` is not on PATH", tool.to_string_lossy()));
+        }
+    }
+    None
+}
+
+/// Runs a program in a thread of this process, catching panics so a bug in the interpreter or the VM doesn't stop
+/// the rest.
+///
+/// `None` if it didn't finish in `timeout`. Threads can't be killed, so the program is left running, but anything it
+/// prints from then on fails, which ends it if it prints at all.
+fn in_process(
+    timeout: Duration,
+    run: impl FnOnce(&mut dyn Write) -> Result<(), RuntimeError> + Send + 'static,
+) -> Option<Execution> {
+    let abandoned = Arc::new(AtomicBool::new(false));
+    let mut out = Captured {
+        stdout: Vec::new(),
+        abandoned: abandoned.clone(),
+    };
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let result = std::panic::catch_unwind(AssertUnwindSafe(|| run(&mut out)));
+        let _ = sender.send(Execution {
+            stdout: String::from_utf8_lossy(&out.stdout).into_owned(),
+            code: match result {
+                Ok(Ok(())) => Some(0),
+                Ok(Err(_)) => Some(1),
+                Err(_) => None,
+            },
+        });
+    });
+    let execution = receiver.recv_timeout(timeout).ok();
+    abandoned.store(execution.is_none(), Ordering::Relaxed);
+    execution
+}
+
+/// Stdout of a program run in this process, which stops accepting writes once the program is abandoned.
+struct Captured {
+    stdout: Vec<u8>,
+    abandoned: Arc<AtomicBool>,
+}
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.abandoned.load(Ordering::Relaxed) {
+            return Err(std::io::ErrorKind::TimedOut.into());
+        }
+        self.stdout.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes the output of `backend` to `dir`, builds it and runs it for at most `timeout`, `None` if it took longer.
+fn execute(backend: &dyn Backend, output: Output, dir: &Path, timeout: Duration) -> Result<Option<Execution>, String> {
+    let write = |path: PathBuf, contents: &str| std::fs::write(&path, contents).map_err(|e| format!("{e}"));
+    std::fs::create_dir_all(dir).map_err(|e| format!("{e}"))?;
+    let source = dir.join("main").with_extension(backend.extension());
+    write(source.clone(), &output.source)?;
+    for (file, contents) in &output.files {
+        write(dir.join(file), contents)?;
+    }
+
+    let mut commands = backend.commands(&source);
+    let mut program = commands.pop().expect("only backends with commands are executed");
+    for mut build in commands {
+        let result = build.current_dir(dir).output().map_err(|e| format!("{e}"))?;
+        if !result.status.success() {
+            return Err(String::from_utf8_lossy(&result.stderr).into_owned());
+        }
+    }
+    let mut child = program
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("{e}"))?;
+    // Read while it runs, a program printing more than the pipe holds would never end otherwise
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let reader = std::thread::spawn(move || {
+        let mut printed = Vec::new();
+        let _ = stdout.read_to_end(&mut printed);
+        printed
+    });
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(|e| format!("{e}"))? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Ok(None);
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    let stdout = reader.join().unwrap_or_default();
+    Ok(Some(Execution {
+        stdout: String::from_utf8_lossy(&stdout).into_owned(),
+        code: status.code(),
+    }))
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let width = self
+            .targets
+            .iter()
+            .map(|t| t.len())
+            .max()
+            .unwrap_or(0)
+            .max("target".len());
+        writeln!(f, "{:width$}  same  diverged  unsupported  build failed  timed out", "target")?;
+        for (i, target) in self.targets.iter().enumerate() {
+            let count = |matches: fn(&Outcome) -> bool| self.runs.iter().filter(|r| matches(&r.outcomes[i])).count();
+            writeln!(
+                f,
+                "{target:width$}  {:>4}  {:>8}  {:>11}  {:>12}  {:>9}",
+                count(|o| matches!(o, Outcome::Same)),
+                count(|o| matches!(o, Outcome::Diverged(_))),
+                count(|o| matches!(o, Outcome::Unsupported(_))),
+                count(|o| matches!(o, Outcome::BuildFailed(_))),
+                count(|o| matches!(o, Outcome::TimedOut)),
+            )?;
+        }
+        for (target, reason) in &self.skipped {
+            writeln!(f, "{target:width$}  skipped, {reason}")?;
+        }
+        if !self.rejected.is_empty() {
+            writeln!(f, "\n{} program(s) don't check: {}", self.rejected.len(), self.rejected.join(", "))?;
+        }
+        if !self.crashed.is_empty() {
+            let (count, names) = (self.crashed.len(), self.crashed.join(", "));
+            writeln!(f, "\n{count} program(s) make the interpreter panic: {names}")?;
+        }
+        if !self.timed_out.is_empty() {
+            let (count, names) = (self.timed_out.len(), self.timed_out.join(", "));
+            writeln!(f, "\n{count} program(s) don't end in time with the interpreter: {names}")?;
+        }
+        if !self.native.is_empty() {
+            let (count, names) = (self.native.len(), self.native.join(", "));
+            writeln!(f, "\n{count} program(s) use native code, which the interpreter can't run: {names}")?;
+        }
+
+        if self.runs.is_empty() {
+            writeln!(f, "\nno program could be compared")?;
+        }
+        for run in &self.runs {
+            for (target, outcome) in self.targets.iter().zip(&run.outcomes) {
+                match outcome {
+                    Outcome::Diverged(found) => {
+                        writeln!(f, "\n{target} diverged on {}", run.program)?;
+                        execution(f, "expected", &run.expected)?;
+                        execution(f, "found", found)?;
+                    }
+                    Outcome::BuildFailed(error) => {
+                        writeln!(f, "\n{target} failed to build {}", run.program)?;
+                        for line in error.lines().take(10) {
+                            writeln!(f, "  | {line}")?;
+                        }
+                    }
+                    Outcome::TimedOut => writeln!(f, "\n{target} timed out on {}", run.program)?,
+                    Outcome::Same | Outcome::Unsupported(_) => {}
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn execution(f: &mut std::fmt::Formatter<'_>, label: &str, execution: &Execution) -> std::fmt::Result {
+    match execution.code {
+        Some(code) => writeln!(f, "  {label} (exit code {code}):")?,
+        None => writeln!(f, "  {label} (crashed):")?,
+    }
+    for line in execution.stdout.lines() {
+        writeln!(f, "  | {line}")?;
+    }
+    Ok(())
+}
@@ -0,0 +1,645 @@
+//! Reference interpreter, executing a checked [`Main`] directly.
+//!
+//! It defines what a program means: every backend must print exactly what [`run`] prints, and fail with the same
+//! message when [`run`] returns a [`RuntimeError`].
+use crate::backend::Types;
+use crate::source::{SourceMap, Span};
+use crate::tree::{self, interpolation, Cmp, Expr, ExprKind, ListAccessMode, Main, Op, Segment, TupleAccessMode};
+use crate::tree::{Type, ValueKind};
+use rustc_hash::FxHashMap;
+use std::cmp::Ordering;
+use std::io::Write;
+
+/// Value a [`tree::Value`] evaluates to.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Range(i64, i64),
+    Int(i64),
+    Num(f64),
+    Bool(bool),
+    Char(char),
+    Str(String),
+    Tuple(Vec<Value>),
+    Struct(Vec<(String, Value)>),
+    List(Vec<Value>),
+    /// Entries in insertion order
+    Dict(Vec<(Value, Value)>),
+    /// Result of calling a function without a return type
+    Void,
+}
+
+/// Error that terminates a program, like accessing a list out of bounds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeError {
+    pub span: Span,
+    /// Text printed to stderr, the same in every flavor
+    pub message: String,
+}
+
+impl std::fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+/// Error of native blocks and calls to native functions, which only have an implementation in the backends.
+pub(crate) const NATIVE: &str = "Native code only runs in the flavors it's written for";
+// Errors of trees the checker rejects, which can still be run when they're built without checking them
+pub(crate) const NOT_A_BOOL: &str = "Condition is not a bool";
+pub(crate) const NOT_ITERABLE: &str = "Only ranges and lists can be iterated";
+pub(crate) const ARGUMENT_COUNT: &str = "Wrong number of arguments";
+
+impl RuntimeError {
+    /// Error pointing at the line and text of `span`, like `ERROR: Out-of-bounds in line 3, "list[5]".`
+    pub(crate) fn at(source_map: &SourceMap, span: Span, message: impl std::fmt::Display) -> RuntimeError {
+        let line = source_map.line_col(span).0.line;
+        let text = source_map.snippet(span).unwrap_or_default();
+        RuntimeError {
+            span,
+            message: format!("ERROR: {message} in line {line}, \"{text}\"."),
+        }
+    }
+}
+
+/// Executes `main`, writing everything it prints to `out`.
+pub fn run(main: &Main, out: &mut dyn Write) -> Result<(), RuntimeError> {
+    let mut interpreter = Interpreter {
+        main,
+        out,
+        types: Types::new(),
+        frames: vec![vec![FxHashMap::default()]],
+        fns: FxHashMap::default(),
+    };
+    for expr in &main.exprs {
+        interpreter.exec(expr)?;
+    }
+    Ok(())
+}
+
+struct Interpreter<'a, 'o> {
+    main: &'a Main,
+    out: &'o mut dyn Write,
+    /// Static types of the variables, needed where the value alone is ambiguous (e.g. `list += [1]`)
+    types: Types,
+    /// Variables of each function being executed, with one map per block
+    frames: Vec<Vec<FxHashMap<String, Value>>>,
+    fns: FxHashMap<String, &'a Expr>,
+}
+
+impl<'a, 'o> Interpreter<'a, 'o> {
+    fn error(&self, span: Span, message: impl std::fmt::Display) -> RuntimeError {
+        RuntimeError::at(&self.main.source_map, span, message)
+    }
+
+    fn scopes(&mut self) -> &mut Vec<FxHashMap<String, Value>> {
+        self.frames.last_mut().unwrap()
+    }
+
+    fn declare(&mut self, name: &str, r#type: Type, value: Value) {
+        self.types.insert(name, r#type);
+        self.scopes().last_mut().unwrap().insert(name.to_owned(), value);
+    }
+
+    fn var(&mut self, name: &str) -> &mut Value {
+        let scope = self.scopes().iter_mut().rev().find(|scope| scope.contains_key(name));
+        match scope.and_then(|scope| scope.get_mut(name)) {
+            Some(value) => value,
+            None => unreachable!("the checker rejects undeclared variables"),
+        }
+    }
+
+    fn print(&mut self, text: &str, span: Span) -> Result<(), RuntimeError> {
+        self.out.write_all(text.as_bytes()).map_err(|e| RuntimeError {
+            span,
+            message: format!("ERROR: {e}"),
+        })
+    }
+
+    fn block(&mut self, exprs: &'a [Expr], vars: Vec<(String, Type, Value)>) -> Result<(), RuntimeError> {
+        self.types.push();
+        self.scopes().push(FxHashMap::default());
+        for (name, r#type, value) in vars {
+            self.declare(&name, r#type, value);
+        }
+        let result = exprs.iter().try_for_each(|expr| self.exec(expr));
+        self.scopes().pop();
+        self.types.pop();
+        result
+    }
+
+    fn exec(&mut self, expr: &'a Expr) -> Result<(), RuntimeError> {
+        match &expr.kind {
+            ExprKind::Init { name, r#type, value } => {
+                let value = coerce(self.eval(value)?, r#type);
+                self.declare(name, r#type.clone(), value);
+            }
+            ExprKind::Decl { name, r#type } => self.declare(name, r#type.clone(), default(r#type)),
+            ExprKind::Assig { name, value } => {
+                let r#type = self.types.get(name).cloned().unwrap_or(Type::Err);
+                let value = coerce(self.eval(value)?, &r#type);
+                *self.var(name) = value;
+            }
+            ExprKind::Typedef { .. } => {}
+            ExprKind::If { cmp, exprs, elif } | ExprKind::Elif { cmp, exprs, elif } => {
+                if self.truth(cmp)? {
+                    self.block(exprs, Vec::new())?;
+                } else if let Some(elif) = elif {
+                    self.exec(elif)?;
+                }
+            }
+            ExprKind::Else { exprs } => self.block(exprs, Vec::new())?,
+            ExprKind::For { var, range, exprs } => {
+                let element = self.types.element_of(range);
+                // The range is evaluated once, so the body can modify the original list
+                let items = match self.eval(range)? {
+                    Value::Range(start, end) => (start..end).map(Value::Int).collect(),
+                    Value::List(items) => items,
+                    _ => return Err(self.error(range.span, NOT_ITERABLE)),
+                };
+                for item in items {
+                    self.block(exprs, vec![(var.clone(), element.clone(), item)])?;
+                }
+            }
+            ExprKind::While { cmp, exprs } => {
+                while self.truth(cmp)? {
+                    self.block(exprs, Vec::new())?;
+                }
+            }
+            ExprKind::Fn { name, r#type, args, .. } => {
+                self.types.insert_fn(name, r#type.clone(), args);
+                self.fns.insert(name.clone(), expr);
+            }
+            ExprKind::Call { name, args } => {
+                self.call(name, args, expr.span)?;
+            }
+            ExprKind::Native { .. } => return Err(self.error(expr.span, NATIVE)),
+            ExprKind::NativeFn { name, r#type, args, .. } => {
+                self.types.insert_fn(name, r#type.clone(), args);
+                self.fns.insert(name.clone(), expr);
+            }
+            ExprKind::Err => unreachable!("the interpreter only receives valid programs"),
+        }
+        Ok(())
+    }
+
+    fn truth(&mut self, cmp: &'a tree::Value) -> Result<bool, RuntimeError> {
+        match self.eval(cmp)? {
+            Value::Bool(b) => Ok(b),
+            _ => Err(self.error(cmp.span, NOT_A_BOOL)),
+        }
+    }
+
+    fn call(&mut self, name: &str, args: &'a [tree::Value], span: Span) -> Result<Value, RuntimeError> {
+        if self.types.is_builtin(name) {
+            let arg = &args[0];
+            let text = match (name, &arg.kind) {
+                ("dbg", _) => {
+                    let text = self.main.source_map.snippet(arg.span).unwrap_or_default();
+                    format!("{text} = {}\n", self.eval(arg)?.show(true))
+                }
+                (_, ValueKind::Str(s)) => {
+                    let mut text = String::new();
+                    for (_, segment) in interpolation(s) {
+                        match segment {
+                            Segment::Text(t) => text.push_str(t),
+                            Segment::Var(var) => text.push_str(&self.var(var).show(false)),
+                        }
+                    }
+                    text
+                }
+                _ => self.eval(arg)?.show(false),
+            };
+            let newline = if name == "println" { "\n" } else { "" };
+            self.print(&format!("{text}{newline}"), span)?;
+            return Ok(Value::Void);
+        }
+
+        let function: &'a Expr = self.fns[name];
+        if let ExprKind::NativeFn { .. } = function.kind {
+            return Err(self.error(span, NATIVE));
+        }
+        let ExprKind::Fn {
+            r#type,
+            args: params,
+            exprs,
+            ..
+        } = &function.kind
+        else {
+            unreachable!("only functions are stored")
+        };
+        if params.len() != args.len() {
+            return Err(self.error(span, ARGUMENT_COUNT));
+        }
+        let mut vars = Vec::new();
+        for ((param, t), arg) in params.iter().zip(args) {
+            vars.push((param.clone(), t.clone(), coerce(self.eval(arg)?, t)));
+        }
+        // Functions only see their arguments
+        self.frames.push(vec![FxHashMap::default()]);
+        let result = self.block(exprs, vars);
+        self.frames.pop();
+        result?;
+        // Polyglot functions can't return a value yet, so the result is always the default one
+        Ok(default(r#type))
+    }
+
+    fn eval(&mut self, value: &'a tree::Value) -> Result<Value, RuntimeError> {
+        let evaluated = match &value.kind {
+            ValueKind::Range((start, end)) => Value::Range(*start, *end),
+            ValueKind::Int(i) => Value::Int(*i),
+            ValueKind::Num(n) => Value::Num(*n),
+            ValueKind::Bool(b) => Value::Bool(*b),
+            ValueKind::Char(c) => Value::Char(*c),
+            ValueKind::Str(s) => Value::Str(s.clone()),
+            ValueKind::Tuple(items) => Value::Tuple(items.iter().map(|v| self.eval(v)).collect::<Result<_, _>>()?),
+            ValueKind::Struct(members) => {
+                let mut evaluated = Vec::new();
+                for (name, v) in members {
+                    evaluated.push((name.clone(), self.eval(v)?));
+                }
+                Value::Struct(evaluated)
+            }
+            ValueKind::TupleAccess { name, access_mode, .. } => match (self.var(name), access_mode) {
+                (Value::Tuple(items), TupleAccessMode::Index(i)) => items[*i].clone(),
+                (Value::Struct(members), TupleAccessMode::Index(i)) => members[*i].1.clone(),
+                (Value::Struct(members), TupleAccessMode::Member(m)) => {
+                    members.iter().find(|(n, _)| n == m).map(|(_, v)| v.clone()).unwrap()
+                }
+                _ => unreachable!("the checker only allows accessing tuples and structs"),
+            },
+            ValueKind::List(items) => Value::List(items.iter().map(|v| self.eval(v)).collect::<Result<_, _>>()?),
+            ValueKind::ListAccess { name, access_mode, .. } => {
+                let (ListAccessMode::List(key) | ListAccessMode::Dict(key)) = access_mode;
+                let key = self.eval(key)?;
+                let found = match (self.var(name), &key) {
+                    (Value::List(items), Value::Int(i)) => {
+                        let item = usize::try_from(*i).ok().and_then(|i| items.get(i));
+                        item.cloned().ok_or("Out-of-bounds")
+                    }
+                    (Value::Dict(entries), _) => {
+                        let entry = entries.iter().find(|(k, _)| equal(k, &key));
+                        entry.map(|(_, v)| v.clone()).ok_or("Key not found")
+                    }
+                    _ => unreachable!("the checker only allows indexing lists and dicts"),
+                };
+                found.map_err(|message| self.error(value.span, message))?
+            }
+            ValueKind::Dict(pairs) => {
+                let mut entries = Vec::new();
+                for (k, v) in pairs {
+                    let (k, v) = (self.eval(k)?, self.eval(v)?);
+                    insert(&mut entries, k, v);
+                }
+                Value::Dict(entries)
+            }
+            ValueKind::Var(name) => self.var(name).clone(),
+            ValueKind::Op(op) => self.op(op, value.span)?,
+            ValueKind::Cmp(cmp) => self.cmp(cmp)?,
+            ValueKind::Parenthesis(p) => self.eval(p)?,
+            ValueKind::Conversion { r#type, value: v } => {
+                let converted = self.eval(v)?;
+                convert(converted, r#type).map_err(|message| RuntimeError {
+                    span: value.span,
+                    message,
+                })?
+            }
+            ValueKind::Call { name, args } => self.call(name, args, value.span)?,
+            ValueKind::RetExpr(r) => match *r {},
+            ValueKind::Err => unreachable!("the interpreter only receives valid programs"),
+        };
+        Ok(evaluated)
+    }
+
+    fn op(&mut self, op: &'a Op, span: Span) -> Result<Value, RuntimeError> {
+        let (v, symbol) = match op {
+            Op::Add(v) => (v, '+'),
+            Op::Sub(v) => (v, '-'),
+            Op::Mul(v) => (v, '*'),
+            Op::Div(v) => (v, '/'),
+            Op::Mod(v) => (v, '%'),
+            Op::Pow(v) => (v, '^'),
+            Op::ListRemoveAll(v) => {
+                let Value::List(items) = self.var(&v.0).clone() else {
+                    unreachable!("the checker only allows removing from lists")
+                };
+                let list = self.types.get(&v.0).cloned().unwrap_or(Type::Err);
+                let removed = self.list_operand(&list, &v.1)?;
+                return Ok(Value::List(
+                    items
+                        .into_iter()
+                        .filter(|i| !removed.iter().any(|r| equal(i, r)))
+                        .collect(),
+                ));
+            }
+        };
+
+        let lhs = self.eval(&v.0)?;
+        if let Value::List(mut items) = lhs {
+            let list = v.0.r#type.clone();
+            let operand = self.list_operand(&list, &v.1)?;
+            if symbol == '+' {
+                items.extend(operand);
+            } else {
+                // Only the first occurrence of each element is removed
+                for removed in &operand {
+                    if let Some(i) = items.iter().position(|item| equal(item, removed)) {
+                        items.remove(i);
+                    }
+                }
+            }
+            return Ok(Value::List(items));
+        }
+
+        let rhs = self.eval(&v.1)?;
+        let result = match (lhs, rhs) {
+            (Value::Int(a), Value::Int(b)) => Value::Int(match symbol {
+                '+' => a.wrapping_add(b),
+                '-' => a.wrapping_sub(b),
+                '*' => a.wrapping_mul(b),
+                '/' | '%' if b == 0 => return Err(self.error(span, "Division by zero")),
+                // Rust already truncates towards zero, and the remainder takes the sign of the dividend
+                '/' => a.wrapping_div(b),
+                '%' => a.wrapping_rem(b),
+                _ => pow(a, b),
+            }),
+            (a @ (Value::Int(_) | Value::Num(_)), b @ (Value::Int(_) | Value::Num(_))) => {
+                let (a, b) = (number(&a), number(&b));
+                Value::Num(match symbol {
+                    '+' => a + b,
+                    '-' => a - b,
+                    '*' => a * b,
+                    '/' => a / b,
+                    '%' => a % b,
+                    _ => a.powf(b),
+                })
+            }
+            (a @ (Value::Str(_) | Value::Char(_)), b) if symbol == '+' => Value::Str(a.show(false) + &b.show(false)),
+            (a, b) => {
+                let (a, b) = (a.show(true), b.show(true));
+                return Err(self.error(span, format!("Operator '{symbol}' not supported between {a} and {b}")));
+            }
+        };
+        Ok(result)
+    }
+
+    /// Elements added to or removed from a list of type `list`, which may be a single element or a whole list.
+    fn list_operand(&mut self, list: &Type, value: &'a tree::Value) -> Result<Vec<Value>, RuntimeError> {
+        let whole = value.r#type == *list;
+        let element = match list {
+            Type::List(element) => element.as_ref(),
+            t => t,
+        };
+        Ok(match self.eval(value)? {
+            Value::List(items) if whole => items.into_iter().map(|v| coerce(v, element)).collect(),
+            v => vec![coerce(v, element)],
+        })
+    }
+
+    fn cmp(&mut self, cmp: &'a Cmp) -> Result<Value, RuntimeError> {
+        let v = match cmp {
+            Cmp::Not(v) => return Ok(Value::Bool(!self.truth(v)?)),
+            Cmp::And(v) => return Ok(Value::Bool(self.truth(&v.0)? && self.truth(&v.1)?)),
+            Cmp::Or(v) => return Ok(Value::Bool(self.truth(&v.0)? || self.truth(&v.1)?)),
+            Cmp::Less(v) | Cmp::Greater(v) | Cmp::LessEq(v) | Cmp::GreatEq(v) | Cmp::Equal(v) | Cmp::NotEq(v) => v,
+            Cmp::Err => unreachable!("the interpreter only receives valid programs"),
+        };
+        let (lhs, rhs) = (self.eval(&v.0)?, self.eval(&v.1)?);
+        let order = order(&lhs, &rhs);
+        let result = match cmp {
+            Cmp::Equal(_) => equal(&lhs, &rhs),
+            Cmp::NotEq(_) => !equal(&lhs, &rhs),
+            Cmp::Less(_) => order == Some(Ordering::Less),
+            Cmp::Greater(_) => order == Some(Ordering::Greater),
+            Cmp::LessEq(_) => matches!(order, Some(Ordering::Less | Ordering::Equal)),
+            _ => matches!(order, Some(Ordering::Greater | Ordering::Equal)),
+        };
+        Ok(Value::Bool(result))
+    }
+}
+
+impl Value {
+    /// Text printed for the value, strings and chars are quoted when `nested` inside another value.
+    pub fn show(&self, nested: bool) -> String {
+        let join = |items: &mut dyn Iterator<Item = String>| items.collect::<Vec<_>>().join(", ");
+        match self {
+            Value::Range(start, end) => format!("{start}..{end}"),
+            Value::Int(i) => i.to_string(),
+            Value::Num(n) => show_num(*n),
+            Value::Bool(b) => b.to_string(),
+            Value::Char(c) if nested => format!("\"{c}\""),
+            Value::Char(c) => c.to_string(),
+            Value::Str(s) if nested => format!("\"{s}\""),
+            Value::Str(s) => s.clone(),
+            Value::Tuple(items) => format!("({})", join(&mut items.iter().map(|v| v.show(true)))),
+            Value::Struct(members) => {
+                format!("({})", join(&mut members.iter().map(|(m, v)| format!("{m}: {}", v.show(true)))))
+            }
+            Value::List(items) => format!("[{}]", join(&mut items.iter().map(|v| v.show(true)))),
+            Value::Dict(entries) => {
+                let entries = &mut entries
+                    .iter()
+                    .map(|(k, v)| format!("{} -> {}", k.show(true), v.show(true)));
+                format!("[{}]", join(entries))
+            }
+            Value::Void => String::new(),
+        }
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.show(false))
+    }
+}
+
+/// Like Python's `repr`, the shortest text that parses back into `n`.
+fn show_num(n: f64) -> String {
+    if n.is_nan() {
+        return "nan".into();
+    }
+    if n.is_infinite() {
+        return if n > 0.0 { "inf" } else { "-inf" }.into();
+    }
+    let scientific = format!("{n:e}");
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    if !(-4..16).contains(&exponent) {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        return format!("{mantissa}e{sign}{:02}", exponent.abs());
+    }
+    let digits = mantissa.trim_start_matches('-').replace('.', "").len() as i32;
+    format!("{:.*}", (digits - 1 - exponent).max(1) as usize, n)
+}
+
+fn number(value: &Value) -> f64 {
+    match value {
+        Value::Int(i) => *i as f64,
+        Value::Num(n) => *n,
+        _ => unreachable!("only ints and nums are numbers"),
+    }
+}
+
+/// `base ^ exponent` for ints, negative exponents round towards zero.
+pub(crate) fn pow(base: i64, exponent: i64) -> i64 {
+    match u32::try_from(exponent) {
+        Ok(exponent) => base.wrapping_pow(exponent),
+        Err(_) if exponent > 0 => base.wrapping_pow(u32::MAX),
+        Err(_) if base == 1 => 1,
+        Err(_) if base == -1 => {
+            if exponent % 2 == 0 {
+                1
+            } else {
+                -1
+            }
+        }
+        Err(_) => 0,
+    }
+}
+
+/// Structural equality, where ints and nums with the same value are equal.
+fn equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Range(s0, e0), Value::Range(s1, e1)) => s0 == s1 && e0 == e1,
+        (Value::Int(_) | Value::Num(_), Value::Int(_) | Value::Num(_)) => order(a, b) == Some(Ordering::Equal),
+        (Value::Bool(a), Value::Bool(b)) => a == b,
+        (Value::Char(a), Value::Char(b)) => a == b,
+        (Value::Str(a), Value::Str(b)) => a == b,
+        (Value::Tuple(a) | Value::List(a), Value::Tuple(b) | Value::List(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| equal(a, b))
+        }
+        (Value::Struct(a), Value::Struct(b)) => {
+            a.len() == b.len() && a.iter().all(|(m, v)| b.iter().any(|(n, w)| m == n && equal(v, w)))
+        }
+        (Value::Dict(a), Value::Dict(b)) => {
+            a.len() == b.len() && a.iter().all(|(k, v)| b.iter().any(|(l, w)| equal(k, l) && equal(v, w)))
+        }
+        (Value::Void, Value::Void) => true,
+        _ => false,
+    }
+}
+
+/// Order of two values, lists and tuples are compared lexicographically.
+fn order(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Int(a), Value::Int(b)) => Some(a.cmp(b)),
+        (Value::Int(_) | Value::Num(_), Value::Int(_) | Value::Num(_)) => number(a).partial_cmp(&number(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        (Value::Char(a), Value::Char(b)) => Some(a.cmp(b)),
+        (Value::Str(a), Value::Str(b)) => Some(a.cmp(b)),
+        (Value::Tuple(a) | Value::List(a), Value::Tuple(b) | Value::List(b)) => {
+            for (a, b) in a.iter().zip(b) {
+                match order(a, b)? {
+                    Ordering::Equal => {}
+                    different => return Some(different),
+                }
+            }
+            Some(a.len().cmp(&b.len()))
+        }
+        _ => None,
+    }
+}
+
+/// Adds an entry to a dict, replacing the value of an existing key.
+fn insert(entries: &mut Vec<(Value, Value)>, key: Value, value: Value) {
+    match entries.iter_mut().find(|(k, _)| equal(k, &key)) {
+        Some((_, v)) => *v = value,
+        None => entries.push((key, value)),
+    }
+}
+
+/// Adapts `value` to the type of the variable or argument storing it, like `var n: num = 1` storing `1.0`.
+pub(crate) fn coerce(value: Value, r#type: &Type) -> Value {
+    match (value, r#type) {
+        (Value::Int(i), Type::Num) => Value::Num(i as f64),
+        (Value::Num(n), Type::Int) => Value::Int(n as i64),
+        (Value::Tuple(items), Type::Struct(members)) => Value::Struct(
+            items
+                .into_iter()
+                .zip(members)
+                .map(|(v, (m, t))| (m.clone(), coerce(v, t)))
+                .collect(),
+        ),
+        (Value::Struct(items), Type::Struct(members)) => {
+            let mut items = items;
+            let mut ordered = Vec::new();
+            for (m, t) in members {
+                if let Some(i) = items.iter().position(|(n, _)| n == m) {
+                    let (name, v) = items.remove(i);
+                    ordered.push((name, coerce(v, t)));
+                }
+            }
+            Value::Struct(ordered)
+        }
+        (Value::Tuple(items), Type::Tuple(types)) => {
+            Value::Tuple(items.into_iter().zip(types).map(|(v, t)| coerce(v, t)).collect())
+        }
+        (Value::List(items), Type::List(t)) => Value::List(items.into_iter().map(|v| coerce(v, t)).collect()),
+        (Value::Dict(entries), Type::Dict(d)) => Value::Dict(
+            entries
+                .into_iter()
+                .map(|(k, v)| (coerce(k, &d.0), coerce(v, &d.1)))
+                .collect(),
+        ),
+        (value, _) => value,
+    }
+}
+
+/// `type(value)`, converting every element of lists and dicts.
+pub(crate) fn convert(value: Value, into: &Type) -> Result<Value, String> {
+    match value {
+        Value::List(items) => Ok(Value::List(items.into_iter().map(|v| convert(v, into)).collect::<Result<_, _>>()?)),
+        Value::Dict(entries) => {
+            let mut converted = Vec::new();
+            for (k, v) in entries {
+                insert(&mut converted, convert(k, into)?, convert(v, into)?);
+            }
+            Ok(Value::Dict(converted))
+        }
+        value => {
+            let text = value.show(false);
+            let converted = match (into, value) {
+                (Type::Int, Value::Int(i)) => Some(Value::Int(i)),
+                (Type::Int, Value::Num(n)) => Some(Value::Int(n as i64)),
+                (Type::Int, Value::Bool(b)) => Some(Value::Int(b as i64)),
+                (Type::Int, _) => text.trim().parse().ok().map(Value::Int),
+                (Type::Bool, Value::Int(i)) => Some(Value::Bool(i != 0)),
+                (Type::Bool, Value::Num(n)) => Some(Value::Bool(n != 0.0)),
+                (Type::Bool, Value::Bool(b)) => Some(Value::Bool(b)),
+                (Type::Bool, _) => text.parse().ok().map(Value::Bool),
+                (Type::Char, _) => {
+                    let mut chars = text.chars();
+                    match (chars.next(), chars.next()) {
+                        (Some(c), None) => Some(Value::Char(c)),
+                        _ => None,
+                    }
+                }
+                _ => Some(Value::Str(text.clone())),
+            };
+            let article = match into {
+                Type::Int => "an int",
+                Type::Bool => "a bool",
+                _ => "a char",
+            };
+            converted.ok_or_else(|| format!("ERROR: \"{text}\" can not be parsed into {article}"))
+        }
+    }
+}
+
+/// Value of a variable declared without initialization.
+pub(crate) fn default(r#type: &Type) -> Value {
+    match r#type {
+        Type::Range => Value::Range(0, 0),
+        Type::Int => Value::Int(0),
+        Type::Num => Value::Num(0.0),
+        Type::Bool => Value::Bool(false),
+        Type::Char => Value::Char('\0'),
+        Type::Str => Value::Str(String::new()),
+        Type::Tuple(t) => Value::Tuple(t.iter().map(default).collect()),
+        Type::Struct(members) => Value::Struct(members.iter().map(|(m, t)| (m.clone(), default(t))).collect()),
+        Type::List(_) => Value::List(Vec::new()),
+        Type::Dict(_) => Value::Dict(Vec::new()),
+        Type::Void | Type::Custom(_) | Type::Err => Value::Void,
+    }
+}
@@ -0,0 +1,335 @@
+//! Lowered intermediate representation, where every surface construct is desugared.
+//!
+//! [`lower`] turns a checked [`Main`](crate::tree::Main) into a [`Program`] with structured control flow: `elif`
+//! chains become nested [`StmtKind::If`], `while` loops a [`StmtKind::Loop`] with an explicit exit, and `and`/`or`
+//! branch instead of short-circuiting. Every intermediate result is stored in a typed temporary, numbers are
+//! converted explicitly before operating them, and list, dict and string operations are [`Intrinsic`] calls.
+//!
+//! Backends don't consume the IR: they still translate the checked tree, and the IR is only printed by `emit ir`.
+//! Porting them isn't part of the change that added it, until it's made each backend keeps its own desugaring of the
+//! constructs above.
+use crate::source::Span;
+use crate::tree::{NativeSection, Type};
+use std::fmt::{self, Display, Write};
+
+mod lower;
+
+pub use lower::lower;
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Program {
+    /// The first function runs the top level statements
+    pub functions: Vec<Function>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    /// Locals holding the arguments, in order
+    pub params: Vec<Local>,
+    pub r#type: Type,
+    pub locals: Vec<LocalDecl>,
+    pub body: Vec<Stmt>,
+    /// Sections of a native function, which has no body
+    pub native: Option<Vec<NativeSection>>,
+}
+
+/// Variable or temporary of a function, an index into [`Function::locals`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Local(pub u32);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalDecl {
+    /// Name of the variable, temporaries have none
+    pub name: Option<String>,
+    pub r#type: Type,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StmtKind {
+    /// Defines `local`, which is only visible in the rest of the block
+    Let {
+        local: Local,
+        value: Rvalue,
+    },
+    Assign {
+        local: Local,
+        value: Rvalue,
+    },
+    /// Evaluates a value only for its effects
+    Eval(Rvalue),
+    If {
+        cond: Operand,
+        then: Vec<Stmt>,
+        otherwise: Vec<Stmt>,
+    },
+    /// Repeats its body until a [`StmtKind::Break`]
+    Loop(Vec<Stmt>),
+    Break,
+    /// Binds every element of a list or a range to `var`, `iter` is evaluated once before the loop
+    For {
+        var: Local,
+        iter: Operand,
+        body: Vec<Stmt>,
+    },
+    Return(Operand),
+    /// Code copied verbatim into the targets it has a section for
+    Native(Vec<NativeSection>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rvalue {
+    Use(Operand),
+    /// Arithmetic on two ints or two nums, with the type of the local it's stored in
+    Binary(BinOp, Operand, Operand),
+    /// Comparison of two values of the same type
+    Compare(Comparison, Operand, Operand),
+    Not(Operand),
+    /// Conversion written in the source, like `str(1)`
+    Convert(Operand, Type),
+    /// Implicit conversion of a value into a type with a different representation, like an int into a num
+    Coerce(Operand, Type),
+    Intrinsic(Intrinsic, Vec<Operand>),
+    Call(String, Vec<Operand>),
+    Tuple(Vec<Operand>),
+    /// Members are always in the order of the type of the struct
+    Struct(Vec<(String, Operand)>),
+    List(Vec<Operand>),
+    Dict(Vec<(Operand, Operand)>),
+    /// Member of a tuple or a struct, by position
+    Field(Operand, usize),
+    /// Value a declared variable starts with
+    Default(Type),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Local(Local),
+    Const(Const),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Const {
+    Int(i64),
+    Num(f64),
+    Bool(bool),
+    Char(char),
+    Str(String),
+    Range(i64, i64),
+    Void,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Pow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Less,
+    LessEq,
+    Greater,
+    GreatEq,
+    Equal,
+    NotEq,
+}
+
+/// Operation on builtin values that every target implements in its own way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Intrinsic {
+    /// `list + element`
+    ListAppend,
+    /// `list + list`
+    ListExtend,
+    /// `list - list`, removing the first occurrence of every element
+    ListRemove,
+    /// `list -- list`, removing every occurrence of every element
+    ListRemoveAll,
+    /// Element at an index, failing when it's out of bounds
+    ListGet,
+    /// Value of a key, failing when it's missing
+    DictGet,
+    /// Joins a str with a str or a char
+    Concat,
+    /// Text printed for a value
+    Show,
+    /// Text printed for a value inside another one, with strings and chars quoted
+    ShowNested,
+    Print,
+    Println,
+}
+
+impl Program {
+    /// Function named `name`, `main` is the one running the top level statements.
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.iter().find(|f| f.name == name)
+    }
+}
+
+impl Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, function) in self.functions.iter().enumerate() {
+            if i != 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{function}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let params = self.params.iter().map(|p| self.declaration(*p)).collect::<Vec<_>>();
+        writeln!(f, "fn {}({}) -> {}", self.name, params.join(", "), self.r#type)?;
+        match &self.native {
+            Some(sections) => {
+                let targets = sections.iter().map(|s| s.target.as_str()).collect::<Vec<_>>();
+                writeln!(f, "    native {}", targets.join(", "))?;
+            }
+            None => {
+                let mut text = String::new();
+                self.block(&mut text, &self.body, 1)?;
+                write!(f, "{text}")?;
+            }
+        }
+        writeln!(f, "end")
+    }
+}
+
+impl Function {
+    pub fn local(&self, local: Local) -> &LocalDecl {
+        &self.locals[local.0 as usize]
+    }
+
+    /// Local with its name and type, like `%0 a: int`.
+    fn declaration(&self, local: Local) -> String {
+        let decl = self.local(local);
+        match &decl.name {
+            Some(name) => format!("{local} {name}: {}", decl.r#type),
+            None => format!("{local}: {}", decl.r#type),
+        }
+    }
+
+    fn block(&self, text: &mut String, stmts: &[Stmt], depth: usize) -> fmt::Result {
+        let indent = "    ".repeat(depth);
+        for stmt in stmts {
+            match &stmt.kind {
+                StmtKind::Let { local, value } => writeln!(text, "{indent}let {} = {value}", self.declaration(*local))?,
+                StmtKind::Assign { local, value } => writeln!(text, "{indent}{local} = {value}")?,
+                StmtKind::Eval(value) => writeln!(text, "{indent}{value}")?,
+                StmtKind::If { cond, then, otherwise } => {
+                    writeln!(text, "{indent}if {cond}")?;
+                    self.block(text, then, depth + 1)?;
+                    if !otherwise.is_empty() {
+                        writeln!(text, "{indent}else")?;
+                        self.block(text, otherwise, depth + 1)?;
+                    }
+                    writeln!(text, "{indent}end")?;
+                }
+                StmtKind::Loop(body) => {
+                    writeln!(text, "{indent}loop")?;
+                    self.block(text, body, depth + 1)?;
+                    writeln!(text, "{indent}end")?;
+                }
+                StmtKind::Break => writeln!(text, "{indent}break")?,
+                StmtKind::For { var, iter, body } => {
+                    writeln!(text, "{indent}for {} in {iter}", self.declaration(*var))?;
+                    self.block(text, body, depth + 1)?;
+                    writeln!(text, "{indent}end")?;
+                }
+                StmtKind::Return(value) => writeln!(text, "{indent}return {value}")?,
+                StmtKind::Native(sections) => {
+                    let targets = sections.iter().map(|s| s.target.as_str()).collect::<Vec<_>>();
+                    writeln!(text, "{indent}native {}", targets.join(", "))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for Local {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+impl Display for Rvalue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rvalue::Use(operand) => write!(f, "{operand}"),
+            Rvalue::Binary(op, lhs, rhs) => write!(f, "{} {lhs}, {rhs}", snake_case(op)),
+            Rvalue::Compare(c, lhs, rhs) => write!(f, "cmp {lhs} {} {rhs}", comparison(*c)),
+            Rvalue::Not(operand) => write!(f, "not {operand}"),
+            Rvalue::Convert(operand, r#type) => write!(f, "convert {operand} into {type}"),
+            Rvalue::Coerce(operand, r#type) => write!(f, "coerce {operand} into {type}"),
+            Rvalue::Intrinsic(intrinsic, args) => write!(f, "{}({})", snake_case(intrinsic), list(args)),
+            Rvalue::Call(name, args) => write!(f, "call {name}({})", list(args)),
+            Rvalue::Tuple(items) => write!(f, "({})", list(items)),
+            Rvalue::Struct(members) => {
+                let members = members.iter().map(|(m, v)| format!("{m}: {v}")).collect::<Vec<_>>();
+                write!(f, "{{{}}}", members.join(", "))
+            }
+            Rvalue::List(items) => write!(f, "[{}]", list(items)),
+            Rvalue::Dict(pairs) => {
+                let pairs = pairs.iter().map(|(k, v)| format!("{k}: {v}")).collect::<Vec<_>>();
+                write!(f, "dict{{{}}}", pairs.join(", "))
+            }
+            Rvalue::Field(operand, i) => write!(f, "{operand}.{i}"),
+            Rvalue::Default(r#type) => write!(f, "default {type}"),
+        }
+    }
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Local(local) => write!(f, "{local}"),
+            Operand::Const(Const::Int(i)) => write!(f, "{i}"),
+            Operand::Const(Const::Num(n)) => write!(f, "{n:?}"),
+            Operand::Const(Const::Bool(b)) => write!(f, "{b}"),
+            Operand::Const(Const::Char(c)) => write!(f, "{c:?}"),
+            Operand::Const(Const::Str(s)) => write!(f, "{s:?}"),
+            Operand::Const(Const::Range(start, end)) => write!(f, "{start}..{end}"),
+            Operand::Const(Const::Void) => write!(f, "void"),
+        }
+    }
+}
+
+fn list(operands: &[Operand]) -> String {
+    operands.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+}
+
+fn comparison(c: Comparison) -> &'static str {
+    match c {
+        Comparison::Less => "<",
+        Comparison::LessEq => "<=",
+        Comparison::Greater => ">",
+        Comparison::GreatEq => ">=",
+        Comparison::Equal => "==",
+        Comparison::NotEq => "!=",
+    }
+}
+
+fn snake_case(value: &impl fmt::Debug) -> String {
+    let mut snake = String::new();
+    for (i, c) in format!("{value:?}").chars().enumerate() {
+        if c.is_uppercase() && i != 0 {
+            snake.push('_');
+        }
+        snake.push(c.to_ascii_lowercase());
+    }
+    snake
+}
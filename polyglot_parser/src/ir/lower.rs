@@ -0,0 +1,495 @@
+use super::{
+    BinOp, Comparison, Const, Function, Intrinsic, Local, LocalDecl, Operand, Program, Rvalue, Stmt, StmtKind,
+};
+use crate::backend::Types;
+use crate::source::Span;
+use crate::tree::{self, interpolation, Cmp, Expr, ExprKind, ListAccessMode, Main, Op, Segment, TupleAccessMode};
+use crate::tree::{Type, ValueKind};
+use rustc_hash::FxHashMap;
+
+/// Lowers a checked program into the intermediate representation.
+pub fn lower(main: &Main) -> Program {
+    let mut lowering = Lowering {
+        main,
+        types: Types::new(),
+        program: Program::default(),
+        function: Builder::new("main", &[], Type::Void),
+    };
+    for expr in &main.exprs {
+        lowering.expr(expr);
+    }
+    let function = lowering.function.finish();
+    lowering.program.functions.insert(0, function);
+    lowering.program
+}
+
+/// Function being lowered.
+struct Builder {
+    function: Function,
+    /// Local of each variable, with one map per block
+    scopes: Vec<FxHashMap<String, Local>>,
+    /// Statements of the blocks being lowered, the innermost one last
+    blocks: Vec<Vec<Stmt>>,
+}
+
+impl Builder {
+    fn new(name: &str, params: &[(String, Type)], r#type: Type) -> Builder {
+        let mut builder = Builder {
+            function: Function {
+                name: name.to_owned(),
+                params: Vec::new(),
+                r#type,
+                locals: Vec::new(),
+                body: Vec::new(),
+                native: None,
+            },
+            scopes: vec![FxHashMap::default()],
+            blocks: vec![Vec::new()],
+        };
+        for (param, t) in params {
+            let local = builder.declare(Some(param), t.clone());
+            builder.function.params.push(local);
+        }
+        builder
+    }
+
+    /// New local, temporaries have no `name`.
+    fn declare(&mut self, name: Option<&str>, r#type: Type) -> Local {
+        let local = Local(self.function.locals.len() as u32);
+        self.function.locals.push(LocalDecl {
+            name: name.map(str::to_owned),
+            r#type,
+        });
+        if let Some(name) = name {
+            self.scopes.last_mut().unwrap().insert(name.to_owned(), local);
+        }
+        local
+    }
+
+    fn finish(mut self) -> Function {
+        self.function.body = self.blocks.pop().unwrap();
+        self.function
+    }
+}
+
+struct Lowering<'a> {
+    main: &'a Main,
+    /// Signatures of the functions declared so far
+    types: Types,
+    program: Program,
+    function: Builder,
+}
+
+impl Lowering<'_> {
+    fn emit(&mut self, kind: StmtKind, span: Span) {
+        self.function.blocks.last_mut().unwrap().push(Stmt { kind, span });
+    }
+
+    fn enter(&mut self) {
+        self.function.scopes.push(FxHashMap::default());
+        self.function.blocks.push(Vec::new());
+    }
+
+    fn leave(&mut self) -> Vec<Stmt> {
+        self.function.scopes.pop();
+        self.function.blocks.pop().unwrap()
+    }
+
+    fn block(&mut self, exprs: &[Expr]) -> Vec<Stmt> {
+        self.enter();
+        for expr in exprs {
+            self.expr(expr);
+        }
+        self.leave()
+    }
+
+    fn local(&self, name: &str) -> Local {
+        match self.function.scopes.iter().rev().find_map(|scope| scope.get(name)) {
+            Some(local) => *local,
+            None => unreachable!("the checker rejects undeclared variables"),
+        }
+    }
+
+    fn type_of(&self, local: Local) -> Type {
+        self.function.function.local(local).r#type.clone()
+    }
+
+    /// Stores `value` in a new temporary.
+    fn temp(&mut self, value: Rvalue, r#type: Type, span: Span) -> Operand {
+        let local = self.function.declare(None, r#type);
+        self.emit(StmtKind::Let { local, value }, span);
+        Operand::Local(local)
+    }
+
+    /// Returns the default value of `r#type`, as functions can't return anything else yet.
+    fn ret(&mut self, r#type: &Type, span: Span) {
+        let value = match r#type {
+            Type::Void => Operand::Const(Const::Void),
+            t => self.temp(Rvalue::Default(t.clone()), t.clone(), span),
+        };
+        self.emit(StmtKind::Return(value), span);
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        let span = expr.span;
+        match &expr.kind {
+            ExprKind::Init { name, r#type, value } => {
+                // The value is lowered first, as it can refer to a variable it shadows
+                let value = self.coerced_rvalue(value, r#type);
+                let local = self.function.declare(Some(name), r#type.clone());
+                self.emit(StmtKind::Let { local, value }, span);
+            }
+            ExprKind::Decl { name, r#type } => {
+                let local = self.function.declare(Some(name), r#type.clone());
+                self.emit(
+                    StmtKind::Let {
+                        local,
+                        value: Rvalue::Default(r#type.clone()),
+                    },
+                    span,
+                );
+            }
+            ExprKind::Assig { name, value } => {
+                let local = self.local(name);
+                let value = self.coerced_rvalue(value, &self.type_of(local));
+                self.emit(StmtKind::Assign { local, value }, span);
+            }
+            ExprKind::Typedef { .. } => {}
+            ExprKind::If { cmp, exprs, elif } | ExprKind::Elif { cmp, exprs, elif } => {
+                let cond = self.operand(cmp);
+                let then = self.block(exprs);
+                let otherwise = match elif.as_deref() {
+                    Some(Expr {
+                        kind: ExprKind::Else { exprs },
+                        ..
+                    }) => self.block(exprs),
+                    Some(elif) => {
+                        self.enter();
+                        self.expr(elif);
+                        self.leave()
+                    }
+                    None => Vec::new(),
+                };
+                self.emit(StmtKind::If { cond, then, otherwise }, span);
+            }
+            ExprKind::Else { exprs } => {
+                let body = self.block(exprs);
+                self.function.blocks.last_mut().unwrap().extend(body);
+            }
+            ExprKind::For { var, range, exprs } => {
+                let element = self.types.element_of(range);
+                // The range is evaluated once, so the body can modify the original list
+                let iter = match self.rvalue(range) {
+                    Rvalue::Use(Operand::Const(c)) => Operand::Const(c),
+                    value => self.temp(value, range.r#type.clone(), range.span),
+                };
+                self.enter();
+                let var = self.function.declare(Some(var), element);
+                for expr in exprs {
+                    self.expr(expr);
+                }
+                let body = self.leave();
+                self.emit(StmtKind::For { var, iter, body }, span);
+            }
+            ExprKind::While { cmp, exprs } => {
+                self.enter();
+                let cond = self.operand(cmp);
+                let done = self.temp(Rvalue::Not(cond), Type::Bool, cmp.span);
+                let exit = vec![Stmt {
+                    kind: StmtKind::Break,
+                    span,
+                }];
+                self.emit(
+                    StmtKind::If {
+                        cond: done,
+                        then: exit,
+                        otherwise: Vec::new(),
+                    },
+                    span,
+                );
+                let body = self.block(exprs);
+                self.function.blocks.last_mut().unwrap().extend(body);
+                let body = self.leave();
+                self.emit(StmtKind::Loop(body), span);
+            }
+            ExprKind::Fn {
+                name,
+                r#type,
+                args,
+                exprs,
+            } => {
+                self.types.insert_fn(name, r#type.clone(), args);
+                // Functions only see their arguments
+                let outer = std::mem::replace(&mut self.function, Builder::new(name, args, r#type.clone()));
+                for expr in exprs {
+                    self.expr(expr);
+                }
+                self.ret(r#type, span);
+                let lowered = std::mem::replace(&mut self.function, outer);
+                self.program.functions.push(lowered.finish());
+            }
+            ExprKind::Call { name, args } => {
+                if let Some(call) = self.call(name, args, span) {
+                    self.emit(StmtKind::Eval(call), span);
+                }
+            }
+            ExprKind::Native { sections } => self.emit(StmtKind::Native(sections.clone()), span),
+            ExprKind::NativeFn {
+                name,
+                r#type,
+                args,
+                sections,
+            } => {
+                self.types.insert_fn(name, r#type.clone(), args);
+                let mut function = Builder::new(name, args, r#type.clone()).finish();
+                function.native = Some(sections.clone());
+                self.program.functions.push(function);
+            }
+            ExprKind::Err => unreachable!("only valid programs are lowered"),
+        }
+    }
+
+    /// Lowers a call, builtins are emitted as a statement as they have no value.
+    fn call(&mut self, name: &str, args: &[tree::Value], span: Span) -> Option<Rvalue> {
+        if self.types.is_builtin(name) {
+            self.builtin(name, &args[0], span);
+            return None;
+        }
+        let params = self.types.get_fn(name).map(|f| f.args.clone()).unwrap_or_default();
+        let args = args
+            .iter()
+            .zip(&params)
+            .map(|(arg, param)| self.coerced(arg, param))
+            .collect();
+        Some(Rvalue::Call(name.to_owned(), args))
+    }
+
+    fn builtin(&mut self, name: &str, arg: &tree::Value, span: Span) {
+        let str = |s: &str| Operand::Const(Const::Str(s.to_owned()));
+        let text = match (name, &arg.kind) {
+            ("dbg", _) => {
+                let source = self.main.source_map.snippet(arg.span).unwrap_or_default();
+                let value = self.operand(arg);
+                let shown = self.temp(Rvalue::Intrinsic(Intrinsic::ShowNested, vec![value]), Type::Str, span);
+                let text = Rvalue::Intrinsic(Intrinsic::Concat, vec![str(&format!("{source} = ")), shown]);
+                self.temp(text, Type::Str, span)
+            }
+            (_, ValueKind::Str(s)) => {
+                let mut parts = Vec::new();
+                for (_, segment) in interpolation(s) {
+                    parts.push(match segment {
+                        Segment::Text(t) => str(t),
+                        Segment::Var(var) => {
+                            let var = Operand::Local(self.local(var));
+                            self.temp(Rvalue::Intrinsic(Intrinsic::Show, vec![var]), Type::Str, arg.span)
+                        }
+                    });
+                }
+                let mut parts = parts.into_iter();
+                let first = parts.next().unwrap_or_else(|| str(""));
+                parts.fold(first, |text, part| {
+                    self.temp(Rvalue::Intrinsic(Intrinsic::Concat, vec![text, part]), Type::Str, arg.span)
+                })
+            }
+            _ => {
+                let value = self.operand(arg);
+                self.temp(Rvalue::Intrinsic(Intrinsic::Show, vec![value]), Type::Str, arg.span)
+            }
+        };
+        let print = if name == "print" {
+            Intrinsic::Print
+        } else {
+            Intrinsic::Println
+        };
+        self.emit(StmtKind::Eval(Rvalue::Intrinsic(print, vec![text])), span);
+    }
+
+    /// Lowers `value` into an operand, storing it in a temporary if it isn't a constant or a variable.
+    fn operand(&mut self, value: &tree::Value) -> Operand {
+        match self.rvalue(value) {
+            Rvalue::Use(operand) => operand,
+            lowered => self.temp(lowered, value.r#type.clone(), value.span),
+        }
+    }
+
+    /// Lowers `value` and adapts it to `r#type`, only when their representations differ.
+    fn coerced(&mut self, value: &tree::Value, r#type: &Type) -> Operand {
+        match self.coerced_rvalue(value, r#type) {
+            Rvalue::Use(operand) => operand,
+            lowered => self.temp(lowered, r#type.clone(), value.span),
+        }
+    }
+
+    fn coerced_rvalue(&mut self, value: &tree::Value, r#type: &Type) -> Rvalue {
+        if value.r#type.identical(r#type) {
+            self.rvalue(value)
+        } else {
+            Rvalue::Coerce(self.operand(value), r#type.clone())
+        }
+    }
+
+    fn rvalue(&mut self, value: &tree::Value) -> Rvalue {
+        let constant = |c| Rvalue::Use(Operand::Const(c));
+        match &value.kind {
+            ValueKind::Range((start, end)) => constant(Const::Range(*start, *end)),
+            ValueKind::Int(i) => constant(Const::Int(*i)),
+            ValueKind::Num(n) => constant(Const::Num(*n)),
+            ValueKind::Bool(b) => constant(Const::Bool(*b)),
+            ValueKind::Char(c) => constant(Const::Char(*c)),
+            ValueKind::Str(s) => constant(Const::Str(s.clone())),
+            ValueKind::Var(name) => Rvalue::Use(Operand::Local(self.local(name))),
+            ValueKind::Parenthesis(p) => self.rvalue(p),
+            ValueKind::Tuple(items) => Rvalue::Tuple(items.iter().map(|item| self.operand(item)).collect()),
+            ValueKind::Struct(members) => {
+                Rvalue::Struct(members.iter().map(|(m, v)| (m.clone(), self.operand(v))).collect())
+            }
+            ValueKind::TupleAccess { name, access_mode, .. } => {
+                let local = self.local(name);
+                // Struct values always have the members in the order of their type
+                let field = match (self.type_of(local), access_mode) {
+                    (_, TupleAccessMode::Index(i)) => *i,
+                    (Type::Struct(members), TupleAccessMode::Member(m)) => {
+                        members.iter().position(|(n, _)| n == m).unwrap()
+                    }
+                    _ => unreachable!("the checker only allows accessing tuples and structs"),
+                };
+                Rvalue::Field(Operand::Local(local), field)
+            }
+            ValueKind::List(items) => {
+                let element = items[0].r#type.clone();
+                Rvalue::List(items.iter().map(|item| self.coerced(item, &element)).collect())
+            }
+            ValueKind::ListAccess { name, access_mode, .. } => {
+                let local = Operand::Local(self.local(name));
+                match access_mode {
+                    ListAccessMode::List(index) => {
+                        let index = self.operand(index);
+                        Rvalue::Intrinsic(Intrinsic::ListGet, vec![local, index])
+                    }
+                    ListAccessMode::Dict(key) => {
+                        let key = self.operand(key);
+                        Rvalue::Intrinsic(Intrinsic::DictGet, vec![local, key])
+                    }
+                }
+            }
+            ValueKind::Dict(pairs) => {
+                let (key, value) = (pairs[0].0.r#type.clone(), pairs[0].1.r#type.clone());
+                Rvalue::Dict(
+                    pairs
+                        .iter()
+                        .map(|(k, v)| (self.coerced(k, &key), self.coerced(v, &value)))
+                        .collect(),
+                )
+            }
+            ValueKind::Op(op) => self.op(op),
+            ValueKind::Cmp(cmp) => self.cmp(cmp, value.span),
+            ValueKind::Conversion { r#type, value } => Rvalue::Convert(self.operand(value), r#type.clone()),
+            ValueKind::Call { name, args } => self
+                .call(name, args, value.span)
+                .unwrap_or(Rvalue::Use(Operand::Const(Const::Void))),
+            ValueKind::RetExpr(r) => match *r {},
+            ValueKind::Err => unreachable!("only valid programs are lowered"),
+        }
+    }
+
+    /// Lowers an element added to or removed from a list of type `list`, returning whether it's a whole list.
+    fn list_operand(&mut self, list: &Type, value: &tree::Value) -> (Operand, bool) {
+        let whole = value.r#type == *list;
+        let operand = match list {
+            Type::List(element) if !whole => self.coerced(value, element),
+            _ => self.coerced(value, list),
+        };
+        (operand, whole)
+    }
+
+    /// Lowers the elements removed from a list, wrapping a single element into a list.
+    fn removed(&mut self, list: &Type, value: &tree::Value) -> Operand {
+        match self.list_operand(list, value) {
+            (operand, true) => operand,
+            (operand, false) => self.temp(Rvalue::List(vec![operand]), list.clone(), value.span),
+        }
+    }
+
+    fn op(&mut self, op: &Op) -> Rvalue {
+        let (v, binary) = match op {
+            Op::Add(v) => (v, BinOp::Add),
+            Op::Sub(v) => (v, BinOp::Sub),
+            Op::Mul(v) => (v, BinOp::Mul),
+            Op::Div(v) => (v, BinOp::Div),
+            Op::Mod(v) => (v, BinOp::Mod),
+            Op::Pow(v) => (v, BinOp::Pow),
+            Op::ListRemoveAll(v) => {
+                let local = self.local(&v.0);
+                let removed = self.removed(&self.type_of(local), &v.1);
+                return Rvalue::Intrinsic(Intrinsic::ListRemoveAll, vec![Operand::Local(local), removed]);
+            }
+        };
+
+        let (lhs, rhs) = (&v.0.r#type, &v.1.r#type);
+        match (lhs, rhs) {
+            (Type::List(_), _) => {
+                let list = self.operand(&v.0);
+                if binary == BinOp::Sub {
+                    let removed = self.removed(lhs, &v.1);
+                    return Rvalue::Intrinsic(Intrinsic::ListRemove, vec![list, removed]);
+                }
+                let (added, whole) = self.list_operand(lhs, &v.1);
+                let intrinsic = if whole {
+                    Intrinsic::ListExtend
+                } else {
+                    Intrinsic::ListAppend
+                };
+                Rvalue::Intrinsic(intrinsic, vec![list, added])
+            }
+            (Type::Str | Type::Char, _) => {
+                let operands = vec![self.operand(&v.0), self.operand(&v.1)];
+                Rvalue::Intrinsic(Intrinsic::Concat, operands)
+            }
+            (Type::Int, Type::Int) => Rvalue::Binary(binary, self.operand(&v.0), self.operand(&v.1)),
+            // Operating an int with a num results in a num
+            _ => {
+                let lhs = self.coerced(&v.0, &Type::Num);
+                let rhs = self.coerced(&v.1, &Type::Num);
+                Rvalue::Binary(binary, lhs, rhs)
+            }
+        }
+    }
+
+    fn cmp(&mut self, cmp: &Cmp, span: Span) -> Rvalue {
+        let (v, comparison) = match cmp {
+            Cmp::Not(v) => return Rvalue::Not(self.operand(v)),
+            Cmp::And(v) | Cmp::Or(v) => {
+                // Short-circuits, the right operand is only evaluated when the left one doesn't decide the result
+                let lhs = self.rvalue(&v.0);
+                let local = self.function.declare(None, Type::Bool);
+                self.emit(StmtKind::Let { local, value: lhs }, span);
+                self.enter();
+                let rhs = self.rvalue(&v.1);
+                self.emit(StmtKind::Assign { local, value: rhs }, span);
+                let rhs = self.leave();
+                let (then, otherwise) = match cmp {
+                    Cmp::And(_) => (rhs, Vec::new()),
+                    _ => (Vec::new(), rhs),
+                };
+                let cond = Operand::Local(local);
+                self.emit(StmtKind::If { cond, then, otherwise }, span);
+                return Rvalue::Use(Operand::Local(local));
+            }
+            Cmp::Less(v) => (v, Comparison::Less),
+            Cmp::Greater(v) => (v, Comparison::Greater),
+            Cmp::LessEq(v) => (v, Comparison::LessEq),
+            Cmp::GreatEq(v) => (v, Comparison::GreatEq),
+            Cmp::Equal(v) => (v, Comparison::Equal),
+            Cmp::NotEq(v) => (v, Comparison::NotEq),
+            Cmp::Err => unreachable!("only valid programs are lowered"),
+        };
+        match (&v.0.r#type, &v.1.r#type) {
+            (Type::Int, Type::Num) | (Type::Num, Type::Int) => {
+                let lhs = self.coerced(&v.0, &Type::Num);
+                let rhs = self.coerced(&v.1, &Type::Num);
+                Rvalue::Compare(comparison, lhs, rhs)
+            }
+            _ => Rvalue::Compare(comparison, self.operand(&v.0), self.operand(&v.1)),
+        }
+    }
+}
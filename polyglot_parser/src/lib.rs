@@ -1,9 +1,24 @@
+pub mod backend;
+pub mod diagnostic;
+pub mod fix;
+pub mod fmt;
+pub mod fold;
+pub mod harness;
+pub mod interpreter;
+pub mod ir;
 pub mod parser;
+pub mod prune;
+pub mod serialize;
+pub mod source;
 pub mod tree;
+pub mod visit;
+pub mod vm;
 
 #[cfg(test)]
 mod tests {
+    use crate::diagnostic::ErrorCode;
     use crate::parser::*;
+    use crate::source::Span;
     use crate::tree::*;
     use assert_cmd::prelude::*;
     use predicates::prelude::*;
@@ -25,32 +40,1939 @@ mod tests {
 
             file.write_str(code)?;
             let out = parse(file.path(), false).unwrap();
-            let correct = create_main(vec![
-                create_init("a", Type::Int, Value::Int(1), "var a = 1"),
-                create_init("b", Type::Int, Value::Int(-1), "var b = -1"),
-                create_init("c", Type::Int, Value::Int(-555), "var c = -555"),
-                create_init("d", Type::Int, Value::Int(9999999), "var d = 9999999"),
+            let correct = vec![
+                create_init(code, "a", Type::Int, ValueKind::Int(1), "var a = 1"),
+                create_init(code, "b", Type::Int, ValueKind::Int(-1), "var b = -1"),
+                create_init(code, "c", Type::Int, ValueKind::Int(-555), "var c = -555"),
+                create_init(code, "d", Type::Int, ValueKind::Int(9999999), "var d = 9999999"),
+            ];
+
+            assert_eq!(out.exprs, correct);
+            Ok(())
+        }
+    }
+
+    mod types {
+        use super::*;
+
+        #[test]
+        fn annotated() {
+            let code = "var l = [1, 2]\nvar n = l[0] + 1.5\nl += 3\n";
+            let (out, diagnostics) = check("test.pg".as_ref(), code);
+            assert!(diagnostics.is_empty());
+
+            let ExprKind::Init { value, .. } = &out.exprs[1].kind else {
+                panic!("expected an init, found {:?}", out.exprs[1]);
+            };
+            let ValueKind::Op(Op::Add(operands)) = &value.kind else {
+                panic!("expected an addition, found {value:?}");
+            };
+            assert_eq!((&operands.0.r#type, &value.r#type), (&Type::Int, &Type::Num));
+            // Appending an element keeps the type of the list
+            let ExprKind::Assig { value, .. } = &out.exprs[2].kind else {
+                panic!("expected an assignment, found {:?}", out.exprs[2]);
+            };
+            assert!(matches!(&value.r#type, Type::List(element) if **element == Type::Int));
+        }
+
+        #[test]
+        fn operators() {
+            let code = "var a = true / false\nvar b = \"x\" - \"y\"\nvar c = (1, 2) * (1, 2)\nvar d = [1] ^ [1]\nvar e = 'a' + 'b'\nvar f = [1] - 1\nvar s = \"a\"\ns %= \"b\"\n";
+            let (_, diagnostics) = check("test.pg".as_ref(), code);
+            assert!(diagnostics.iter().all(|d| d.code == ErrorCode::InvalidOperandType), "{diagnostics:?}");
+            let lines = diagnostics
+                .iter()
+                .map(|d| code[..d.labels[0].span.start].matches('\n').count() + 1)
+                .collect::<Vec<_>>();
+            assert_eq!(lines, [1, 2, 3, 4, 8]);
+
+            // Adding chars joins them, even when they're not literals
+            let (out, diagnostics) = check("test.pg".as_ref(), "var a = 'a'\nvar b = a + 'b'\n");
+            assert!(diagnostics.is_empty(), "{diagnostics:?}");
+            let ExprKind::Init { value, .. } = &out.exprs[1].kind else {
+                panic!("expected an init, found {:?}", out.exprs[1]);
+            };
+            assert!(matches!(value.r#type, Type::Str));
+        }
+
+        #[test]
+        fn conditions_and_loops() {
+            let code = "var a = 5\nif a\nelif a == 5\nend\nwhile \"yes\"\nend\nfor j in a\n    println(j)\nend\nvar r = 0..2\nfor i in r\n    var n: int = i\nend\n";
+            let (_, diagnostics) = check("test.pg".as_ref(), code);
+            let found = diagnostics.iter().map(|d| (d.code, &code[d.labels[0].span.range()])).collect::<Vec<_>>();
+            assert_eq!(
+                found,
+                [
+                    (ErrorCode::WrongConditionType, "a"),
+                    (ErrorCode::WrongConditionType, "\"yes\""),
+                    (ErrorCode::NotIterable, "a"),
+                ]
+            );
+        }
+
+        #[test]
+        fn argument_count() {
+            let code = "fn f(a: int, b: int)\nend\nfn g(a: int)\nend\nf(1)\ng(1, c)\nvar n = 1 + f(1, 2, 3)\n";
+            let (_, diagnostics) = check("test.pg".as_ref(), code);
+            let found = diagnostics.iter().map(|d| (d.code, &code[d.labels[0].span.range()])).collect::<Vec<_>>();
+            assert_eq!(
+                found,
+                [
+                    (ErrorCode::WrongArgumentType, "f(1)"),
+                    (ErrorCode::UndeclaredVariable, "c"),
+                    (ErrorCode::WrongArgumentType, "g(1, c)"),
+                    (ErrorCode::WrongArgumentType, "f(1, 2, 3)"),
+                ]
+            );
+        }
+
+        #[test]
+        fn serialized() {
+            let (out, _) = check("test.pg".as_ref(), "var a = (1, 'c')\n");
+            let yaml = serde_yaml::to_value(&out).unwrap();
+            let value = &yaml["exprs"][0]["kind"]["Init"]["value"];
+            assert_eq!(value["type"]["Tuple"][1], "Char");
+            assert_eq!(value["kind"]["Tuple"][0]["type"], "Int");
+        }
+    }
+
+    mod grammar {
+        use super::*;
+
+        #[test]
+        fn parenthesized_conditions() {
+            let code = "var a = 1\nif (a > 0) && (a < 5)\n    a = 2\nelif (a < 0) || (a > 9)\n    a = 3\nend\n";
+            let (out, diagnostics) = check("test.pg".as_ref(), code);
+            assert!(diagnostics.is_empty(), "{diagnostics:?}");
+            let ExprKind::If { cmp, elif: Some(elif), .. } = &out.exprs[1].kind else {
+                panic!("expected an if, found {:?}", out.exprs[1]);
+            };
+            assert!(matches!(&cmp.kind, ValueKind::Cmp(Cmp::And(_))), "{cmp:?}");
+            assert!(matches!(&elif.kind, ExprKind::Elif { cmp, .. } if matches!(cmp.kind, ValueKind::Cmp(Cmp::Or(_)))));
+        }
+
+        #[test]
+        fn chars() {
+            let (out, diagnostics) = check("test.pg".as_ref(), "var a = ' '\nvar b = '1'\nvar c = '\u{e9}'\nvar d = '\"'\n");
+            assert!(diagnostics.is_empty(), "{diagnostics:?}");
+            let chars = out
+                .exprs
+                .iter()
+                .map(|expr| match &expr.kind {
+                    ExprKind::Init { value, .. } => value.kind.clone(),
+                    kind => panic!("expected an init, found {kind:?}"),
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(chars, ['\u{20}', '1', '\u{e9}', '"'].map(ValueKind::Char));
+        }
+    }
+
+    mod builtins {
+        use super::*;
+
+        #[test]
+        fn arguments() {
+            let code = "fn f\nend\nprintln(1, 2)\nprint(\"{missing} {code}\")\ndbg(f())\ndbg(\"{missing}\")\nvar code = 1\n";
+            let (_, diagnostics) = check("test.pg".as_ref(), code);
+            let found = diagnostics
+                .iter()
+                .map(|d| (d.code, &code[d.labels[0].span.range()]))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                found,
+                [
+                    (ErrorCode::WrongArgumentType, "println(1, 2)"),
+                    (ErrorCode::UndeclaredVariable, "missing"),
+                    (ErrorCode::UndeclaredVariable, "code"),
+                    (ErrorCode::WrongArgumentType, "f()"),
+                ]
+            );
+        }
+
+        #[test]
+        fn replaced() {
+            let (out, diagnostics) = check("test.pg".as_ref(), "fn print(a: int)\nend\nprint(1)\nprint(\"a\")\n");
+            assert_eq!(diagnostics.len(), 1);
+            assert_eq!(diagnostics[0].code, ErrorCode::WrongArgumentType);
+            assert!(matches!(&out.exprs[1].kind, ExprKind::Call { name, .. } if name == "print"));
+        }
+    }
+
+    mod fold {
+        use super::*;
+
+        fn values(code: &str) -> Vec<ValueKind> {
+            let (out, diagnostics) = check("test.pg".as_ref(), code);
+            assert!(diagnostics.is_empty(), "{diagnostics:?}");
+            out.exprs
+                .into_iter()
+                .filter_map(|expr| match expr.kind {
+                    ExprKind::Init { value, .. } => Some(value.kind),
+                    _ => None,
+                })
+                .collect()
+        }
+
+        #[test]
+        fn constants() {
+            let folded = values("var a = 5 + 6\nvar b = \"a\" + \"b\" + \"c\"\nvar c = 2 ^ 10\nvar d = (1 + 2) * 1.5\nvar e = -7 / 2\nvar f = !(1 < 2.5)\n");
+            assert_eq!(
+                folded,
+                [
+                    ValueKind::Int(11),
+                    ValueKind::Str("abc".into()),
+                    ValueKind::Int(1024),
+                    ValueKind::Num(4.5),
+                    ValueKind::Int(-3),
+                    ValueKind::Bool(false),
+                ]
+            );
+            // Variables are never folded, but the operands that can't change the result are dropped
+            let folded = values("var a = 1\nvar b = a + 1\nvar c = (a > 1) && true\n");
+            assert!(matches!(&folded[1], ValueKind::Op(_)));
+            assert!(matches!(&folded[2], ValueKind::Parenthesis(_)));
+        }
+
+        #[test]
+        fn errors() {
+            let (_, diagnostics) = check("test.pg".as_ref(), "var a = 1 % 0\nvar b = 9223372036854775807 * 2\nvar c = 2 ^ 3\n");
+            let codes = diagnostics.iter().map(|d| d.code).collect::<Vec<_>>();
+            assert_eq!(codes, [ErrorCode::DivisionByZero, ErrorCode::IntegerOverflow]);
+        }
+
+        #[test]
+        fn branches() {
+            let code = "var a = 1\nif 1 > 2\n    a = 2\nelif a == 1\n    a = 3\nelif true\n    a = 4\nelse\n    a = 5\nend\nif true\n    a = 6\nend\n";
+            let (out, diagnostics) = check("test.pg".as_ref(), code);
+            assert!(diagnostics.iter().all(|d| d.code == ErrorCode::ConstantCondition && !d.is_error()));
+            assert_eq!(diagnostics.len(), 3);
+
+            // The first arm is gone, the one that always runs is the else
+            let ExprKind::If { elif: Some(elif), .. } = &out.exprs[1].kind else {
+                panic!("expected an if, found {:?}", out.exprs[1]);
+            };
+            assert!(matches!(&elif.kind, ExprKind::Else { exprs } if &code[exprs[0].span.range()] == "a = 4"));
+            assert_eq!(&code[out.exprs[2].span.range()], "a = 6");
+            assert_eq!(out.exprs.len(), 3);
+        }
+    }
+
+    mod diagnostics {
+        use super::*;
+        use assert_fs::fixture::FileWriteStr;
+
+        #[test]
+        fn json() -> Result<(), Box<dyn std::error::Error>> {
+            let file = assert_fs::NamedTempFile::new("test.pg")?;
+            file.write_str("var a = 1\nvar b: str = a")?;
+
+            Command::cargo_bin("polyglot_parser")?
+                .arg("check")
+                .arg(file.path())
+                .args(["--error-format", "json"])
+                .assert()
+                .failure()
+                .stderr(predicate::str::contains(r#""code":"E0002""#))
+                .stderr(predicate::str::contains(r#""start":{"column":14,"line":2}"#))
+                .stderr(predicate::str::contains(r#""message":"wrong assignment type""#));
+            Ok(())
+        }
+
+        #[test]
+        fn sarif() -> Result<(), Box<dyn std::error::Error>> {
+            let file = assert_fs::NamedTempFile::new("test.pg")?;
+            file.write_str("var a = b")?;
+
+            let output = Command::cargo_bin("polyglot_parser")?
+                .arg("check")
+                .arg(file.path())
+                .args(["--error-format", "sarif"])
+                .output()?;
+            let log: serde_json::Value = serde_json::from_slice(&output.stderr)?;
+            let result = &log["runs"][0]["results"][0];
+            assert_eq!(log["version"], "2.1.0");
+            assert_eq!(result["ruleId"], "E0003");
+            assert_eq!(result["locations"][0]["physicalLocation"]["region"]["startColumn"], 9);
+            Ok(())
+        }
+
+        #[test]
+        fn apart_from_output() -> Result<(), Box<dyn std::error::Error>> {
+            let output = assert_cmd::Command::cargo_bin("polyglot_parser")?
+                .args(["emit", "ast", "--format", "json", "--error-format", "json", "-"])
+                .write_stdin("var a = b\n")
+                .output()?;
+            let tree: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+            let diagnostic: serde_json::Value = serde_json::from_slice(&output.stderr)?;
+            assert!(tree["exprs"].is_array());
+            assert_eq!(diagnostic["code"], "E0003");
+            Ok(())
+        }
+
+        #[test]
+        fn closed_stdout() -> Result<(), Box<dyn std::error::Error>> {
+            let (reader, writer) = std::io::pipe()?;
+            drop(reader);
+            Command::cargo_bin("polyglot_parser")?.arg("schema").stdout(writer).assert().success().stderr("");
+            Ok(())
+        }
+
+        #[test]
+        fn empty_span() {
+            let (out, diagnostics) = check("test.pg".as_ref(), "var a = 1\nvar b = (a\n");
+            let mut rendered = Vec::new();
+            crate::diagnostic::emit_human(&out.source_map, &diagnostics, false, &mut rendered).unwrap();
+            assert!(String::from_utf8(rendered).unwrap().contains(" 2 | var b = (a\n   |          ^ expected Call"));
+        }
+
+        #[test]
+        fn list_removal() {
+            let (_, diagnostics) = check("test.pg".as_ref(), "missing --= 1\nvar n = 1\nn --= 1\n");
+            let found = diagnostics.iter().map(|d| (d.code, d.message.as_str(), d.labels[0].message.as_str())).collect::<Vec<_>>();
+            assert_eq!(
+                found,
+                [
+                    (ErrorCode::UndeclaredVariable, "removing from inexistent list", "not declared"),
+                    (ErrorCode::NotAList, "variable exists but is not a list", "not a list"),
+                ]
+            );
+        }
+    }
+
+    mod spans {
+        use super::*;
+        use crate::source::LineCol;
+        use assert_fs::fixture::FileWriteStr;
+
+        #[test]
+        fn line_col() -> Result<(), Box<dyn std::error::Error>> {
+            let file = assert_fs::NamedTempFile::new("test.pg")?;
+            file.write_str("var list = [1, 2]\nfn f(a: int)\n    var b = list[a]\nend\n")?;
+
+            let (out, _) = check(file.path(), &std::fs::read_to_string(file.path())?);
+            let ExprKind::Fn { exprs, .. } = &out.exprs[1].kind else {
+                panic!("expected a function, found {:?}", out.exprs[1]);
+            };
+            let (start, end) = out.source_map.line_col(exprs[0].span);
+
+            assert_eq!(start, LineCol { line: 3, column: 5 });
+            assert_eq!(end, LineCol { line: 3, column: 20 });
+            assert_eq!(out.source_map.line_col(out.exprs[1].span).1, LineCol { line: 4, column: 4 });
+            Ok(())
+        }
+    }
+
+    mod recovery {
+        use super::*;
+        use assert_fs::fixture::FileWriteStr;
+
+        #[test]
+        fn partial_tree() {
+            let code = "var a = b\nvar c = a + 1\nif a == 1\n    var d: str = 1\n    var e = d\nend\n";
+            let (out, diagnostics) = check("test.pg".as_ref(), code);
+
+            let codes = diagnostics.iter().map(|d| d.code.as_str()).collect::<Vec<_>>();
+            assert_eq!(codes, ["E0003", "E0002"]);
+            assert!(matches!(&out.exprs[0].kind, ExprKind::Init { r#type: Type::Err, .. }));
+            let ExprKind::If { exprs, .. } = &out.exprs[2].kind else {
+                panic!("expected an if, found {:?}", out.exprs[2]);
+            };
+            assert_eq!(exprs.len(), 2);
+            assert_eq!(&code[exprs[0].span.range()], "var d: str = 1");
+            assert!(matches!(&exprs[0].kind, ExprKind::Init { value, .. } if value.is_err()));
+        }
+
+        #[test]
+        fn deduplicated() {
+            let (_, diagnostics) = check("test.pg".as_ref(), "var a = b + 1\n");
+            assert_eq!(diagnostics.len(), 1);
+        }
+
+        #[test]
+        fn error_limit() -> Result<(), Box<dyn std::error::Error>> {
+            let file = assert_fs::NamedTempFile::new("test.pg")?;
+            file.write_str("var a = b\nvar c = d\nvar e = f\n")?;
+
+            Command::cargo_bin("polyglot_parser")?
+                .arg("check")
+                .arg(file.path())
+                .args(["--error-limit", "1"])
+                .assert()
+                .failure()
+                .stderr(predicate::str::contains("variable 'b' does not exist"))
+                .stderr(predicate::str::contains("variable 'd' does not exist").not())
+                .stderr(predicate::str::contains("error limit reached, 2 more errors not shown"))
+                .stderr(predicate::str::contains("error: aborting due to 3 errors\n"));
+            Ok(())
+        }
+    }
+
+    mod language {
+        use super::*;
+        use crate::diagnostic::ErrorCode;
+
+        /// Codes of the diagnostics reported for `code`, in order.
+        fn codes(code: &str) -> Vec<ErrorCode> {
+            check("test.pg".as_ref(), code).1.iter().map(|d| d.code).collect()
+        }
+
+        #[test]
+        fn conditions() {
+            assert_eq!(codes("var a = 1\nif (a > 0) => a = 2\nelif a < 0\n    a = 3\nend\nwhile (a < 9)\n    a += 1\nend\n"), []);
+        }
+
+        #[test]
+        fn chars() {
+            let (out, diagnostics) = check("test.pg".as_ref(), "var a = '1'\n");
+            assert!(diagnostics.is_empty(), "{diagnostics:?}");
+            assert!(matches!(&out.exprs[0].kind, ExprKind::Init { value, .. } if value.kind == ValueKind::Char('1')));
+        }
+
+        #[test]
+        fn builtins() {
+            let code = "var a = 1\nprintln(\"{a} {b}\")\nprint(1, 2)\ndbg(\"{b}\")\n";
+            assert_eq!(codes(code), [ErrorCode::UndeclaredVariable, ErrorCode::WrongArgumentType]);
+        }
+
+        #[test]
+        fn block_scopes() {
+            assert_eq!(codes("var a = 1\nif a == 1\n    a = 2\n    var b = a\nend\nvar c = b\n"), [ErrorCode::UndeclaredVariable]);
+        }
+
+        #[test]
+        fn list_operators() {
+            let code = "var list = [1, 2]\nlist += 3\nlist -= 1\nvar more = list + 4\nlist *= 2\n";
+            assert_eq!(codes(code), [ErrorCode::WrongAssignmentType]);
+        }
+
+        #[test]
+        fn list_index() {
+            let code = "var list = [1, 2]\nvar i = 1\nvar a = list[i]\nvar b = list[-1]\nvar c = list[\"a\"]\n";
+            assert_eq!(codes(code), [ErrorCode::NegativeIndex, ErrorCode::ListAccessedAsDict]);
+        }
+
+        #[test]
+        fn int_and_num() {
+            let (out, diagnostics) = check("test.pg".as_ref(), "var a = 1 + 2.5\nvar b: num = a\n");
+            assert!(diagnostics.is_empty(), "{diagnostics:?}");
+            assert!(matches!(&out.exprs[0].kind, ExprKind::Init { r#type: Type::Num, .. }));
+        }
+
+        #[test]
+        fn comments() {
+            let (out, diagnostics) = check("test.pg".as_ref(), "// first\nvar a = 1 // second\n/* third\n*/\nvar b = a\n");
+            assert!(diagnostics.is_empty(), "{diagnostics:?}");
+            assert_eq!(out.exprs.len(), 2);
+        }
+
+        #[test]
+        fn modules() {
+            let code = "use math\nvar a = math::sqrt(2.0)\nmath::pi\n";
+            assert_eq!(codes(code), [ErrorCode::UnsupportedModule; 3]);
+        }
+    }
+
+    mod backends {
+        use super::*;
+        use crate::backend;
+        use assert_fs::fixture::{FileWriteStr, PathChild};
+        use assert_fs::TempDir;
+
+        #[test]
+        fn registry() {
+            let yaml = backend::find("yaml").unwrap();
+            assert_eq!(yaml.extension(), "yml");
+            assert_eq!(yaml.capabilities().dict_keys, backend::DictKeys::Any);
+            assert!(backend::find("cobol").is_none());
+        }
+
+        #[test]
+        fn target() -> Result<(), Box<dyn std::error::Error>> {
+            let dir = TempDir::new()?;
+            let file = dir.child("test.pg");
+            file.write_str("var a = 1\n")?;
+
+            Command::cargo_bin("polyglot_parser")?.args(["build", "--target", "yaml"]).arg(file.path()).assert().success();
+            assert!(std::fs::read_to_string(dir.child("test.yml").path())?.contains("Init"));
+            Command::cargo_bin("polyglot_parser")?
+                .arg("build")
+                .arg(file.path())
+                .args(["--target", "cobol"])
+                .assert()
+                .failure()
+                .stderr(predicate::str::contains("possible values: yaml"));
+            Ok(())
+        }
+
+        #[test]
+        fn unsupported() -> Result<(), Box<dyn std::error::Error>> {
+            let code = "fn f()\nend\nvar v = f()\nvar d = [[1] -> 2]\nif [1] < [2]\n    println(d)\nend\n";
+            let (main, _) = check("test.pg".as_ref(), code);
+            let messages = |target| -> Vec<_> {
+                let diagnostics = backend::find(target).unwrap().check(&main);
+                diagnostics.into_iter().map(|d| d.message).collect()
+            };
+            assert_eq!(
+                messages("c"),
+                [
+                    "void variables are not supported by the `c` target",
+                    "ordered comparisons of lists are not supported by the `c` target"
+                ]
+            );
+            assert_eq!(messages("js"), ["dictionaries with `[int]` keys are not supported by the `js` target"]);
+            assert!(messages("yaml").is_empty());
+
+            // Nothing is written when the target can't express the program
+            let dir = TempDir::new()?;
+            let file = dir.child("test.pg");
+            file.write_str(code)?;
+            Command::cargo_bin("polyglot_parser")?
+                .arg("build")
+                .arg(file.path())
+                .args(["--target", "python"])
+                .assert()
+                .failure()
+                .stderr(predicate::str::contains("--> ").and(predicate::str::contains("test.pg:4:9")));
+            assert!(!dir.child("test.py").exists());
+            Ok(())
+        }
+
+        #[test]
+        fn diverging() -> Result<(), Box<dyn std::error::Error>> {
+            let code = "fn count(n: int)\n    if n > 0\n        count(n - 1)\n    end\nend\ncount(3)\nvar big = 9007199254740993\nprintln(big * 2)\nprintln(big * 3)\n";
+            let (main, _) = check("test.pg".as_ref(), code);
+            let warnings = |target| -> Vec<_> {
+                let diagnostics = backend::find(target).unwrap().check(&main);
+                assert!(diagnostics.iter().all(|d| d.code == ErrorCode::DivergesInTarget && !d.is_error()));
+                diagnostics.into_iter().map(|d| d.labels[0].message.clone()).collect()
+            };
+            assert_eq!(
+                warnings("python"),
+                ["keeps growing instead of wrapping on overflow", "fails after 1000 nested calls"]
+            );
+            assert_eq!(warnings("js"), ["is rounded to the nearest double", "loses precision beyond 2^53"]);
+            assert!(warnings("c").is_empty());
+            assert!(warnings("rust").is_empty());
+
+            // Programs are still written when the target only runs them differently
+            let dir = TempDir::new()?;
+            let file = dir.child("test.pg");
+            file.write_str(code)?;
+            Command::cargo_bin("polyglot_parser")?
+                .arg("build")
+                .arg(file.path())
+                .args(["--target", "js"])
+                .assert()
+                .success()
+                .stderr(predicate::str::contains("warning: ints beyond 2^53 behave differently in the `js` target"));
+            assert!(dir.child("test.mjs").exists());
+            Ok(())
+        }
+    }
+
+    mod targets {
+        use super::*;
+        use assert_fs::fixture::{FileWriteStr, PathChild};
+        use assert_fs::TempDir;
+
+        /// Translates `code` to `target`, builds it with the backend's commands and runs it.
+        ///
+        /// `strict` is passed to the compiler, so warnings in the generated code fail the test. `None` if the toolchain
+        /// isn't on PATH, so the test is skipped where it isn't installed and runs everywhere else.
+        pub fn run(target: &str, code: &str, strict: &[&str]) -> Option<std::process::Output> {
+            let backend = crate::backend::find(target).unwrap();
+            if let Some(reason) = crate::harness::missing_toolchain(backend) {
+                eprintln!("skipping a `{target}` test, {reason}");
+                return None;
+            }
+            let dir = TempDir::new().unwrap();
+            let file = dir.child("test.pg");
+            file.write_str(code).unwrap();
+            Command::cargo_bin("polyglot_parser").unwrap().args(["build", "--target", target]).arg(file.path()).assert().success();
+            let source = dir.child(format!("test.{}", backend.extension()));
+            let mut commands = backend.commands(source.path());
+            let mut program = commands.pop().unwrap();
+            for mut build in commands {
+                build.args(strict).assert().success();
+            }
+            Some(program.output().unwrap_or_else(|e| panic!("can't run the `{target}` program: {e}")))
+        }
+
+        pub fn lists(target: &str, strict: &[&str]) {
+            let code = "var list = [1, 2, 3, 1, 2, 3]\nlist += 4\nlist -= 1\nprintln(list)\nlist --= 2\nvar copy = list\ncopy += [5, 6]\nprintln(\"{list} {copy}\")\ncopy --= [3, 5]\nprintln(copy)\n";
+            let Some(out) = run(target, code, strict) else { return };
+            assert_eq!(String::from_utf8_lossy(&out.stdout), "[2, 3, 1, 2, 3, 4]\n[3, 1, 3, 4] [3, 1, 3, 4, 5, 6]\n[1, 4, 6]\n");
+        }
+
+        pub fn out_of_bounds(target: &str, strict: &[&str]) {
+            let code = "var list = [\"hello\", \"world\"]\nprintln(list[1])\nprintln(list[2])\n";
+            let Some(out) = run(target, code, strict) else { return };
+            assert_eq!(String::from_utf8_lossy(&out.stdout), "world\n");
+            assert_eq!(String::from_utf8_lossy(&out.stderr), "ERROR: Out-of-bounds in line 3, \"list[2]\".\n");
+            assert!(!out.status.success());
+        }
+    }
+
+    mod python {
+        use super::*;
+
+        const TARGET: &str = "python";
+        /// Python programs aren't compiled, there are no warnings to turn into errors
+        const STRICT: &[&str] = &[];
+
+        fn run(code: &str) -> Option<std::process::Output> {
+            targets::run(TARGET, code, STRICT)
+        }
+
+        #[test]
+        fn lists() {
+            targets::lists(TARGET, STRICT);
+        }
+
+        #[test]
+        fn out_of_bounds() {
+            targets::out_of_bounds(TARGET, STRICT);
+        }
+
+        #[test]
+        fn structs_and_division() {
+            let code = "type Person = (age: int, name: str)\nvar person: Person = (52, \"Alex\")\nfn show(p: Person)\n    println(p)\nend\nshow(person)\nprint(7 / -2)\nprint(' ')\nprintln(-7 % 2)\n";
+            let Some(out) = run(code) else { return };
+            assert_eq!(String::from_utf8_lossy(&out.stdout), "(age: 52, name: \"Alex\")\n-3 -1\n");
+        }
+
+        #[test]
+        fn nums() {
+            let code = "var a: num = 2\nprintln(a)\nvar b = -10.0 ^ 401.0\nprintln(b)\nvar c = 2.0\nc ^= 2000.0\nprintln(c)\n";
+            let Some(out) = run(code) else { return };
+            assert_eq!(String::from_utf8_lossy(&out.stdout), "2.0\n-inf\ninf\n");
+        }
+
+        #[test]
+        fn block_scopes() {
+            let code = "var s = \"x\"\nvar i = 10\nif i > 0\n    var s = \"y\"\n    s += \"z\"\n    println(s)\nend\nfor i in 0..3\n    var s = i\nend\nvar n = 1\nwhile n > 0\n    var i = 0\n    i -= 1\n    println(i)\n    n -= 1\nend\nprintln(\"{s} {i}\")\n";
+            let Some(out) = run(code) else { return };
+            assert_eq!(String::from_utf8_lossy(&out.stdout), "yz\n-1\nx 10\n");
+        }
+
+        #[test]
+        fn arithmetic_errors() {
+            let code = "var e = -1\nprintln(2 ^ e)\nprintln(1.0 / (e + 1))\nprintln(7 / (e + 1))\n";
+            let Some(out) = run(code) else { return };
+            assert_eq!(String::from_utf8_lossy(&out.stdout), "0\ninf\n");
+            assert_eq!(String::from_utf8_lossy(&out.stderr), "ERROR: Division by zero in line 4, \"7 / (e + 1)\".\n");
+            assert!(!out.status.success());
+        }
+    }
+
+    mod c {
+        use super::*;
+
+        const TARGET: &str = "c";
+        /// Compiler flags that turn warnings in the generated code into errors
+        const STRICT: &[&str] = &["-Wall", "-Werror"];
+
+        fn run(code: &str) -> Option<std::process::Output> {
+            targets::run(TARGET, code, STRICT)
+        }
+
+        #[test]
+        fn lists() {
+            targets::lists(TARGET, STRICT);
+        }
+
+        #[test]
+        fn out_of_bounds() {
+            targets::out_of_bounds(TARGET, STRICT);
+        }
+
+        #[test]
+        fn containers() {
+            let code = "type Person = (age: int, name: str)\nvar person: Person = (52, \"Alex\")\nvar people = [person]\nvar ages = [\"Alex\" -> 52.5]\nvar pair = (1, 'c')\nprintln(people)\ndbg(ages)\nprintln(pair)\nprintln(str([1, 2]))\n";
+            let Some(out) = run(code) else { return };
+            assert_eq!(
+                String::from_utf8_lossy(&out.stdout),
+                "[(age: 52, name: \"Alex\")]\nages = [\"Alex\" -> 52.5]\n(1, \"c\")\n[\"1\", \"2\"]\n"
+            );
+        }
+
+        #[test]
+        fn arithmetic_errors() {
+            let code = "var m = -1\nvar min = m - 9223372036854775807\nprintln(min / m)\nprintln(min % m)\nprintln(min - 1)\nprintln(min * m)\nvar two = 2\nprintln(two ^ 64)\nvar n = 7\nn /= m + 1\n";
+            let Some(out) = run(code) else { return };
+            assert_eq!(
+                String::from_utf8_lossy(&out.stdout),
+                "-9223372036854775808\n0\n9223372036854775807\n-9223372036854775808\n0\n"
+            );
+            assert_eq!(String::from_utf8_lossy(&out.stderr), "ERROR: Division by zero in line 10, \"n /= m + 1\".\n");
+            assert!(!out.status.success());
+        }
+    }
+
+    mod rust {
+        use super::*;
+
+        const TARGET: &str = "rust";
+        /// Compiler flags that turn warnings in the generated code into errors
+        const STRICT: &[&str] = &["-D", "warnings"];
+
+        fn run(code: &str) -> Option<std::process::Output> {
+            targets::run(TARGET, code, STRICT)
+        }
+
+        #[test]
+        fn lists() {
+            targets::lists(TARGET, STRICT);
+        }
+
+        #[test]
+        fn out_of_bounds() {
+            targets::out_of_bounds(TARGET, STRICT);
+        }
+
+        #[test]
+        fn value_semantics() {
+            let code = "var list = [1, 2, 3, 1]\nvar copy = list\ncopy -= 1\nlist += copy\nvar words = [\"a\"]\nvar more = words + \"b\"\nprintln(\"{list} {copy} {words} {more}\")\n";
+            let Some(out) = run(code) else { return };
+            assert_eq!(String::from_utf8_lossy(&out.stdout), "[1, 2, 3, 1, 2, 3, 1] [2, 3, 1] [\"a\"] [\"a\", \"b\"]\n");
+        }
+
+        #[test]
+        fn containers() {
+            let code = "type Person = (age: int, name: str)\nvar person: Person = (52, \"Alex\")\nvar people = [person]\nvar ages = [\"Alex\" -> 52.5]\nvar pair = (1, 'c')\nprintln(people)\ndbg(ages)\nprintln(pair)\nprintln(1.5 + 2)\n";
+            let Some(out) = run(code) else { return };
+            assert_eq!(
+                String::from_utf8_lossy(&out.stdout),
+                "[(age: 52, name: \"Alex\")]\nages = [\"Alex\" -> 52.5]\n(1, \"c\")\n3.5\n"
+            );
+        }
+
+        #[test]
+        fn arithmetic() {
+            let code = "var big = 9223372036854775807\nprintln(big + 1)\nvar y = 2\nprintln(y ^ 64)\nvar ages = [\"Sam\" -> 1, \"Alex\" -> 2]\nprintln(str(ages))\nvar prices = [1.5 -> \"a\"]\nprintln(prices == [1.5 -> \"a\"])\nbig %= y - 2\n";
+            let Some(out) = run(code) else { return };
+            assert_eq!(
+                String::from_utf8_lossy(&out.stdout),
+                "-9223372036854775808\n0\n[\"Sam\" -> \"1\", \"Alex\" -> \"2\"]\ntrue\n"
+            );
+            assert_eq!(String::from_utf8_lossy(&out.stderr), "ERROR: Division by zero in line 9, \"big %= y - 2\".\n");
+            assert!(!out.status.success());
+        }
+
+        #[test]
+        fn key_not_found() {
+            let Some(out) = run("var ages = [\"Alex\" -> 52]\nprint(ages[\"Alex\"])\nprintln(ages[\"Sam\"])\n") else { return };
+            assert_eq!(String::from_utf8_lossy(&out.stdout), "52");
+            assert_eq!(String::from_utf8_lossy(&out.stderr), "ERROR: Key not found in line 3, \"ages[\"Sam\"]\".\n");
+            assert!(!out.status.success());
+        }
+    }
+
+    mod interpreter {
+        use super::*;
+        use crate::interpreter::{self, RuntimeError};
+        use assert_fs::fixture::FileWriteStr;
+
+        /// Runs `code`, returning what it printed and how it failed.
+        fn run(code: &str) -> (String, Result<(), RuntimeError>) {
+            let (main, diagnostics) = check("test.pg".as_ref(), code);
+            assert!(diagnostics.is_empty(), "{diagnostics:?}");
+            let mut out = Vec::new();
+            let result = interpreter::run(&main, &mut out);
+            (String::from_utf8(out).unwrap(), result)
+        }
+
+        #[test]
+        fn semantics() {
+            let code = "var list = [1, 2, 1]\nvar copy = list\ncopy -= 1\nvar n: num = 7 / -2\nvar d = [1 -> 'a']\nfn f(l: [int])\n    l += 5\n    print(l)\nend\nf(list)\nprintln(\" {list} {copy} {n} {d}\")\ndbg(-7 % 2)\n";
+            let (out, result) = run(code);
+            assert_eq!(out, "[1, 2, 1, 5] [1, 2, 1] [2, 1] -3.0 [1 -> \"a\"]\n-7 % 2 = -1\n");
+            assert!(result.is_ok());
+        }
+
+        #[test]
+        fn runtime_errors() {
+            let (out, result) = run("var list = [1]\nprint(list[0])\nprintln(list[1])\n");
+            assert_eq!(out, "1");
+            let error = result.unwrap_err();
+            assert_eq!(error.to_string(), "ERROR: Out-of-bounds in line 3, \"list[1]\".");
+            assert_eq!(error.span, Span::new(0, 38, 45));
+
+            let (_, result) = run("var a = int(\"hey!\")\n");
+            assert_eq!(result.unwrap_err().to_string(), "ERROR: \"hey!\" can not be parsed into an int");
+        }
+
+        #[test]
+        fn division() {
+            let code = "var a = 7\nvar b = -7\nvar zero = 0.0\ndbg(a / -2)\ndbg(b % 2)\ndbg(1.0 / zero)\ndbg(zero / zero)\nprint(a % 0)\n";
+            let (out, result) = run(code);
+            assert_eq!(out, "a / -2 = -3\nb % 2 = -1\n1.0 / zero = inf\nzero / zero = nan\n");
+            assert_eq!(result.unwrap_err().to_string(), "ERROR: Division by zero in line 8, \"a % 0\".");
+        }
+
+        #[test]
+        fn block_scopes() {
+            let code = "var s = \"x\"\nvar i = 10\nif i > 0\n    s += \"y\"\n    var s = 1\n    s += 1\n    println(s)\nend\nfor i in 0..2\n    var last = i\nend\nprintln(\"{s} {i}\")\n";
+            let (out, result) = run(code);
+            assert_eq!(out, "2\nxy 10\n");
+            assert!(result.is_ok());
+
+            let (_, diagnostics) = check("test.pg".as_ref(), &format!("{code}println(last)\n"));
+            assert_eq!(diagnostics.iter().map(|d| d.code).collect::<Vec<_>>(), [ErrorCode::UndeclaredVariable]);
+        }
+
+        /// Trees the checker rejects still fail with an error instead of a panic if they're run anyway.
+        #[test]
+        fn unchecked() {
+            let failure = |main: &Main| interpreter::run(main, &mut Vec::new()).unwrap_err().to_string();
+            let (main, _) = check("test.pg".as_ref(), "var a = 5\nwhile a\nend\n");
+            assert_eq!(failure(&main), "ERROR: Condition is not a bool in line 2, \"a\".");
+            let (main, _) = check("test.pg".as_ref(), "var a = 5\nfor i in a\nend\n");
+            assert_eq!(failure(&main), "ERROR: Only ranges and lists can be iterated in line 2, \"a\".");
+
+            let (mut main, diagnostics) = check("test.pg".as_ref(), "fn f(a: int, b: int)\nend\nf(1, 2)\n");
+            assert!(diagnostics.is_empty());
+            let ExprKind::Call { args, .. } = &mut main.exprs[1].kind else {
+                panic!("expected a call, found {:?}", main.exprs[1]);
+            };
+            args.pop();
+            assert_eq!(failure(&main), "ERROR: Wrong number of arguments in line 3, \"f(1, 2)\".");
+        }
+
+        #[test]
+        fn run_command() -> Result<(), Box<dyn std::error::Error>> {
+            let file = assert_fs::NamedTempFile::new("test.pg")?;
+            file.write_str("var ages = [\"Alex\" -> 52]\nprintln(ages[\"Alex\"])\nprintln(ages[\"Sam\"])\n")?;
+
+            Command::cargo_bin("polyglot_parser")?
+                .arg("run")
+                .arg(file.path())
+                .assert()
+                .failure()
+                .stdout("52\n")
+                .stderr("ERROR: Key not found in line 3, \"ages[\"Sam\"]\".\n");
+            Ok(())
+        }
+    }
+
+    mod vm {
+        use super::*;
+        use crate::interpreter::{self, RuntimeError};
+        use crate::vm;
+        use assert_fs::fixture::FileWriteStr;
+
+        /// Runs `code` in the VM and in the interpreter, checking both print the same and fail the same way.
+        fn run(code: &str) -> (String, Result<(), RuntimeError>) {
+            let (main, diagnostics) = check("test.pg".as_ref(), code);
+            assert!(diagnostics.is_empty(), "{diagnostics:?}");
+            let mut out = Vec::new();
+            let result = vm::run(&vm::compile(&main), &main.source_map, &mut out);
+            let mut expected = Vec::new();
+            let expected_result = interpreter::run(&main, &mut expected);
+            assert_eq!(String::from_utf8_lossy(&out), String::from_utf8_lossy(&expected));
+            assert_eq!(result, expected_result);
+            (String::from_utf8(out).unwrap(), result)
+        }
+
+        #[test]
+        fn semantics() {
+            let code = "type Person = (age: int, name: str)\nvar people: [Person] = [(8, \"Sam\"), (30, \"Alex\")]\nfn older(p: Person, years: num)\n    var age = p.age + years\n    println(\"{p} {age}\")\nend\nfor p in people\n    older(p, 1)\n    people += p\nend\nvar list = [1, 2, 1]\nlist -= 1\nlist --= 2\nvar d = [1 -> 'a']\nvar found = d[1] == 'a'\nif found && list == [1]\n    println(\"half: \" + str(7 / 2))\nend\nfor i in 0..3\n    print(i ^ 2)\nend\ndbg(people[3])\n";
+            let (out, result) = run(code);
+            assert_eq!(
+                out,
+                "(age: 8, name: \"Sam\") 9.0\n(age: 30, name: \"Alex\") 31.0\nhalf: 3\n014people[3] = (age: 30, name: \"Alex\")\n"
+            );
+            assert!(result.is_ok());
+        }
+
+        #[test]
+        fn runtime_errors() {
+            let (out, result) = run("var a = 1\nprint(a)\nprintln(a / (a - 1))\n");
+            assert_eq!(out, "1");
+            assert_eq!(result.unwrap_err().to_string(), "ERROR: Division by zero in line 3, \"a / (a - 1)\".");
+
+            let (_, result) = run("fn f(c: str)\n    var b = bool(c)\nend\nf(\"no\")\n");
+            assert_eq!(result.unwrap_err().to_string(), "ERROR: \"no\" can not be parsed into a bool");
+        }
+
+        #[test]
+        fn unchecked_operands() {
+            // Trees the checker didn't produce can divide booleans, which fails instead of panicking
+            let (mut main, _) = check("test.pg".as_ref(), "var a = true\nvar b = a\n");
+            let ExprKind::Init { value, .. } = &mut main.exprs[1].kind else {
+                panic!("expected an init, found {:?}", main.exprs[1]);
+            };
+            let operand = value.clone();
+            value.kind = ValueKind::Op(Op::Div(Box::new((operand.clone(), operand))));
+
+            let result = vm::run(&vm::compile(&main), &main.source_map, &mut Vec::new());
+            assert_eq!(result, interpreter::run(&main, &mut Vec::new()));
+            assert_eq!(
+                result.unwrap_err().to_string(),
+                "ERROR: Operator '/' not supported between true and true in line 2, \"a\"."
+            );
+        }
+
+        #[test]
+        fn unchecked() {
+            let failure = |main: &Main| {
+                let result = vm::run(&vm::compile(main), &main.source_map, &mut Vec::new());
+                assert_eq!(result, interpreter::run(main, &mut Vec::new()));
+                result.unwrap_err().to_string()
+            };
+            let (main, _) = check("test.pg".as_ref(), "var a = 5\nif a\n    println(a)\nend\n");
+            assert_eq!(failure(&main), "ERROR: Condition is not a bool in line 2, \"a\".");
+            let (main, _) = check("test.pg".as_ref(), "var a = 5\nfor j in a\n    println(j)\nend\n");
+            assert_eq!(failure(&main), "ERROR: Only ranges and lists can be iterated in line 2, \"a\".");
+
+            let (mut main, _) = check("test.pg".as_ref(), "fn f(a: int, b: int)\n    println(\"{a} {b}\")\nend\nf(1, 2)\n");
+            let ExprKind::Call { args, .. } = &mut main.exprs[1].kind else {
+                panic!("expected a call, found {:?}", main.exprs[1]);
+            };
+            let extra = args[0].clone();
+            args.pop();
+            assert_eq!(failure(&main), "ERROR: Wrong number of arguments in line 4, \"f(1, 2)\".");
+            let ExprKind::Call { args, .. } = &mut main.exprs[1].kind else { unreachable!() };
+            args.extend([extra.clone(), extra]);
+            assert_eq!(failure(&main), "ERROR: Wrong number of arguments in line 4, \"f(1, 2)\".");
+        }
+
+        #[test]
+        fn disassemble() -> Result<(), Box<dyn std::error::Error>> {
+            let file = assert_fs::NamedTempFile::new("test.pg")?;
+            file.write_str("var a = 1\nvar b = a + 2\nvar c = a * 2.5\n")?;
+
+            Command::cargo_bin("polyglot_parser")?
+                .args(["run", "--disassemble"])
+                .arg(file.path())
+                .assert()
+                .success()
+                .stdout(predicate::str::starts_with("fn main (0 params, 3 locals)\n0000    1  int 1\n"))
+                .stdout(predicate::str::contains("add_int").and(predicate::str::contains("int_to_num\n")))
+                .stdout(predicate::str::contains("mul_num"));
+            Ok(())
+        }
+    }
+
+    mod ir {
+        use super::*;
+        use crate::ir::{self, Intrinsic, Rvalue, StmtKind};
+        use assert_fs::fixture::FileWriteStr;
+
+        fn lower(code: &str) -> ir::Program {
+            let (main, diagnostics) = check("test.pg".as_ref(), code);
+            assert!(diagnostics.is_empty(), "{diagnostics:?}");
+            ir::lower(&main)
+        }
+
+        #[test]
+        fn desugared() {
+            let program = lower("var a = 1\nif a == 0\n    a = 2\nelif a == 1\n    a = 3\nelse\n    a = 4\nend\n");
+            let main = program.function("main").unwrap();
+            // The elif is an if inside the else of the first one
+            let Some(StmtKind::If { otherwise, .. }) = main.body.last().map(|s| &s.kind) else {
+                panic!("expected an if, found {:?}", main.body.last());
+            };
+            let Some(StmtKind::If { otherwise, .. }) = otherwise.last().map(|s| &s.kind) else {
+                panic!("expected an elif, found {otherwise:?}");
+            };
+            assert!(matches!(&otherwise[..], [stmt] if matches!(stmt.kind, StmtKind::Assign { .. })));
+
+            let program = lower("var l = [1]\nl += 2\nl --= 1\n");
+            let values = program.functions[0].body.iter().filter_map(|stmt| match &stmt.kind {
+                StmtKind::Assign { value: Rvalue::Intrinsic(i, _), .. } => Some(*i),
+                _ => None,
+            });
+            assert_eq!(values.collect::<Vec<_>>(), [Intrinsic::ListAppend, Intrinsic::ListRemoveAll]);
+        }
+
+        #[test]
+        fn explicit() {
+            let program = lower("fn half(n: num): num\nend\nvar a = 1\nvar b = half(a) + a\nvar c = (a > 1) || (b < 1.0)\n");
+            let text = program.to_string();
+            assert!(text.contains("let %1: num = coerce %0 into num\n    let %2: num = call half(%1)"), "{text}");
+            // Short-circuits by only evaluating the right operand when needed
+            assert!(text.contains("if %5\n    else\n        %5 = cmp %4 < 1.0\n    end\n    let %6 c: bool = %5"), "{text}");
+            assert!(text.ends_with("fn half(%0 n: num) -> num\n    let %1: num = default num\n    return %1\nend\n"), "{text}");
+        }
+
+        #[test]
+        fn corpus() -> Result<(), Box<dyn std::error::Error>> {
+            for file in ["../README.md", "rosetta.pg", "test.pg"] {
+                for program in crate::harness::corpus(file.as_ref())? {
+                    let (main, diagnostics) = check(program.name.as_ref(), &program.source);
+                    if diagnostics.is_empty() {
+                        assert!(!ir::lower(&main).to_string().is_empty());
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        #[test]
+        fn emit_command() -> Result<(), Box<dyn std::error::Error>> {
+            let file = assert_fs::NamedTempFile::new("test.pg")?;
+            file.write_str("var a = 2\nprintln(a * 1.5)\n")?;
+
+            Command::cargo_bin("polyglot_parser")?
+                .args(["emit", "ir"])
+                .arg(file.path())
+                .assert()
+                .success()
+                .stdout(predicate::str::starts_with("fn main() -> void\n    let %0 a: int = 2\n"))
+                .stdout(predicate::str::contains("mul %1, 1.5"));
+            assert!(!file.path().with_extension("yml").exists());
+            Ok(())
+        }
+    }
+
+    mod prune {
+        use super::*;
+        use assert_fs::fixture::FileWriteStr;
+
+        fn names(code: &str) -> Vec<String> {
+            let (mut out, diagnostics) = check("test.pg".as_ref(), code);
+            assert!(diagnostics.is_empty(), "{diagnostics:?}");
+            crate::prune::prune(&mut out);
+            out.exprs
+                .into_iter()
+                .filter_map(|expr| match expr.kind {
+                    ExprKind::Fn { name, .. } | ExprKind::NativeFn { name, .. } | ExprKind::Typedef { name, .. } => Some(name),
+                    _ => None,
+                })
+                .collect()
+        }
+
+        #[test]
+        fn functions() {
+            // `unused` is only called by another unused function
+            let code = "fn unused\n    println(1)\nend\nfn dead\n    unused()\nend\nfn show(n: num)\n    println(n)\nend\nfn half(n: num): num\n    show(n / 2)\nend\nvar a = half(3)\n";
+            assert_eq!(names(code), ["show", "half"]);
+
+            let code = "fn helper\n    println(1)\nend\nnative python\n    helper()\nend\n";
+            assert_eq!(names(code), ["helper"]);
+        }
+
+        #[test]
+        fn typedefs() {
+            let code = "type Point = (x: int, y: int)\ntype Size = (w: num, h: num)\ntype Id = int\nvar p: Point = (x: 1, y: 2)\n";
+            assert_eq!(names(code), ["Point"]);
+        }
+
+        #[test]
+        fn never_finishing() {
+            let lengths = |code: &str| {
+                let (mut out, _) = check("test.pg".as_ref(), code);
+                crate::prune::prune(&mut out);
+                let ExprKind::Fn { exprs, .. } = &out.exprs[0].kind else {
+                    panic!("expected a function, found {:?}", out.exprs[0]);
+                };
+                (out.exprs.len(), exprs.len())
+            };
+            // `after` is only called once the loop is over
+            let code = "fn spin\n    while true\n    end\n    println(1)\nend\nfn after\nend\nvar a = 1\nwhile 1 < 2\n    spin()\nend\nafter()\n";
+            assert_eq!(lengths(code), (3, 1));
+
+            let code = "fn spin\n    while true\n        native python\n            break\n        end\n    end\n    println(1)\nend\nspin()\n";
+            assert_eq!(lengths(code), (2, 2));
+        }
+
+        #[test]
+        fn keep_dead_code() -> Result<(), Box<dyn std::error::Error>> {
+            let file = assert_fs::NamedTempFile::new("test.pg")?;
+            file.write_str("fn unused\n    println(1)\nend\nprintln(2)\n")?;
+            let python = file.path().with_extension("py");
+
+            let build = |args: &[&str]| -> Result<String, Box<dyn std::error::Error>> {
+                Command::cargo_bin("polyglot_parser")?.args(["build", "--target", "python"]).args(args).arg(file.path()).assert().success();
+                Ok(std::fs::read_to_string(&python)?)
+            };
+            assert!(build(&[])?.contains("def unused"));
+            assert!(!build(&["--release"])?.contains("def unused"));
+            assert!(build(&["--release", "--keep-dead-code"])?.contains("def unused"));
+            // Debug builds already keep everything
+            assert!(build(&["--keep-dead-code"])?.contains("def unused"));
+            std::fs::remove_file(python)?;
+            Ok(())
+        }
+    }
+
+    mod visit {
+        use super::*;
+        use crate::visit::*;
+
+        const CODE: &str = "fn f(n: int): int\nend\nvar a = 1\nif a > 1\n    f(1)\nelif a == f(2) + 1\n    a = f(3)\nelse\n    var b = [f(4) -> \"x\"]\nend\n";
+
+        struct Ints(Vec<i64>);
+
+        impl Visitor<'_> for Ints {
+            fn visit_value(&mut self, value: &Value) {
+                if let ValueKind::Int(i) = value.kind {
+                    self.0.push(i);
+                }
+                walk_value(self, value);
+            }
+        }
+
+        fn ints(exprs: &[Expr]) -> Vec<i64> {
+            let mut ints = Ints(Vec::new());
+            ints.visit_block(exprs);
+            ints.0
+        }
+
+        #[test]
+        fn visitor() {
+            let (out, diagnostics) = check("test.pg".as_ref(), CODE);
+            assert!(diagnostics.is_empty(), "{diagnostics:?}");
+            // Elif chains and both operands of operations and comparisons are visited
+            assert_eq!(ints(&out.exprs), [1, 1, 1, 2, 1, 3, 4]);
+        }
+
+        #[test]
+        fn visitor_mut() {
+            struct Rename;
+            impl VisitorMut for Rename {
+                fn visit_value_mut(&mut self, value: &mut Value) {
+                    if let ValueKind::Var(name) = &mut value.kind {
+                        name.push('_');
+                    }
+                    walk_value_mut(self, value);
+                }
+            }
+
+            let (mut out, _) = check("test.pg".as_ref(), CODE);
+            Rename.visit_block_mut(&mut out.exprs);
+            let ExprKind::If { cmp, elif: Some(elif), .. } = &out.exprs[2].kind else {
+                panic!("expected an if, found {:?}", out.exprs[2]);
+            };
+            assert!(matches!(&cmp.kind, ValueKind::Cmp(Cmp::Greater(operands)) if operands.0.kind == ValueKind::Var("a_".into())));
+            let ExprKind::Elif { cmp, .. } = &elif.kind else {
+                panic!("expected an elif, found {elif:?}");
+            };
+            assert!(matches!(&cmp.kind, ValueKind::Cmp(Cmp::Equal(operands)) if operands.0.kind == ValueKind::Var("a_".into())));
+        }
+
+        #[test]
+        fn fold() {
+            struct Identity;
+            impl Fold for Identity {}
+            struct Double;
+            impl Fold for Double {
+                fn fold_value(&mut self, value: Value) -> Value {
+                    let mut value = walk_fold_value(self, value);
+                    if let ValueKind::Int(i) = &mut value.kind {
+                        *i *= 2;
+                    }
+                    value
+                }
+            }
+
+            let (out, _) = check("test.pg".as_ref(), CODE);
+            assert_eq!(Identity.fold_block(out.exprs.clone()), out.exprs);
+            assert_eq!(ints(&Double.fold_block(out.exprs)), [2, 2, 2, 4, 2, 6, 8]);
+        }
+    }
+
+    mod from_ast {
+        use super::*;
+        use crate::serialize::{self, Format, LoadError};
+        use assert_fs::fixture::FileWriteStr;
+
+        const CODE: &str = "type Point = (x: int, y: num)\nvar p: Point = (x: 1, y: 2.5)\nif p.x > 0\n    println(p.y)\nend\n";
+
+        #[test]
+        fn round_trip() {
+            let (main, _) = check("test.pg".as_ref(), CODE);
+            let yaml = serde_yaml::to_string(&main).unwrap();
+            assert!(yaml.contains(&format!("\nversion: {SCHEMA_VERSION}\n")), "{yaml}");
+
+            let loaded = serialize::read(Format::Yaml, yaml.as_bytes()).unwrap();
+            assert_eq!(loaded.version, SCHEMA_VERSION);
+            assert_eq!(loaded.exprs, main.exprs);
+        }
+
+        #[test]
+        fn versions() {
+            let (main, _) = check("test.pg".as_ref(), CODE);
+            let yaml = serde_yaml::to_string(&main).unwrap();
+            let current = format!("version: {SCHEMA_VERSION}\n");
+
+            let older = yaml.replace(&current, &format!("version: {}\n", SCHEMA_VERSION - 1));
+            assert_eq!(serialize::read(Format::Yaml, older.as_bytes()), Err(LoadError::Older(SCHEMA_VERSION - 1)));
+            let newer = yaml.replace(&current, &format!("version: {}\n", SCHEMA_VERSION + 1));
+            assert_eq!(serialize::read(Format::Yaml, newer.as_bytes()), Err(LoadError::Newer(SCHEMA_VERSION + 1)));
+            assert_eq!(serialize::read(Format::Yaml, yaml.replace(&current, "").as_bytes()), Err(LoadError::Unversioned));
+
+            let Err(LoadError::Invalid(e)) = serialize::read(Format::Yaml, yaml.replace("Init", "Initialize").as_bytes()) else {
+                panic!("an unknown expression was read");
+            };
+            assert!(e.contains("unknown variant `Initialize`"), "{e}");
+
+            // `emit ast` writes trees of programs with errors, which can't be translated
+            let (main, diagnostics) = check("test.pg".as_ref(), "var a = 1\nvar b: str = a\nprintln(b)\n");
+            assert!(!diagnostics.is_empty());
+            let yaml = serde_yaml::to_string(&main).unwrap();
+            assert_eq!(serialize::read(Format::Yaml, yaml.as_bytes()), Err(LoadError::Unchecked));
+        }
+
+        #[test]
+        fn build_command() -> Result<(), Box<dyn std::error::Error>> {
+            let file = assert_fs::NamedTempFile::new("test.pg")?;
+            file.write_str(CODE)?;
+            let tree = file.path().with_extension("yml");
+            Command::cargo_bin("polyglot_parser")?.arg("build").arg(file.path()).assert().success();
+
+            // The source isn't needed anymore
+            std::fs::remove_file(file.path())?;
+            Command::cargo_bin("polyglot_parser")?
+                .arg("build")
+                .arg("--from-ast")
+                .arg(&tree)
+                .args(["--target", "python"])
+                .assert()
+                .success();
+            let python = file.path().with_extension("py");
+            assert!(std::fs::read_to_string(&python)?.contains("class Point"));
+
+            // Diagnostics of the target point at the line of the program, which isn't there to be shown
+            let file = assert_fs::NamedTempFile::new("void.pg")?;
+            file.write_str("fn f()\nend\nvar v = f()\n")?;
+            Command::cargo_bin("polyglot_parser")?.arg("build").arg(file.path()).assert().success();
+            Command::cargo_bin("polyglot_parser")?
+                .arg("build")
+                .arg("--from-ast")
+                .arg(file.path().with_extension("yml"))
+                .args(["--target", "c", "-o", "-"])
+                .assert()
+                .code(1)
+                .stderr(predicate::str::contains("void variables are not supported by the `c` target\n  --> "))
+                .stderr(predicate::str::contains("void.pg:3:1\n"));
+            std::fs::remove_file(file.path().with_extension("yml"))?;
+
+            std::fs::write(&tree, "version: 0\n")?;
+            Command::cargo_bin("polyglot_parser")?
+                .arg("build")
+                .arg("--from-ast")
+                .arg(&tree)
+                .assert()
+                .failure()
+                .stderr(predicate::str::contains("version 0 of the format"));
+            std::fs::remove_file(tree)?;
+            std::fs::remove_file(python)?;
+            Ok(())
+        }
+    }
+
+    mod serialize {
+        use super::*;
+        use crate::serialize::{self, Format};
+        use assert_fs::fixture::FileWriteStr;
+
+        const FORMATS: [Format; 4] = [Format::Yaml, Format::Json, Format::Ron, Format::Bin];
+
+        #[test]
+        fn round_trip() -> Result<(), Box<dyn std::error::Error>> {
+            for file in ["../README.md", "rosetta.pg", "test.pg"] {
+                for program in crate::harness::corpus(file.as_ref())? {
+                    let (main, diagnostics) = check(program.name.as_ref(), &program.source);
+                    // Trees of programs with errors are written but can't be read back
+                    if diagnostics.iter().any(|d| d.is_error()) {
+                        continue;
+                    }
+                    for format in FORMATS {
+                        let read = serialize::read(format, &serialize::write(format, &main));
+                        assert_eq!(read.as_ref(), Ok(&main), "{} in {format:?}", program.name);
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        #[test]
+        fn schema() -> Result<(), Box<dyn std::error::Error>> {
+            let schema: serde_json::Value = serde_json::from_str(&serialize::schema())?;
+            let schema = jsonschema::JSONSchema::compile(&schema).expect("the schema is valid");
+            let valid = |yaml: &str| -> Result<(), String> {
+                let tree: serde_json::Value = serde_yaml::from_str(yaml).map_err(|e| e.to_string())?;
+                schema.validate(&tree).map_err(|errors| errors.map(|e| format!("{e} at {}", e.instance_path)).collect())
+            };
+
+            valid(&std::fs::read_to_string("test.yml")?)?;
+            for file in ["../README.md", "rosetta.pg"] {
+                for program in crate::harness::corpus(file.as_ref())? {
+                    let (main, _) = check(program.name.as_ref(), &program.source);
+                    let yaml = String::from_utf8(serialize::write(Format::Yaml, &main))?;
+                    valid(&yaml).map_err(|e| format!("{}: {e}", program.name))?;
+                }
+            }
+
+            let (main, _) = check("test.pg".as_ref(), "var a = 1\n");
+            let yaml = String::from_utf8(serialize::write(Format::Yaml, &main))?;
+            assert!(valid(&yaml.replace("Int: 1", "Int: one")).is_err());
+            assert!(valid(&yaml.replace("Init", "Initialize")).is_err());
+            Ok(())
+        }
+
+        #[test]
+        fn output() -> Result<(), Box<dyn std::error::Error>> {
+            let file = assert_fs::NamedTempFile::new("test.pg")?;
+            file.write_str("var a = 3\nprintln(a * 2)\n")?;
+
+            let printed = Command::cargo_bin("polyglot_parser")?
+                .arg("build")
+                .arg(file.path())
+                .args(["--emit-format", "json", "-o", "-"])
+                .assert()
+                .success()
+                .get_output()
+                .stdout
+                .clone();
+            assert!(serialize::read(Format::Json, &printed).is_ok());
+            assert!(!file.path().with_extension("json").exists());
+
+            let dir = assert_fs::TempDir::new()?;
+            let tree = dir.path().join("test.cbor");
+            Command::cargo_bin("polyglot_parser")?
+                .arg("build")
+                .arg(file.path())
+                .args(["--emit-format", "bin", "-o"])
+                .arg(dir.path())
+                .assert()
+                .success();
+            Command::cargo_bin("polyglot_parser")?
+                .args(["build", "--target", "python", "-o", "-", "--from-ast"])
+                .arg(&tree)
+                .assert()
+                .success()
+                .stdout(predicate::str::contains("print(a * 2)"));
+
+            Command::cargo_bin("polyglot_parser")?
+                .arg("build")
+                .arg(file.path())
+                .args(["--target", "python", "--emit-format", "ron"])
+                .assert()
+                .failure();
+            Ok(())
+        }
+    }
+
+    mod fmt {
+        use super::*;
+        use crate::fmt::format;
+        use crate::ir;
+        use assert_fs::fixture::FileWriteStr;
+
+        /// Lines of a program, each one ended by a newline.
+        fn lines(lines: &[&str]) -> String {
+            lines.iter().map(|line| format!("{line}\n")).collect()
+        }
+
+        #[test]
+        fn layout() {
+            let code = lines(&[
+                "// squares",
+                "fn show(a:int, b :int):int",
+                "  println(a*b) // trailing",
+                "",
+                "",
+                "    for i in 0..3",
+                "  println(i^2)",
+                "    end",
+                "end",
+                "var d:[str->int]=[\"a\"->1]",
+                "if d[\"a\"]==1=>print(\"one\")",
+                "elif !(d[\"a\"]>=100)&&true=>print(\"small\")",
+                "else=>print(\"big\")",
+                "/* done */",
+            ]);
+            let formatted = lines(&[
+                "// squares",
+                "fn show(a: int, b: int): int",
+                "    println(a * b) // trailing",
+                "",
+                "    for i in 0..3",
+                "        println(i ^ 2)",
+                "    end",
+                "end",
+                "var d: [str -> int] = [\"a\" -> 1]",
+                "if d[\"a\"] == 1                => print(\"one\")",
+                "elif !(d[\"a\"] >= 100) && true => print(\"small\")",
+                "else                          => print(\"big\")",
+                "/* done */",
+            ]);
+            assert_eq!(format(&code).unwrap(), formatted);
+        }
+
+        #[test]
+        fn native() {
+            let code = lines(&[
+                "native fn hypot(a: num, b: num): num",
+                "native python",
+                "        import math",
+                "        if a:",
+                "            return math.hypot(a, b) // not a comment",
+                "end",
+            ]);
+            let formatted = lines(&[
+                "native fn hypot(a: num, b: num): num",
+                "native python",
+                "    import math",
+                "    if a:",
+                "        return math.hypot(a, b) // not a comment",
+                "end",
             ]);
+            assert_eq!(format(&code).unwrap(), formatted);
+        }
+
+        #[test]
+        fn corpus() -> Result<(), Box<dyn std::error::Error>> {
+            for file in ["../README.md", "rosetta.pg", "test.pg"] {
+                for program in crate::harness::corpus(file.as_ref())? {
+                    let Ok(formatted) = format(&program.source) else {
+                        continue;
+                    };
+                    assert_eq!(format(&formatted).as_ref(), Ok(&formatted), "{} isn't idempotent", program.name);
+                    for comment in program.source.lines().filter_map(|line| line.split_once("// ")) {
+                        assert!(formatted.contains(comment.1.trim_end()), "{} lost `{}`", program.name, comment.1);
+                    }
+
+                    let (before, diagnostics) = check(program.name.as_ref(), &program.source);
+                    if diagnostics.iter().any(|d| d.is_error()) {
+                        continue;
+                    }
+                    let (after, diagnostics) = check(program.name.as_ref(), &formatted);
+                    assert!(!diagnostics.iter().any(|d| d.is_error()), "{}:\n{formatted}", program.name);
+                    assert_eq!(ir::lower(&before).to_string(), ir::lower(&after).to_string(), "{}", program.name);
+                }
+            }
+            Ok(())
+        }
+
+        #[test]
+        fn syntax_error() {
+            let error = format("var a = \n").unwrap_err();
+            assert_eq!(error.code, ErrorCode::SyntaxError);
+        }
+
+        #[test]
+        fn examples() -> Result<(), Box<dyn std::error::Error>> {
+            Command::cargo_bin("polyglot_parser")?.args(["fmt", "--check", "rosetta.pg", "test.pg"]).assert().success();
+            Ok(())
+        }
+
+        #[test]
+        fn check_command() -> Result<(), Box<dyn std::error::Error>> {
+            let file = assert_fs::NamedTempFile::new("test.pg")?;
+            file.write_str("var a=1\nif a>0\nprintln(a)\nend\n")?;
+
+            Command::cargo_bin("polyglot_parser")?
+                .args(["fmt", "--check"])
+                .arg(file.path())
+                .assert()
+                .failure()
+                .stdout(predicate::str::contains(file.path().to_str().unwrap()));
+            assert_eq!(std::fs::read_to_string(file.path())?, "var a=1\nif a>0\nprintln(a)\nend\n");
+
+            Command::cargo_bin("polyglot_parser")?.arg("fmt").arg(file.path()).assert().success();
+            assert_eq!(std::fs::read_to_string(file.path())?, "var a = 1\nif a > 0\n    println(a)\nend\n");
+            Command::cargo_bin("polyglot_parser")?
+                .args(["fmt", "--check"])
+                .arg(file.path())
+                .assert()
+                .success()
+                .stdout("");
+            Ok(())
+        }
+    }
+
+    mod cli {
+        use super::*;
+        // Can write to the stdin of the program
+        use assert_cmd::Command;
+        use assert_fs::fixture::{FileWriteStr, PathChild};
+        use assert_fs::TempDir;
+
+        #[test]
+        fn check_command() -> Result<(), Box<dyn std::error::Error>> {
+            let dir = TempDir::new()?;
+            dir.child("a.pg").write_str("var a = 1\n")?;
+            dir.child("b.pg").write_str("var b = a\n")?;
+
+            Command::cargo_bin("polyglot_parser")?.arg("check").arg(dir.child("a.pg").path()).assert().success();
+            assert!(!dir.child("a.yml").exists());
+            // Every program is checked even after one fails
+            Command::cargo_bin("polyglot_parser")?
+                .arg("check")
+                .arg(dir.path().join("*.pg"))
+                .assert()
+                .code(1)
+                .stderr(predicate::str::contains("b.pg:1:9"));
+            Ok(())
+        }
+
+        #[test]
+        fn inputs() -> Result<(), Box<dyn std::error::Error>> {
+            let dir = TempDir::new()?;
+            dir.child("a.pg").write_str("println(1)\n")?;
+            dir.child("b.pg").write_str("println(2)\n")?;
+            let out = dir.child("out");
+
+            Command::cargo_bin("polyglot_parser")?
+                .args(["build", "--target", "python", "-o"])
+                .arg(out.path())
+                .arg(dir.path().join("[ab].pg"))
+                .assert()
+                .success();
+            assert!(out.child("a.py").exists() && out.child("b.py").exists());
+            assert!(!dir.child("a.py").exists());
+
+            Command::cargo_bin("polyglot_parser")?
+                .args(["build", "--target", "python", "-"])
+                .write_stdin("var a = 3\nprintln(a)\n")
+                .assert()
+                .success()
+                .stdout(predicate::str::contains("a = 3"));
+            Command::cargo_bin("polyglot_parser")?
+                .args(["run", "-"])
+                .write_stdin("println(5)\n")
+                .assert()
+                .success()
+                .stdout("5\n");
+            Command::cargo_bin("polyglot_parser")?
+                .arg("check")
+                .arg(dir.path().join("c*.pg"))
+                .assert()
+                .code(2)
+                .stderr(predicate::str::contains("no file matches"));
+            Ok(())
+        }
+
+        #[test]
+        fn emit_command() -> Result<(), Box<dyn std::error::Error>> {
+            Command::cargo_bin("polyglot_parser")?
+                .args(["emit", "tokens", "-"])
+                .write_stdin("var a = 1\n")
+                .assert()
+                .success()
+                .stdout(predicate::str::contains("    Init 0..9\n      Name 4..5 \"a\"\n"));
+            Command::cargo_bin("polyglot_parser")?
+                .args(["emit", "ast", "--format", "json", "-"])
+                .write_stdin("var a = 1\n")
+                .assert()
+                .success()
+                .stdout(predicate::str::contains("\"Init\""));
+            // The tree of a program with errors is still printed
+            Command::cargo_bin("polyglot_parser")?
+                .args(["emit", "ast", "-"])
+                .write_stdin("var a = b\n")
+                .assert()
+                .code(1)
+                .stdout(predicate::str::contains("Init"));
+            Ok(())
+        }
+
+        #[test]
+        fn exit_codes() -> Result<(), Box<dyn std::error::Error>> {
+            let dir = TempDir::new()?;
+            let file = dir.child("test.pg");
+            file.write_str("println(1)\n")?;
+            dir.child("taken").write_str("")?;
+
+            Command::cargo_bin("polyglot_parser")?.arg(file.path()).assert().code(2);
+            Command::cargo_bin("polyglot_parser")?
+                .args(["emit", "ir", "--format", "json"])
+                .arg(file.path())
+                .assert()
+                .code(2);
+            Command::cargo_bin("polyglot_parser")?
+                .args(["run", "-"])
+                .write_stdin("var a = [1]\nprintln(a[3])\n")
+                .assert()
+                .code(1);
+            // The output directory can't be created
+            Command::cargo_bin("polyglot_parser")?
+                .args(["build", "-o"])
+                .arg(dir.child("taken").path())
+                .arg(file.path())
+                .assert()
+                .code(3)
+                .stderr(predicate::str::contains("can't create"));
+            Ok(())
+        }
+    }
+
+    mod harness {
+        use super::*;
+        use crate::backend::{Backend, Capabilities, Output};
+        use crate::diagnostic::Diagnostic;
+        use crate::harness::{self, Execution, Outcome, Program};
+        use assert_fs::fixture::FileWriteStr;
+        use std::time::Duration;
+
+        /// Backend whose programs always print "hello".
+        struct Hello;
+
+        impl Backend for Hello {
+            fn name(&self) -> &'static str {
+                "hello"
+            }
+
+            fn extension(&self) -> &'static str {
+                "txt"
+            }
+
+            fn capabilities(&self) -> Capabilities {
+                Capabilities::default()
+            }
+
+            fn emit(&self, _main: &Main) -> Result<Output, Diagnostic> {
+                Ok(Output {
+                    source: "hello\n".into(),
+                    files: Vec::new(),
+                })
+            }
+
+            fn commands(&self, source: &std::path::Path) -> Vec<Command> {
+                let mut cat = Command::new("cat");
+                cat.arg(source);
+                vec![cat]
+            }
+        }
+
+        /// Backend whose toolchain is never installed.
+        struct Missing;
+
+        impl Backend for Missing {
+            fn name(&self) -> &'static str {
+                "missing"
+            }
+
+            fn extension(&self) -> &'static str {
+                "txt"
+            }
+
+            fn capabilities(&self) -> Capabilities {
+                Capabilities::default()
+            }
+
+            fn emit(&self, _main: &Main) -> Result<Output, Diagnostic> {
+                Ok(Output::default())
+            }
+
+            fn commands(&self, _source: &std::path::Path) -> Vec<Command> {
+                vec![Command::new("polyglot-missing-toolchain")]
+            }
+        }
+
+        /// Backend whose programs never end.
+        struct Endless;
+
+        impl Backend for Endless {
+            fn name(&self) -> &'static str {
+                "endless"
+            }
+
+            fn extension(&self) -> &'static str {
+                "txt"
+            }
+
+            fn capabilities(&self) -> Capabilities {
+                Capabilities::default()
+            }
+
+            fn emit(&self, _main: &Main) -> Result<Output, Diagnostic> {
+                Ok(Output::default())
+            }
+
+            fn commands(&self, _source: &std::path::Path) -> Vec<Command> {
+                let mut sleep = Command::new("sleep");
+                sleep.arg("60");
+                vec![sleep]
+            }
+        }
+
+        #[test]
+        fn markdown_corpus() -> Result<(), Box<dyn std::error::Error>> {
+            let file = assert_fs::NamedTempFile::new("README.md")?;
+            file.write_str("# Title\nText\n    not code\n\n    var a = 1\n\n    println(a)\nText\n```\nprintln(2)\n```\n")?;
+
+            let programs = harness::corpus(file.path())?;
+            let name = file.path().display();
+            assert_eq!(
+                programs,
+                [
+                    Program {
+                        name: format!("{name}:5"),
+                        source: "var a = 1\n\nprintln(a)\n".into(),
+                    },
+                    Program {
+                        name: format!("{name}:10"),
+                        source: "println(2)\n".into(),
+                    },
+                ]
+            );
+            Ok(())
+        }
 
-            assert_eq!(out, correct);
+        #[cfg(unix)]
+        #[test]
+        fn divergences() {
+            let program = |name: &str, source: &str| Program {
+                name: name.into(),
+                source: source.into(),
+            };
+            let programs = [
+                program("same.pg", "println(\"hello\")\n"),
+                program("different.pg", "var list = [1]\nprintln(list[1])\n"),
+                program("invalid.pg", "var a = b\n"),
+            ];
+            let report = harness::compare(&programs, &[&Hello, &Missing], Duration::from_secs(10));
+
+            assert_eq!(report.targets, ["vm", "hello"]);
+            assert_eq!(report.skipped, [("missing", "`polyglot-missing-toolchain` is not on PATH".to_owned())]);
+            assert_eq!(report.rejected, ["invalid.pg"]);
+            assert_eq!(report.runs[0].outcomes, [Outcome::Same, Outcome::Same]);
+            let found = Execution {
+                stdout: "hello\n".into(),
+                code: Some(0),
+            };
+            assert_eq!(report.runs[1].outcomes, [Outcome::Same, Outcome::Diverged(found)]);
+            assert!(!report.success());
+            assert!(report.to_string().contains("hello diverged on different.pg\n  expected (exit code 1):\n  found (exit code 0):\n  | hello\n"));
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn timeouts() {
+            let programs = [
+                Program {
+                    name: "endless.pg".into(),
+                    source: "while true\n    println(1)\nend\n".into(),
+                },
+                Program {
+                    name: "hello.pg".into(),
+                    source: "println(\"hello\")\n".into(),
+                },
+            ];
+            let report = harness::compare(&programs, &[&Endless], Duration::from_millis(200));
+
+            assert_eq!(report.timed_out, ["endless.pg"]);
+            assert_eq!(report.runs.len(), 1);
+            assert_eq!(report.runs[0].outcomes, [Outcome::Same, Outcome::TimedOut]);
+            assert!(!report.success());
+            assert!(report.to_string().contains("endless timed out on hello.pg\n"));
+        }
+
+        #[test]
+        fn nothing_compared() {
+            let programs = [Program {
+                name: "invalid.pg".into(),
+                source: "var a = b\n".into(),
+            }];
+            let report = harness::compare(&programs, &[], Duration::from_secs(10));
+
+            assert!(report.runs.is_empty());
+            assert!(!report.success());
+            assert!(report.to_string().contains("no program could be compared"));
+            assert!(!harness::compare(&[], &[], Duration::from_secs(10)).success());
+        }
+
+        #[test]
+        fn difftest_command() -> Result<(), Box<dyn std::error::Error>> {
+            let file = assert_fs::NamedTempFile::new("test.pg")?;
+            file.write_str("var ages = [\"Alex\" -> 52]\nprintln(ages)\nprintln(ages[\"Sam\"])\n")?;
+
+            Command::cargo_bin("polyglot_parser")?
+                .arg("difftest")
+                .arg(file.path())
+                .assert()
+                .success()
+                .stdout(predicate::str::starts_with("target  same  diverged  unsupported  build failed  timed out\nvm         1"))
+                .stdout(predicate::str::contains("yaml    skipped, its output can't be executed"));
             Ok(())
         }
     }
 
-    fn create_main(vec: Vec<Expr>) -> Main {
-        Main(vec)
+    mod native {
+        use super::*;
+        use crate::backend;
+        use crate::diagnostic::ErrorCode;
+        use crate::interpreter;
+
+        const CODE: &str = "native fn twice(n: int): int\nnative python\n    if n > 0:\n        return n * 2\n    return 0\nnative js\n    return n * 2;\nend\nvar a = twice(21)\nnative python\n    print(a)\nnative js\n    console.log(a);\nend\n";
+
+        #[test]
+        fn sections() {
+            let (main, diagnostics) = check("test.pg".as_ref(), CODE);
+            assert!(diagnostics.is_empty(), "{diagnostics:?}");
+            let ExprKind::NativeFn { sections, r#type, .. } = &main.exprs[0].kind else {
+                panic!("expected a native function, found {:?}", main.exprs[0].kind)
+            };
+            assert_eq!(*r#type, Type::Int);
+            assert_eq!(sections[0].target, "python");
+            assert_eq!(sections[0].code, "if n > 0:\n    return n * 2\nreturn 0");
+            assert_eq!(sections[1].code, "return n * 2;");
+
+            let (_, diagnostics) = check("test.pg".as_ref(), "native cobol\n    x\nnative c\n    y\nnative c\n    z\nend\n");
+            let codes = diagnostics.iter().map(|d| d.code).collect::<Vec<_>>();
+            assert_eq!(codes, [ErrorCode::InvalidNativeSection; 2]);
+        }
+
+        #[test]
+        fn same_signature() {
+            // Native functions share their names with the others
+            let (_, diagnostics) = check("test.pg".as_ref(), &format!("fn twice(n: int): int\n    println(n)\nend\n{CODE}"));
+            let codes = diagnostics.iter().map(|d| d.code).collect::<Vec<_>>();
+            assert_eq!(codes, [ErrorCode::DuplicateFunction]);
+            let (_, diagnostics) = check("test.pg".as_ref(), &format!("{CODE}fn twice()\nend\n"));
+            let codes = diagnostics.iter().map(|d| d.code).collect::<Vec<_>>();
+            assert_eq!(codes, [ErrorCode::DuplicateFunction]);
+        }
+
+        #[test]
+        fn splice() {
+            let (main, _) = check("test.pg".as_ref(), CODE);
+            let python = backend::find("python").unwrap().emit(&main).unwrap().source;
+            assert!(python.contains("def twice(n: int) -> int:\n    if n > 0:\n        return n * 2\n    return 0\n"));
+            assert!(python.ends_with("a = twice(21)\nprint(a)\n"));
+            // TypeScript uses the JavaScript section
+            let ts = backend::find("ts").unwrap().emit(&main).unwrap().source;
+            assert!(ts.contains("function twice(n: number): number {\n    return n * 2;\n}"));
+
+            let error = backend::find("c").unwrap().emit(&main).unwrap_err();
+            assert_eq!(error.code, ErrorCode::UnsupportedByTarget);
+            assert_eq!(error.message, "native code without an implementation for the `c` target");
+        }
+
+        #[test]
+        fn not_interpreted() {
+            let (main, _) = check("test.pg".as_ref(), CODE);
+            let error = interpreter::run(&main, &mut Vec::new()).unwrap_err();
+            assert_eq!(error.message, "ERROR: Native code only runs in the flavors it's written for in line 9, \"twice(21)\".");
+            let program = crate::vm::compile(&main);
+            assert_eq!(crate::vm::run(&program, &main.source_map, &mut Vec::new()).unwrap_err(), error);
+        }
     }
 
-    fn create_init(name: &str, r#type: Type, value: Value, context: &str) -> Expr {
-        Expr::Init {
-            name: name.into(),
-            r#type,
-            value,
-            context: context.into(),
+    mod js {
+        use super::*;
+        use assert_fs::fixture::{FileWriteStr, PathChild};
+        use assert_fs::TempDir;
+
+        const TARGET: &str = "js";
+        /// JavaScript programs aren't compiled, there are no warnings to turn into errors
+        const STRICT: &[&str] = &[];
+
+        fn run(code: &str) -> Option<std::process::Output> {
+            targets::run(TARGET, code, STRICT)
+        }
+
+        #[test]
+        fn lists() {
+            targets::lists(TARGET, STRICT);
+        }
+
+        #[test]
+        fn int_semantics() {
+            let Some(out) = run("var a = -7\nprintln(a / 2)\nprintln(a % 2)\nprintln(a / 2.0)\nprintln(2 ^ -1)\n") else { return };
+            assert_eq!(String::from_utf8_lossy(&out.stdout), "-3\n-1\n-3.5\n0\n");
+        }
+
+        #[test]
+        fn arithmetic_errors() {
+            let code = "var zero = 0\nvar nothing = 0.0\nprintln(1.0 / nothing)\nvar n = 7\nn %= zero\n";
+            let Some(out) = run(code) else { return };
+            assert_eq!(String::from_utf8_lossy(&out.stdout), "inf\n");
+            assert_eq!(String::from_utf8_lossy(&out.stderr), "ERROR: Division by zero in line 5, \"n %= zero\".\n");
+            assert!(!out.status.success());
+        }
+
+        #[test]
+        fn containers() {
+            let code = "type Person = (age: int, name: str)\nvar person: Person = (52, \"Alex\")\nvar flags = [1 -> true]\nvar list = [1, 2, 1]\nvar copy = list\ncopy -= 1\nprintln(\"{person} {flags} {list} {copy}\")\ndbg((1, 'c'))\n";
+            let Some(out) = run(code) else { return };
+            assert_eq!(
+                String::from_utf8_lossy(&out.stdout),
+                "(age: 52, name: \"Alex\") [1 -> true] [1, 2, 1] [2, 1]\n(1, 'c') = (1, \"c\")\n"
+            );
+        }
+
+        #[test]
+        fn out_of_bounds() {
+            targets::out_of_bounds(TARGET, STRICT);
+        }
+
+        #[test]
+        fn typescript() {
+            let dir = TempDir::new().unwrap();
+            let file = dir.child("test.pg");
+            file.write_str("type Point = (x: int, y: int)\nvar p: Point = (1, 2)\nvar d = [1 -> \"a\"]\n").unwrap();
+            Command::cargo_bin("polyglot_parser").unwrap().args(["build", "--target", "ts"]).arg(file.path()).assert().success();
+            let source = std::fs::read_to_string(dir.child("test.ts").path()).unwrap();
+            assert!(source.contains("type Point = Readonly<{ x: number; y: number }>;"));
+            assert!(source.contains("let p: Point = record({ x: 1, y: 2 });"));
+            assert!(source.contains("let d: Map<number, string> = new Map([[1, \"a\"]]);"));
+            assert!(dir.child("runtime.d.ts").path().exists());
         }
     }
 
-    trait MainUtils {}
+    mod fixes {
+        use super::*;
+        use crate::diagnostic::{Applicability, Diagnostic, ErrorCode, TextEdit};
+        use crate::fix::{self, FixError};
+        use assert_fs::fixture::FileWriteStr;
+
+        #[test]
+        fn conversion() -> Result<(), Box<dyn std::error::Error>> {
+            let file = assert_fs::NamedTempFile::new("test.pg")?;
+            file.write_str("var a = 1\nvar b: str = a\n")?;
+
+            Command::cargo_bin("polyglot_parser")?
+                .arg("check")
+                .arg(file.path())
+                .arg("--fix")
+                .assert()
+                .success()
+                .stderr(predicate::str::contains("fixed 1 problem(s)"));
+            assert_eq!(std::fs::read_to_string(file.path())?, "var a = 1\nvar b: str = str(a)\n");
+            Ok(())
+        }
+
+        #[test]
+        fn overlap() {
+            let edit = |start, end, replacement: &str| TextEdit {
+                span: Span::new(0, start, end),
+                replacement: replacement.into(),
+            };
+            let diagnostic = |edits| {
+                Diagnostic::error(ErrorCode::WrongAssignmentType, "wrong assignment type").with_fix(
+                    "",
+                    edits,
+                    Applicability::MachineApplicable,
+                )
+            };
+            let diagnostics = [diagnostic(vec![edit(0, 3, "b")]), diagnostic(vec![edit(2, 4, "c")])];
 
-    impl MainUtils for Main {}
+            assert_eq!(
+                fix::apply("aaaa", 0, &diagnostics),
+                Err(FixError::Overlap(Span::new(0, 0, 3), Span::new(0, 2, 4)))
+            );
+            assert_eq!(fix::apply("aaaa", 0, &diagnostics[..1]).unwrap().unwrap().source, "ba");
+        }
+    }
+
+    /// Builds the `Init` found at `context` inside `code`, with its spans.
+    fn create_init(code: &str, name: &str, r#type: Type, value: ValueKind, context: &str) -> Expr {
+        let start = code.find(context).unwrap();
+        let end = start + context.len();
+        let value_start = start + context.find("= ").unwrap() + 2;
+        Expr::new(
+            ExprKind::Init {
+                name: name.into(),
+                value: Value::new(value, Span::new(0, value_start, end), r#type.clone()),
+                r#type,
+            },
+            Span::new(0, start, end),
+        )
+    }
 }
@@ -1,27 +1,456 @@
-mod parser;
-mod tree;
-use crate::parser::*;
 use clap::Parser;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
+use polyglot_parser::backend;
+use polyglot_parser::diagnostic::{self, Diagnostic, ErrorFormat};
+use polyglot_parser::fix;
+use polyglot_parser::fmt;
+use polyglot_parser::harness;
+use polyglot_parser::interpreter;
+use polyglot_parser::ir;
+use polyglot_parser::parser::*;
+use polyglot_parser::prune;
+use polyglot_parser::serialize::{self, Format};
+use polyglot_parser::source::SourceMap;
+use polyglot_parser::tree::Main;
+use polyglot_parser::vm;
 
 // TODO! Check all values on list and dictionary too see if all have the same type
 
 #[derive(clap::Parser)]
-#[clap(version, about)]
+#[clap(
+    version,
+    about,
+    after_help = "Exits with 1 when a program has errors or fails, 2 when the command line is wrong and 3 when polyglot itself fails"
+)]
 struct Cli {
-    file: std::path::PathBuf,
-    #[clap(short, long)]
-    debug: bool,
+    #[clap(subcommand)]
+    command: Command,
+    /// How to print errors and warnings
+    #[clap(long, value_enum, default_value = "human", global = true)]
+    error_format: ErrorFormat,
+    /// Stop reporting errors after the first N
+    #[clap(long, value_name = "N", global = true)]
+    error_limit: Option<usize>,
 }
 
-fn main() -> Result<(), ParseErr> {
+#[derive(clap::Args)]
+struct Inputs {
+    /// Programs to read, `-` reads one from stdin. Patterns like `*.pg` are expanded even if the shell doesn't
+    #[clap(required = true, value_name = "FILE")]
+    files: Vec<PathBuf>,
+}
+
+#[derive(clap::Args)]
+struct BuildOptions {
+    /// Language to translate the programs into
+    #[clap(long, default_value = "yaml", value_parser = clap::builder::PossibleValuesParser::new(backend::names()))]
+    target: String,
+    /// Make a release build, meant to be run instead of read: the functions and types that are never used and the
+    /// statements that can't run are left out
+    #[clap(long)]
+    release: bool,
+    /// Keep the code a release build leaves out, does nothing without `--release`
+    #[clap(long)]
+    keep_dead_code: bool,
+    /// Format of the tree written by the `yaml` target [default: yaml]
+    #[clap(long, value_enum)]
+    emit_format: Option<Format>,
+    /// Directory to write the translations to instead of next to each program, `-` prints them. Programs read
+    /// from stdin are printed by default
+    #[clap(short, long, value_name = "DIR")]
+    output: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, PartialEq, clap::ValueEnum)]
+enum Emit {
+    /// The checked tree, as the `yaml` target writes it
+    Ast,
+    /// The lowered intermediate representation, with every construct desugared
+    Ir,
+    /// The pairs matched by the grammar, the program isn't checked
+    Tokens,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Check programs, reporting their errors and warnings without writing anything
+    Check {
+        #[clap(flatten)]
+        inputs: Inputs,
+        /// Apply machine applicable suggestions to the programs
+        #[clap(long)]
+        fix: bool,
+    },
+    /// Translate programs into another language, next to each of them
+    Build {
+        /// Programs to translate, `-` reads one from stdin. Patterns like `*.pg` are expanded even if the shell
+        /// doesn't
+        #[clap(value_name = "FILE", required_unless_present = "from-ast")]
+        files: Vec<PathBuf>,
+        /// Translate a program that was already checked, from the tree written by `emit ast` or the `yaml` target.
+        /// Its format is chosen by its extension, a tree with any other extension is read as YAML
+        #[clap(long, value_name = "TREE", conflicts_with = "files")]
+        from_ast: Option<PathBuf>,
+        #[clap(flatten)]
+        options: BuildOptions,
+    },
+    /// Print a representation of programs instead of translating them
+    Emit {
+        #[clap(value_enum)]
+        what: Emit,
+        #[clap(flatten)]
+        inputs: Inputs,
+        /// Format of the tree printed by `ast` [default: yaml]
+        #[clap(long, value_enum)]
+        format: Option<Format>,
+        /// Print `ast` and `ir` as a release build (`build --release`) translates them, without the code it leaves out
+        #[clap(long)]
+        release: bool,
+    },
+    /// Execute a program with the reference interpreter instead of translating it
+    Run {
+        /// Program to execute, `-` reads it from stdin
+        file: PathBuf,
+        /// Compile the program to bytecode and execute it in the virtual machine, which is faster
+        #[clap(long)]
+        vm: bool,
+        /// Print the bytecode of the program instead of executing it
+        #[clap(long)]
+        disassemble: bool,
+    },
+    /// Rewrite programs in the canonical layout, with blocks indented four spaces and spaces around operators. A
+    /// program read from stdin is printed
+    Fmt {
+        #[clap(flatten)]
+        inputs: Inputs,
+        /// Don't write anything, list the programs that aren't formatted and fail if there's any
+        #[clap(long)]
+        check: bool,
+    },
+    /// Print the JSON Schema of the trees written by `emit ast` and the `yaml` target
+    Schema,
+    /// Run programs with the interpreter, the VM and every backend whose toolchain is installed, reporting where
+    /// they behave differently
+    Difftest {
+        /// Programs to run, every code block of a Markdown file is a different program
+        #[clap(required = true)]
+        files: Vec<PathBuf>,
+        /// Seconds every target can run a program for before it's stopped
+        #[clap(long, value_name = "SECONDS", default_value_t = 10)]
+        timeout: u64,
+    },
+}
+
+/// Why a command failed, each reason exits with its own code. When several programs fail, the worst one is kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Failure {
+    /// A program has errors, or fails when it's executed
+    Program = 1,
+    /// The command line is wrong, clap exits with the same code for the mistakes it finds
+    Usage = 2,
+    /// Polyglot itself failed, because of a bug or something like an output it can't write
+    Internal = 3,
+}
+
+type Outcome = Result<(), Failure>;
+
+/// Name of the program read from stdin in diagnostics.
+const STDIN: &str = "<stdin>";
+
+fn main() -> ExitCode {
     let cli = Cli::parse();
-    let main = parse(&cli.file, cli.debug)?;
+    let report = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        report(info);
+        eprintln!("error: polyglot failed unexpectedly, this is a bug");
+    }));
+    match std::panic::catch_unwind(|| execute(&cli)) {
+        Ok(Ok(())) => ExitCode::SUCCESS,
+        Ok(Err(failure)) => ExitCode::from(failure as u8),
+        Err(_) => ExitCode::from(Failure::Internal as u8),
+    }
+}
+
+fn execute(cli: &Cli) -> Outcome {
+    match &cli.command {
+        Command::Check { inputs, fix } => {
+            each(&inputs.files, |file, source| check_program(cli, file, source, *fix).map(drop))
+        }
+        Command::Build { options, .. } if options.emit_format.is_some() && options.target != "yaml" => {
+            Err(usage("--emit-format only applies to the `yaml` target"))
+        }
+        Command::Build { from_ast: Some(tree), options, .. } => {
+            let bytes = std::fs::read(tree).map_err(|e| usage(format!("can't read {}: {e}", tree.display())))?;
+            let format = Format::of(tree).unwrap_or(Format::Yaml);
+            let main = serialize::read(format, &bytes).map_err(|e| {
+                eprintln!("error: can't load {}: {e}", tree.display());
+                Failure::Program
+            })?;
+            translate(cli, options, tree, main)
+        }
+        Command::Build { files, from_ast: None, options } => each(files, |file, source| {
+            let main = check_program(cli, file, source, false)?;
+            translate(cli, options, file, main)
+        }),
+        Command::Emit { what, inputs, format, release } => {
+            if format.is_some() && *what != Emit::Ast {
+                return Err(usage("--format only applies to `emit ast`"));
+            }
+            each(&inputs.files, |file, source| emit(cli, *what, file, source, *format, *release))
+        }
+        Command::Run { file, vm, disassemble } => {
+            let (file, source) = read(file)?;
+            run(cli, &file, source, *vm, *disassemble)
+        }
+        Command::Fmt { inputs, check } => each(&inputs.files, |file, source| format(cli, file, source, *check)),
+        Command::Schema => print(format!("{}\n", serialize::schema())),
+        Command::Difftest { files, timeout } => difftest(files, Duration::from_secs(*timeout)),
+    }
+}
+
+/// Runs `command` on the program of every file, going on after one fails so all of them are reported.
+fn each(files: &[PathBuf], mut command: impl FnMut(&Path, String) -> Outcome) -> Outcome {
+    let mut outcome = Ok(());
+    for file in expand(files)? {
+        if let Err(failure) = read(&file).and_then(|(file, source)| command(&file, source)) {
+            outcome = Err(outcome.err().map_or(failure, |worst: Failure| worst.max(failure)));
+        }
+    }
+    outcome
+}
+
+/// Files of the command line, with the patterns the shell didn't expand replaced by the files they match.
+fn expand(files: &[PathBuf]) -> Result<Vec<PathBuf>, Failure> {
+    let mut expanded = Vec::new();
+    for file in files {
+        let pattern = file.to_string_lossy();
+        if !pattern.contains(['*', '?', '[']) {
+            expanded.push(file.clone());
+            continue;
+        }
+        let matches = glob::glob(&pattern).map_err(|e| usage(format!("invalid pattern {pattern}: {e}")))?;
+        let matches = matches.filter_map(Result::ok).collect::<Vec<_>>();
+        if matches.is_empty() {
+            return Err(usage(format!("no file matches {pattern}")));
+        }
+        expanded.extend(matches);
+    }
+    Ok(expanded)
+}
+
+/// Source of `file` and the name it's reported with, `-` is read from stdin.
+fn read(file: &Path) -> Result<(PathBuf, String), Failure> {
+    if file.as_os_str() == "-" {
+        let mut source = String::new();
+        std::io::stdin().read_to_string(&mut source).map_err(|e| usage(format!("can't read stdin: {e}")))?;
+        return Ok((PathBuf::from(STDIN), source));
+    }
+    let source = std::fs::read_to_string(file).map_err(|e| usage(format!("can't read {}: {e}", file.display())))?;
+    Ok((file.to_owned(), source))
+}
+
+fn write(path: &Path, contents: impl AsRef<[u8]>) -> Outcome {
+    std::fs::write(path, contents).map_err(|e| internal(format!("can't write {}: {e}", path.display())))
+}
+
+/// Writes `output` to stdout. A closed stdout, like at the end of `| head`, isn't an error as nobody reads the rest.
+fn print(output: impl AsRef<[u8]>) -> Outcome {
+    let mut stdout = std::io::stdout().lock();
+    written(stdout.write_all(output.as_ref()).and_then(|()| stdout.flush()))
+}
+
+/// Outcome of writing to stdout, with the same exception as [`print`].
+fn written(result: std::io::Result<()>) -> Outcome {
+    match result {
+        Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => Err(internal(format!("can't write to stdout: {e}"))),
+        _ => Ok(()),
+    }
+}
+
+fn usage(message: impl std::fmt::Display) -> Failure {
+    eprintln!("error: {message}");
+    Failure::Usage
+}
+
+fn internal(message: impl std::fmt::Display) -> Failure {
+    eprintln!("error: {message}");
+    Failure::Internal
+}
+
+fn is_stdin(file: &Path) -> bool {
+    file.as_os_str() == STDIN
+}
+
+/// Checks a program, reporting its diagnostics, and returns its tree if it has no errors.
+fn check_program(cli: &Cli, file: &Path, mut source: String, fix: bool) -> Result<Main, Failure> {
+    let (mut main, mut diagnostics) = check(file, &source);
+    if fix && is_stdin(file) {
+        return Err(usage("--fix can't rewrite a program read from stdin"));
+    }
+    if fix {
+        match fix::apply(&source, 0, &diagnostics) {
+            Ok(Some(fixed)) => {
+                write(file, &fixed.source)?;
+                eprintln!("fixed {} problem(s) in {}", fixed.applied, file.display());
+                source = fixed.source;
+                (main, diagnostics) = check(file, &source);
+            }
+            Ok(None) => {}
+            Err(e) => eprintln!("warning: {e}, {} was not modified", file.display()),
+        }
+    }
+    diagnostic::emit(cli.error_format, &main.source_map, &diagnostics, cli.error_limit).unwrap();
+    if diagnostics.iter().any(Diagnostic::is_error) {
+        return Err(Failure::Program);
+    }
+    Ok(main)
+}
+
+/// Reports diagnostics of a program that wasn't checked, like its syntax errors.
+fn report(cli: &Cli, file: &Path, source: String, diagnostics: &[Diagnostic]) -> Failure {
+    let mut source_map = SourceMap::default();
+    source_map.add(file, source);
+    diagnostic::emit(cli.error_format, &source_map, diagnostics, cli.error_limit).unwrap();
+    Failure::Program
+}
+
+/// Translates a checked program into the target of `options`, next to `file` unless there's an output directory.
+fn translate(cli: &Cli, options: &BuildOptions, file: &Path, mut main: Main) -> Outcome {
+    if options.release && !options.keep_dead_code {
+        prune::prune(&mut main);
+    }
+
+    let backend = backend::find(&options.target).unwrap();
+    // Every construct the target can't express is reported before writing anything, along with the ones it runs
+    // differently
+    let unsupported = backend.check(&main);
+    if !unsupported.is_empty() {
+        diagnostic::emit(cli.error_format, &main.source_map, &unsupported, cli.error_limit).unwrap();
+    }
+    if unsupported.iter().any(Diagnostic::is_error) {
+        return Err(Failure::Program);
+    }
+    // The `yaml` target writes the checked tree, which can be read back from any of its formats
+    let (source, extension, files) = if backend.name() == "yaml" {
+        let format = options.emit_format.unwrap_or(Format::Yaml);
+        (serialize::write(format, &main), format.extension(), Vec::new())
+    } else {
+        match backend.emit(&main) {
+            Ok(output) => (output.source.into_bytes(), backend.extension(), output.files),
+            Err(diagnostic) => {
+                diagnostic::emit(cli.error_format, &main.source_map, &[diagnostic], None).unwrap();
+                return Err(Failure::Program);
+            }
+        }
+    };
+
+    // Support files go next to the translation, or next to the program when it's printed
+    let dir = match options.output.as_deref() {
+        Some(dir) if dir.as_os_str() == "-" => None,
+        None if is_stdin(file) => None,
+        Some(dir) => {
+            std::fs::create_dir_all(dir).map_err(|e| internal(format!("can't create {}: {e}", dir.display())))?;
+            Some(dir)
+        }
+        None => Some(file.parent().unwrap_or(Path::new(""))),
+    };
+    let dir = match dir {
+        Some(dir) => {
+            let name = Path::new(file.file_name().unwrap_or(file.as_os_str()));
+            write(&dir.join(name).with_extension(extension), source)?;
+            dir
+        }
+        None => {
+            print(source)?;
+            file.parent().unwrap_or(Path::new(""))
+        }
+    };
+    for (file, contents) in files {
+        write(&dir.join(file), contents)?;
+    }
+    Ok(())
+}
+
+fn emit(cli: &Cli, what: Emit, file: &Path, source: String, format: Option<Format>, release: bool) -> Outcome {
+    if what == Emit::Tokens {
+        return match tokens(&source) {
+            Ok(tokens) => print(tokens),
+            Err(diagnostic) => Err(report(cli, file, source, &[diagnostic])),
+        };
+    }
+
+    let (mut main, diagnostics) = check(file, &source);
+    diagnostic::emit(cli.error_format, &main.source_map, &diagnostics, cli.error_limit).unwrap();
+    let valid = !diagnostics.iter().any(Diagnostic::is_error);
+    if valid && release {
+        prune::prune(&mut main);
+    }
+    match what {
+        // The tree of a program with errors can still be inspected
+        Emit::Ast => print(serialize::write(format.unwrap_or(Format::Yaml), &main))?,
+        Emit::Ir if valid => print(ir::lower(&main).to_string())?,
+        _ => {}
+    }
+    if valid {
+        Ok(())
+    } else {
+        Err(Failure::Program)
+    }
+}
+
+fn run(cli: &Cli, file: &Path, source: String, use_vm: bool, disassemble: bool) -> Outcome {
+    let main = check_program(cli, file, source, false)?;
 
-    let buffer = serde_yaml::to_string(&main).unwrap();
-    if cli.debug {
-        println!("{buffer}")
+    let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+    let result = if use_vm || disassemble {
+        let program = vm::compile(&main);
+        if disassemble {
+            return print(program.disassemble(&main.source_map));
+        }
+        vm::run(&program, &main.source_map, &mut out)
+    } else {
+        interpreter::run(&main, &mut out)
+    };
+    written(out.flush())?;
+    if let Err(error) = result {
+        // Fails like the translated programs do, without any other output
+        eprintln!("{error}");
+        return Err(Failure::Program);
+    }
+    Ok(())
+}
+
+fn format(cli: &Cli, file: &Path, source: String, check: bool) -> Outcome {
+    let formatted = match fmt::format(&source) {
+        Ok(formatted) => formatted,
+        Err(diagnostic) => return Err(report(cli, file, source, &[diagnostic])),
+    };
+    if check {
+        if formatted != source {
+            print(format!("{}\n", file.display()))?;
+            return Err(Failure::Program);
+        }
+    } else if is_stdin(file) {
+        print(formatted)?;
+    } else if formatted != source {
+        write(file, formatted)?;
+    }
+    Ok(())
+}
+
+fn difftest(files: &[PathBuf], timeout: Duration) -> Outcome {
+    let mut programs = Vec::new();
+    for file in expand(files)? {
+        let found = harness::corpus(&file).map_err(|e| usage(format!("can't read {}: {e}", file.display())))?;
+        programs.extend(found);
+    }
+    let report = harness::compare(&programs, backend::backends(), timeout);
+    print(report.to_string())?;
+    if !report.success() {
+        return Err(Failure::Program);
     }
-    let path = cli.file.with_extension("yml");
-    std::fs::write(path, buffer).unwrap();
     Ok(())
 }
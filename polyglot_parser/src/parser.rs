@@ -6,6 +6,8 @@ use std::ops::Range;
 use std::sync::atomic::AtomicBool;
 use std::sync::Mutex;
 
+use crate::diagnostic::{Applicability, Diagnostic, ErrorCode, ErrorFormat, TextEdit};
+use crate::source::{FileId, SourceMap, Span};
 use crate::tree::*;
 use clap::Parser as P;
 use akin::akin;
@@ -13,264 +15,277 @@ use either::Either;
 use pest::iterators::{Pair, Pairs};
 use pest::Parser as Pest;
 use serde::{Deserialize, Serialize, Serializer};
-use show_my_errors::{AnnotationList, Stylesheet};
 
 #[derive(pest_derive::Parser, pest_typed_tree::TypedTree, Serialize, Deserialize, Debug)]
 #[grammar = "grammar.pest"]
 pub struct Parser;
 
+/// Parses and checks `file`, printing any diagnostic to stderr.
 pub fn parse(file: &std::path::Path, debug: bool) -> Result<Main, ParseErr> {
-    let mut global = Scope::default();
-    global.set_file(file.into(), std::fs::read_to_string(&file).unwrap());
+    let source = std::fs::read_to_string(file).unwrap();
+    let (main, diagnostics) = check(file, &source);
+    crate::diagnostic::emit(ErrorFormat::Human, &main.source_map, &diagnostics, None).unwrap();
+
+    if diagnostics.iter().any(Diagnostic::is_error) && !debug {
+        Err(ParseErr)
+    } else {
+        Ok(main)
+    }
+}
 
-    let file = global.file_as_str().to_owned();
-    let root = nodes::Main::new(Parser::parse(Rule::Main, &file).unwrap().next().unwrap());
-    let mut err_found = false;
+/// Pairs the grammar matches in `source`, one per line and indented by their depth, along with the text of the ones
+/// without inner pairs. Syntax errors are reported in file `0`.
+pub fn tokens(source: &str) -> Result<String, Diagnostic> {
+    fn write(pair: Pair<Rule>, depth: usize, out: &mut String) {
+        let span = pair.as_span();
+        out.push_str(&format!("{}{:?} {}..{}", "  ".repeat(depth), pair.as_rule(), span.start(), span.end()));
+        let mut inner = pair.clone().into_inner().peekable();
+        if inner.peek().is_none() {
+            out.push_str(&format!(" {:?}", span.as_str()));
+        }
+        out.push('\n');
+        for pair in inner {
+            write(pair, depth + 1, out);
+        }
+    }
+
+    let mut out = String::new();
+    for pair in Parser::parse(Rule::Main, source).map_err(|e| syntax_error(e, 0))? {
+        write(pair, 0, &mut out);
+    }
+    Ok(out)
+}
+
+/// Parses and checks `source`, returning the tree along with every diagnostic found.
+pub fn check(file: &std::path::Path, source: &str) -> (Main, Vec<Diagnostic>) {
+    let mut source_map = SourceMap::default();
+    let mut global = Scope::default();
+    global.set_file_id(source_map.add(file, source));
+
+    let mut main = Main::new(source_map, Vec::new());
+    let root = match Parser::parse(Rule::Main, source) {
+        Ok(mut pairs) => nodes::Main::new(pairs.next().unwrap()),
+        Err(e) => {
+            report(syntax_error(e, global.file_id()), &global);
+            return (main, global.take_diagnostics());
+        }
+    };
 
-    let mut main = Main::new(Vec::new());
     for expr in root.list_Expr() {
         let expr = parse_expr(expr, &mut global);
-        err_found = err_found || expr == Expr::Err;
-        main.0.push(expr)
+        main.exprs.push(expr)
     }
 
-    if err_found && !debug {
-        Err(ParseErr)
-    } else {
-        Ok(main)
+    let mut diagnostics = global.take_diagnostics();
+    // Constants are only evaluated once the whole program is valid
+    if !diagnostics.iter().any(Diagnostic::is_error) {
+        diagnostics.extend(crate::fold::fold(&mut main));
     }
+    (main, diagnostics)
+}
+
+pub(crate) fn syntax_error(e: pest::error::Error<Rule>, file_id: FileId) -> Diagnostic {
+    let range = match e.location {
+        pest::error::InputLocation::Pos(p) => p..p,
+        pest::error::InputLocation::Span((start, end)) => start..end,
+    };
+    let span = Span::new(file_id, range.start, range.end);
+    Diagnostic::error(ErrorCode::SyntaxError, "invalid syntax").with_primary(span, e.variant.message())
 }
 
 fn parse_expr(expr: nodes::Expr, scope: &mut Scope) -> Expr {
-    match expr.to_enum() {
-        nodes::ExprChildren::Use(u) => todo!(),
-        nodes::ExprChildren::ModuleAccess(m) => todo!(),
+    let kind = match expr.to_enum() {
+        nodes::ExprChildren::Use(u) => unsupported_module(u.range(), scope),
+        nodes::ExprChildren::ModuleAccess(m) => unsupported_module(m.range(), scope),
         nodes::ExprChildren::Init(init) => parse_init(init, scope),
         nodes::ExprChildren::Decl(decl) => parse_decl(decl, scope),
+        nodes::ExprChildren::UntypedDecl(decl) => parse_untyped_decl(decl, scope),
         nodes::ExprChildren::Assig(assig) => parse_assig(assig, scope),
         nodes::ExprChildren::ListRemAssig(listrem) => parse_list_remove_assign(listrem, scope),
-        nodes::ExprChildren::AddAssig(aa) => parse_assig_op(
-            aa.get_Name(),
-            aa.get_Value(),
-            Op::Add(Box::new((Value::Err, Value::Err))),
-            aa.text().into(),
-            scope,
-        ),
-        nodes::ExprChildren::SubAssig(sa) => parse_assig_op(
-            sa.get_Name(),
-            sa.get_Value(),
-            Op::Sub(Box::new((Value::Err, Value::Err))),
-            sa.text().into(),
-            scope,
-        ),
-        nodes::ExprChildren::MulAssig(ma) => parse_assig_op(
-            ma.get_Name(),
-            ma.get_Value(),
-            Op::Mul(Box::new((Value::Err, Value::Err))),
-            ma.text().into(),
-            scope,
-        ),
-        nodes::ExprChildren::DivAssig(da) => parse_assig_op(
-            da.get_Name(),
-            da.get_Value(),
-            Op::Div(Box::new((Value::Err, Value::Err))),
-            da.text().into(),
-            scope,
-        ),
-        nodes::ExprChildren::ModAssig(ma) => parse_assig_op(
-            ma.get_Name(),
-            ma.get_Value(),
-            Op::Mod(Box::new((Value::Err, Value::Err))),
-            ma.text().into(),
-            scope,
-        ),
-        nodes::ExprChildren::PowAssig(pa) => parse_assig_op(
-            pa.get_Name(),
-            pa.get_Value(),
-            Op::Pow(Box::new((Value::Err, Value::Err))),
-            pa.text().into(),
-            scope,
-        ),
+        nodes::ExprChildren::AddAssig(aa) => parse_assig_op(aa.get_Name(), aa.get_Value(), Op::Add, true, scope),
+        nodes::ExprChildren::SubAssig(sa) => parse_assig_op(sa.get_Name(), sa.get_Value(), Op::Sub, true, scope),
+        nodes::ExprChildren::MulAssig(ma) => parse_assig_op(ma.get_Name(), ma.get_Value(), Op::Mul, false, scope),
+        nodes::ExprChildren::DivAssig(da) => parse_assig_op(da.get_Name(), da.get_Value(), Op::Div, false, scope),
+        nodes::ExprChildren::ModAssig(ma) => parse_assig_op(ma.get_Name(), ma.get_Value(), Op::Mod, false, scope),
+        nodes::ExprChildren::PowAssig(pa) => parse_assig_op(pa.get_Name(), pa.get_Value(), Op::Pow, false, scope),
         nodes::ExprChildren::Typedef(t) => {
             let name = t.get_Name().to_string();
             let r#type = parse_type(&t.get_Type(), scope);
             scope.insert(name.clone(), r#type.clone());
-            Expr::Typedef { name, r#type }
+            ExprKind::Typedef { name, r#type }
         }
-        nodes::ExprChildren::If(i) => parse_if(i, scope),
+        nodes::ExprChildren::If(i) => return parse_if(i, scope),
         nodes::ExprChildren::For(f) => parse_for(f, scope),
         nodes::ExprChildren::While(w) => parse_while(w, scope),
         nodes::ExprChildren::Fn(f) => parse_fn(f, scope),
         nodes::ExprChildren::Call(c) => parse_call(c, scope),
-    }
+        nodes::ExprChildren::Native(n) => match parse_native_sections(n.list_NativeSection().collect(), scope) {
+            Some(sections) => ExprKind::Native { sections },
+            None => ExprKind::Err,
+        },
+        nodes::ExprChildren::NativeFn(f) => parse_native_fn(f, scope),
+    };
+
+    Expr::new(kind, span(expr.range(), scope))
 }
 
-fn parse_init(init: nodes::Init, scope: &mut Scope) -> Expr {
+fn parse_init(init: nodes::Init, scope: &mut Scope) -> ExprKind {
     let name = init.get_Name().to_string();
     let node_v = init.get_Value();
     let parsed_v = parse_value(&node_v, scope);
-    if parsed_v == Value::Err {
-        return Expr::Err;
-    }
+    let vt = parsed_v.r#type.clone();
 
-    let ty = match init.get_Type() {
+    let (ty, value) = match init.get_Type() {
         // Check if variable is of the same type as assignment
         Some(node_t) => {
             let t = parse_type(&node_t, scope);
-            let vt = parse_type_from_value(&parsed_v, scope);
-            if t == vt {
-                t
+            if same_type(&t, &vt) {
+                (t, parsed_v)
             } else {
-                return printerr(
-                    &node_v.range(),
-                    "wrong assignment type",
-                    &format!("expected '{t}', found '{vt}'"),
-                    scope,
-                )
-                .expr_err();
+                let diagnostic = Diagnostic::error(ErrorCode::WrongAssignmentType, "wrong assignment type")
+                    .with_primary(parsed_v.span, format!("expected '{t}', found '{vt}'"));
+                let kind = report(conversion_fix(diagnostic, &parsed_v, &vt, &t), scope).value_err();
+                (t, Value::new(kind, parsed_v.span, Type::Err))
             }
         }
         // No explicit type annotation, inferring type from value
-        None => parse_type_from_value(&parsed_v, scope),
+        None => (vt, parsed_v),
     };
 
+    // Declared even if the value is wrong, uses of an erroneous variable are not reported again
     scope.insert(name.clone(), ty.clone());
-    Expr::Init {
+    ExprKind::Init {
         name,
         r#type: ty,
-        value: parsed_v,
-        context: init.text().to_owned(),
+        value,
     }
 }
 
-fn parse_decl(decl: nodes::Decl, scope: &mut Scope) -> Expr {
+fn parse_decl(decl: nodes::Decl, scope: &mut Scope) -> ExprKind {
     let name = decl.get_Name().to_string();
     let r#type = parse_type(&decl.get_Type(), scope);
-    if r#type == Type::Err {
-        return Expr::Err;
-    }
-
     scope.insert(name.clone(), r#type.clone());
-    Expr::Decl {
-        name,
-        r#type,
-        context: decl.text().to_owned(),
-    }
+    ExprKind::Decl { name, r#type }
 }
 
-fn parse_assig(assig: nodes::Assig, scope: &Scope) -> Expr {
+fn parse_untyped_decl(decl: nodes::UntypedDecl, scope: &mut Scope) -> ExprKind {
+    let name = decl.get_Name();
+    let name_span = span(name.range(), scope);
+    scope.insert(name.to_string(), Type::Err);
+    report(
+        Diagnostic::error(ErrorCode::MissingTypeAnnotation, "variable declared without a type")
+            .with_primary(name_span, "type cannot be inferred without a value")
+            .with_fix(
+                "annotate the type explicitly",
+                vec![TextEdit {
+                    span: Span::new(name_span.file_id, name_span.end, name_span.end),
+                    replacement: ": TYPE".into(),
+                }],
+                Applicability::HasPlaceholders,
+            ),
+        scope,
+    )
+    .expr_err()
+}
+
+fn parse_assig(assig: nodes::Assig, scope: &Scope) -> ExprKind {
     let name = assig.get_Name().to_string();
-    let name_range = assig.get_Name().range();
+    let name_span = span(assig.get_Name().range(), scope);
     let value = parse_value(&assig.get_Value(), scope);
-    if value == Value::Err {
-        return Expr::Err;
+    if value.is_err() {
+        return ExprKind::Err;
     }
-    let value_range = assig.get_Value().range();
 
     if let Some(var_t) = scope.get(&name).cloned() {
-        let inferred = parse_type_from_value(&value, scope);
-        if inferred != var_t {
-            return printerr(
-                &value_range,
-                "wrong assignment type",
-                format!("expected '{var_t}', found '{inferred}'"),
-                scope,
-            )
-            .expr_err();
+        let inferred = &value.r#type;
+        if !same_type(&var_t, inferred) {
+            let diagnostic = Diagnostic::error(ErrorCode::WrongAssignmentType, "wrong assignment type")
+                .with_primary(value.span, format!("expected '{var_t}', found '{inferred}'"));
+            return report(conversion_fix(diagnostic, &value, inferred, &var_t), scope).expr_err();
         }
     } else {
-        return printerr(&name_range, "assignment to inexistent variable", "not declared", scope).expr_err();
+        return printerr(name_span, ErrorCode::UndeclaredVariable, "assignment to inexistent variable", "not declared", scope).expr_err();
     }
 
-    Expr::Assig {
-        name,
-        value,
-        context: assig.text().to_owned(),
-    }
+    ExprKind::Assig { name, value }
 }
 
-fn parse_list_remove_assign(listrem: nodes::ListRemAssig, scope: &Scope) -> Expr {
+fn parse_list_remove_assign(listrem: nodes::ListRemAssig, scope: &Scope) -> ExprKind {
     let name = listrem.get_Name().to_string();
-    let name_range = listrem.get_Name().range();
+    let name_span = span(listrem.get_Name().range(), scope);
     let value = parse_value(&listrem.get_Value(), scope);
-    if value == Value::Err {
-        return Expr::Err;
+    if value.is_err() {
+        return ExprKind::Err;
     }
-    let value_range = listrem.get_Value().range();
     let var_t = if let Some(t) = scope.get(&name) {
         t
     } else {
-        return printerr(&name_range, "variable exists but is not a list", "not a list", scope).expr_err();
+        return printerr(name_span, ErrorCode::UndeclaredVariable, "removing from inexistent list", "not declared", scope).expr_err();
     };
 
-    if let Type::List(lt) = var_t {
-        let vt = parse_type_from_value(&value, scope);
-        if **lt != vt {
-            return printerr(&value_range, "wrong type", &format!("expected '{lt}', found '{vt}'"), scope).expr_err();
+    if var_t.is_err() {
+        return ExprKind::Err;
+    } else if let Type::List(lt) = var_t {
+        let vt = &value.r#type;
+        // Like `-=`, a whole list of elements can be removed
+        if !same_type(lt, vt) && !same_type(var_t, vt) {
+            return printerr(value.span, ErrorCode::WrongElementType, "wrong type", format!("expected '{lt}', found '{vt}'"), scope).expr_err();
         }
     } else {
-        return printerr(&name_range, "removing from inexistent list", "not declared", scope).expr_err();
+        return printerr(name_span, ErrorCode::NotAList, "variable exists but is not a list", "not a list", scope).expr_err();
     }
 
-    Expr::Assig {
-        name: listrem.get_Name().to_string(),
-        value: Value::Op {
-            op: Op::ListRemoveAll(Box::new((name, value))),
-            range: value_range,
-        },
-        context: listrem.text().into(),
+    let (value_span, list_t) = (value.span, var_t.clone());
+    ExprKind::Assig {
+        name: name.clone(),
+        value: Value::new(ValueKind::Op(Op::ListRemoveAll(Box::new((name, value)))), value_span, list_t),
     }
 }
 
-fn parse_assig_op(name_n: nodes::Name, value_n: nodes::Value, operation: Op, context: String, scope: &Scope) -> Expr {
+fn parse_assig_op(
+    name_n: nodes::Name,
+    value_n: nodes::Value,
+    operation: fn(Box<(Value, Value)>) -> Op,
+    list_op: bool,
+    scope: &Scope,
+) -> ExprKind {
     let name = name_n.to_string();
-    let name_range = name_n.range();
+    let name_span = span(name_n.range(), scope);
     let value = parse_value(&value_n, scope);
-    if value == Value::Err {
-        return Expr::Err;
+    if value.is_err() {
+        return ExprKind::Err;
     }
-    let value_range = value_n.range();
 
-    if let Some(var_t) = scope.get(&name).cloned() {
-        let inferred = parse_type_from_value(&value, scope);
-        if inferred != var_t {
-            return printerr(
-                &value_range,
-                "wrong assignment type",
-                format!("expected '{var_t}', found '{inferred}'"),
-                scope,
-            )
-            .expr_err();
+    let var_t = if let Some(var_t) = scope.get(&name).cloned() {
+        let inferred = &value.r#type;
+        // Elements can be added to or removed from lists
+        let element = matches!(&var_t, Type::List(l) if list_op && same_type(l, inferred));
+        if !element && !same_type(&var_t, inferred) {
+            let diagnostic = Diagnostic::error(ErrorCode::WrongAssignmentType, "wrong assignment type")
+                .with_primary(value.span, format!("expected '{var_t}', found '{inferred}'"));
+            return report(conversion_fix(diagnostic, &value, inferred, &var_t), scope).expr_err();
         }
+        var_t
     } else {
-        return printerr(&name_range, "assignment to inexistent variable", "not declared", scope).expr_err();
-    }
-
-    let value = Value::Op {
-        op: operation.set_value(
-            Value::Var {
-                name: name.clone(),
-                range: value_range.clone(),
-            },
-            value,
-        ),
-        range: value_range,
+        return printerr(name_span, ErrorCode::UndeclaredVariable, "assignment to inexistent variable", "not declared", scope).expr_err();
     };
 
-    Expr::Assig { name, value, context }
+    let op_span = name_span.to(value.span);
+    let var = Value::new(ValueKind::Var(name.clone()), name_span, var_t);
+    let op = operation(Box::new((var, value)));
+    if !supported_op(&op, op_span, scope) {
+        return ExprKind::Err;
+    }
+    let value = typed_value(ValueKind::Op(op), op_span, scope);
+
+    ExprKind::Assig { name, value }
 }
 
 fn parse_if(i: impl ToIf, scope: &mut Scope) -> Expr {
-    let cmp = parse_value(&i.get_Value(), scope);
-    let mut if_scope = scope.clone_into_new_scope(Vec::new());
-
-    let mut exprs = Vec::new();
-    let mut err_found = false;
-    for expr in i.list_Expr() {
-        let expr = parse_expr(expr, &mut if_scope);
-        err_found = err_found || expr == Expr::Err;
-        exprs.push(expr);
-    }
+    let cmp = parse_condition(&i.get_Value(), scope);
+    let mut if_scope = scope.clone_into_block_scope(Vec::new());
+
+    let exprs = i.list_Expr().into_iter().map(|expr| parse_expr(expr, &mut if_scope)).collect();
 
     let elif = if let Some(elif) = i.get_Elif() {
         Some(Box::new(parse_if(elif, scope)))
@@ -278,110 +293,124 @@ fn parse_if(i: impl ToIf, scope: &mut Scope) -> Expr {
         i.get_Else().map(|els| Box::new(parse_else(els, scope)))
     };
 
-    if err_found {
-        Expr::Err
-    } else if i.is_elif() {
-        Expr::Elif {
-            cmp,
-            exprs,
-            elif,
-            context: i.get_Ctx(),
-        }
+    let kind = if i.is_elif() {
+        ExprKind::Elif { cmp, exprs, elif }
     } else {
-        Expr::If {
-            cmp,
-            exprs,
-            elif,
-            context: i.get_Ctx(),
-        }
+        ExprKind::If { cmp, exprs, elif }
+    };
+    Expr::new(kind, span(i.get_Range(), scope))
+}
+
+/// Value of an `if`, `elif` or `while`, which has to be a bool.
+fn parse_condition(value: &impl ToValueEnum, scope: &Scope) -> Value {
+    let cmp = parse_value(value, scope);
+    if !cmp.r#type.is_err() && !matches!(cmp.r#type, Type::Bool) {
+        let found = &cmp.r#type;
+        printerr(cmp.span, ErrorCode::WrongConditionType, "condition is not a bool", format!("expected 'bool', found '{found}'"), scope);
     }
+    cmp
 }
 
 fn parse_else(e: nodes::Else, scope: &mut Scope) -> Expr {
-    let mut scope = scope.clone_into_new_scope(Vec::new());
-
-    let mut exprs = Vec::new();
-    let mut err_found = false;
-    for expr in e.list_Expr() {
-        let expr = parse_expr(expr, &mut scope);
-        err_found = err_found || expr == Expr::Err;
-        exprs.push(expr);
-    }
+    let mut else_scope = scope.clone_into_block_scope(Vec::new());
 
-    Expr::Else {
-        exprs,
-        context: e.text().into(),
-    }
+    let exprs = e.list_Expr().map(|expr| parse_expr(expr, &mut else_scope)).collect();
+
+    Expr::new(ExprKind::Else { exprs }, span(e.range(), scope))
 }
 // TODO! Arreglar parsing dels char
-fn parse_for(f: nodes::For, scope: &mut Scope) -> Expr {
+fn parse_for(f: nodes::For, scope: &mut Scope) -> ExprKind {
     let mut names = f.list_Name();
     let var = names.next().unwrap().to_string();
     
     let range_type;
     let range = if let Some(n) = names.next() {
-        let range = Value::Var { name: n.to_string(), range: n.range() };
-        let r_t = parse_type_from_value(&range, scope);
-        range_type = if let Type::List(l) = r_t {
-            *l
-        } else {
-            r_t
+        let range = typed_value(ValueKind::Var(n.to_string()), span(n.range(), scope), scope);
+        range_type = match &range.r#type {
+            Type::List(l) => *l.clone(),
+            Type::Range => Type::Int,
+            Type::Err => Type::Err,
+            found => {
+                printerr(range.span, ErrorCode::NotIterable, "loop over a value that can't be iterated", format!("expected a list or range, found '{found}'"), scope);
+                Type::Err
+            }
         };
         range
     } else {
         let r = f.list_Range().next().unwrap();
         range_type = Type::Int;
-        Value::Range((r.get_first_Int().text().parse().unwrap(), r.get_second_Int().text().parse().unwrap()))
+        Value::new(
+            ValueKind::Range((r.get_first_Int().text().parse().unwrap(), r.get_second_Int().text().parse().unwrap())),
+            span(r.range(), scope),
+            Type::Range,
+        )
     };
     
-    let mut for_scope = scope.clone_into_new_scope(vec![(var.clone(), range_type)]);
+    let mut for_scope = scope.clone_into_block_scope(vec![(var.clone(), range_type)]);
     
-    let mut err_found = false;
-    let mut exprs = Vec::new();
-    for expr in f.list_Expr() {
-        let expr = parse_expr(expr, &mut for_scope);
-        err_found = err_found || expr == Expr::Err;
+    let exprs = f.list_Expr().map(|expr| parse_expr(expr, &mut for_scope)).collect();
 
-        exprs.push(expr);
-    }
-    
-    if err_found {
-        Expr::Err
-    } else {
-        Expr::For { var, range, exprs, context: f.text().into() }
-    }
+    ExprKind::For { var, range, exprs }
 }
 
-fn parse_while(w: nodes::While, scope: &mut Scope) -> Expr {
-    let cmp = parse_value(&w.get_Value(), scope);
+fn parse_while(w: nodes::While, scope: &mut Scope) -> ExprKind {
+    let cmp = parse_condition(&w.get_Value(), scope);
 
-    let mut while_scope = scope.clone_into_new_scope(Vec::new());
+    let mut while_scope = scope.clone_into_block_scope(Vec::new());
     
-    let mut err_found = false;
-    let mut exprs = Vec::new();
-    for expr in w.list_Expr() {
-        let expr = parse_expr(expr, &mut while_scope);
-        err_found = err_found || expr == Expr::Err;
+    let exprs = w.list_Expr().map(|expr| parse_expr(expr, &mut while_scope)).collect();
+
+    ExprKind::While { cmp, exprs }
+}
+
+fn parse_fn(f: nodes::Fn, scope: &mut Scope) -> ExprKind {
+    let Some((name, r#type, args)) = parse_signature(f.get_Name(), f.get_Type(), f.get_ArgsDef(), scope) else {
+        return ExprKind::Err;
+    };
+    let mut fn_scope = scope.clone_into_new_scope(args.clone());
+    let exprs = f.list_Expr().map(|expr| parse_expr(expr, &mut fn_scope)).collect();
 
-        exprs.push(expr);
+    ExprKind::Fn {
+        name,
+        r#type,
+        args,
+        exprs,
     }
-    
-    if err_found {
-        Expr::Err
-    } else {
-        Expr::While { cmp, exprs, context: w.text().into() }
+}
+
+/// Native functions are declared like any other, but their body is trusted to match the signature.
+fn parse_native_fn(f: nodes::NativeFn, scope: &mut Scope) -> ExprKind {
+    let Some((name, r#type, args)) = parse_signature(f.get_Name(), f.get_Type(), f.get_ArgsDef(), scope) else {
+        return ExprKind::Err;
+    };
+    match parse_native_sections(f.list_NativeSection().collect(), scope) {
+        Some(sections) => ExprKind::NativeFn {
+            name,
+            r#type,
+            args,
+            sections,
+        },
+        None => ExprKind::Err,
     }
 }
 
-fn parse_fn(f: nodes::Fn, scope: &mut Scope) -> Expr {
-    let name = f.get_Name().to_string();
-    let r#type = if let Some(ty) = f.get_Type() {
+/// Name, return type and arguments of a function.
+type Signature = (String, Type, Vec<(String, Type)>);
+
+/// Signature of a function, declared in `scope`, or nothing if the name is taken.
+fn parse_signature(
+    name: nodes::Name,
+    ty: Option<nodes::Type>,
+    args: Option<nodes::ArgsDef>,
+    scope: &mut Scope,
+) -> Option<Signature> {
+    let r#type = if let Some(ty) = ty {
         parse_type(&ty, scope)
     } else {
         Type::Void
     };
 
-    let args = if let Some(args) = f.get_ArgsDef() {
+    let args: Vec<_> = if let Some(args) = args {
         args.list_Name()
             .zip(args.list_Type())
             .map(|(name, ty)| (name.to_string(), parse_type(&ty, scope)))
@@ -390,206 +419,336 @@ fn parse_fn(f: nodes::Fn, scope: &mut Scope) -> Expr {
         Vec::new()
     };
 
-    if scope.insert_fn(name.clone(), r#type.clone(), &args) {
-        return printerr(
-            &f.get_Name().range(),
+    if scope.insert_fn(name.to_string(), r#type.clone(), &args) {
+        printerr(
+            span(name.range(), scope),
+            ErrorCode::DuplicateFunction,
             "function with the same name is defined",
             "already exists, try changing the name",
             scope,
-        )
-        .expr_err();
+        );
+        return None;
     }
-    let mut fn_scope = scope.clone_into_new_scope(args.clone());
-    let exprs = f.list_Expr().map(|expr| parse_expr(expr, &mut fn_scope)).collect();
+    Some((name.to_string(), r#type, args))
+}
 
-    Expr::Fn {
-        name,
-        r#type,
-        args,
-        exprs,
-        context: f.text().into(),
+fn parse_native_sections(sections: Vec<nodes::NativeSection>, scope: &Scope) -> Option<Vec<NativeSection>> {
+    let mut parsed: Vec<NativeSection> = Vec::new();
+    let mut valid = true;
+    for section in sections {
+        let target = section.get_NativeTarget();
+        let name = target.text().to_owned();
+        let target_span = span(target.range(), scope);
+        if !crate::backend::names().any(|n| n == name) {
+            let targets = crate::backend::names().collect::<Vec<_>>().join(", ");
+            printerr(target_span, ErrorCode::InvalidNativeSection, "native code for an unknown target", format!("expected one of {targets}"), scope);
+            valid = false;
+        } else if parsed.iter().any(|s| s.target == name) {
+            printerr(target_span, ErrorCode::InvalidNativeSection, "native code repeated for the same target", "this target already has a section", scope);
+            valid = false;
+        }
+        parsed.push(NativeSection {
+            target: name,
+            code: dedent(section.get_NativeCode().text()),
+            span: span(section.range(), scope),
+        });
+    }
+    valid.then_some(parsed)
+}
+
+/// Lines of a native section without the indentation they share, the first line is the rest of `native <target>`.
+pub(crate) fn dedent(code: &str) -> String {
+    let mut lines = code.lines();
+    let first = lines.next().unwrap_or_default().trim();
+    let lines = lines.collect::<Vec<_>>();
+    let indent = lines
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    let mut dedented = lines.iter().map(|line| line.get(indent..).unwrap_or_default()).collect::<Vec<_>>();
+    if !first.is_empty() {
+        dedented.insert(0, first);
     }
+    dedented.join("\n")
 }
 
-fn parse_call(c: nodes::Call, scope: &Scope) -> Expr {
+fn parse_call(c: nodes::Call, scope: &Scope) -> ExprKind {
     let name = c.get_Name().to_string();
     let func = if let Some(func) = scope.get_fn(&name) {
         func
+    } else if BUILTINS.contains(&name.as_str()) {
+        return parse_builtin_call(c, name, scope);
     } else {
-        return printerr(&c.get_Name().range(), "call to non declared function", "does not exist", scope).expr_err();
+        return printerr(span(c.get_Name().range(), scope), ErrorCode::UndeclaredFunction, "call to non declared function", "does not exist", scope).expr_err();
     };
 
-    let mut err = false;
-    let mut i = 0;
-    let args = c
-        .list_Value()
-        .map(|val| {
-            let value = parse_value(&val, scope);
-            let vt = parse_type_from_value(&value, scope);
-            let at = &func.args[i];
-            i += 1;
-
-            if vt != *at {
-                err = true;
-                return printerr(&val.range(), "wrong argument type", format!("expected '{at}', found '{vt}'"), scope)
-                    .value_err();
+    let values = c.list_Value().collect::<Vec<_>>();
+    if values.len() != func.args.len() {
+        // The arguments are still checked, their own errors are reported too
+        values.iter().for_each(|val| drop(parse_value(val, scope)));
+        let (expected, found) = (func.args.len(), values.len());
+        return printerr(
+            span(c.range(), scope),
+            ErrorCode::WrongArgumentType,
+            "wrong number of arguments",
+            format!("'{name}' takes {expected} argument(s), found {found}"),
+            scope,
+        )
+        .expr_err();
+    }
+
+    let args = values
+        .iter()
+        .zip(&func.args)
+        .map(|(val, at)| {
+            let value = parse_value(val, scope);
+            let vt = &value.r#type;
+
+            if !same_type(at, vt) {
+                let diagnostic = Diagnostic::error(ErrorCode::WrongArgumentType, "wrong argument type")
+                    .with_primary(value.span, format!("expected '{at}', found '{vt}'"));
+                let kind = report(conversion_fix(diagnostic, &value, vt, at), scope).value_err();
+                return Value::new(kind, value.span, Type::Err);
             }
 
             value
         })
         .collect();
 
-    if err {
-        return Expr::Err;
+    ExprKind::Call { name, args }
+}
+
+/// Builtins take a single value of any type, `print` and `println` interpolate variables in strings.
+fn parse_builtin_call(c: nodes::Call, name: String, scope: &Scope) -> ExprKind {
+    let args = c.list_Value().map(|val| parse_value(&val, scope)).collect::<Vec<_>>();
+    if args.len() != 1 {
+        return printerr(
+            span(c.range(), scope),
+            ErrorCode::WrongArgumentType,
+            "wrong number of arguments",
+            format!("'{name}' takes exactly one argument"),
+            scope,
+        )
+        .expr_err();
     }
 
-    Expr::Call { name, args }
+    match &args[0].kind {
+        ValueKind::Str(s) if name != "dbg" => {
+            for (offset, segment) in interpolation(s) {
+                if let Segment::Var(var) = segment {
+                    if scope.get(var).is_none() {
+                        // Skip the opening quote and brace
+                        let start = args[0].span.start + offset + 2;
+                        let var_span = Span::new(args[0].span.file_id, start, start + var.len());
+                        printerr(var_span, ErrorCode::UndeclaredVariable, format!("variable '{var}' does not exist"), "not declared", scope);
+                    }
+                }
+            }
+        }
+        _ => {
+            if args[0].r#type == Type::Void {
+                printerr(args[0].span, ErrorCode::WrongArgumentType, "wrong argument type", "functions returning void have no value", scope);
+            }
+        }
+    }
+
+    ExprKind::Call { name, args }
 }
 
 fn parse_value(value: &impl ToValueEnum, scope: &Scope) -> Value {
-    match value.to_value_enum() {
-        nodes::ValueChildren::Range(r) => Value::Range((r.get_first_Int().text().parse().unwrap(), r.get_second_Int().text().parse().unwrap())),
-        nodes::ValueChildren::Int(i) => Value::Int(i.text().parse().unwrap()),
-        nodes::ValueChildren::Num(n) => Value::Num(n.text().parse().unwrap()),
-        nodes::ValueChildren::Bool(b) => Value::Bool(Bool::Primitive(b.text() == "true")),
-        nodes::ValueChildren::Char(c) => Value::Char(c.text().as_bytes()[0] as char),
+    let kind = match value.to_value_enum() {
+        nodes::ValueChildren::Range(r) => ValueKind::Range((r.get_first_Int().text().parse().unwrap(), r.get_second_Int().text().parse().unwrap())),
+        nodes::ValueChildren::Int(i) => ValueKind::Int(i.text().parse().unwrap()),
+        nodes::ValueChildren::Num(n) => ValueKind::Num(n.text().parse().unwrap()),
+        nodes::ValueChildren::Bool(b) => ValueKind::Bool(b.text() == "true"),
+        nodes::ValueChildren::Char(c) => ValueKind::Char(c.text().chars().nth(1).unwrap()),
         nodes::ValueChildren::Str(s) => {
-            Value::Str(s.text().strip_prefix('"').unwrap().strip_suffix('"').unwrap().into())
+            ValueKind::Str(s.text().strip_prefix('"').unwrap().strip_suffix('"').unwrap().into())
         }
-        nodes::ValueChildren::Tuple(t) => Value::Tuple(
+        nodes::ValueChildren::Tuple(t) => ValueKind::Tuple(
             t.list_Value()
                 .map(|val| parse_value(&val, scope))
                 .collect::<Vec<Value>>(),
         ),
-        nodes::ValueChildren::Struct(s) => Value::Struct(
+        nodes::ValueChildren::Struct(s) => ValueKind::Struct(
             s.list_StructVal()
                 .map(|sval| (sval.get_Name().text().to_owned(), parse_value(&sval.get_Value(), scope)))
                 .collect(),
         ),
-        nodes::ValueChildren::TupleAccess(ta) => Value::TupleAccess {
+        nodes::ValueChildren::TupleAccess(ta) => ValueKind::TupleAccess {
             name: ta.get_Name().text().into(),
             access_mode: match ta.get_TupleAccessType().to_enum() {
                 nodes::TupleAccessTypeChildren::Name(n) => TupleAccessMode::Member(n.text().into()),
                 nodes::TupleAccessTypeChildren::Index(i) => TupleAccessMode::Index(i.text().parse().unwrap()),
             },
-            name_range: ta.get_Name().range(),
-            access_range: ta.get_TupleAccessType().range(),
+            name_span: span(ta.get_Name().range(), scope),
+            access_span: span(ta.get_TupleAccessType().range(), scope),
         },
-        nodes::ValueChildren::List(l) => Value::List(
+        nodes::ValueChildren::List(l) => ValueKind::List(
             l.list_Value()
                 .map(|val| parse_value(&val, scope))
                 .collect::<Vec<Value>>(),
         ),
         nodes::ValueChildren::ListAccess(la) => parse_value_list_access(la, scope),
-        nodes::ValueChildren::Dict(d) => Value::Dict(
+        nodes::ValueChildren::Dict(d) => ValueKind::Dict(
             d.list_DictPair()
                 .map(|pair| (parse_value(&pair.get_first_Value(), scope), parse_value(&pair.get_second_Value(), scope)))
                 .collect::<Vec<(Value, Value)>>(),
         ),
-        nodes::ValueChildren::Name(n) => Value::Var {
-            name: n.text().into(),
-            range: n.range(),
-        },
+        nodes::ValueChildren::Name(n) => ValueKind::Var(n.text().into()),
         
         nodes::ValueChildren::Op(op) => parse_value_op(op, scope),
-        nodes::ValueChildren::Cmp(cmp) => Value::Bool(Bool::Cmp(parse_value_cmp(cmp, scope))),
-        nodes::ValueChildren::Parenthesis(p) => Value::Parenthesis(Box::new(parse_value(&p.get_Value(), scope))),
+        nodes::ValueChildren::Cmp(cmp) => ValueKind::Cmp(parse_value_cmp(cmp, scope)),
+        nodes::ValueChildren::Parenthesis(p) => ValueKind::Parenthesis(Box::new(parse_value(&p.get_Value(), scope))),
         nodes::ValueChildren::And(and) => {
             let lhs = if let Some(lhs) = and.list_Lhs().next() {
                 parse_value(&lhs, scope)
             } else {
                 let cmp = and.list_Cmp().next().unwrap();
-                let range = cmp.span().start()..cmp.span().end();
-                Value::Cmp {
-                    cmp: parse_value_cmp(cmp, scope),
-                    range,
-                }
+                let cmp_span = span(cmp.range(), scope);
+                Value::new(ValueKind::Cmp(parse_value_cmp(cmp, scope)), cmp_span, Type::Bool)
             };
 
             let rhs = parse_value(&and.get_Value(), scope);
 
-            Value::Cmp {
-                cmp: Cmp::And(Box::new((lhs, rhs))),
-                range: and.span().start()..and.span().end(),
-            }
+            ValueKind::Cmp(Cmp::And(Box::new((lhs, rhs))))
         }
         nodes::ValueChildren::Or(or) => {
             let lhs = if let Some(lhs) = or.list_Lhs().next() {
                 parse_value(&lhs, scope)
             } else {
                 let cmp = or.list_Cmp().next().unwrap();
-                let range = cmp.span().start()..cmp.span().end();
-                Value::Cmp {
-                    cmp: parse_value_cmp(cmp, scope),
-                    range,
-                }
+                let cmp_span = span(cmp.range(), scope);
+                Value::new(ValueKind::Cmp(parse_value_cmp(cmp, scope)), cmp_span, Type::Bool)
             };
 
             let rhs = parse_value(&or.get_Value(), scope);
 
-            Value::Cmp {
-                cmp: Cmp::Or(Box::new((lhs, rhs))),
-                range: or.span().start()..or.span().end(),
-            }
+            ValueKind::Cmp(Cmp::Or(Box::new((lhs, rhs))))
         }
         nodes::ValueChildren::Call(c) => {
-            let call = parse_call(c, scope);
-            if let Expr::Call { name, args } = call {
-                Value::Call { name, args }
+            if let ExprKind::Call { name, args } = parse_call(c, scope) {
+                ValueKind::Call { name, args }
             } else {
-                Value::Err
+                ValueKind::Err
             }
         }
-        nodes::ValueChildren::ModuleAccess(m) => todo!(),
-        nodes::ValueChildren::TypeConversion(t) => todo!(),
+        nodes::ValueChildren::ModuleAccess(m) => {
+            unsupported_module(m.range(), scope);
+            ValueKind::Err
+        }
+        nodes::ValueChildren::TypeConversion(t) => parse_value_conversion(t, scope),
+    };
+
+    typed_value(kind, span(value.range(), scope), scope)
+}
+
+/// Value of `kind` annotated with its type, any error found while resolving it is reported once here.
+fn typed_value(kind: ValueKind, span: Span, scope: &Scope) -> Value {
+    let r#type = parse_type_from_value(&kind, span, scope);
+    Value::new(kind, span, r#type)
+}
+
+fn parse_value_conversion(conversion: nodes::TypeConversion, scope: &Scope) -> ValueKind {
+    let r#type = match conversion.text().split('(').next().map(str::trim) {
+        Some("int") => Type::Int,
+        Some("bool") => Type::Bool,
+        Some("char") => Type::Char,
+        _ => Type::Str,
+    };
+    let value = parse_value(&conversion.get_Value(), scope);
+    let value_t = &value.r#type;
+    if *value_t == Type::Err || value_t.converted_into(&r#type).is_some() {
+        ValueKind::Conversion { r#type, value: Box::new(value) }
+    } else {
+        printerr(
+            value.span,
+            ErrorCode::InvalidConversion,
+            "invalid conversion",
+            format!("'{value_t}' cannot be converted into '{type}'"),
+            scope,
+        )
+        .value_err()
     }
 }
 
-fn parse_value_list_access(la: nodes::ListAccess, scope: &Scope) -> Value {
+/// Suggests wrapping `value` in a conversion when a value of type `from` can be turned into `to`.
+fn conversion_fix(diagnostic: Diagnostic, value: &Value, from: &Type, to: &Type) -> Diagnostic {
+    if from.converted_into(to).as_ref() != Some(to) || matches!(to, Type::List(_) | Type::Dict(_)) {
+        return diagnostic;
+    }
+    // Anything can be printed, but parsing a string or truncating a number may fail or lose data
+    let applicability = if *to == Type::Str {
+        Applicability::MachineApplicable
+    } else {
+        Applicability::MaybeIncorrect
+    };
+    let span = value.span;
+    diagnostic.with_fix(
+        format!("convert the value into '{to}'"),
+        vec![
+            TextEdit { span: Span::new(span.file_id, span.start, span.start), replacement: format!("{to}(") },
+            TextEdit { span: Span::new(span.file_id, span.end, span.end), replacement: ")".into() },
+        ],
+        applicability,
+    )
+}
+
+fn parse_value_list_access(la: nodes::ListAccess, scope: &Scope) -> ValueKind {
     let name = la.get_Name().text().into();
-    let name_range = la.get_Name().range();
-    let access_range = {
+    let name_span = span(la.get_Name().range(), scope);
+    let access_span = {
         let range = la.get_Value().range();
-        range.start - 1..range.end + 1
+        span(range.start - 1..range.end + 1, scope)
     };
     let list_type = if let Some(t) = scope.get(&name).cloned() {
         t
     } else {
-        return printerr(&name_range, "accessed invalid list/dictionary", "list/dictionary does not exist", scope)
+        return printerr(name_span, ErrorCode::InvalidAccess, "accessed invalid list/dictionary", "list/dictionary does not exist", scope)
             .value_err();
     };
 
     let (access_type, access_mode) = match list_type {
+        t if t.is_err() => return ValueKind::Err,
         Type::List(list) => (
             *list,
-            if let nodes::ValueChildren::Int(i) = la.get_Value().to_enum() {
-                if let Ok(i) = i.text().parse() {
-                    ListAccessMode::List(i)
-                } else {
+            match parse_value(&la.get_Value(), scope) {
+                index if index.is_err() => return ValueKind::Err,
+                Value { kind: ValueKind::Int(i), .. } if i < 0 => {
                     return printerr(
-                        &access_range,
+                        access_span,
+                        ErrorCode::NegativeIndex,
                         "negative index",
                         "index is negative, lists can only be accessed with positive numbers",
                         scope,
                     )
                     .value_err();
                 }
-            } else {
-                return printerr(
-                    &access_range,
-                    "accessing list as a dictionary",
-                    format!("use the index of the element you want to access instead: {name}[0]"),
+                index if matches!(index.r#type, Type::Int | Type::Err) => {
+                    ListAccessMode::List(Box::new(index))
+                }
+                _ => return report(
+                    Diagnostic::error(ErrorCode::ListAccessedAsDict, "accessing list as a dictionary")
+                        .with_primary(access_span, "lists can only be accessed by index")
+                        .with_fix(
+                            "use the index of the element you want to access instead",
+                            vec![TextEdit { span: access_span, replacement: "[0]".into() }],
+                            Applicability::HasPlaceholders,
+                        ),
                     scope,
                 )
-                .value_err();
+                .value_err(),
             },
         ),
         Type::Dict(dict) => {
             let value = parse_value(&la.get_Value(), scope);
-            let value_type = parse_type_from_value(&value, scope);
-            if dict.0 != value_type {
+            let value_type = &value.r#type;
+            if !same_type(&dict.0, value_type) {
                 return printerr(
-                    &access_range,
+                    access_span,
+                    ErrorCode::WrongAccessType,
                     "wrong access type",
                     format!("expected {} found {value_type}", dict.0),
                     scope,
@@ -602,7 +761,8 @@ fn parse_value_list_access(la: nodes::ListAccess, scope: &Scope) -> Value {
         Type::Custom(_) => unreachable!(),
         _ => {
             return printerr(
-                &name_range,
+                name_span,
+                ErrorCode::NotAList,
                 "variable exists but is not a list/dictionary",
                 "not a list/dictionary",
                 scope,
@@ -611,53 +771,38 @@ fn parse_value_list_access(la: nodes::ListAccess, scope: &Scope) -> Value {
         }
     };
 
-    Value::ListAccess {
+    ValueKind::ListAccess {
         name,
         access_type,
         access_mode,
-        name_range,
-        access_range,
+        name_span,
+        access_span,
     }
 }
 
-fn parse_value_op(op: nodes::Op, scope: &Scope) -> Value {
-    let same_types = |lhs, rhs| -> bool {
-        let lhs_t = parse_type_from_value(lhs, scope);
-        let rhs_t = parse_type_from_value(rhs, scope);
-
-        if lhs_t == rhs_t {
-            true
-        } else {
-            printerr(
-                &(op.span().start()..op.span().end()),
-                "cannot add values of different types",
-                format!("cannot add '{lhs_t}' to '{rhs_t}'"),
-                scope,
-            )
-            ._false()
-        }
-    };
-    let span = op.span();
+fn parse_value_op(op: nodes::Op, scope: &Scope) -> ValueKind {
+    let op_span = span(op.range(), scope);
     let op = 
             akin! {
                 let &node = [Add, Sub, Div, Mod, Mul, Pow];
                 let &msg = [add, substract, divide, modulo, multiply, power];
+                let &list_op = [true, true, false, false, false, false];
                 let &branch = {
                     nodes::OpChildren::*node(v) => {
                         let lhs = parse_value(&v.get_Lhs(), scope);
                         let rhs = parse_value(&v.get_Value(), scope);
     
-                        let lhs_t = parse_type_from_value(&lhs, scope);
-                        let rhs_t = parse_type_from_value(&rhs, scope);
+                        let (lhs_t, rhs_t) = (&lhs.r#type, &rhs.r#type);
     
-                        let same_types = if lhs_t == rhs_t {
+                        let same_types = if same_type(lhs_t, rhs_t) {
                             true
-                        } else if let Type::List(list) = lhs_t {
-                            if rhs_t == *list {
+                        } else if let (Type::List(list), true) = (lhs_t, *list_op) {
+                            if same_type(list, rhs_t) {
                                 true
                             } else {
                                 printerr(
-                                    &(op.span().start()..op.span().end()),
+                                    op_span,
+                                    ErrorCode::MismatchedOperands,
                                     "cannot *msg values of different types",
                                     format!("cannot *msg '{}' to '{}'", list, rhs_t),
                                     scope,
@@ -666,7 +811,8 @@ fn parse_value_op(op: nodes::Op, scope: &Scope) -> Value {
                             }
                         } else {
                             printerr(
-                                &(op.span().start()..op.span().end()),
+                                op_span,
+                                ErrorCode::MismatchedOperands,
                                 "cannot *msg values of different types",
                                 format!("cannot *msg '{}' to '{}'", lhs_t, rhs_t),
                                 scope,
@@ -674,10 +820,11 @@ fn parse_value_op(op: nodes::Op, scope: &Scope) -> Value {
                             ._false()
                         };
                         
-                        if same_types {
-                            Op::*node(Box::new((lhs, rhs)))
+                        let op = Op::*node(Box::new((lhs, rhs)));
+                        if same_types && supported_op(&op, op_span, scope) {
+                            op
                         } else {
-                            return Value::Err;
+                            return ValueKind::Err;
                         }
                     },
                 };
@@ -687,40 +834,70 @@ fn parse_value_op(op: nodes::Op, scope: &Scope) -> Value {
                 }
             };
 
-    Value::Op {
-        op,
-        range: span.start()..span.end(),
-    }
+    ValueKind::Op(op)
+}
+
+/// Reports operators applied to values that don't support them, like dividing booleans or substracting strings.
+fn supported_op(op: &Op, op_span: Span, scope: &Scope) -> bool {
+    let (v, symbol) = match op {
+        Op::Add(v) => (v, '+'),
+        Op::Sub(v) => (v, '-'),
+        Op::Mul(v) => (v, '*'),
+        Op::Div(v) => (v, '/'),
+        Op::Mod(v) => (v, '%'),
+        Op::Pow(v) => (v, '^'),
+        Op::ListRemoveAll(_) => return true,
+    };
+    let lhs_t = &v.0.r#type;
+    let supported = match lhs_t {
+        Type::Int | Type::Num | Type::Err => true,
+        Type::Str | Type::Char => symbol == '+',
+        Type::List(_) => matches!(symbol, '+' | '-'),
+        _ => false,
+    };
+    supported
+        || printerr(
+            op_span,
+            ErrorCode::InvalidOperandType,
+            format!("operator '{symbol}' not supported"),
+            format!("'{lhs_t}' values don't support '{symbol}'"),
+            scope,
+        )
+        ._false()
 }
 
 fn parse_value_cmp(cmp: nodes::Cmp, scope: &Scope) -> Cmp {
-    let can_cmp = |lhs, rhs| -> bool {
-        let lty = parse_type_from_value(lhs, scope);
-        let rty = parse_type_from_value(rhs, scope);
+    let cmp_span = span(cmp.range(), scope);
+    let can_cmp = |lhs: &Value, rhs: &Value| -> bool {
+        let (lty, rty) = (&lhs.r#type, &rhs.r#type);
 
-        if lty == rty {
+        if lty.is_err() || rty.is_err() {
+            false
+        } else if lty == rty {
             match lty {
                 Type::Tuple(_) | Type::Struct(_) => printerr(
-                    &cmp.range(),
+                    cmp_span,
+                    ErrorCode::ComparingCompound,
                     "comparing tuple/struct",
                     "tuples/structs cannot be compared, create a function if you need it",
                     scope,
                 )
                 ._false(),
                 Type::Void => printerr(
-                    &cmp.range(),
+                    cmp_span,
+                    ErrorCode::ComparingVoid,
                     "trying to compare void expressions",
                     "functions return void, which cannot be compared",
                     scope,
                 )
                 ._false(),
                 Type::Custom(_) => unreachable!(),
-                Type::Err => false,
                 _ => true,
             }
         } else {
             printerr(
-                &cmp.range(),
+                cmp_span,
+                ErrorCode::MismatchedComparison,
                 "comparing values of different types",
                 "only comparisons of the same type are allowed",
                 scope,
@@ -749,12 +926,11 @@ fn parse_value_cmp(cmp: nodes::Cmp, scope: &Scope) -> Cmp {
 
             nodes::CmpChildren::Not(n) => {
                 let lhs = parse_value(&n.get_Value(), scope);
-                let lhs_t = parse_type_from_value(&lhs, scope);
-                if lhs_t == Type::Bool {
+                let lhs_t = &lhs.r#type;
+                if *lhs_t == Type::Bool {
                     Cmp::Not(Box::new(lhs))
-                } else if lhs_t != Type::Err {
-                    let span = n.span();
-                    printerr(&(span.start()..span.end()), "wrong negation type", format!("type '{}' can't be negated", lhs_t), scope).cmp_err()
+                } else if *lhs_t != Type::Err {
+                    printerr(cmp_span, ErrorCode::WrongNegationType, "wrong negation type", format!("type '{}' can't be negated", lhs_t), scope).cmp_err()
                 } else {
                     Cmp::Err
                 }
@@ -787,93 +963,97 @@ fn parse_type(ty: &nodes::Type, scope: &Scope) -> Type {
             if let Some(r#type) = scope.get(c.text()) {
                 r#type.clone()
             } else {
-                printerr(&(c.span().start()..c.span().end()), "not declared type", "does not exist", scope).type_err()
+                printerr(span(c.span().start()..c.span().end(), scope), ErrorCode::UndeclaredType, "not declared type", "does not exist", scope).type_err()
             }
         }
         nodes::TypeChildren::TVoid(_) => unreachable!(),
     }
 }
 
-fn parse_type_from_value(value: &Value, scope: &Scope) -> Type {
-    match value {
-        Value::Range(_) => Type::Range,
-        Value::Int(_) => Type::Int,
-        Value::Num(_) => Type::Num,
-        Value::Bool(_) => Type::Bool,
-        Value::Cmp { .. } => Type::Bool,
-        Value::Char(_) => Type::Char,
-        Value::Str(_) => Type::Str,
-        Value::Tuple(t) => Type::Tuple(
-            t.iter()
-                .map(|val| parse_type_from_value(val, scope))
-                .collect::<Vec<Type>>(),
-        ),
-        Value::Struct(members) => Type::Struct(
-            members
-                .iter()
-                .map(|mem| (mem.0.clone(), parse_type_from_value(&mem.1, scope)))
-                .collect(),
-        ),
-        Value::List(l) => Type::List(Box::new(parse_type_from_value(&l[0], scope))),
-        Value::Dict(d) => {
-            Type::Dict(Box::new((parse_type_from_value(&d[0].0, scope), parse_type_from_value(&d[0].1, scope))))
-        }
-        Value::Var { name, range } => {
+/// Type of a value of `kind`, from the types its inner values already carry.
+fn parse_type_from_value(kind: &ValueKind, span: Span, scope: &Scope) -> Type {
+    match kind {
+        ValueKind::Range(_) => Type::Range,
+        ValueKind::Int(_) => Type::Int,
+        ValueKind::Num(_) => Type::Num,
+        ValueKind::Bool(_) => Type::Bool,
+        ValueKind::Cmp(_) => Type::Bool,
+        ValueKind::Char(_) => Type::Char,
+        ValueKind::Str(_) => Type::Str,
+        ValueKind::Tuple(t) => Type::Tuple(t.iter().map(|val| val.r#type.clone()).collect::<Vec<Type>>()),
+        ValueKind::Struct(members) => Type::Struct(members.iter().map(|mem| (mem.0.clone(), mem.1.r#type.clone())).collect()),
+        ValueKind::List(l) => Type::List(Box::new(l[0].r#type.clone())),
+        ValueKind::Dict(d) => Type::Dict(Box::new((d[0].0.r#type.clone(), d[0].1.r#type.clone()))),
+        ValueKind::Var(name) => {
             if let Some(var_t) = scope.get(name).cloned() {
                 var_t
             } else {
-                printerr(range, &format!("variable '{name}' does not exist"), "not declared", scope).type_err()
+                printerr(span, ErrorCode::UndeclaredVariable, format!("variable '{name}' does not exist"), "not declared", scope).type_err()
             }
         }
-        Value::TupleAccess {
+        ValueKind::TupleAccess {
             name,
             access_mode,
-            name_range,
-            access_range,
-        } => parse_type_from_tuple_access(name, access_mode, name_range, access_range, scope),
-        Value::ListAccess { access_type, .. } => access_type.clone(),
-        Value::Op { op, range } => match op {
+            name_span,
+            access_span,
+        } => parse_type_from_tuple_access(name, access_mode, *name_span, *access_span, scope),
+        ValueKind::ListAccess { access_type, .. } => access_type.clone(),
+        ValueKind::Op(op) => match op {
             Op::Add(v) | Op::Sub(v) | Op::Mul(v) | Op::Div(v) | Op::Mod(v) | Op::Pow(v) => {
-                parse_type_from_value(&v.0, scope)
+                // Operating an int with a num results in a num, and adding chars joins them
+                match (&v.0.r#type, &v.1.r#type) {
+                    (Type::Int, Type::Num) => Type::Num,
+                    (Type::Char, _) => Type::Str,
+                    (lhs, _) => lhs.clone(),
+                }
             }
             Op::ListRemoveAll(lra) => printerr(
-                range,
+                span,
+                ErrorCode::OperationNotPermitted,
                 "operation not permitted",
                 format!("only use as expression, '{} --= ...'", lra.0),
                 scope,
             )
             .type_err(),
         },
-        Value::Parenthesis(p) => parse_type_from_value(p, scope),
+        ValueKind::Parenthesis(p) => p.r#type.clone(),
+        ValueKind::Conversion { r#type, value } => value.r#type.converted_into(r#type).unwrap_or(Type::Err),
         // TODO! Complex values
-        Value::Call { name, args } => scope.get_fn_type(name),
-        Value::RetExpr(_) => todo!(),
-        Value::Err => Type::Err,
+        ValueKind::Call { name, args } => scope.get_fn_type(name),
+        ValueKind::RetExpr(r) => match *r {},
+        ValueKind::Err => Type::Err,
     }
 }
 
 fn parse_type_from_tuple_access(
     name: &str,
     access_type: &TupleAccessMode,
-    name_range: &Range<usize>,
-    access_range: &Range<usize>,
+    name_span: Span,
+    access_span: Span,
     scope: &Scope,
 ) -> Type {
     let tuple_t = if let Some(ty) = scope.get(name) {
         ty.clone()
     } else {
-        return printerr(name_range, "accessed invalid tuple/struct", "struct does not exist", scope).type_err();
+        return printerr(name_span, ErrorCode::InvalidAccess, "accessed invalid tuple/struct", "struct does not exist", scope).type_err();
     };
+    if tuple_t.is_err() {
+        return Type::Err;
+    }
 
     match access_type {
         TupleAccessMode::Member(member) => {
             let mut struct_t = if let Type::Struct(t) = tuple_t {
                 t
             } else {
-                return printerr(
-                    access_range,
-                    "accessed tuple by member name",
-                    format!("use index instead: {name}.0"),
+                return report(
+                    Diagnostic::error(ErrorCode::TupleAccessedByMember, "accessed tuple by member name")
+                        .with_primary(access_span, "tuples have no member names")
+                        .with_fix(
+                            "use index instead",
+                            vec![TextEdit { span: access_span, replacement: "0".into() }],
+                            Applicability::HasPlaceholders,
+                        ),
                     scope,
                 )
                 .type_err();
@@ -883,7 +1063,7 @@ fn parse_type_from_tuple_access(
             if let Ok(ty) = struct_t.binary_search_by_key(&member, |(a, _)| a) {
                 struct_t[ty].1.clone()
             } else {
-                printerr(access_range, format!("member '{name}.{member}' does not exist"), "not declared", scope);
+                printerr(access_span, ErrorCode::UndeclaredMember, format!("member '{name}.{member}' does not exist"), "not declared", scope);
                 Type::Err
             }
         }
@@ -891,10 +1071,10 @@ fn parse_type_from_tuple_access(
             let tuple_t = if let Type::Tuple(t) = tuple_t {
                 t
             } else {
-                return printerr(
-                    access_range,
-                    "accessed struct by index",
-                    format!("use member name instead: {name}.member"),
+                return report(
+                    Diagnostic::error(ErrorCode::StructAccessedByIndex, "accessed struct by index")
+                        .with_primary(access_span, "structs are accessed by member name")
+                        .with_suggestion(format!("use member name instead: {name}.member")),
                     scope,
                 )
                 .type_err();
@@ -911,7 +1091,8 @@ fn parse_type_from_tuple_access(
                 };
 
                 printerr(
-                    &(name_range.start..access_range.end),
+                    name_span.to(access_span),
+                    ErrorCode::IndexOutOfBounds,
                     "index out of bounds",
                     format!("tuple has {n_elems}, trying to access element number {}", index + 1),
                     scope,
@@ -929,12 +1110,12 @@ impl ParseErr {
         Type::Err
     }
 
-    pub fn value_err(self) -> Value {
-        Value::Err
+    pub fn value_err(self) -> ValueKind {
+        ValueKind::Err
     }
 
-    pub fn expr_err(self) -> Expr {
-        Expr::Err
+    pub fn expr_err(self) -> ExprKind {
+        ExprKind::Err
     }
     pub fn cmp_err(self) -> Cmp {
         Cmp::Err
@@ -949,23 +1130,38 @@ impl ParseErr {
     }
 }
 
-// TODO! Fix lines in AnnotationList not starting at 0
-fn printerr(range: &std::ops::Range<usize>, header: impl AsRef<str>, text: impl AsRef<str>, scope: &Scope) -> ParseErr {
-    let mut list = AnnotationList::new(scope.file_path().to_string_lossy(), scope.file_as_str());
-    list.error(range.start..range.end, header.as_ref(), text.as_ref())
-        .unwrap();
-    list.show_stderr(&Stylesheet::colored()).unwrap();
-    println!();
+fn printerr(span: Span, code: ErrorCode, header: impl AsRef<str>, text: impl AsRef<str>, scope: &Scope) -> ParseErr {
+    report(Diagnostic::error(code, header.as_ref()).with_primary(span, text.as_ref()), scope)
+}
+
+/// Modules can be written but not resolved yet, so using one is an error instead of a crash.
+fn unsupported_module(range: Range<usize>, scope: &Scope) -> ExprKind {
+    let header = "modules are not implemented yet";
+    printerr(span(range, scope), ErrorCode::UnsupportedModule, header, "module used here", scope);
+    ExprKind::Err
+}
+
+fn report(diagnostic: Diagnostic, scope: &Scope) -> ParseErr {
+    scope.report(diagnostic);
     ParseErr
 }
 
+/// Whether a value of type `found` can be used where `expected` is, types with errors are accepted so they are reported only once.
+fn same_type(expected: &Type, found: &Type) -> bool {
+    expected.is_err() || found.is_err() || expected == found
+}
+
+fn span(range: Range<usize>, scope: &Scope) -> Span {
+    Span::new(scope.file_id(), range.start, range.end)
+}
+
 // NODE HELPER TRAITS
 pub trait GetRange {
     fn range(&self) -> std::ops::Range<usize>;
 }
 
 akin! {
-    let &node = [Value, Type, Name, TupleAccess, TupleAccessType, Cmp];
+    let &node = [Expr, Value, Lhs, Type, Name, TupleAccess, TupleAccessType, Cmp, Op, Range, Else, Call, NativeSection, NativeTarget, Use, ModuleAccess];
 
     impl GetRange for crate::parser::nodes::*node<'_> {
         fn range(&self) -> std::ops::Range<usize> {
@@ -974,7 +1170,8 @@ akin! {
     }
 }
 
-pub trait ToValueEnum {
+pub trait ToValueEnum: GetRange {
+
     fn to_value_enum(&self) -> nodes::ValueChildren<'_>;
 }
 
@@ -1012,15 +1209,15 @@ impl NameNodeUtils for nodes::Name<'_> {
 
 #[allow(non_snake_case)]
 pub trait ToIf {
-    fn get_Value(&self) -> nodes::Value;
+    fn get_Value(&self) -> nodes::Value<'_>;
 
-    fn list_Expr(&self) -> Vec<nodes::Expr>;
+    fn list_Expr(&self) -> Vec<nodes::Expr<'_>>;
 
-    fn get_Elif(&self) -> Option<nodes::Elif>;
+    fn get_Elif(&self) -> Option<nodes::Elif<'_>>;
 
-    fn get_Else(&self) -> Option<nodes::Else>;
+    fn get_Else(&self) -> Option<nodes::Else<'_>>;
 
-    fn get_Ctx(&self) -> String;
+    fn get_Range(&self) -> Range<usize>;
 
     fn is_elif(&self) -> bool;
 }
@@ -1030,24 +1227,24 @@ akin! {
     let &elif = [false, true];
     
     impl ToIf for nodes::*node<'_> {
-        fn get_Value(&self) -> nodes::Value {
+        fn get_Value(&self) -> nodes::Value<'_> {
             self.get_Value()
         }
         
-        fn list_Expr(&self) -> Vec<nodes::Expr> {
+        fn list_Expr(&self) -> Vec<nodes::Expr<'_>> {
             self.list_Expr().collect()
         }
         
-        fn get_Elif(&self) -> Option<nodes::Elif> {
+        fn get_Elif(&self) -> Option<nodes::Elif<'_>> {
             self.list_Elif().next()
         }
 
-        fn get_Else(&self) -> Option<nodes::Else> {
+        fn get_Else(&self) -> Option<nodes::Else<'_>> {
             self.list_Else().next()
         }
 
-        fn get_Ctx(&self) -> String {
-            self.text().into()
+        fn get_Range(&self) -> Range<usize> {
+            self.span().start()..self.span().end()
         }
         
         fn is_elif(&self) -> bool {
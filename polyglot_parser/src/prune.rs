@@ -0,0 +1,159 @@
+//! Removal of the functions and types a program never uses.
+//!
+//! Reachability starts at the top level statements and follows every call, so a function only called by another
+//! unused function is removed too. Struct typedefs are kept while a reachable value has their type, which is how
+//! backends name them. Native code can call functions or name types the checker doesn't see, so any word of a
+//! native section matching one counts as a use.
+//!
+//! Statements after one that never finishes can't run either, so they're removed before looking for uses. Without
+//! `ret` or `break` in the language, only a `while` whose condition is always true never finishes.
+use crate::tree::*;
+use crate::visit::{self, Fold, Visitor};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+/// Removes unreachable functions, unused typedefs and statements that can't run from a checked program.
+pub fn prune(main: &mut Main) {
+    main.exprs = Unreachable.fold_block(std::mem::take(&mut main.exprs));
+
+    let mut definitions = Definitions::default();
+    definitions.visit_block(&main.exprs);
+
+    let mut reach = Reachability::default();
+    reach.visit_block(&main.exprs);
+    while let Some(name) = reach.pending.pop() {
+        for expr in definitions.0.get(name.as_str()).into_iter().flatten() {
+            reach.function(expr);
+        }
+    }
+    main.exprs = reach.fold_block(std::mem::take(&mut main.exprs));
+}
+
+/// Drops the statements that follow one that never finishes, in every block.
+struct Unreachable;
+
+impl Fold for Unreachable {
+    fn fold_block(&mut self, mut exprs: Vec<Expr>) -> Vec<Expr> {
+        if let Some(last) = exprs.iter().position(never_finishes) {
+            exprs.truncate(last + 1);
+        }
+        visit::walk_fold_block(self, exprs)
+    }
+}
+
+/// Whether the statements after `expr` can't run, like after a `ret`.
+///
+/// Native code in a loop may leave it with the `break` or `return` of its target, so such loops can finish.
+fn never_finishes(expr: &Expr) -> bool {
+    let ExprKind::While { cmp, exprs } = &expr.kind else {
+        return false;
+    };
+    let mut native = NativeCode(false);
+    native.visit_block(exprs);
+    matches!(cmp.kind, ValueKind::Bool(true)) && !native.0
+}
+
+/// Whether a native section appears in the visited statements.
+struct NativeCode(bool);
+
+impl Visitor<'_> for NativeCode {
+    fn visit_expr(&mut self, expr: &Expr) {
+        self.0 |= matches!(expr.kind, ExprKind::Native { .. });
+        visit::walk_expr(self, expr);
+    }
+}
+
+/// Functions declared anywhere in the program, by name.
+#[derive(Default)]
+struct Definitions<'a>(FxHashMap<&'a str, Vec<&'a Expr>>);
+
+impl<'a> Visitor<'a> for Definitions<'a> {
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        if let ExprKind::Fn { name, .. } | ExprKind::NativeFn { name, .. } = &expr.kind {
+            self.0.entry(name.as_str()).or_default().push(expr);
+        }
+        visit::walk_expr(self, expr);
+    }
+}
+
+#[derive(Default)]
+struct Reachability {
+    called: FxHashSet<String>,
+    /// Called functions whose bodies haven't been visited yet
+    pending: Vec<String>,
+    /// Struct types of the reachable values and declarations
+    structs: Vec<Type>,
+    /// Words of the reachable native sections
+    words: FxHashSet<String>,
+}
+
+impl Visitor<'_> for Reachability {
+    /// Function declarations are only visited once they're called.
+    fn visit_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Typedef { .. } | ExprKind::Fn { .. } | ExprKind::NativeFn { .. } => {}
+            ExprKind::Call { name, .. } => {
+                self.call(name);
+                visit::walk_expr(self, expr);
+            }
+            ExprKind::Native { sections } => self.native(sections),
+            _ => visit::walk_expr(self, expr),
+        }
+    }
+
+    fn visit_value(&mut self, value: &Value) {
+        self.visit_type(&value.r#type);
+        if let ValueKind::Call { name, .. } = &value.kind {
+            self.call(name);
+        }
+        visit::walk_value(self, value);
+    }
+
+    fn visit_type(&mut self, r#type: &Type) {
+        if matches!(r#type, Type::Struct(_)) && !self.structs.iter().any(|s| s.identical(r#type)) {
+            self.structs.push(r#type.clone());
+        }
+        visit::walk_type(self, r#type);
+    }
+}
+
+/// Drops the declarations that were never reached.
+impl Fold for Reachability {
+    fn fold_block(&mut self, exprs: Vec<Expr>) -> Vec<Expr> {
+        let kept = exprs.into_iter().filter(|expr| self.used(expr)).collect::<Vec<_>>();
+        visit::walk_fold_block(self, kept)
+    }
+}
+
+impl Reachability {
+    /// Visits the signature and body of a called function.
+    fn function(&mut self, expr: &Expr) {
+        if let ExprKind::NativeFn { sections, .. } = &expr.kind {
+            self.native(sections);
+        }
+        visit::walk_expr(self, expr);
+    }
+
+    fn call(&mut self, name: &str) {
+        if self.called.insert(name.to_owned()) {
+            self.pending.push(name.to_owned());
+        }
+    }
+
+    fn native(&mut self, sections: &[NativeSection]) {
+        for section in sections {
+            for word in section.code.split(|c: char| !c.is_alphanumeric() && c != '_') {
+                if !word.is_empty() && self.words.insert(word.to_owned()) {
+                    self.call(word);
+                }
+            }
+        }
+    }
+
+    fn used(&self, expr: &Expr) -> bool {
+        match &expr.kind {
+            ExprKind::Fn { name, .. } | ExprKind::NativeFn { name, .. } => self.called.contains(name),
+            ExprKind::Typedef { name, r#type } => self.words.contains(name) || self.structs.iter().any(|s| s == r#type),
+            _ => true,
+        }
+    }
+}
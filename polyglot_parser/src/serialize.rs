@@ -0,0 +1,156 @@
+//! Formats a checked tree can be written in, every one of them reads back exactly the same [`Main`].
+//!
+//! Documents start with the [`SCHEMA_VERSION`] they were written with, which is checked before reading the rest so
+//! a tree from another version of the format gets a clear error instead of a confusing one about its contents.
+use crate::tree::{Cmp, Expr, ExprKind, Main, Type, Value, ValueKind, SCHEMA_VERSION};
+use crate::visit::{self, Visitor};
+use serde::Deserialize;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    Yaml,
+    Json,
+    Ron,
+    /// CBOR, a compact binary encoding that describes itself like JSON does
+    Bin,
+}
+
+/// Why a serialized tree can't be read.
+#[derive(Debug, PartialEq)]
+pub enum LoadError {
+    /// The document has no `version`, it was written before trees had one or isn't a tree at all
+    Unversioned,
+    Older(u32),
+    Newer(u32),
+    /// The document isn't a tree of the current format
+    Invalid(String),
+    /// The tree was written by `emit ast` for a program with errors, backends only translate checked programs
+    Unchecked,
+}
+
+/// The part of a document read before the rest, any other field is ignored.
+#[derive(Deserialize)]
+struct Header {
+    version: Option<u32>,
+}
+
+impl Format {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Yaml => "yml",
+            Format::Json => "json",
+            Format::Ron => "ron",
+            Format::Bin => "cbor",
+        }
+    }
+
+    /// Format of a file by its extension, if it's one of the extensions trees are written with.
+    pub fn of(path: &Path) -> Option<Format> {
+        match path.extension()?.to_str()? {
+            "yml" | "yaml" => Some(Format::Yaml),
+            "json" => Some(Format::Json),
+            "ron" => Some(Format::Ron),
+            "cbor" => Some(Format::Bin),
+            _ => None,
+        }
+    }
+}
+
+pub fn write(format: Format, main: &Main) -> Vec<u8> {
+    match format {
+        Format::Yaml => serde_yaml::to_string(main).unwrap().into_bytes(),
+        Format::Json => serde_json::to_vec_pretty(main).unwrap(),
+        Format::Ron => ron::ser::to_string_pretty(main, ron::ser::PrettyConfig::default())
+            .unwrap()
+            .into_bytes(),
+        Format::Bin => {
+            let mut bytes = Vec::new();
+            ciborium::ser::into_writer(main, &mut bytes).unwrap();
+            bytes
+        }
+    }
+}
+
+/// JSON Schema of the trees written in any format, generated from the types of the tree.
+pub fn schema() -> String {
+    serde_json::to_string_pretty(&schemars::schema_for!(Main)).unwrap()
+}
+
+/// Reads a tree written by [`write`] in the same `format`, checking its version first.
+pub fn read(format: Format, bytes: &[u8]) -> Result<Main, LoadError> {
+    let header: Header = decode(format, bytes)?;
+    match header.version {
+        None => Err(LoadError::Unversioned),
+        Some(v) if v < SCHEMA_VERSION => Err(LoadError::Older(v)),
+        Some(v) if v > SCHEMA_VERSION => Err(LoadError::Newer(v)),
+        Some(_) => {
+            let main: Main = decode(format, bytes)?;
+            let mut errors = Errors(false);
+            errors.visit_block(&main.exprs);
+            if errors.0 {
+                return Err(LoadError::Unchecked);
+            }
+            Ok(main)
+        }
+    }
+}
+
+/// Looks for the nodes the checker puts where a program has errors.
+struct Errors(bool);
+
+impl<'ast> Visitor<'ast> for Errors {
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        self.0 |= matches!(expr.kind, ExprKind::Err);
+        visit::walk_expr(self, expr)
+    }
+
+    fn visit_value(&mut self, value: &'ast Value) {
+        self.0 |= matches!(value.kind, ValueKind::Err) || value.r#type.is_err();
+        visit::walk_value(self, value)
+    }
+
+    fn visit_cmp(&mut self, cmp: &'ast Cmp) {
+        self.0 |= matches!(cmp, Cmp::Err);
+        visit::walk_cmp(self, cmp)
+    }
+
+    fn visit_type(&mut self, r#type: &'ast Type) {
+        self.0 |= r#type.is_err();
+        visit::walk_type(self, r#type)
+    }
+}
+
+fn decode<T: for<'de> Deserialize<'de>>(format: Format, bytes: &[u8]) -> Result<T, LoadError> {
+    let invalid = |e: &dyn std::fmt::Display| LoadError::Invalid(e.to_string());
+    match format {
+        Format::Yaml => serde_yaml::from_slice(bytes).map_err(|e| invalid(&e)),
+        Format::Json => serde_json::from_slice(bytes).map_err(|e| invalid(&e)),
+        // Lets the header read `version: 1` as an option
+        Format::Ron => ron::Options::default()
+            .with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME)
+            .from_bytes(bytes)
+            .map_err(|e| invalid(&e)),
+        Format::Bin => ciborium::de::from_reader(bytes).map_err(|e| invalid(&e)),
+    }
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::Unversioned => {
+                write!(f, "the tree has no `version`, translate the program again to write a new one")
+            }
+            LoadError::Older(v) => write!(
+                f,
+                "the tree is in version {v} of the format, translate the program again to write version {SCHEMA_VERSION}"
+            ),
+            LoadError::Newer(v) => write!(
+                f,
+                "the tree is in version {v} of the format, which is newer than the supported version {SCHEMA_VERSION}"
+            ),
+            LoadError::Invalid(e) => write!(f, "invalid tree: {e}"),
+            LoadError::Unchecked => write!(f, "the tree is of a program with errors, fix them and translate it again"),
+        }
+    }
+}
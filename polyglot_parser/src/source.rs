@@ -0,0 +1,123 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+pub type FileId = usize;
+
+/// Byte range inside one of the files of a [`SourceMap`].
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    pub file_id: FileId,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// 1-based line and column (in characters) of a byte offset.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct LineCol {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Default)]
+pub struct SourceFile {
+    pub path: PathBuf,
+    /// Byte offset where each line starts, so positions can be resolved without the contents.
+    pub line_starts: Vec<usize>,
+    #[serde(skip)]
+    pub contents: String,
+}
+
+/// Every file taking part in a compilation, used to turn a [`Span`] into lines and columns.
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+// IMPLS
+/// The contents aren't serialized, so they don't take part in comparisons either.
+impl PartialEq for SourceFile {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path && self.line_starts == other.line_starts
+    }
+}
+
+impl Span {
+    pub fn new(file_id: FileId, start: usize, end: usize) -> Span {
+        Span { file_id, start, end }
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    /// Span covering both `self` and `other`.
+    pub fn to(&self, other: Span) -> Span {
+        Span::new(self.file_id, self.start.min(other.start), self.end.max(other.end))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+impl SourceFile {
+    pub fn new(path: PathBuf, contents: String) -> SourceFile {
+        let line_starts = std::iter::once(0)
+            .chain(contents.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        SourceFile {
+            path,
+            line_starts,
+            contents,
+        }
+    }
+
+    pub fn line_col(&self, offset: usize) -> LineCol {
+        let line = self.line_starts.partition_point(|&start| start <= offset).max(1);
+        let line_start = self.line_starts[line - 1];
+        let column = match self.contents.get(line_start..offset) {
+            Some(text) => text.chars().count(),
+            None => offset - line_start,
+        };
+        LineCol {
+            line,
+            column: column + 1,
+        }
+    }
+}
+
+impl SourceMap {
+    pub fn add(&mut self, path: impl Into<PathBuf>, contents: impl Into<String>) -> FileId {
+        self.files.push(SourceFile::new(path.into(), contents.into()));
+        self.files.len() - 1
+    }
+
+    pub fn file(&self, id: FileId) -> &SourceFile {
+        &self.files[id]
+    }
+
+    pub fn files(&self) -> &[SourceFile] {
+        &self.files
+    }
+
+    pub fn path(&self, id: FileId) -> &Path {
+        &self.files[id].path
+    }
+
+    pub fn contents(&self, id: FileId) -> &str {
+        &self.files[id].contents
+    }
+
+    /// Start and end position of `span`.
+    pub fn line_col(&self, span: Span) -> (LineCol, LineCol) {
+        let file = self.file(span.file_id);
+        (file.line_col(span.start), file.line_col(span.end))
+    }
+
+    /// Source text covered by `span`, if the contents are available.
+    pub fn snippet(&self, span: Span) -> Option<&str> {
+        self.files.get(span.file_id)?.contents.get(span.range())
+    }
+}
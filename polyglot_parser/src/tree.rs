@@ -1,36 +1,53 @@
 #![allow(unused_imports)]
 #![allow(unused_variables)]
 
+use crate::diagnostic::Diagnostic;
+use crate::source::{FileId, SourceMap, Span};
 use derive_new::new;
 use either::Either;
 use rustc_hash::FxHashMap;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::{
+    cell::RefCell,
     collections::HashMap,
     ops::{Add, Range},
     path::PathBuf,
+    rc::Rc,
 };
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, new)]
-pub struct Main(pub Vec<Expr>);
+/// Format of serialized trees, increased whenever a change to the tree makes older ones unreadable.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Clone, new)]
+pub struct Main {
+    /// Version of the format the tree was written in, only trees of the current one can be read
+    #[new(value = "SCHEMA_VERSION")]
+    pub version: u32,
+    pub source_map: SourceMap,
+    pub exprs: Vec<Expr>,
+}
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
-pub enum Expr {
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Clone, new)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Clone)]
+pub enum ExprKind {
     Init {
         name: String,
         r#type: Type,
         value: Value,
-        context: String,
     },
     Decl {
         name: String,
         r#type: Type,
-        context: String,
     },
     Assig {
         name: String,
         value: Value,
-        context: String,
     },
     Typedef {
         name: String,
@@ -40,52 +57,73 @@ pub enum Expr {
         cmp: Value,
         exprs: Vec<Expr>,
         elif: Option<Box<Expr>>,
-        context: String,
     },
     Elif {
         cmp: Value,
         exprs: Vec<Expr>,
         elif: Option<Box<Expr>>,
-        context: String,
     },
     Else {
         exprs: Vec<Expr>,
-        context: String,
     },
     For {
         var: String,
         range: Value,
         exprs: Vec<Expr>,
-        context: String,
     },
     While {
         cmp: Value,
         exprs: Vec<Expr>,
-        context: String,
     },
     Fn {
         name: String,
         r#type: Type,
         args: Vec<(String, Type)>,
         exprs: Vec<Expr>,
-        context: String,
     },
     Call {
         name: String,
         args: Vec<Value>,
     },
+    /// Code copied verbatim into the targets it has a section for
+    Native {
+        sections: Vec<NativeSection>,
+    },
+    /// Function with a body written in each target, its signature is trusted by the checker
+    NativeFn {
+        name: String,
+        r#type: Type,
+        args: Vec<(String, Type)>,
+        sections: Vec<NativeSection>,
+    },
     Err,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+/// Code of a `native <target>` section, without the indentation shared by its lines.
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Clone)]
+pub struct NativeSection {
+    pub target: String,
+    pub code: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq)]
 pub enum RetExpr {}
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
-pub enum Value {
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, new)]
+pub struct Value {
+    pub kind: ValueKind,
+    pub span: Span,
+    /// Resolved by the checker, custom types are replaced by their definition
+    pub r#type: Type,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq)]
+pub enum ValueKind {
     Range((i64, i64)),
     Int(i64),
     Num(f64),
-    Bool(Bool),
+    Bool(bool),
     Char(char),
     Str(String),
     Tuple(Vec<Value>),
@@ -93,31 +131,26 @@ pub enum Value {
     TupleAccess {
         name: String,
         access_mode: TupleAccessMode,
-        name_range: Range<usize>,
-        access_range: Range<usize>,
+        name_span: Span,
+        access_span: Span,
     },
     List(Vec<Value>),
     ListAccess {
         name: String,
         access_mode: ListAccessMode,
         access_type: Type,
-        name_range: Range<usize>,
-        access_range: Range<usize>,
+        name_span: Span,
+        access_span: Span,
     },
     Dict(Vec<(Value, Value)>),
-    Var {
-        name: String,
-        range: Range<usize>,
-    },
-    Op {
-        op: Op,
-        range: Range<usize>,
-    },
-    Cmp {
-        cmp: Cmp,
-        range: Range<usize>,
-    },
+    Var(String),
+    Op(Op),
+    Cmp(Cmp),
     Parenthesis(Box<Value>),
+    Conversion {
+        r#type: Type,
+        value: Box<Value>,
+    },
     Call {
         name: String,
         args: Vec<Value>,
@@ -126,10 +159,14 @@ pub enum Value {
     Err,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
-pub enum Bool {
-    Primitive(bool),
-    Cmp(Cmp),
+/// Functions available without being declared, a function with the same name replaces them.
+pub const BUILTINS: [&str; 3] = ["print", "println", "dbg"];
+
+/// Piece of a string passed to `print`, where `{name}` is replaced by the value of `name`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Segment<'a> {
+    Text(&'a str),
+    Var(&'a str),
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, new)]
@@ -138,19 +175,19 @@ pub struct Call {
     pub args: Vec<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Clone)]
 pub enum TupleAccessMode {
     Member(String),
     Index(usize),
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Clone)]
 pub enum ListAccessMode {
-    List(usize),
+    List(Box<Value>),
     Dict(Box<Value>),
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Clone)]
 pub enum Op {
     Add(Box<(Value, Value)>),
     Sub(Box<(Value, Value)>),
@@ -161,7 +198,7 @@ pub enum Op {
     Pow(Box<(Value, Value)>),
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Clone)]
 pub enum Cmp {
     Less(Box<(Value, Value)>),
     Greater(Box<(Value, Value)>),
@@ -175,7 +212,7 @@ pub enum Cmp {
     Err,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialOrd, Eq, Ord)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialOrd, Eq, Ord)]
 pub enum Type {
     Range,
     Int,
@@ -194,7 +231,7 @@ pub enum Type {
 
 // CUSTOM DEFINED
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct Fn {
     pub r#type: Type,
     pub args: Vec<Type>,
@@ -206,7 +243,8 @@ pub struct Scope {
     //vars: std::collections::HashMap<String, Type>,
     funcs: std::collections::HashMap<String, Fn, std::hash::BuildHasherDefault<rustc_hash::FxHasher>>,
     //funcs: std::collections::HashMap<String, Fn>,
-    file: (String, PathBuf),
+    file_id: FileId,
+    diagnostics: Rc<RefCell<Vec<Diagnostic>>>,
 }
 
 // IMPLS
@@ -216,6 +254,8 @@ impl PartialEq for Type {
             (Self::Tuple(l0), Self::Tuple(r0)) => l0 == r0,
             (Self::Struct(l0), Self::Struct(r0)) => l0 == r0,
             (Self::Custom(l0), Self::Custom(r0)) => l0 == r0,
+            (Self::List(l0), Self::List(r0)) => l0 == r0,
+            (Self::Dict(l0), Self::Dict(r0)) => l0 == r0,
             (Self::Struct(l0), Self::Tuple(r0)) => {
                 for (l0, r0) in l0.iter().zip(r0) {
                     if l0.1 != *r0 {
@@ -230,6 +270,84 @@ impl PartialEq for Type {
     }
 }
 
+/// Splits `s` into text and interpolated variables, braces not surrounding a name are kept as text.
+pub fn interpolation(s: &str) -> Vec<(usize, Segment<'_>)> {
+    let mut segments = Vec::new();
+    let mut text_start = 0;
+    let mut rest = 0;
+    while let Some(open) = s[rest..].find('{').map(|i| i + rest) {
+        let name_len = s[open + 1..]
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(s.len() - open - 1);
+        let name = &s[open + 1..open + 1 + name_len];
+        let is_var = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && s[open + 1 + name_len..].starts_with('}');
+        if is_var {
+            if text_start < open {
+                segments.push((text_start, Segment::Text(&s[text_start..open])));
+            }
+            segments.push((open, Segment::Var(name)));
+            text_start = open + name_len + 2;
+        }
+        rest = open + 1;
+    }
+    if text_start < s.len() {
+        segments.push((text_start, Segment::Text(&s[text_start..])));
+    }
+    segments
+}
+
+impl Type {
+    /// Whether values of both types have the same representation, unlike `==` which mixes ints and nums.
+    pub fn identical(&self, other: &Type) -> bool {
+        match (self, other) {
+            (Type::Tuple(a), Type::Tuple(b)) => a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.identical(b)),
+            (Type::Struct(a), Type::Struct(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.0 == b.0 && a.1.identical(&b.1))
+            }
+            (Type::List(a), Type::List(b)) => a.identical(b),
+            (Type::Dict(a), Type::Dict(b)) => a.0.identical(&b.0) && a.1.identical(&b.1),
+            _ => std::mem::discriminant(self) == std::mem::discriminant(other),
+        }
+    }
+
+    /// Type resulting of converting `self` into `to`, conversions apply to each element of lists and dictionaries.
+    pub fn converted_into(&self, to: &Type) -> Option<Type> {
+        let primitive = |t: &Type| matches!(t, Type::Int | Type::Num | Type::Bool | Type::Char | Type::Str);
+        match self {
+            _ if !matches!(to, Type::Int | Type::Bool | Type::Char | Type::Str) => None,
+            Type::List(l) if primitive(l) => Some(Type::List(Box::new(to.clone()))),
+            Type::Dict(d) if primitive(&d.0) && primitive(&d.1) => Some(Type::Dict(Box::new((to.clone(), to.clone())))),
+            t if primitive(t) => Some(to.clone()),
+            _ => None,
+        }
+    }
+
+    /// Whether the type comes from an expression that already reported an error.
+    pub fn is_err(&self) -> bool {
+        match self {
+            Type::Err => true,
+            Type::Tuple(t) => t.iter().any(Type::is_err),
+            Type::Struct(s) => s.iter().any(|(_, t)| t.is_err()),
+            Type::List(l) => l.is_err(),
+            Type::Dict(d) => d.0.is_err() || d.1.is_err(),
+            _ => false,
+        }
+    }
+}
+
+impl Expr {
+    pub fn is_err(&self) -> bool {
+        matches!(self.kind, ExprKind::Err)
+    }
+}
+
+impl Value {
+    pub fn is_err(&self) -> bool {
+        matches!(self.kind, ValueKind::Err)
+    }
+}
+
 impl std::fmt::Display for Type {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -284,16 +402,36 @@ impl Scope {
         self.funcs.insert(name, Fn { r#type, args }).is_some()
     }
 
-    pub fn set_file(&mut self, name: PathBuf, contents: String) {
-        self.file = (contents, name);
+    pub fn set_file_id(&mut self, file_id: FileId) {
+        self.file_id = file_id;
+    }
+
+    pub fn file_id(&self) -> FileId {
+        self.file_id
     }
 
-    pub fn file_as_str(&self) -> &str {
-        self.file.0.as_str()
+    /// Records a diagnostic, shared by every scope cloned from this one.
+    ///
+    /// Diagnostics with the same message at the same span are only recorded once.
+    pub fn report(&self, diagnostic: Diagnostic) {
+        let mut diagnostics = self.diagnostics.borrow_mut();
+        let span = diagnostic.primary_span();
+        if !diagnostics.iter().any(|d| d.primary_span() == span && d.message == diagnostic.message) {
+            diagnostics.push(diagnostic)
+        }
     }
 
-    pub fn file_path(&self) -> &std::path::Path {
-        self.file.1.as_path()
+    pub fn take_diagnostics(&self) -> Vec<Diagnostic> {
+        self.diagnostics.take()
+    }
+
+    /// Scope of a block (if, for, while...), which can use every variable declared outside it.
+    pub fn clone_into_block_scope(&self, new_scope_variables: Vec<(String, Type)>) -> Scope {
+        let mut scope = self.clone_into_new_scope(new_scope_variables);
+        for (name, r#type) in &self.vars {
+            scope.vars.entry(name.clone()).or_insert_with(|| r#type.clone());
+        }
+        scope
     }
 
     pub fn clone_into_new_scope(&self, new_scope_variables: Vec<(String, Type)>) -> Scope {
@@ -307,7 +445,8 @@ impl Scope {
         Scope {
             vars: map,
             funcs: self.funcs.clone(),
-            file: self.file.clone(),
+            file_id: self.file_id,
+            diagnostics: self.diagnostics.clone(),
         }
     }
 }
@@ -0,0 +1,600 @@
+//! Traversal of the tree, so a pass only handles the nodes it cares about.
+//!
+//! [`Visitor`] reads a tree, [`VisitorMut`] modifies it in place and [`Fold`] rebuilds it from owned nodes. Every
+//! method defaults to its `walk` function, which visits the children of the node, so an implementation overrides
+//! the nodes it handles and calls the `walk` function to keep descending. The `walk` functions match every variant
+//! without a wildcard, so adding one to the tree fails to compile until it's traversed here.
+use crate::tree::*;
+
+pub trait Visitor<'ast> {
+    fn visit_block(&mut self, exprs: &'ast [Expr]) {
+        walk_block(self, exprs)
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        walk_expr(self, expr)
+    }
+
+    fn visit_value(&mut self, value: &'ast Value) {
+        walk_value(self, value)
+    }
+
+    fn visit_op(&mut self, op: &'ast Op) {
+        walk_op(self, op)
+    }
+
+    fn visit_cmp(&mut self, cmp: &'ast Cmp) {
+        walk_cmp(self, cmp)
+    }
+
+    /// Types written in the program, the resolved type of each value is not visited.
+    fn visit_type(&mut self, r#type: &'ast Type) {
+        walk_type(self, r#type)
+    }
+}
+
+pub fn walk_block<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, exprs: &'ast [Expr]) {
+    for expr in exprs {
+        visitor.visit_expr(expr);
+    }
+}
+
+pub fn walk_expr<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, expr: &'ast Expr) {
+    match &expr.kind {
+        ExprKind::Init { r#type, value, .. } => {
+            visitor.visit_type(r#type);
+            visitor.visit_value(value);
+        }
+        ExprKind::Decl { r#type, .. } | ExprKind::Typedef { r#type, .. } => visitor.visit_type(r#type),
+        ExprKind::Assig { value, .. } => visitor.visit_value(value),
+        ExprKind::If { cmp, exprs, elif } | ExprKind::Elif { cmp, exprs, elif } => {
+            visitor.visit_value(cmp);
+            visitor.visit_block(exprs);
+            if let Some(elif) = elif {
+                visitor.visit_expr(elif);
+            }
+        }
+        ExprKind::Else { exprs } => visitor.visit_block(exprs),
+        ExprKind::For {
+            range: value, exprs, ..
+        }
+        | ExprKind::While { cmp: value, exprs } => {
+            visitor.visit_value(value);
+            visitor.visit_block(exprs);
+        }
+        ExprKind::Fn {
+            r#type, args, exprs, ..
+        } => {
+            visitor.visit_type(r#type);
+            for (_, arg) in args {
+                visitor.visit_type(arg);
+            }
+            visitor.visit_block(exprs);
+        }
+        ExprKind::Call { args, .. } => {
+            for arg in args {
+                visitor.visit_value(arg);
+            }
+        }
+        ExprKind::NativeFn { r#type, args, .. } => {
+            visitor.visit_type(r#type);
+            for (_, arg) in args {
+                visitor.visit_type(arg);
+            }
+        }
+        ExprKind::Native { .. } | ExprKind::Err => {}
+    }
+}
+
+pub fn walk_value<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, value: &'ast Value) {
+    match &value.kind {
+        ValueKind::Tuple(items) | ValueKind::List(items) => {
+            for item in items {
+                visitor.visit_value(item);
+            }
+        }
+        ValueKind::Struct(members) => {
+            for (_, member) in members {
+                visitor.visit_value(member);
+            }
+        }
+        ValueKind::Dict(pairs) => {
+            for (k, v) in pairs {
+                visitor.visit_value(k);
+                visitor.visit_value(v);
+            }
+        }
+        ValueKind::ListAccess {
+            access_mode: ListAccessMode::List(index) | ListAccessMode::Dict(index),
+            access_type,
+            ..
+        } => {
+            visitor.visit_type(access_type);
+            visitor.visit_value(index);
+        }
+        ValueKind::Op(op) => visitor.visit_op(op),
+        ValueKind::Cmp(cmp) => visitor.visit_cmp(cmp),
+        ValueKind::Parenthesis(inner) => visitor.visit_value(inner),
+        ValueKind::Conversion { r#type, value } => {
+            visitor.visit_type(r#type);
+            visitor.visit_value(value);
+        }
+        ValueKind::Call { args, .. } => {
+            for arg in args {
+                visitor.visit_value(arg);
+            }
+        }
+        ValueKind::Range(_)
+        | ValueKind::Int(_)
+        | ValueKind::Num(_)
+        | ValueKind::Bool(_)
+        | ValueKind::Char(_)
+        | ValueKind::Str(_)
+        | ValueKind::TupleAccess { .. }
+        | ValueKind::Var(_)
+        | ValueKind::RetExpr(_)
+        | ValueKind::Err => {}
+    }
+}
+
+pub fn walk_op<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, op: &'ast Op) {
+    match op {
+        Op::Add(v) | Op::Sub(v) | Op::Mul(v) | Op::Div(v) | Op::Mod(v) | Op::Pow(v) => {
+            visitor.visit_value(&v.0);
+            visitor.visit_value(&v.1);
+        }
+        Op::ListRemoveAll(v) => visitor.visit_value(&v.1),
+    }
+}
+
+pub fn walk_cmp<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, cmp: &'ast Cmp) {
+    match cmp {
+        Cmp::Less(v)
+        | Cmp::Greater(v)
+        | Cmp::LessEq(v)
+        | Cmp::GreatEq(v)
+        | Cmp::Equal(v)
+        | Cmp::NotEq(v)
+        | Cmp::Or(v)
+        | Cmp::And(v) => {
+            visitor.visit_value(&v.0);
+            visitor.visit_value(&v.1);
+        }
+        Cmp::Not(v) => visitor.visit_value(v),
+        Cmp::Err => {}
+    }
+}
+
+pub fn walk_type<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, r#type: &'ast Type) {
+    match r#type {
+        Type::Tuple(types) => {
+            for t in types {
+                visitor.visit_type(t);
+            }
+        }
+        Type::Struct(members) => {
+            for (_, t) in members {
+                visitor.visit_type(t);
+            }
+        }
+        Type::List(element) => visitor.visit_type(element),
+        Type::Dict(types) => {
+            visitor.visit_type(&types.0);
+            visitor.visit_type(&types.1);
+        }
+        Type::Range
+        | Type::Int
+        | Type::Num
+        | Type::Bool
+        | Type::Char
+        | Type::Str
+        | Type::Void
+        | Type::Custom(_)
+        | Type::Err => {}
+    }
+}
+
+pub trait VisitorMut {
+    fn visit_block_mut(&mut self, exprs: &mut Vec<Expr>) {
+        walk_block_mut(self, exprs)
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        walk_expr_mut(self, expr)
+    }
+
+    fn visit_value_mut(&mut self, value: &mut Value) {
+        walk_value_mut(self, value)
+    }
+
+    fn visit_op_mut(&mut self, op: &mut Op) {
+        walk_op_mut(self, op)
+    }
+
+    fn visit_cmp_mut(&mut self, cmp: &mut Cmp) {
+        walk_cmp_mut(self, cmp)
+    }
+
+    /// Types written in the program, the resolved type of each value is not visited.
+    fn visit_type_mut(&mut self, r#type: &mut Type) {
+        walk_type_mut(self, r#type)
+    }
+}
+
+pub fn walk_block_mut<V: VisitorMut + ?Sized>(visitor: &mut V, exprs: &mut Vec<Expr>) {
+    for expr in exprs {
+        visitor.visit_expr_mut(expr);
+    }
+}
+
+pub fn walk_expr_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expr: &mut Expr) {
+    match &mut expr.kind {
+        ExprKind::Init { r#type, value, .. } => {
+            visitor.visit_type_mut(r#type);
+            visitor.visit_value_mut(value);
+        }
+        ExprKind::Decl { r#type, .. } | ExprKind::Typedef { r#type, .. } => visitor.visit_type_mut(r#type),
+        ExprKind::Assig { value, .. } => visitor.visit_value_mut(value),
+        ExprKind::If { cmp, exprs, elif } | ExprKind::Elif { cmp, exprs, elif } => {
+            visitor.visit_value_mut(cmp);
+            visitor.visit_block_mut(exprs);
+            if let Some(elif) = elif {
+                visitor.visit_expr_mut(elif);
+            }
+        }
+        ExprKind::Else { exprs } => visitor.visit_block_mut(exprs),
+        ExprKind::For {
+            range: value, exprs, ..
+        }
+        | ExprKind::While { cmp: value, exprs } => {
+            visitor.visit_value_mut(value);
+            visitor.visit_block_mut(exprs);
+        }
+        ExprKind::Fn {
+            r#type, args, exprs, ..
+        } => {
+            visitor.visit_type_mut(r#type);
+            for (_, arg) in args {
+                visitor.visit_type_mut(arg);
+            }
+            visitor.visit_block_mut(exprs);
+        }
+        ExprKind::Call { args, .. } => {
+            for arg in args {
+                visitor.visit_value_mut(arg);
+            }
+        }
+        ExprKind::NativeFn { r#type, args, .. } => {
+            visitor.visit_type_mut(r#type);
+            for (_, arg) in args {
+                visitor.visit_type_mut(arg);
+            }
+        }
+        ExprKind::Native { .. } | ExprKind::Err => {}
+    }
+}
+
+pub fn walk_value_mut<V: VisitorMut + ?Sized>(visitor: &mut V, value: &mut Value) {
+    match &mut value.kind {
+        ValueKind::Tuple(items) | ValueKind::List(items) => {
+            for item in items {
+                visitor.visit_value_mut(item);
+            }
+        }
+        ValueKind::Struct(members) => {
+            for (_, member) in members {
+                visitor.visit_value_mut(member);
+            }
+        }
+        ValueKind::Dict(pairs) => {
+            for (k, v) in pairs {
+                visitor.visit_value_mut(k);
+                visitor.visit_value_mut(v);
+            }
+        }
+        ValueKind::ListAccess {
+            access_mode: ListAccessMode::List(index) | ListAccessMode::Dict(index),
+            access_type,
+            ..
+        } => {
+            visitor.visit_type_mut(access_type);
+            visitor.visit_value_mut(index);
+        }
+        ValueKind::Op(op) => visitor.visit_op_mut(op),
+        ValueKind::Cmp(cmp) => visitor.visit_cmp_mut(cmp),
+        ValueKind::Parenthesis(inner) => visitor.visit_value_mut(inner),
+        ValueKind::Conversion { r#type, value } => {
+            visitor.visit_type_mut(r#type);
+            visitor.visit_value_mut(value);
+        }
+        ValueKind::Call { args, .. } => {
+            for arg in args {
+                visitor.visit_value_mut(arg);
+            }
+        }
+        ValueKind::Range(_)
+        | ValueKind::Int(_)
+        | ValueKind::Num(_)
+        | ValueKind::Bool(_)
+        | ValueKind::Char(_)
+        | ValueKind::Str(_)
+        | ValueKind::TupleAccess { .. }
+        | ValueKind::Var(_)
+        | ValueKind::RetExpr(_)
+        | ValueKind::Err => {}
+    }
+}
+
+pub fn walk_op_mut<V: VisitorMut + ?Sized>(visitor: &mut V, op: &mut Op) {
+    match op {
+        Op::Add(v) | Op::Sub(v) | Op::Mul(v) | Op::Div(v) | Op::Mod(v) | Op::Pow(v) => {
+            visitor.visit_value_mut(&mut v.0);
+            visitor.visit_value_mut(&mut v.1);
+        }
+        Op::ListRemoveAll(v) => visitor.visit_value_mut(&mut v.1),
+    }
+}
+
+pub fn walk_cmp_mut<V: VisitorMut + ?Sized>(visitor: &mut V, cmp: &mut Cmp) {
+    match cmp {
+        Cmp::Less(v)
+        | Cmp::Greater(v)
+        | Cmp::LessEq(v)
+        | Cmp::GreatEq(v)
+        | Cmp::Equal(v)
+        | Cmp::NotEq(v)
+        | Cmp::Or(v)
+        | Cmp::And(v) => {
+            visitor.visit_value_mut(&mut v.0);
+            visitor.visit_value_mut(&mut v.1);
+        }
+        Cmp::Not(v) => visitor.visit_value_mut(v),
+        Cmp::Err => {}
+    }
+}
+
+pub fn walk_type_mut<V: VisitorMut + ?Sized>(visitor: &mut V, r#type: &mut Type) {
+    match r#type {
+        Type::Tuple(types) => {
+            for t in types {
+                visitor.visit_type_mut(t);
+            }
+        }
+        Type::Struct(members) => {
+            for (_, t) in members {
+                visitor.visit_type_mut(t);
+            }
+        }
+        Type::List(element) => visitor.visit_type_mut(element),
+        Type::Dict(types) => {
+            visitor.visit_type_mut(&mut types.0);
+            visitor.visit_type_mut(&mut types.1);
+        }
+        Type::Range
+        | Type::Int
+        | Type::Num
+        | Type::Bool
+        | Type::Char
+        | Type::Str
+        | Type::Void
+        | Type::Custom(_)
+        | Type::Err => {}
+    }
+}
+
+/// Rebuilds a tree from owned nodes, [`Fold::fold_block`] can replace an expression by any number of them.
+pub trait Fold {
+    fn fold_block(&mut self, exprs: Vec<Expr>) -> Vec<Expr> {
+        walk_fold_block(self, exprs)
+    }
+
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+        walk_fold_expr(self, expr)
+    }
+
+    fn fold_value(&mut self, value: Value) -> Value {
+        walk_fold_value(self, value)
+    }
+
+    fn fold_op(&mut self, op: Op) -> Op {
+        walk_fold_op(self, op)
+    }
+
+    fn fold_cmp(&mut self, cmp: Cmp) -> Cmp {
+        walk_fold_cmp(self, cmp)
+    }
+
+    /// Types written in the program, the resolved type of each value is not folded.
+    fn fold_type(&mut self, r#type: Type) -> Type {
+        walk_fold_type(self, r#type)
+    }
+}
+
+pub fn walk_fold_block<F: Fold + ?Sized>(folder: &mut F, exprs: Vec<Expr>) -> Vec<Expr> {
+    exprs.into_iter().map(|expr| folder.fold_expr(expr)).collect()
+}
+
+pub fn walk_fold_expr<F: Fold + ?Sized>(folder: &mut F, expr: Expr) -> Expr {
+    let kind = match expr.kind {
+        ExprKind::Init { name, r#type, value } => ExprKind::Init {
+            name,
+            r#type: folder.fold_type(r#type),
+            value: folder.fold_value(value),
+        },
+        ExprKind::Decl { name, r#type } => ExprKind::Decl {
+            name,
+            r#type: folder.fold_type(r#type),
+        },
+        ExprKind::Assig { name, value } => ExprKind::Assig {
+            name,
+            value: folder.fold_value(value),
+        },
+        ExprKind::Typedef { name, r#type } => ExprKind::Typedef {
+            name,
+            r#type: folder.fold_type(r#type),
+        },
+        ExprKind::If { cmp, exprs, elif } => ExprKind::If {
+            cmp: folder.fold_value(cmp),
+            exprs: folder.fold_block(exprs),
+            elif: elif.map(|elif| Box::new(folder.fold_expr(*elif))),
+        },
+        ExprKind::Elif { cmp, exprs, elif } => ExprKind::Elif {
+            cmp: folder.fold_value(cmp),
+            exprs: folder.fold_block(exprs),
+            elif: elif.map(|elif| Box::new(folder.fold_expr(*elif))),
+        },
+        ExprKind::Else { exprs } => ExprKind::Else {
+            exprs: folder.fold_block(exprs),
+        },
+        ExprKind::For { var, range, exprs } => ExprKind::For {
+            var,
+            range: folder.fold_value(range),
+            exprs: folder.fold_block(exprs),
+        },
+        ExprKind::While { cmp, exprs } => ExprKind::While {
+            cmp: folder.fold_value(cmp),
+            exprs: folder.fold_block(exprs),
+        },
+        ExprKind::Fn {
+            name,
+            r#type,
+            args,
+            exprs,
+        } => ExprKind::Fn {
+            name,
+            r#type: folder.fold_type(r#type),
+            args: args.into_iter().map(|(n, t)| (n, folder.fold_type(t))).collect(),
+            exprs: folder.fold_block(exprs),
+        },
+        ExprKind::Call { name, args } => ExprKind::Call {
+            name,
+            args: args.into_iter().map(|arg| folder.fold_value(arg)).collect(),
+        },
+        ExprKind::NativeFn {
+            name,
+            r#type,
+            args,
+            sections,
+        } => ExprKind::NativeFn {
+            name,
+            r#type: folder.fold_type(r#type),
+            args: args.into_iter().map(|(n, t)| (n, folder.fold_type(t))).collect(),
+            sections,
+        },
+        kind @ (ExprKind::Native { .. } | ExprKind::Err) => kind,
+    };
+    Expr::new(kind, expr.span)
+}
+
+pub fn walk_fold_value<F: Fold + ?Sized>(folder: &mut F, value: Value) -> Value {
+    let kind = match value.kind {
+        ValueKind::Tuple(items) => ValueKind::Tuple(items.into_iter().map(|v| folder.fold_value(v)).collect()),
+        ValueKind::List(items) => ValueKind::List(items.into_iter().map(|v| folder.fold_value(v)).collect()),
+        ValueKind::Struct(members) => {
+            ValueKind::Struct(members.into_iter().map(|(m, v)| (m, folder.fold_value(v))).collect())
+        }
+        ValueKind::Dict(pairs) => ValueKind::Dict(
+            pairs
+                .into_iter()
+                .map(|(k, v)| (folder.fold_value(k), folder.fold_value(v)))
+                .collect(),
+        ),
+        ValueKind::ListAccess {
+            name,
+            access_mode,
+            access_type,
+            name_span,
+            access_span,
+        } => ValueKind::ListAccess {
+            name,
+            access_mode: match access_mode {
+                ListAccessMode::List(index) => ListAccessMode::List(Box::new(folder.fold_value(*index))),
+                ListAccessMode::Dict(index) => ListAccessMode::Dict(Box::new(folder.fold_value(*index))),
+            },
+            access_type: folder.fold_type(access_type),
+            name_span,
+            access_span,
+        },
+        ValueKind::Op(op) => ValueKind::Op(folder.fold_op(op)),
+        ValueKind::Cmp(cmp) => ValueKind::Cmp(folder.fold_cmp(cmp)),
+        ValueKind::Parenthesis(inner) => ValueKind::Parenthesis(Box::new(folder.fold_value(*inner))),
+        ValueKind::Conversion { r#type, value } => ValueKind::Conversion {
+            r#type: folder.fold_type(r#type),
+            value: Box::new(folder.fold_value(*value)),
+        },
+        ValueKind::Call { name, args } => ValueKind::Call {
+            name,
+            args: args.into_iter().map(|arg| folder.fold_value(arg)).collect(),
+        },
+        kind @ (ValueKind::Range(_)
+        | ValueKind::Int(_)
+        | ValueKind::Num(_)
+        | ValueKind::Bool(_)
+        | ValueKind::Char(_)
+        | ValueKind::Str(_)
+        | ValueKind::TupleAccess { .. }
+        | ValueKind::Var(_)
+        | ValueKind::RetExpr(_)
+        | ValueKind::Err) => kind,
+    };
+    Value::new(kind, value.span, value.r#type)
+}
+
+pub fn walk_fold_op<F: Fold + ?Sized>(folder: &mut F, op: Op) -> Op {
+    let mut pair = |v: Box<(Value, Value)>| {
+        let (lhs, rhs) = *v;
+        Box::new((folder.fold_value(lhs), folder.fold_value(rhs)))
+    };
+    match op {
+        Op::Add(v) => Op::Add(pair(v)),
+        Op::Sub(v) => Op::Sub(pair(v)),
+        Op::Mul(v) => Op::Mul(pair(v)),
+        Op::Div(v) => Op::Div(pair(v)),
+        Op::Mod(v) => Op::Mod(pair(v)),
+        Op::Pow(v) => Op::Pow(pair(v)),
+        Op::ListRemoveAll(v) => {
+            let (name, value) = *v;
+            Op::ListRemoveAll(Box::new((name, folder.fold_value(value))))
+        }
+    }
+}
+
+pub fn walk_fold_cmp<F: Fold + ?Sized>(folder: &mut F, cmp: Cmp) -> Cmp {
+    let mut pair = |v: Box<(Value, Value)>| {
+        let (lhs, rhs) = *v;
+        Box::new((folder.fold_value(lhs), folder.fold_value(rhs)))
+    };
+    match cmp {
+        Cmp::Less(v) => Cmp::Less(pair(v)),
+        Cmp::Greater(v) => Cmp::Greater(pair(v)),
+        Cmp::LessEq(v) => Cmp::LessEq(pair(v)),
+        Cmp::GreatEq(v) => Cmp::GreatEq(pair(v)),
+        Cmp::Equal(v) => Cmp::Equal(pair(v)),
+        Cmp::NotEq(v) => Cmp::NotEq(pair(v)),
+        Cmp::Or(v) => Cmp::Or(pair(v)),
+        Cmp::And(v) => Cmp::And(pair(v)),
+        Cmp::Not(v) => Cmp::Not(Box::new(folder.fold_value(*v))),
+        Cmp::Err => Cmp::Err,
+    }
+}
+
+pub fn walk_fold_type<F: Fold + ?Sized>(folder: &mut F, r#type: Type) -> Type {
+    match r#type {
+        Type::Tuple(types) => Type::Tuple(types.into_iter().map(|t| folder.fold_type(t)).collect()),
+        Type::Struct(members) => Type::Struct(members.into_iter().map(|(m, t)| (m, folder.fold_type(t))).collect()),
+        Type::List(element) => Type::List(Box::new(folder.fold_type(*element))),
+        Type::Dict(types) => {
+            let (key, value) = *types;
+            Type::Dict(Box::new((folder.fold_type(key), folder.fold_type(value))))
+        }
+        r#type @ (Type::Range
+        | Type::Int
+        | Type::Num
+        | Type::Bool
+        | Type::Char
+        | Type::Str
+        | Type::Void
+        | Type::Custom(_)
+        | Type::Err) => r#type,
+    }
+}
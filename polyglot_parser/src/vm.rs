@@ -0,0 +1,571 @@
+//! Bytecode virtual machine, a faster alternative to the [`interpreter`].
+//!
+//! [`compile`] turns a checked [`Main`](crate::tree::Main) into a [`Program`] for a stack machine, and [`run`]
+//! executes it, printing and failing exactly like the interpreter does.
+use crate::interpreter::{self, RuntimeError};
+use crate::source::SourceMap;
+use std::cmp::Ordering;
+use std::io::Write;
+use std::rc::Rc;
+
+mod bytecode;
+mod compiler;
+
+pub use bytecode::{Comparison, Function, Instr, Program};
+pub use compiler::compile;
+
+/// Value of the VM, compound values are shared and only copied when modified.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Int(i64),
+    Num(f64),
+    Bool(bool),
+    Char(char),
+    Range(Rc<(i64, i64)>),
+    Str(Rc<String>),
+    Tuple(Rc<Vec<Value>>),
+    Struct(Rc<Struct>),
+    List(Rc<Vec<Value>>),
+    /// Entries in insertion order
+    Dict(Rc<Vec<(Value, Value)>>),
+    Void,
+}
+
+// Every value fits in two words
+const _: () = assert!(std::mem::size_of::<Value>() == 16);
+
+#[derive(Debug, Clone)]
+pub struct Struct {
+    /// Shared by every struct built by the same instruction
+    pub names: Rc<[String]>,
+    pub values: Vec<Value>,
+}
+
+impl Value {
+    /// Text printed for the value, strings and chars are quoted when `nested` inside another value.
+    pub fn show(&self, nested: bool) -> String {
+        match self {
+            Value::Int(i) => i.to_string(),
+            Value::Str(s) if !nested => s.to_string(),
+            _ => interpreter::Value::from(self).show(nested),
+        }
+    }
+
+    /// Whether both values are the same constant, so it's only stored once in the pool.
+    fn same_constant(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Num(a), Value::Num(b)) => a.to_bits() == b.to_bits(),
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Char(a), Value::Char(b)) => a == b,
+            (Value::Str(a), Value::Str(b)) => a == b,
+            (Value::Range(a), Value::Range(b)) => a == b,
+            (Value::Void, Value::Void) => true,
+            _ => false,
+        }
+    }
+}
+
+impl From<&Value> for interpreter::Value {
+    fn from(value: &Value) -> Self {
+        let all = |items: &[Value]| items.iter().map(interpreter::Value::from).collect();
+        match value {
+            Value::Int(i) => interpreter::Value::Int(*i),
+            Value::Num(n) => interpreter::Value::Num(*n),
+            Value::Bool(b) => interpreter::Value::Bool(*b),
+            Value::Char(c) => interpreter::Value::Char(*c),
+            Value::Range(r) => interpreter::Value::Range(r.0, r.1),
+            Value::Str(s) => interpreter::Value::Str(s.to_string()),
+            Value::Tuple(items) => interpreter::Value::Tuple(all(items)),
+            Value::Struct(s) => {
+                let members = s.names.iter().zip(&s.values);
+                interpreter::Value::Struct(members.map(|(m, v)| (m.clone(), v.into())).collect())
+            }
+            Value::List(items) => interpreter::Value::List(all(items)),
+            Value::Dict(entries) => {
+                interpreter::Value::Dict(entries.iter().map(|(k, v)| (k.into(), v.into())).collect())
+            }
+            Value::Void => interpreter::Value::Void,
+        }
+    }
+}
+
+impl From<interpreter::Value> for Value {
+    fn from(value: interpreter::Value) -> Self {
+        let all = |items: Vec<interpreter::Value>| Rc::new(items.into_iter().map(Value::from).collect());
+        match value {
+            interpreter::Value::Range(start, end) => Value::Range(Rc::new((start, end))),
+            interpreter::Value::Int(i) => Value::Int(i),
+            interpreter::Value::Num(n) => Value::Num(n),
+            interpreter::Value::Bool(b) => Value::Bool(b),
+            interpreter::Value::Char(c) => Value::Char(c),
+            interpreter::Value::Str(s) => Value::Str(Rc::new(s)),
+            interpreter::Value::Tuple(items) => Value::Tuple(all(items)),
+            interpreter::Value::Struct(members) => {
+                let (names, values): (Vec<_>, Vec<_>) = members.into_iter().map(|(m, v)| (m, v.into())).unzip();
+                Value::Struct(Rc::new(Struct {
+                    names: names.into(),
+                    values,
+                }))
+            }
+            interpreter::Value::List(items) => Value::List(all(items)),
+            interpreter::Value::Dict(entries) => {
+                Value::Dict(Rc::new(entries.into_iter().map(|(k, v)| (k.into(), v.into())).collect()))
+            }
+            interpreter::Value::Void => Value::Void,
+        }
+    }
+}
+
+/// Executes `program`, writing everything it prints to `out`.
+pub fn run(program: &Program, source_map: &SourceMap, out: &mut dyn Write) -> Result<(), RuntimeError> {
+    let mut vm = Vm {
+        program,
+        source_map,
+        out,
+        stack: Vec::with_capacity(256),
+        frames: Vec::new(),
+    };
+    vm.run()
+}
+
+/// Function being executed.
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    function: usize,
+    /// Next instruction
+    ip: usize,
+    /// Position of the first local in the stack
+    base: usize,
+}
+
+struct Vm<'a, 'o> {
+    program: &'a Program,
+    source_map: &'a SourceMap,
+    out: &'o mut dyn Write,
+    /// Locals of every frame, each followed by the operands of its instructions
+    stack: Vec<Value>,
+    /// Callers of the current frame
+    frames: Vec<Frame>,
+}
+
+impl<'a, 'o> Vm<'a, 'o> {
+    /// Error at the instruction executed last in `frame`.
+    fn error(&self, frame: Frame, message: impl std::fmt::Display) -> RuntimeError {
+        let span = self.program.functions[frame.function].spans[frame.ip - 1];
+        RuntimeError::at(self.source_map, span, message)
+    }
+
+    /// Error for arithmetic on operands that aren't numbers, which only trees the checker didn't accept contain.
+    fn unsupported(&self, frame: Frame, instr: Instr, a: &Value, b: &Value) -> RuntimeError {
+        let symbol = match instr {
+            Instr::AddInt | Instr::AddNum => '+',
+            Instr::SubInt | Instr::SubNum => '-',
+            Instr::MulInt | Instr::MulNum => '*',
+            Instr::DivInt | Instr::DivNum => '/',
+            Instr::ModInt | Instr::ModNum => '%',
+            _ => '^',
+        };
+        let (a, b) = (a.show(true), b.show(true));
+        self.error(frame, format!("Operator '{symbol}' not supported between {a} and {b}"))
+    }
+
+    fn push(&mut self, value: Value) {
+        self.stack.push(value);
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("the compiler keeps the stack balanced")
+    }
+
+    fn pop_int(&mut self) -> i64 {
+        match self.pop() {
+            Value::Int(i) => i,
+            value => unreachable!("expected an int, found {value:?}"),
+        }
+    }
+
+    fn pop_num(&mut self) -> f64 {
+        match self.pop() {
+            Value::Num(n) => n,
+            value => unreachable!("expected a num, found {value:?}"),
+        }
+    }
+
+    /// Pops a condition, which is only something else than a bool in trees the checker didn't accept.
+    fn pop_bool(&mut self, frame: Frame) -> Result<bool, RuntimeError> {
+        match self.pop() {
+            Value::Bool(b) => Ok(b),
+            _ => Err(self.error(frame, interpreter::NOT_A_BOOL)),
+        }
+    }
+
+    fn pop_list(&mut self) -> Rc<Vec<Value>> {
+        match self.pop() {
+            Value::List(items) => items,
+            value => unreachable!("expected a list, found {value:?}"),
+        }
+    }
+
+    fn pop_many(&mut self, count: u32) -> Vec<Value> {
+        self.stack.split_off(self.stack.len() - count as usize)
+    }
+
+    /// The list on top of the stack, copied first if it's shared.
+    fn top_list(&mut self) -> &mut Vec<Value> {
+        list(self.stack.last_mut().unwrap())
+    }
+
+    fn write(&mut self, text: &str, frame: Frame) -> Result<(), RuntimeError> {
+        self.out.write_all(text.as_bytes()).map_err(|e| RuntimeError {
+            span: self.program.functions[frame.function].spans[frame.ip - 1],
+            message: format!("ERROR: {e}"),
+        })
+    }
+
+    fn run(&mut self) -> Result<(), RuntimeError> {
+        let program = self.program;
+        let mut frame = Frame {
+            function: 0,
+            ip: 0,
+            base: 0,
+        };
+        let mut code = &program.functions[0].code;
+        self.stack.resize(program.functions[0].locals as usize, Value::Void);
+
+        loop {
+            let instr = code[frame.ip];
+            frame.ip += 1;
+            match instr {
+                Instr::Const(c) => self.push(program.constants[c as usize].clone()),
+                Instr::Int(i) => self.push(Value::Int(i)),
+                Instr::Load(local) => self.push(self.stack[frame.base + local as usize].clone()),
+                Instr::Store(local) => {
+                    let value = self.pop();
+                    self.stack[frame.base + local as usize] = value;
+                }
+                Instr::Pop => {
+                    self.pop();
+                }
+
+                Instr::AddInt | Instr::SubInt | Instr::MulInt | Instr::DivInt | Instr::ModInt | Instr::PowInt => {
+                    let (b, a) = (self.pop(), self.pop());
+                    let (&Value::Int(a), &Value::Int(b)) = (&a, &b) else {
+                        return Err(self.unsupported(frame, instr, &a, &b));
+                    };
+                    let result = match instr {
+                        Instr::AddInt => a.wrapping_add(b),
+                        Instr::SubInt => a.wrapping_sub(b),
+                        Instr::MulInt => a.wrapping_mul(b),
+                        Instr::DivInt | Instr::ModInt if b == 0 => return Err(self.error(frame, "Division by zero")),
+                        Instr::DivInt => a.wrapping_div(b),
+                        Instr::ModInt => a.wrapping_rem(b),
+                        _ => interpreter::pow(a, b),
+                    };
+                    self.push(Value::Int(result));
+                }
+                Instr::AddNum | Instr::SubNum | Instr::MulNum | Instr::DivNum | Instr::ModNum | Instr::PowNum => {
+                    let (b, a) = (self.pop(), self.pop());
+                    let (&Value::Num(a), &Value::Num(b)) = (&a, &b) else {
+                        return Err(self.unsupported(frame, instr, &a, &b));
+                    };
+                    let result = match instr {
+                        Instr::AddNum => a + b,
+                        Instr::SubNum => a - b,
+                        Instr::MulNum => a * b,
+                        Instr::DivNum => a / b,
+                        Instr::ModNum => a % b,
+                        _ => a.powf(b),
+                    };
+                    self.push(Value::Num(result));
+                }
+                Instr::IntToNum => {
+                    let i = self.pop_int();
+                    self.push(Value::Num(i as f64));
+                }
+                Instr::NumToInt => {
+                    let n = self.pop_num();
+                    self.push(Value::Int(n as i64));
+                }
+                Instr::Concat => {
+                    let b = self.pop();
+                    let a = self.pop();
+                    self.push(Value::Str(Rc::new(a.show(false) + &b.show(false))));
+                }
+
+                Instr::Append => {
+                    let element = self.pop();
+                    self.top_list().push(element);
+                }
+                Instr::Extend => {
+                    let added = self.pop_list();
+                    self.top_list().extend(added.iter().cloned());
+                }
+                Instr::Remove => {
+                    let removed = self.pop_list();
+                    let items = self.top_list();
+                    // Only the first occurrence of each element is removed
+                    for removed in removed.iter() {
+                        if let Some(i) = items.iter().position(|item| equal(item, removed)) {
+                            items.remove(i);
+                        }
+                    }
+                }
+                Instr::RemoveAll => {
+                    let removed = self.pop_list();
+                    self.top_list().retain(|item| !removed.iter().any(|r| equal(item, r)));
+                }
+                Instr::AppendLocal(local) => {
+                    let element = self.pop();
+                    list(&mut self.stack[frame.base + local as usize]).push(element);
+                }
+                Instr::ExtendLocal(local) => {
+                    let added = self.pop_list();
+                    list(&mut self.stack[frame.base + local as usize]).extend(added.iter().cloned());
+                }
+
+                Instr::CmpInt(c) => {
+                    let (b, a) = (self.pop_int(), self.pop_int());
+                    self.push(Value::Bool(holds(c, Some(a.cmp(&b)))));
+                }
+                Instr::CmpNum(c) => {
+                    let (b, a) = (self.pop_num(), self.pop_num());
+                    self.push(Value::Bool(holds(c, a.partial_cmp(&b))));
+                }
+                Instr::Cmp(c) => {
+                    let b = self.pop();
+                    let a = self.pop();
+                    let result = match c {
+                        Comparison::Equal => equal(&a, &b),
+                        Comparison::NotEq => !equal(&a, &b),
+                        _ => holds(c, order(&a, &b)),
+                    };
+                    self.push(Value::Bool(result));
+                }
+                Instr::Not => {
+                    let b = self.pop_bool(frame)?;
+                    self.push(Value::Bool(!b));
+                }
+
+                Instr::Jump(to) => frame.ip = to as usize,
+                Instr::JumpIfFalse(to) => {
+                    if !self.pop_bool(frame)? {
+                        frame.ip = to as usize;
+                    }
+                }
+                Instr::ForNext { iter, var, exit } => {
+                    let (iter, var) = (frame.base + iter as usize, frame.base + var as usize);
+                    let Value::Int(index) = self.stack[iter + 1] else {
+                        unreachable!("loops store their index after the iterated value")
+                    };
+                    let next = match &self.stack[iter] {
+                        Value::Range(range) => {
+                            let value = range.0 + index;
+                            (value < range.1).then_some(Value::Int(value))
+                        }
+                        Value::List(items) => items.get(index as usize).cloned(),
+                        _ => return Err(self.error(frame, interpreter::NOT_ITERABLE)),
+                    };
+                    match next {
+                        Some(value) => {
+                            self.stack[var] = value;
+                            self.stack[iter + 1] = Value::Int(index + 1);
+                        }
+                        None => frame.ip = exit as usize,
+                    }
+                }
+                Instr::Call { function, args } => {
+                    let callee = &program.functions[function as usize];
+                    if args != callee.params {
+                        return Err(self.error(frame, interpreter::ARGUMENT_COUNT));
+                    }
+                    let base = self.stack.len() - args as usize;
+                    self.stack.resize(base + callee.locals as usize, Value::Void);
+                    self.frames.push(frame);
+                    frame = Frame {
+                        function: function as usize,
+                        ip: 0,
+                        base,
+                    };
+                    code = &callee.code;
+                }
+                Instr::Native => return Err(self.error(frame, interpreter::NATIVE)),
+                Instr::Return => {
+                    let result = self.pop();
+                    self.stack.truncate(frame.base);
+                    match self.frames.pop() {
+                        Some(caller) => {
+                            frame = caller;
+                            code = &program.functions[frame.function].code;
+                            self.push(result);
+                        }
+                        None => return Ok(()),
+                    }
+                }
+
+                Instr::MakeTuple(count) => {
+                    let items = self.pop_many(count);
+                    self.push(Value::Tuple(Rc::new(items)));
+                }
+                Instr::MakeStruct(shape) => {
+                    let names = program.shapes[shape as usize].clone();
+                    let values = self.pop_many(names.len() as u32);
+                    self.push(Value::Struct(Rc::new(Struct { names, values })));
+                }
+                Instr::MakeList(count) => {
+                    let items = self.pop_many(count);
+                    self.push(Value::List(Rc::new(items)));
+                }
+                Instr::MakeDict(count) => {
+                    let mut pairs = self.pop_many(count * 2).into_iter();
+                    let mut entries: Vec<(Value, Value)> = Vec::new();
+                    while let (Some(key), Some(value)) = (pairs.next(), pairs.next()) {
+                        match entries.iter_mut().find(|(k, _)| equal(k, &key)) {
+                            Some((_, v)) => *v = value,
+                            None => entries.push((key, value)),
+                        }
+                    }
+                    self.push(Value::Dict(Rc::new(entries)));
+                }
+                Instr::Field(field) => {
+                    let value = match self.pop() {
+                        Value::Tuple(items) => items[field as usize].clone(),
+                        Value::Struct(s) => s.values[field as usize].clone(),
+                        value => unreachable!("the checker only allows accessing tuples and structs, found {value:?}"),
+                    };
+                    self.push(value);
+                }
+                Instr::Index => {
+                    let index = self.pop_int();
+                    let items = self.pop_list();
+                    match usize::try_from(index).ok().and_then(|i| items.get(i)) {
+                        Some(item) => self.push(item.clone()),
+                        None => return Err(self.error(frame, "Out-of-bounds")),
+                    }
+                }
+                Instr::Key => {
+                    let key = self.pop();
+                    let Value::Dict(entries) = self.pop() else {
+                        unreachable!("the checker only allows indexing lists and dicts")
+                    };
+                    match entries.iter().find(|(k, _)| equal(k, &key)) {
+                        Some((_, value)) => self.push(value.clone()),
+                        None => return Err(self.error(frame, "Key not found")),
+                    }
+                }
+                Instr::Convert(r#type) => {
+                    let value = interpreter::Value::from(&self.pop());
+                    match interpreter::convert(value, &program.types[r#type as usize]) {
+                        Ok(converted) => self.push(converted.into()),
+                        Err(message) => {
+                            let span = program.functions[frame.function].spans[frame.ip - 1];
+                            return Err(RuntimeError { span, message });
+                        }
+                    }
+                }
+                Instr::Coerce(r#type) => {
+                    let value = interpreter::Value::from(&self.pop());
+                    self.push(interpreter::coerce(value, &program.types[r#type as usize]).into());
+                }
+
+                Instr::Show { nested } => {
+                    let text = self.pop().show(nested);
+                    self.push(Value::Str(Rc::new(text)));
+                }
+                Instr::Join(count) => {
+                    let mut text = String::new();
+                    for part in self.pop_many(count) {
+                        match part {
+                            Value::Str(s) => text.push_str(&s),
+                            part => unreachable!("only strs are joined, found {part:?}"),
+                        }
+                    }
+                    self.push(Value::Str(Rc::new(text)));
+                }
+                Instr::Print | Instr::Println => {
+                    let mut text = match self.pop() {
+                        Value::Str(s) => Rc::try_unwrap(s).unwrap_or_else(|s| s.to_string()),
+                        value => value.show(false),
+                    };
+                    if instr == Instr::Println {
+                        text.push('\n');
+                    }
+                    self.write(&text, frame)?;
+                }
+            }
+        }
+    }
+}
+
+/// Items of a list, copied first if they're shared.
+fn list(value: &mut Value) -> &mut Vec<Value> {
+    match value {
+        Value::List(items) => Rc::make_mut(items),
+        value => unreachable!("expected a list, found {value:?}"),
+    }
+}
+
+fn holds(c: Comparison, order: Option<Ordering>) -> bool {
+    match c {
+        Comparison::Less => order == Some(Ordering::Less),
+        Comparison::LessEq => matches!(order, Some(Ordering::Less | Ordering::Equal)),
+        Comparison::Greater => order == Some(Ordering::Greater),
+        Comparison::GreatEq => matches!(order, Some(Ordering::Greater | Ordering::Equal)),
+        Comparison::Equal => order == Some(Ordering::Equal),
+        Comparison::NotEq => order != Some(Ordering::Equal),
+    }
+}
+
+fn number(value: &Value) -> f64 {
+    match value {
+        Value::Int(i) => *i as f64,
+        Value::Num(n) => *n,
+        _ => unreachable!("only ints and nums are numbers"),
+    }
+}
+
+/// Structural equality, where ints and nums with the same value are equal.
+fn equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Int(a), Value::Int(b)) => a == b,
+        (Value::Int(_) | Value::Num(_), Value::Int(_) | Value::Num(_)) => number(a) == number(b),
+        (Value::Bool(a), Value::Bool(b)) => a == b,
+        (Value::Char(a), Value::Char(b)) => a == b,
+        (Value::Str(a), Value::Str(b)) => a == b,
+        (Value::Range(a), Value::Range(b)) => a == b,
+        (Value::Tuple(a) | Value::List(a), Value::Tuple(b) | Value::List(b)) => {
+            a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| equal(a, b))
+        }
+        (Value::Struct(a), Value::Struct(b)) => {
+            let mut members = a.names.iter().zip(&a.values);
+            a.values.len() == b.values.len()
+                && members.all(|(m, v)| b.names.iter().zip(&b.values).any(|(n, w)| m == n && equal(v, w)))
+        }
+        (Value::Dict(a), Value::Dict(b)) => {
+            a.len() == b.len() && a.iter().all(|(k, v)| b.iter().any(|(l, w)| equal(k, l) && equal(v, w)))
+        }
+        (Value::Void, Value::Void) => true,
+        _ => false,
+    }
+}
+
+/// Order of two values, lists and tuples are compared lexicographically.
+fn order(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Int(a), Value::Int(b)) => Some(a.cmp(b)),
+        (Value::Int(_) | Value::Num(_), Value::Int(_) | Value::Num(_)) => number(a).partial_cmp(&number(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        (Value::Char(a), Value::Char(b)) => Some(a.cmp(b)),
+        (Value::Str(a), Value::Str(b)) => Some(a.cmp(b)),
+        (Value::Tuple(a) | Value::List(a), Value::Tuple(b) | Value::List(b)) => {
+            for (a, b) in a.iter().zip(b.iter()) {
+                match order(a, b)? {
+                    Ordering::Equal => {}
+                    different => return Some(different),
+                }
+            }
+            Some(a.len().cmp(&b.len()))
+        }
+        _ => None,
+    }
+}
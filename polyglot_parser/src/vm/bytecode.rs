@@ -0,0 +1,213 @@
+use super::Value;
+use crate::source::{SourceMap, Span};
+use crate::tree::Type;
+use std::fmt::Write;
+use std::rc::Rc;
+
+/// Instruction of the stack machine.
+///
+/// Operands are popped from the top of the stack, the rightmost one first, and the result is pushed back.
+/// Arithmetic and comparisons are specialized for ints and nums, mixed operands are converted with
+/// [`Instr::IntToNum`] when compiling, so only the generic instructions look at the values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instr {
+    /// Pushes a constant of the pool
+    Const(u32),
+    Int(i64),
+    /// Pushes a local variable of the current frame
+    Load(u16),
+    Store(u16),
+    Pop,
+
+    AddInt,
+    SubInt,
+    MulInt,
+    DivInt,
+    ModInt,
+    PowInt,
+    AddNum,
+    SubNum,
+    MulNum,
+    DivNum,
+    ModNum,
+    PowNum,
+    IntToNum,
+    /// Truncates a num into an int
+    NumToInt,
+    /// Joins a str with a str or a char
+    Concat,
+
+    /// `list + element`
+    Append,
+    /// `list + list`
+    Extend,
+    /// `list - list`, removing the first occurrence of every element
+    Remove,
+    /// `list -- list`, removing every occurrence of every element
+    RemoveAll,
+    /// Appends an element to a local list in place, instead of copying it like [`Instr::Append`]
+    AppendLocal(u16),
+    ExtendLocal(u16),
+
+    CmpInt(Comparison),
+    CmpNum(Comparison),
+    /// Comparison of any other values, lists and tuples are ordered lexicographically
+    Cmp(Comparison),
+    Not,
+
+    Jump(u32),
+    /// Pops a bool and jumps if it's false
+    JumpIfFalse(u32),
+    /// Binds the next element of the list or range in local `iter` to local `var`, or jumps to `exit` when there
+    /// are no more elements. The index of the next element is stored in local `iter + 1`.
+    ForNext {
+        iter: u16,
+        var: u16,
+        exit: u32,
+    },
+    Call {
+        function: u32,
+        args: u16,
+    },
+    /// Returns the value on top of the stack to the caller
+    Return,
+    /// Fails, native blocks and functions can't be executed
+    Native,
+
+    MakeTuple(u32),
+    /// Struct with the members of one of the shapes of the program
+    MakeStruct(u32),
+    MakeList(u32),
+    /// Dict with the given number of entries, pushed as key and value
+    MakeDict(u32),
+    /// Element of a tuple or struct
+    Field(u32),
+    /// Element of a list, fails when out of bounds
+    Index,
+    /// Value of a key of a dict, fails if it's missing
+    Key,
+    /// Converts a value into one of the types of the program, like `int("5")`
+    Convert(u32),
+    /// Adapts a value to one of the types of the program, like storing `1` in a `num` variable
+    Coerce(u32),
+
+    /// Text of a value, strings and chars are quoted if `nested`
+    Show {
+        nested: bool,
+    },
+    /// Joins the given number of strs
+    Join(u32),
+    Print,
+    Println,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Less,
+    LessEq,
+    Greater,
+    GreatEq,
+    Equal,
+    NotEq,
+}
+
+/// Compiled function, the top level code of a program is the function `main`.
+#[derive(Debug, Clone, Default)]
+pub struct Function {
+    pub name: String,
+    pub params: u16,
+    /// Number of locals, including the parameters
+    pub locals: u16,
+    pub code: Vec<Instr>,
+    /// Span of the value or expression each instruction was compiled from
+    pub spans: Vec<Span>,
+}
+
+/// Compiled program, executed by [`run`](super::run).
+#[derive(Debug, Clone, Default)]
+pub struct Program {
+    /// Every function, the first one is `main`
+    pub functions: Vec<Function>,
+    pub constants: Vec<Value>,
+    /// Types used by [`Instr::Convert`] and [`Instr::Coerce`]
+    pub types: Vec<Type>,
+    /// Member names of the structs built by [`Instr::MakeStruct`]
+    pub shapes: Vec<Rc<[String]>>,
+}
+
+impl Program {
+    /// Human readable listing of the bytecode, with the line each instruction comes from.
+    pub fn disassemble(&self, source_map: &SourceMap) -> String {
+        let mut text = String::new();
+        for function in &self.functions {
+            let (name, params, locals) = (&function.name, function.params, function.locals);
+            writeln!(text, "fn {name} ({params} params, {locals} locals)").unwrap();
+            let mut last_line = 0;
+            for (i, (instr, span)) in function.code.iter().zip(&function.spans).enumerate() {
+                let line = source_map.line_col(*span).0.line;
+                let line_text = if line == last_line {
+                    "|".to_owned()
+                } else {
+                    line.to_string()
+                };
+                last_line = line;
+                writeln!(text, "{i:04} {line_text:>4}  {}", self.instr(instr)).unwrap();
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    fn instr(&self, instr: &Instr) -> String {
+        let shape = |s: &u32| self.shapes[*s as usize].join(", ");
+        match instr {
+            Instr::Const(c) => format!("const {c} ({})", self.constants[*c as usize].show(true)),
+            Instr::Int(i) => format!("int {i}"),
+            Instr::Load(l) => format!("load {l}"),
+            Instr::Store(l) => format!("store {l}"),
+            Instr::AppendLocal(l) => format!("append_local {l}"),
+            Instr::ExtendLocal(l) => format!("extend_local {l}"),
+            Instr::CmpInt(c) => format!("cmp_int {}", comparison(*c)),
+            Instr::CmpNum(c) => format!("cmp_num {}", comparison(*c)),
+            Instr::Cmp(c) => format!("cmp {}", comparison(*c)),
+            Instr::Jump(to) => format!("jump {to:04}"),
+            Instr::JumpIfFalse(to) => format!("jump_if_false {to:04}"),
+            Instr::ForNext { iter, var, exit } => format!("for_next {iter} {var} {exit:04}"),
+            Instr::Call { function, args } => {
+                format!("call {} ({args} args)", self.functions[*function as usize].name)
+            }
+            Instr::MakeTuple(n) => format!("make_tuple {n}"),
+            Instr::MakeStruct(s) => format!("make_struct ({})", shape(s)),
+            Instr::MakeList(n) => format!("make_list {n}"),
+            Instr::MakeDict(n) => format!("make_dict {n}"),
+            Instr::Field(i) => format!("field {i}"),
+            Instr::Convert(t) => format!("convert {}", self.types[*t as usize]),
+            Instr::Coerce(t) => format!("coerce {}", self.types[*t as usize]),
+            Instr::Show { nested } => format!("show{}", if *nested { " nested" } else { "" }),
+            Instr::Join(n) => format!("join {n}"),
+            other => snake_case(&format!("{other:?}")),
+        }
+    }
+}
+
+fn comparison(c: Comparison) -> &'static str {
+    match c {
+        Comparison::Less => "<",
+        Comparison::LessEq => "<=",
+        Comparison::Greater => ">",
+        Comparison::GreatEq => ">=",
+        Comparison::Equal => "==",
+        Comparison::NotEq => "!=",
+    }
+}
+
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            snake.push('_');
+        }
+        snake.push(c.to_ascii_lowercase());
+    }
+    snake
+}
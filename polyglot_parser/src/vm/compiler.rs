@@ -0,0 +1,577 @@
+use super::bytecode::{Comparison, Function, Instr, Program};
+use super::Value;
+use crate::backend::Types;
+use crate::interpreter;
+use crate::source::Span;
+use crate::tree::{self, interpolation, Cmp, Expr, ExprKind, ListAccessMode, Main, Op, Segment, TupleAccessMode};
+use crate::tree::{Type, ValueKind};
+use rustc_hash::FxHashMap;
+use std::rc::Rc;
+
+/// Compiles a checked program into bytecode.
+pub fn compile(main: &Main) -> Program {
+    let mut compiler = Compiler {
+        main,
+        types: Types::new(),
+        program: Program::default(),
+        fns: FxHashMap::default(),
+        function: Builder::new("main", &[]),
+    };
+    // Reserved for `main`, which is only complete at the end
+    compiler.program.functions.push(Function::default());
+    for expr in &main.exprs {
+        compiler.expr(expr);
+    }
+    let span = main.exprs.last().map_or_else(Span::default, |e| e.span);
+    compiler.ret(&Type::Void, span);
+    compiler.program.functions[0] = compiler.function.function;
+    compiler.program
+}
+
+/// Function being compiled.
+struct Builder {
+    function: Function,
+    /// Local of each variable, with one map per block
+    scopes: Vec<FxHashMap<String, u16>>,
+}
+
+impl Builder {
+    fn new(name: &str, params: &[(String, tree::Type)]) -> Builder {
+        let mut builder = Builder {
+            function: Function {
+                name: name.to_owned(),
+                params: params.len() as u16,
+                ..Function::default()
+            },
+            scopes: vec![FxHashMap::default()],
+        };
+        for (param, _) in params {
+            builder.declare(param);
+        }
+        builder
+    }
+
+    fn declare(&mut self, name: &str) -> u16 {
+        let local = self.hidden();
+        self.scopes.last_mut().unwrap().insert(name.to_owned(), local);
+        local
+    }
+
+    /// Local without a name, used to keep the state of loops.
+    fn hidden(&mut self) -> u16 {
+        self.function.locals += 1;
+        self.function.locals - 1
+    }
+}
+
+struct Compiler<'a> {
+    main: &'a Main,
+    /// Static types of the variables, which decide the instructions used
+    types: Types,
+    program: Program,
+    /// Index of every function in the program
+    fns: FxHashMap<String, u32>,
+    function: Builder,
+}
+
+impl<'a> Compiler<'a> {
+    fn emit(&mut self, instr: Instr, span: Span) -> u32 {
+        self.function.function.code.push(instr);
+        self.function.function.spans.push(span);
+        self.here() - 1
+    }
+
+    fn here(&self) -> u32 {
+        self.function.function.code.len() as u32
+    }
+
+    /// Makes the jump at `at` go to the next instruction emitted.
+    fn patch(&mut self, at: u32) {
+        let here = self.here();
+        match &mut self.function.function.code[at as usize] {
+            Instr::Jump(to) | Instr::JumpIfFalse(to) | Instr::ForNext { exit: to, .. } => *to = here,
+            instr => unreachable!("only jumps are patched, found {instr:?}"),
+        }
+    }
+
+    fn constant(&mut self, value: Value, span: Span) {
+        let constants = &mut self.program.constants;
+        let index = match constants.iter().position(|c| c.same_constant(&value)) {
+            Some(i) => i,
+            None => {
+                constants.push(value);
+                constants.len() - 1
+            }
+        };
+        self.emit(Instr::Const(index as u32), span);
+    }
+
+    fn type_index(&mut self, r#type: &Type) -> u32 {
+        let types = &mut self.program.types;
+        match types.iter().position(|t| t.identical(r#type)) {
+            Some(i) => i as u32,
+            None => {
+                types.push(r#type.clone());
+                types.len() as u32 - 1
+            }
+        }
+    }
+
+    fn shape(&mut self, names: Vec<String>) -> u32 {
+        let shapes = &mut self.program.shapes;
+        match shapes.iter().position(|s| **s == *names) {
+            Some(i) => i as u32,
+            None => {
+                shapes.push(Rc::from(names));
+                shapes.len() as u32 - 1
+            }
+        }
+    }
+
+    fn local(&self, name: &str) -> u16 {
+        match self.function.scopes.iter().rev().find_map(|scope| scope.get(name)) {
+            Some(local) => *local,
+            None => unreachable!("the checker rejects undeclared variables"),
+        }
+    }
+
+    fn declare(&mut self, name: &str, r#type: Type) -> u16 {
+        self.types.insert(name, r#type);
+        self.function.declare(name)
+    }
+
+    fn push(&mut self) {
+        self.types.push();
+        self.function.scopes.push(FxHashMap::default());
+    }
+
+    fn pop(&mut self) {
+        self.function.scopes.pop();
+        self.types.pop();
+    }
+
+    fn block(&mut self, exprs: &[Expr]) {
+        self.push();
+        for expr in exprs {
+            self.expr(expr);
+        }
+        self.pop();
+    }
+
+    /// Returns the default value of `r#type`, as functions can't return anything else yet.
+    fn ret(&mut self, r#type: &Type, span: Span) {
+        self.constant(interpreter::default(r#type).into(), span);
+        self.emit(Instr::Return, span);
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        let span = expr.span;
+        match &expr.kind {
+            ExprKind::Init { name, r#type, value } => {
+                self.coerced(value, r#type);
+                let local = self.declare(name, r#type.clone());
+                self.emit(Instr::Store(local), span);
+            }
+            ExprKind::Decl { name, r#type } => {
+                self.constant(interpreter::default(r#type).into(), span);
+                let local = self.declare(name, r#type.clone());
+                self.emit(Instr::Store(local), span);
+            }
+            ExprKind::Assig { name, value } => {
+                let r#type = self.types.get(name).cloned().unwrap_or(Type::Err);
+                let local = self.local(name);
+                if !self.in_place(name, local, &r#type, value) {
+                    self.coerced(value, &r#type);
+                    self.emit(Instr::Store(local), span);
+                }
+            }
+            ExprKind::Typedef { .. } => {}
+            ExprKind::If { cmp, exprs, elif } | ExprKind::Elif { cmp, exprs, elif } => {
+                self.value(cmp);
+                let skip = self.emit(Instr::JumpIfFalse(0), cmp.span);
+                self.block(exprs);
+                match elif {
+                    Some(elif) => {
+                        let end = self.emit(Instr::Jump(0), span);
+                        self.patch(skip);
+                        self.expr(elif);
+                        self.patch(end);
+                    }
+                    None => self.patch(skip),
+                }
+            }
+            ExprKind::Else { exprs } => self.block(exprs),
+            ExprKind::For { var, range, exprs } => {
+                let element = self.types.element_of(range);
+                // The range is evaluated once, so the body can modify the original list
+                self.value(range);
+                let iter = self.function.hidden();
+                let index = self.function.hidden();
+                self.emit(Instr::Store(iter), span);
+                self.emit(Instr::Int(0), span);
+                self.emit(Instr::Store(index), span);
+                self.push();
+                let var = self.declare(var, element);
+                let start = self.emit(Instr::ForNext { iter, var, exit: 0 }, range.span);
+                for expr in exprs {
+                    self.expr(expr);
+                }
+                self.pop();
+                self.emit(Instr::Jump(start), span);
+                self.patch(start);
+            }
+            ExprKind::While { cmp, exprs } => {
+                let start = self.here();
+                self.value(cmp);
+                let exit = self.emit(Instr::JumpIfFalse(0), cmp.span);
+                self.block(exprs);
+                self.emit(Instr::Jump(start), span);
+                self.patch(exit);
+            }
+            ExprKind::Fn {
+                name,
+                r#type,
+                args,
+                exprs,
+            } => {
+                self.types.insert_fn(name, r#type.clone(), args);
+                let index = self.program.functions.len() as u32;
+                self.program.functions.push(Function::default());
+                self.fns.insert(name.clone(), index);
+
+                // Functions only see their arguments
+                let outer = std::mem::replace(&mut self.function, Builder::new(name, args));
+                self.types.push();
+                for (arg, t) in args {
+                    self.types.insert(arg, t.clone());
+                }
+                for expr in exprs {
+                    self.expr(expr);
+                }
+                self.types.pop();
+                self.ret(r#type, span);
+                let compiled = std::mem::replace(&mut self.function, outer);
+                self.program.functions[index as usize] = compiled.function;
+            }
+            ExprKind::Call { name, args } => {
+                if self.call(name, args, span) {
+                    self.emit(Instr::Pop, span);
+                }
+            }
+            ExprKind::Native { .. } => {
+                self.emit(Instr::Native, span);
+            }
+            // Only its signature is needed, calls fail like native blocks
+            ExprKind::NativeFn { name, r#type, args, .. } => self.types.insert_fn(name, r#type.clone(), args),
+            ExprKind::Err => unreachable!("the compiler only receives valid programs"),
+        }
+    }
+
+    /// Compiles `list = list + operand` without copying the list, returning whether `value` has that form.
+    fn in_place(&mut self, name: &str, local: u16, list: &Type, value: &tree::Value) -> bool {
+        let ValueKind::Op(Op::Add(v)) = &value.kind else {
+            return false;
+        };
+        if !matches!(list, Type::List(_)) || !matches!(&v.0.kind, ValueKind::Var(n) if n == name) {
+            return false;
+        }
+        let instr = match self.list_operand(list, &v.1) {
+            true => Instr::ExtendLocal(local),
+            false => Instr::AppendLocal(local),
+        };
+        self.emit(instr, value.span);
+        true
+    }
+
+    /// Compiles an element added to or removed from a list of type `list`, returning whether it's a whole list.
+    fn list_operand(&mut self, list: &Type, value: &tree::Value) -> bool {
+        let whole = value.r#type == *list;
+        match list {
+            Type::List(element) if !whole => self.coerced(value, element),
+            _ => self.coerced(value, list),
+        }
+        whole
+    }
+
+    /// Compiles `value` and adapts it to `r#type`, only when their representations differ.
+    fn coerced(&mut self, value: &tree::Value, r#type: &Type) {
+        self.value(value);
+        let found = value.r#type.clone();
+        match (&found, r#type) {
+            (Type::Int, Type::Num) => {
+                self.emit(Instr::IntToNum, value.span);
+            }
+            (Type::Num, Type::Int) => {
+                self.emit(Instr::NumToInt, value.span);
+            }
+            _ if found.identical(r#type) => {}
+            _ => {
+                let index = self.type_index(r#type);
+                self.emit(Instr::Coerce(index), value.span);
+            }
+        }
+    }
+
+    /// Compiles a call, returning whether it leaves a value on the stack.
+    fn call(&mut self, name: &str, args: &[tree::Value], span: Span) -> bool {
+        if self.types.is_builtin(name) {
+            self.builtin(name, &args[0], span);
+            return false;
+        }
+        let params = self.types.get_fn(name).map(|f| f.args.clone()).unwrap_or_default();
+        // Every argument is pushed, so the VM can tell calls with the wrong number of them apart
+        for (i, arg) in args.iter().enumerate() {
+            match params.get(i) {
+                Some(param) => self.coerced(arg, param),
+                None => self.value(arg),
+            }
+        }
+        let args = args.len() as u16;
+        match self.fns.get(name) {
+            Some(&function) => self.emit(Instr::Call { function, args }, span),
+            // Native functions aren't compiled
+            None => self.emit(Instr::Native, span),
+        };
+        true
+    }
+
+    fn builtin(&mut self, name: &str, arg: &tree::Value, span: Span) {
+        match (name, &arg.kind) {
+            ("dbg", _) => {
+                let text = self.main.source_map.snippet(arg.span).unwrap_or_default();
+                self.constant(Value::Str(Rc::new(format!("{text} = "))), span);
+                self.value(arg);
+                self.emit(Instr::Show { nested: true }, span);
+                self.emit(Instr::Join(2), span);
+                self.emit(Instr::Println, span);
+                return;
+            }
+            (_, ValueKind::Str(s)) => {
+                let segments = interpolation(s);
+                for (_, segment) in &segments {
+                    match segment {
+                        Segment::Text(t) => self.constant(Value::Str(Rc::new(t.to_string())), arg.span),
+                        Segment::Var(var) => {
+                            let local = self.local(var);
+                            self.emit(Instr::Load(local), arg.span);
+                            self.emit(Instr::Show { nested: false }, arg.span);
+                        }
+                    }
+                }
+                if segments.len() != 1 {
+                    self.emit(Instr::Join(segments.len() as u32), arg.span);
+                }
+            }
+            _ => self.value(arg),
+        }
+        let print = if name == "println" {
+            Instr::Println
+        } else {
+            Instr::Print
+        };
+        self.emit(print, span);
+    }
+
+    fn value(&mut self, value: &tree::Value) {
+        let span = value.span;
+        match &value.kind {
+            ValueKind::Range((start, end)) => self.constant(Value::Range(Rc::new((*start, *end))), span),
+            ValueKind::Int(i) => {
+                self.emit(Instr::Int(*i), span);
+            }
+            ValueKind::Num(n) => self.constant(Value::Num(*n), span),
+            ValueKind::Bool(b) => self.constant(Value::Bool(*b), span),
+            ValueKind::Char(c) => self.constant(Value::Char(*c), span),
+            ValueKind::Str(s) => self.constant(Value::Str(Rc::new(s.clone())), span),
+            ValueKind::Tuple(items) => {
+                for item in items {
+                    self.value(item);
+                }
+                self.emit(Instr::MakeTuple(items.len() as u32), span);
+            }
+            ValueKind::Struct(members) => {
+                for (_, v) in members {
+                    self.value(v);
+                }
+                let shape = self.shape(members.iter().map(|(m, _)| m.clone()).collect());
+                self.emit(Instr::MakeStruct(shape), span);
+            }
+            ValueKind::TupleAccess { name, access_mode, .. } => {
+                let local = self.local(name);
+                self.emit(Instr::Load(local), span);
+                // Struct values always have the members in the order of their type
+                let field = match (self.types.get(name), access_mode) {
+                    (_, TupleAccessMode::Index(i)) => *i,
+                    (Some(Type::Struct(members)), TupleAccessMode::Member(m)) => {
+                        members.iter().position(|(n, _)| n == m).unwrap()
+                    }
+                    _ => unreachable!("the checker only allows accessing tuples and structs"),
+                };
+                self.emit(Instr::Field(field as u32), span);
+            }
+            ValueKind::List(items) => {
+                let element = items[0].r#type.clone();
+                for item in items {
+                    self.coerced(item, &element);
+                }
+                self.emit(Instr::MakeList(items.len() as u32), span);
+            }
+            ValueKind::ListAccess { name, access_mode, .. } => {
+                let local = self.local(name);
+                self.emit(Instr::Load(local), span);
+                match access_mode {
+                    ListAccessMode::List(index) => {
+                        self.value(index);
+                        self.emit(Instr::Index, span);
+                    }
+                    ListAccessMode::Dict(key) => {
+                        self.value(key);
+                        self.emit(Instr::Key, span);
+                    }
+                }
+            }
+            ValueKind::Dict(pairs) => {
+                let (key, value) = (pairs[0].0.r#type.clone(), pairs[0].1.r#type.clone());
+                for (k, v) in pairs {
+                    self.coerced(k, &key);
+                    self.coerced(v, &value);
+                }
+                self.emit(Instr::MakeDict(pairs.len() as u32), span);
+            }
+            ValueKind::Var(name) => {
+                let local = self.local(name);
+                self.emit(Instr::Load(local), span);
+            }
+            ValueKind::Op(op) => self.op(op, span),
+            ValueKind::Cmp(cmp) => self.cmp(cmp, span),
+            ValueKind::Parenthesis(p) => self.value(p),
+            ValueKind::Conversion { r#type, value } => {
+                self.value(value);
+                let index = self.type_index(r#type);
+                self.emit(Instr::Convert(index), span);
+            }
+            ValueKind::Call { name, args } => {
+                if !self.call(name, args, span) {
+                    self.constant(Value::Void, span);
+                }
+            }
+            ValueKind::RetExpr(r) => match *r {},
+            ValueKind::Err => unreachable!("the compiler only receives valid programs"),
+        }
+    }
+
+    fn op(&mut self, op: &Op, span: Span) {
+        let (v, int, num) = match op {
+            Op::Add(v) => (v, Instr::AddInt, Instr::AddNum),
+            Op::Sub(v) => (v, Instr::SubInt, Instr::SubNum),
+            Op::Mul(v) => (v, Instr::MulInt, Instr::MulNum),
+            Op::Div(v) => (v, Instr::DivInt, Instr::DivNum),
+            Op::Mod(v) => (v, Instr::ModInt, Instr::ModNum),
+            Op::Pow(v) => (v, Instr::PowInt, Instr::PowNum),
+            Op::ListRemoveAll(v) => {
+                let list = self.types.get(&v.0).cloned().unwrap_or(Type::Err);
+                let local = self.local(&v.0);
+                self.emit(Instr::Load(local), span);
+                if !self.list_operand(&list, &v.1) {
+                    self.emit(Instr::MakeList(1), span);
+                }
+                self.emit(Instr::RemoveAll, span);
+                return;
+            }
+        };
+
+        let (lhs, rhs) = (v.0.r#type.clone(), v.1.r#type.clone());
+        self.value(&v.0);
+        match (&lhs, &rhs) {
+            (Type::List(_), _) => {
+                let whole = self.list_operand(&lhs, &v.1);
+                let add = matches!(op, Op::Add(_));
+                if !add && !whole {
+                    self.emit(Instr::MakeList(1), span);
+                }
+                let instr = match (add, whole) {
+                    (true, true) => Instr::Extend,
+                    (true, false) => Instr::Append,
+                    (false, _) => Instr::Remove,
+                };
+                self.emit(instr, span);
+            }
+            (Type::Str | Type::Char, _) => {
+                self.value(&v.1);
+                self.emit(Instr::Concat, span);
+            }
+            (Type::Int, Type::Int) => {
+                self.value(&v.1);
+                self.emit(int, span);
+            }
+            _ => {
+                self.numeric(&lhs, &v.1, &rhs);
+                self.emit(num, span);
+            }
+        }
+    }
+
+    /// Compiles the right operand of an operation on nums, after converting the left one if it's an int.
+    fn numeric(&mut self, lhs: &Type, rhs: &tree::Value, rhs_type: &Type) {
+        if matches!(lhs, Type::Int) {
+            self.emit(Instr::IntToNum, rhs.span);
+        }
+        self.value(rhs);
+        if matches!(rhs_type, Type::Int) {
+            self.emit(Instr::IntToNum, rhs.span);
+        }
+    }
+
+    fn cmp(&mut self, cmp: &Cmp, span: Span) {
+        let (v, comparison) = match cmp {
+            Cmp::Not(v) => {
+                self.value(v);
+                self.emit(Instr::Not, span);
+                return;
+            }
+            Cmp::And(v) | Cmp::Or(v) => {
+                // Short-circuits, leaving the left operand as the result
+                let and = matches!(cmp, Cmp::And(_));
+                self.value(&v.0);
+                let skip = self.emit(Instr::JumpIfFalse(0), span);
+                if and {
+                    self.value(&v.1);
+                } else {
+                    self.constant(Value::Bool(true), span);
+                }
+                let end = self.emit(Instr::Jump(0), span);
+                self.patch(skip);
+                if and {
+                    self.constant(Value::Bool(false), span);
+                } else {
+                    self.value(&v.1);
+                }
+                self.patch(end);
+                return;
+            }
+            Cmp::Less(v) => (v, Comparison::Less),
+            Cmp::Greater(v) => (v, Comparison::Greater),
+            Cmp::LessEq(v) => (v, Comparison::LessEq),
+            Cmp::GreatEq(v) => (v, Comparison::GreatEq),
+            Cmp::Equal(v) => (v, Comparison::Equal),
+            Cmp::NotEq(v) => (v, Comparison::NotEq),
+            Cmp::Err => unreachable!("the compiler only receives valid programs"),
+        };
+        let (lhs, rhs) = (v.0.r#type.clone(), v.1.r#type.clone());
+        self.value(&v.0);
+        match (&lhs, &rhs) {
+            (Type::Int, Type::Int) => {
+                self.value(&v.1);
+                self.emit(Instr::CmpInt(comparison), span);
+            }
+            (Type::Int | Type::Num, Type::Int | Type::Num) => {
+                self.numeric(&lhs, &v.1, &rhs);
+                self.emit(Instr::CmpNum(comparison), span);
+            }
+            _ => {
+                self.value(&v.1);
+                self.emit(Instr::Cmp(comparison), span);
+            }
+        }
+    }
+}